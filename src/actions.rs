@@ -0,0 +1,86 @@
+//! Validation of the `action` parameter taken by the management tools.
+//!
+//! Each tool's actions form an enum implementing [`Action`], which lists every
+//! action with the parameters it needs. Parsing is strict: an unknown action
+//! is rejected before anything is sent to Gmail, with an error listing the
+//! valid actions and their requirements.
+
+/// The actions one tool supports.
+pub trait Action: Copy + PartialEq + 'static {
+    /// What the actions apply to, such as `label`, for error messages.
+    const SUBJECT: &'static str;
+    /// Every action as `(action, name, parameter requirements)`, the default first.
+    const ALL: &'static [(Self, &'static str, &'static str)];
+
+    /// Name of the action as given in the `action` parameter.
+    fn name(self) -> &'static str {
+        Self::ALL
+            .iter()
+            .find(|(action, _, _)| *action == self)
+            .map_or("", |(_, name, _)| name)
+    }
+}
+
+/// Parse an action name, accepting any case and falling back to the default when blank.
+pub fn parse<A: Action>(s: &str) -> Result<A, String> {
+    let s = s.trim().to_ascii_lowercase();
+    if s.is_empty() {
+        return Ok(A::ALL[0].0);
+    }
+    A::ALL
+        .iter()
+        .find(|(_, name, _)| *name == s)
+        .map(|(action, _, _)| *action)
+        .ok_or_else(|| {
+            format!(
+                "Unknown {} action '{}'; valid actions are:\n{}",
+                A::SUBJECT,
+                s,
+                usage::<A>()
+            )
+        })
+}
+
+/// One line per action with its parameter requirements.
+pub fn usage<A: Action>() -> String {
+    A::ALL
+        .iter()
+        .enumerate()
+        .map(|(i, (_, name, params))| {
+            let default = if i == 0 { " (default)" } else { "" };
+            format!("- {}{}: {}", name, default, params)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Fruit {
+        Pick,
+        Peel,
+    }
+
+    impl Action for Fruit {
+        const SUBJECT: &'static str = "fruit";
+        const ALL: &'static [(Self, &'static str, &'static str)] = &[
+            (Fruit::Pick, "pick", "no parameters"),
+            (Fruit::Peel, "peel", "requires 'id'"),
+        ];
+    }
+
+    #[test]
+    fn test_parse_action() {
+        assert_eq!(parse::<Fruit>(" PEEL "), Ok(Fruit::Peel));
+        assert_eq!(parse::<Fruit>(""), Ok(Fruit::Pick));
+        assert_eq!(Fruit::Peel.name(), "peel");
+        let err = parse::<Fruit>("eat").unwrap_err();
+        assert_eq!(
+            err,
+            "Unknown fruit action 'eat'; valid actions are:\n- pick (default): no parameters\n- peel: requires 'id'"
+        );
+    }
+}
@@ -0,0 +1,657 @@
+//! OAuth2 authentication for the Gmail API.
+//!
+//! By default the installed-app flow opens a browser and receives the code on a
+//! local redirect. Headless machines (SSH sessions, containers) can use the
+//! device flow instead, which shows a code to enter on another device, or the
+//! interactive flow, where the user pastes the redirect URL back into the server.
+//! Hosted servers can use the web flow, which completes consent through the
+//! server's own routes (see [`crate::web_auth`]).
+//!
+//! Containers can skip all of that, and every file, by passing an existing
+//! refresh token and client credentials in the environment.
+
+use crate::actions::{self, Action};
+use crate::token_store::{self, cached_tokens, CachedToken, SecretStorage, TokenStore};
+use crate::{capabilities, util, GmailMcpError};
+use crate::{GmailHub, SCOPE_FULL, SCOPE_MODIFY, SCOPE_READONLY};
+use google_gmail1::common;
+use google_gmail1::hyper::{header, Method, Request};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tracing::{info, warn};
+use yup_oauth2::authenticator::{AuthenticatorBuilder, DefaultAuthenticator};
+use yup_oauth2::authenticator_delegate::{
+    DeviceAuthResponse, DeviceFlowDelegate, InstalledFlowDelegate,
+};
+use yup_oauth2::authorized_user::AuthorizedUserSecret;
+use yup_oauth2::{
+    ApplicationSecret, AuthorizedUserAuthenticator, DeviceFlowAuthenticator,
+    InstalledFlowAuthenticator, InstalledFlowReturnMethod,
+};
+
+/// Environment variable selecting the OAuth flow (`redirect`, `device`, or `interactive`).
+pub const FLOW_ENV: &str = "GMAIL_AUTH_FLOW";
+/// Where the OAuth client credentials are read from.
+pub const CLIENT_SECRET_PATH: &str = "client_secret.json";
+/// Where tokens are cached between runs with the default file store.
+pub const TOKEN_CACHE_PATH: &str = "token_cache.json";
+/// Environment variables with OAuth client credentials, used instead of [`CLIENT_SECRET_PATH`].
+pub const CLIENT_ID_ENV: &str = "GMAIL_CLIENT_ID";
+pub const CLIENT_SECRET_ENV: &str = "GMAIL_CLIENT_SECRET";
+/// Environment variable with a refresh token; when set, no OAuth flow or token cache is used.
+pub const REFRESH_TOKEN_ENV: &str = "GMAIL_REFRESH_TOKEN";
+
+/// Google's OAuth endpoints, for credentials that do not come from a client secret file.
+const GOOGLE_AUTH_URI: &str = "https://accounts.google.com/o/oauth2/auth";
+const GOOGLE_TOKEN_URI: &str = "https://oauth2.googleapis.com/token";
+
+/// Google's device authorization endpoint and grant type; yup-oauth2 defaults
+/// to the legacy ones.
+const DEVICE_CODE_URL: &str = "https://oauth2.googleapis.com/device/code";
+const DEVICE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// How the user grants access on first run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AuthFlow {
+    /// Open a browser and receive the code on a local HTTP redirect.
+    #[default]
+    Redirect,
+    /// Show a code to enter at google.com/device from any other device.
+    Device,
+    /// Show the consent URL and read the redirect URL or code pasted on stdin.
+    Interactive,
+    /// Complete consent in a browser through the server's own OAuth routes.
+    Web,
+}
+
+impl FromStr for AuthFlow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "" | "redirect" | "browser" => Ok(Self::Redirect),
+            "device" => Ok(Self::Device),
+            "interactive" | "paste" => Ok(Self::Interactive),
+            "web" => Ok(Self::Web),
+            other => Err(format!(
+                "Unknown {} '{}'; expected redirect, device, interactive, or web",
+                FLOW_ENV, other
+            )),
+        }
+    }
+}
+
+impl AuthFlow {
+    /// Read the flow from [`FLOW_ENV`], defaulting to [`AuthFlow::Redirect`].
+    pub fn from_env() -> Result<Self, String> {
+        std::env::var(FLOW_ENV).unwrap_or_default().parse()
+    }
+}
+
+/// Where the OAuth client credentials come from.
+#[derive(Debug, Clone)]
+pub enum Credentials {
+    /// Client credentials from [`CLIENT_SECRET_PATH`] or the environment; tokens
+    /// come from an OAuth flow and are cached in the token store.
+    Client(ApplicationSecret),
+    /// A refresh token from [`REFRESH_TOKEN_ENV`]; access tokens are kept in memory only.
+    RefreshToken(AuthorizedUserSecret),
+}
+
+impl Credentials {
+    /// Read credentials from the environment, falling back to [`CLIENT_SECRET_PATH`].
+    pub async fn load() -> Result<Self, GmailMcpError> {
+        if let Some(credentials) = Self::from_env().map_err(GmailMcpError::MissingCredentials)? {
+            return Ok(credentials);
+        }
+        info!("Gmail API: Loading credentials from {}", CLIENT_SECRET_PATH);
+        let secret = yup_oauth2::read_application_secret(CLIENT_SECRET_PATH)
+            .await
+            .map_err(|e| {
+                GmailMcpError::MissingCredentials(format!(
+                    "Cannot read {}: {}; alternatively set {} and {}",
+                    CLIENT_SECRET_PATH, e, CLIENT_ID_ENV, CLIENT_SECRET_ENV
+                ))
+            })?;
+        Ok(Self::Client(secret))
+    }
+
+    /// Credentials given entirely in the environment, if any.
+    pub fn from_env() -> Result<Option<Self>, String> {
+        Self::from_vars(
+            env_var(CLIENT_ID_ENV),
+            env_var(CLIENT_SECRET_ENV),
+            env_var(REFRESH_TOKEN_ENV),
+        )
+    }
+
+    fn from_vars(
+        client_id: Option<String>,
+        client_secret: Option<String>,
+        refresh_token: Option<String>,
+    ) -> Result<Option<Self>, String> {
+        let (client_id, client_secret) = match (client_id, client_secret, &refresh_token) {
+            (Some(id), Some(secret), _) => (id, secret),
+            (None, None, None) => return Ok(None),
+            _ => {
+                return Err(format!(
+                    "{} and {} must both be set{}",
+                    CLIENT_ID_ENV,
+                    CLIENT_SECRET_ENV,
+                    if refresh_token.is_some() {
+                        format!(" to use {}", REFRESH_TOKEN_ENV)
+                    } else {
+                        String::new()
+                    }
+                ))
+            }
+        };
+        info!("Gmail API: Using client credentials from the environment");
+        Ok(Some(match refresh_token {
+            Some(refresh_token) => Self::RefreshToken(AuthorizedUserSecret {
+                client_id,
+                client_secret,
+                refresh_token,
+                key_type: "authorized_user".to_string(),
+            }),
+            None => Self::Client(ApplicationSecret {
+                client_id,
+                client_secret,
+                auth_uri: GOOGLE_AUTH_URI.to_string(),
+                token_uri: GOOGLE_TOKEN_URI.to_string(),
+                redirect_uris: vec!["http://localhost".to_string()],
+                ..Default::default()
+            }),
+        }))
+    }
+
+    pub fn client_id(&self) -> &str {
+        match self {
+            Self::Client(secret) => &secret.client_id,
+            Self::RefreshToken(secret) => &secret.client_id,
+        }
+    }
+
+    /// Where these credentials were read from, for status reports.
+    fn source(&self) -> &'static str {
+        match self {
+            Self::RefreshToken(_) => REFRESH_TOKEN_ENV,
+            Self::Client(_) if env_var(CLIENT_ID_ENV).is_some() => CLIENT_ID_ENV,
+            Self::Client(_) => CLIENT_SECRET_PATH,
+        }
+    }
+}
+
+/// Read a non-empty environment variable.
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name)
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+/// Shows the device code in the server log rather than on stdout.
+struct LogDeviceCode;
+
+impl DeviceFlowDelegate for LogDeviceCode {
+    fn present_user_code<'a>(
+        &'a self,
+        response: &'a DeviceAuthResponse,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>> {
+        Box::pin(async move {
+            warn!(
+                "Gmail auth: Visit {} and enter code {} to grant access (expires at {})",
+                response.verification_uri, response.user_code, response.expires_at
+            );
+        })
+    }
+}
+
+/// Redirect target for the interactive flow. Nothing listens there: the browser
+/// shows an error page whose address holds the code for the user to copy.
+const INTERACTIVE_REDIRECT_URI: &str = "http://localhost";
+
+/// Shows the consent URL in the server log and reads the pasted reply from stdin.
+struct PasteRedirect;
+
+impl InstalledFlowDelegate for PasteRedirect {
+    fn redirect_uri(&self) -> Option<&str> {
+        Some(INTERACTIVE_REDIRECT_URI)
+    }
+
+    fn present_user_url<'a>(
+        &'a self,
+        url: &'a str,
+        _need_code: bool,
+    ) -> Pin<Box<dyn Future<Output = Result<String, String>> + Send + 'a>> {
+        Box::pin(async move {
+            warn!(
+                "Gmail auth: Open {} in any browser and grant access. The browser then fails to \
+                 load {}; copy that page's full address and paste it here, followed by Enter",
+                url, INTERACTIVE_REDIRECT_URI
+            );
+            let mut input = String::new();
+            BufReader::new(tokio::io::stdin())
+                .read_line(&mut input)
+                .await
+                .map_err(|e| format!("Could not read the pasted code: {}", e))?;
+            auth_code_from_input(&input).ok_or_else(|| "No authorization code was pasted".into())
+        })
+    }
+}
+
+/// Extract the authorization code from a pasted redirect URL, or take the input
+/// as the code itself.
+fn auth_code_from_input(input: &str) -> Option<String> {
+    let input = input.trim();
+    let code = match input.split_once('?') {
+        Some((_, query)) => query
+            .split(['&', '#'])
+            .find_map(|pair| pair.strip_prefix("code="))
+            .map(util::percent_decode)?,
+        None => input.to_string(),
+    };
+    (!code.is_empty()).then_some(code)
+}
+
+/// Point an authenticator builder at the chosen token store.
+fn with_store<C, F>(
+    builder: AuthenticatorBuilder<C, F>,
+    store: TokenStore,
+) -> AuthenticatorBuilder<C, F> {
+    match SecretStorage::new(store) {
+        Some(storage) => builder.with_storage(Box::new(storage)),
+        None => builder.persist_tokens_to_disk(TOKEN_CACHE_PATH),
+    }
+}
+
+/// Build an authenticator for `flow`, caching tokens in `store`.
+///
+/// With [`Credentials::RefreshToken`] neither is used: tokens are refreshed
+/// directly and kept in memory.
+pub async fn authenticator(
+    credentials: Credentials,
+    flow: AuthFlow,
+    store: TokenStore,
+) -> std::io::Result<DefaultAuthenticator> {
+    let secret = match credentials {
+        Credentials::Client(secret) => secret,
+        Credentials::RefreshToken(secret) => {
+            info!(
+                "Gmail API: Setting up OAuth2 authenticator from {}",
+                REFRESH_TOKEN_ENV
+            );
+            return AuthorizedUserAuthenticator::builder(secret).build().await;
+        }
+    };
+    info!(
+        "Gmail API: Setting up OAuth2 authenticator ({:?} flow, {:?} token store)",
+        flow, store
+    );
+    match flow {
+        AuthFlow::Redirect => {
+            with_store(
+                InstalledFlowAuthenticator::builder(
+                    secret,
+                    InstalledFlowReturnMethod::HTTPRedirect,
+                ),
+                store,
+            )
+            .build()
+            .await
+        }
+        AuthFlow::Interactive => {
+            with_store(
+                InstalledFlowAuthenticator::builder(secret, InstalledFlowReturnMethod::Interactive)
+                    .flow_delegate(Box::new(PasteRedirect)),
+                store,
+            )
+            .build()
+            .await
+        }
+        #[cfg(feature = "server")]
+        AuthFlow::Web => {
+            let config = crate::web_auth::WebAuthConfig::from_env();
+            with_store(
+                InstalledFlowAuthenticator::builder(secret, InstalledFlowReturnMethod::Interactive)
+                    .flow_delegate(Box::new(crate::web_auth::WebRedirect::new(&config))),
+                store,
+            )
+            .build()
+            .await
+        }
+        #[cfg(not(feature = "server"))]
+        AuthFlow::Web => Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "The web OAuth flow requires the server feature",
+        )),
+        AuthFlow::Device => {
+            with_store(
+                DeviceFlowAuthenticator::builder(secret)
+                    .device_code_url(DEVICE_CODE_URL)
+                    .grant_type(DEVICE_GRANT_TYPE)
+                    .flow_delegate(Box::new(LogDeviceCode)),
+                store,
+            )
+            .build()
+            .await
+        }
+    }
+}
+
+/// Google's token revocation endpoint.
+const REVOKE_URL: &str = "https://oauth2.googleapis.com/revoke";
+
+/// Scopes that allow reading the profile, used to look up the account address.
+const PROFILE_SCOPES: &[&str] = &[SCOPE_READONLY, SCOPE_MODIFY, SCOPE_FULL];
+
+/// How long the status check waits for the profile before giving up.
+const PROFILE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Authentication state reported to MCP clients.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AuthStatus {
+    /// `Redirect`, `Device`, `Interactive`, or `Web`.
+    pub flow: String,
+    /// `File`, `Keyring`, or `Encrypted`.
+    pub token_store: String,
+    /// Whether client credentials could be loaded.
+    pub credentials_loaded: bool,
+    /// Where the credentials came from: `client_secret.json` or an environment variable.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credentials_source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    /// Tokens cached by earlier authorizations.
+    pub tokens: Vec<CachedToken>,
+    /// Every scope covered by a cached token.
+    pub granted_scopes: Vec<String>,
+    /// Scopes of enabled capabilities that still need consent.
+    pub missing_scopes: Vec<String>,
+    /// The authenticated account, when a cached token allows reading it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    /// Problems found while checking, such as unreadable credentials.
+    pub errors: Vec<String>,
+}
+
+/// Report the authentication state without starting an OAuth flow.
+pub async fn auth_status() -> Result<AuthStatus, GmailMcpError> {
+    let flow = AuthFlow::from_env().map_err(GmailMcpError::Invalid)?;
+    let store = TokenStore::from_env().map_err(GmailMcpError::Invalid)?;
+    let mut errors = Vec::new();
+
+    let credentials = match Credentials::load().await {
+        Ok(credentials) => Some(credentials),
+        Err(e) => {
+            errors.push(e.to_string());
+            None
+        }
+    };
+    let client_id = credentials.as_ref().map(|c| c.client_id().to_string());
+    let from_refresh_token = matches!(credentials, Some(Credentials::RefreshToken(_)));
+
+    let tokens = cached_tokens(store).await.unwrap_or_else(|e| {
+        errors.push(format!("Cannot read cached tokens: {}", e));
+        Vec::new()
+    });
+    let mut granted_scopes: Vec<String> = tokens.iter().flat_map(|t| t.scopes.clone()).collect();
+    granted_scopes.sort();
+    granted_scopes.dedup();
+    // A refresh token's scopes were granted elsewhere and are not known here
+    let missing_scopes = if from_refresh_token {
+        Vec::new()
+    } else {
+        capabilities::enabled()
+            .scopes()
+            .into_iter()
+            .filter(|s| !granted_scopes.iter().any(|g| g == s))
+            .map(str::to_string)
+            .collect()
+    };
+
+    // Only ask for the profile with a scope that is already cached, so the check
+    // never opens a consent screen; a refresh token never opens one
+    let profile_scope = PROFILE_SCOPES
+        .iter()
+        .find(|s| from_refresh_token || granted_scopes.iter().any(|g| g == *s));
+    let mut email = None;
+    if let (Some(scope), Some(_)) = (profile_scope, &client_id) {
+        match account_email(scope).await {
+            Ok(address) => email = address,
+            Err(e) => errors.push(format!("Cannot read the account profile: {}", e)),
+        }
+    }
+
+    Ok(AuthStatus {
+        flow: format!("{:?}", flow),
+        token_store: format!("{:?}", store),
+        credentials_loaded: client_id.is_some(),
+        credentials_source: credentials.map(|c| c.source().to_string()),
+        client_id,
+        tokens,
+        granted_scopes,
+        missing_scopes,
+        email,
+        errors,
+    })
+}
+
+/// Fetch the address of the authenticated account using a cached `scope`.
+async fn account_email(scope: &str) -> Result<Option<String>, GmailMcpError> {
+    let hub = crate::build_hub().await?;
+    crate::quota::record("getProfile", 1);
+    let call = hub.users().get_profile("me").add_scope(scope).doit();
+    let (_, profile) = tokio::time::timeout(PROFILE_TIMEOUT, call)
+        .await
+        .map_err(|_| GmailMcpError::Network("Timed out waiting for the profile".to_string()))??;
+    Ok(profile.email_address)
+}
+
+/// Result of clearing the cached tokens, returned to MCP clients as JSON.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LogoutResponse {
+    /// Number of cached grants removed.
+    pub cleared: usize,
+    /// Number of grants revoked with Google.
+    pub revoked: usize,
+    /// Whether a new OAuth flow completed afterwards.
+    pub reauthorized: bool,
+    /// The state after re-authorizing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<AuthStatus>,
+    /// Tokens that could not be revoked.
+    pub errors: Vec<String>,
+}
+
+/// Percent-encode a value for an `application/x-www-form-urlencoded` body.
+fn form_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Revoke a refresh or access token, ending the grant it belongs to.
+async fn revoke_token(hub: &GmailHub, token: &str) -> Result<(), GmailMcpError> {
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(REVOKE_URL)
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(common::to_body(Some(format!(
+            "token={}",
+            form_encode(token)
+        ))))?;
+    let response = hub.client.request(request).await?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = common::to_bytes(response.into_body())
+            .await
+            .unwrap_or_default();
+        return Err(GmailMcpError::from_status(
+            status.as_u16(),
+            &String::from_utf8_lossy(&body),
+        ));
+    }
+    Ok(())
+}
+
+/// Delete the cached tokens, optionally revoking them with Google first, and
+/// with `reauthorize` start a fresh OAuth flow.
+pub async fn logout(revoke: bool, reauthorize: bool) -> Result<LogoutResponse, GmailMcpError> {
+    let store = TokenStore::from_env().map_err(GmailMcpError::Invalid)?;
+    let credentials = Credentials::from_env().map_err(GmailMcpError::MissingCredentials)?;
+    let from_refresh_token = matches!(credentials, Some(Credentials::RefreshToken(_)));
+    if reauthorize && from_refresh_token {
+        return Err(GmailMcpError::Invalid(format!(
+            "Cannot re-authorize while {} is set; replace the token in the environment",
+            REFRESH_TOKEN_ENV
+        )));
+    }
+    let mut secrets = token_store::clear(store).await?;
+    // The shared client still holds the cleared tokens in memory, and the next
+    // sign-in may be to another account
+    crate::client::reset().await;
+    crate::cache::clear().await;
+    #[cfg(feature = "search")]
+    crate::search_index::remove(Vec::new(), true).await;
+    // The sync start point is a history ID of the old account
+    crate::state::record_sync(None);
+    info!("Gmail auth: Cleared {} cached grants", secrets.len());
+    let cleared = secrets.len();
+    if revoke && from_refresh_token {
+        secrets.extend(env_var(REFRESH_TOKEN_ENV));
+    }
+
+    let mut revoked = 0;
+    let mut errors = Vec::new();
+    if revoke && !secrets.is_empty() {
+        let hub = crate::build_hub().await?;
+        for secret in &secrets {
+            match revoke_token(&hub, secret).await {
+                Ok(()) => revoked += 1,
+                Err(e) => {
+                    warn!("Gmail auth: {}", e);
+                    errors.push(e.to_string());
+                }
+            }
+        }
+    }
+
+    let status = if reauthorize {
+        info!("Gmail auth: Starting a new OAuth flow");
+        crate::connect().await?;
+        Some(auth_status().await?)
+    } else {
+        None
+    };
+
+    Ok(LogoutResponse {
+        cleared,
+        revoked,
+        reauthorized: status.is_some(),
+        status,
+        errors,
+    })
+}
+
+/// Actions of the `gmail_auth` tool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthAction {
+    Status,
+    Logout,
+    Reauth,
+}
+
+impl Action for AuthAction {
+    const SUBJECT: &'static str = "auth";
+    const ALL: &'static [(Self, &'static str, &'static str)] = &[
+        (AuthAction::Status, "status", "no parameters"),
+        (AuthAction::Logout, "logout", "optional revoke"),
+        (AuthAction::Reauth, "reauth", "optional revoke"),
+    ];
+}
+
+impl FromStr for AuthAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        actions::parse(s)
+    }
+}
+
+/// Inspect or reset authentication for the MCP tool.
+pub async fn manage_auth(action: AuthAction, revoke: bool) -> Result<String, GmailMcpError> {
+    match action {
+        AuthAction::Status => Ok(serde_json::to_string_pretty(&auth_status().await?)?),
+        AuthAction::Logout => Ok(serde_json::to_string_pretty(&logout(revoke, false).await?)?),
+        AuthAction::Reauth => Ok(serde_json::to_string_pretty(&logout(revoke, true).await?)?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auth_code_from_input() {
+        assert_eq!(
+            auth_code_from_input("http://localhost/?code=4%2F0Ab_c-d&scope=x\n").as_deref(),
+            Some("4/0Ab_c-d")
+        );
+        assert_eq!(
+            auth_code_from_input("  4/0Ab_c-d ").as_deref(),
+            Some("4/0Ab_c-d")
+        );
+        assert_eq!(
+            auth_code_from_input("http://localhost/?error=access_denied"),
+            None
+        );
+        assert_eq!(auth_code_from_input("\n"), None);
+    }
+
+    #[test]
+    fn test_credentials_from_vars() {
+        let some = |s: &str| Some(s.to_string());
+        assert!(Credentials::from_vars(None, None, None).unwrap().is_none());
+
+        let client = Credentials::from_vars(some("id"), some("secret"), None).unwrap();
+        assert!(
+            matches!(client, Some(Credentials::Client(ref s)) if s.token_uri == GOOGLE_TOKEN_URI)
+        );
+
+        let refresh = Credentials::from_vars(some("id"), some("secret"), some("1//tok")).unwrap();
+        assert!(
+            matches!(refresh, Some(Credentials::RefreshToken(ref s)) if s.refresh_token == "1//tok")
+        );
+        assert_eq!(refresh.unwrap().client_id(), "id");
+
+        assert!(Credentials::from_vars(None, None, some("1//tok")).is_err());
+        assert!(Credentials::from_vars(some("id"), None, None).is_err());
+    }
+
+    #[test]
+    fn test_form_encode() {
+        assert_eq!(form_encode("1//0gAb-c_d.e~"), "1%2F%2F0gAb-c_d.e~");
+        assert_eq!(form_encode("a b+c"), "a%20b%2Bc");
+    }
+
+    #[test]
+    fn test_auth_flow_from_str() {
+        assert_eq!("".parse::<AuthFlow>(), Ok(AuthFlow::Redirect));
+        assert_eq!("Device".parse::<AuthFlow>(), Ok(AuthFlow::Device));
+        assert_eq!("paste".parse::<AuthFlow>(), Ok(AuthFlow::Interactive));
+        assert_eq!("web".parse::<AuthFlow>(), Ok(AuthFlow::Web));
+        assert_eq!(" redirect ".parse::<AuthFlow>(), Ok(AuthFlow::Redirect));
+        assert!("oob".parse::<AuthFlow>().is_err());
+    }
+}
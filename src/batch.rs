@@ -0,0 +1,369 @@
+//! Batched message retrieval through Gmail's HTTP batch endpoint.
+//!
+//! Instead of one HTTPS round trip per message, up to [`MAX_BATCH_SIZE`] `GET`s
+//! are packed into a single `multipart/mixed` request and the responses are
+//! unpacked from the multipart reply.
+
+use crate::retry::Backoff;
+use crate::{limits, progress, FetchFormat, GmailMcpError};
+use crate::{GmailHub, SCOPE_READONLY};
+use futures::stream::{self, StreamExt};
+use google_gmail1::api::Message;
+use google_gmail1::common;
+use google_gmail1::hyper::{header, Method, Request};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::Semaphore;
+use tracing::{info, warn};
+
+/// The Gmail batch endpoint.
+pub const BATCH_URL: &str = "https://gmail.googleapis.com/batch/gmail/v1";
+/// Gmail accepts at most 100 calls per batch request.
+pub const MAX_BATCH_SIZE: usize = 100;
+
+const BOUNDARY: &str = "batch_gmail_mcp";
+
+/// One response unpacked from a batch reply.
+#[derive(Debug, PartialEq)]
+struct BatchPart {
+    /// Position of the matching request, from its `Content-ID`.
+    index: Option<usize>,
+    /// HTTP status of the individual call.
+    status: u16,
+    /// Body of the individual call.
+    body: String,
+}
+
+/// Build the multipart body with one `GET` per message ID.
+fn build_batch_body(ids: &[&str], format: FetchFormat) -> String {
+    let mut params = format!("format={}", format.api_format());
+    if let Some(fields) = format.fields() {
+        params.push_str(&format!("&fields={}", fields));
+    }
+
+    let mut body = String::new();
+    for (i, id) in ids.iter().enumerate() {
+        body.push_str(&format!(
+            "--{}\r\nContent-Type: application/http\r\nContent-ID: <item-{}>\r\n\r\nGET /gmail/v1/users/me/messages/{}?{}\r\n\r\n",
+            BOUNDARY, i, id, params
+        ));
+    }
+    body.push_str(&format!("--{}--\r\n", BOUNDARY));
+    body
+}
+
+/// Split a block of text at its first blank line into headers and body.
+fn split_head(text: &str) -> (&str, &str) {
+    if let Some(pos) = text.find("\r\n\r\n") {
+        (&text[..pos], &text[pos + 4..])
+    } else if let Some(pos) = text.find("\n\n") {
+        (&text[..pos], &text[pos + 2..])
+    } else {
+        (text, "")
+    }
+}
+
+/// Extract the `boundary` parameter from a multipart `Content-Type`.
+fn boundary_from_content_type(content_type: &str) -> Option<String> {
+    content_type.split(';').find_map(|param| {
+        let (name, value) = param.trim().split_once('=')?;
+        name.eq_ignore_ascii_case("boundary")
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+/// Unpack the individual responses from a batch reply.
+fn parse_batch_response(body: &str, boundary: &str) -> Vec<BatchPart> {
+    let delimiter = format!("--{}", boundary);
+    body.split(delimiter.as_str())
+        .map(|part| part.trim_start_matches("\r\n").trim_start_matches('\n'))
+        .filter(|part| !part.is_empty() && !part.starts_with("--"))
+        .filter_map(|part| {
+            let (outer_headers, http) = split_head(part);
+            let index = outer_headers.lines().find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                if !name.trim().eq_ignore_ascii_case("content-id") {
+                    return None;
+                }
+                let value = value.trim().trim_start_matches('<').trim_end_matches('>');
+                value.rsplit('-').next()?.parse().ok()
+            });
+            let (head, body) = split_head(http);
+            let status = head
+                .lines()
+                .next()?
+                .split_whitespace()
+                .nth(1)?
+                .parse()
+                .ok()?;
+            Some(BatchPart {
+                index,
+                status,
+                body: body.trim_end().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Send one batch request and return its parts.
+async fn send_batch(
+    hub: &GmailHub,
+    ids: &[&str],
+    format: FetchFormat,
+) -> Result<Vec<BatchPart>, GmailMcpError> {
+    // The token request below takes one slot; every other part needs its own
+    limits::throttle(ids.len().saturating_sub(1) as u32).await;
+    crate::quota::record("messages.get", ids.len() as u32);
+    let token = hub
+        .auth
+        .get_token(&[SCOPE_READONLY])
+        .await
+        .map_err(|e| GmailMcpError::Auth(e.to_string()))?
+        .ok_or_else(|| {
+            GmailMcpError::Auth("No access token available for the batch request".to_string())
+        })?;
+
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(BATCH_URL)
+        .header(header::AUTHORIZATION, format!("Bearer {}", token))
+        .header(
+            header::CONTENT_TYPE,
+            format!("multipart/mixed; boundary={}", BOUNDARY),
+        )
+        .body(common::to_body(Some(build_batch_body(ids, format))))?;
+    let response = hub.client.request(request).await?;
+
+    let status = response.status();
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let body = common::to_bytes(response.into_body())
+        .await
+        .unwrap_or_default();
+    let body = String::from_utf8_lossy(&body);
+    if !status.is_success() {
+        return Err(GmailMcpError::from_status(status.as_u16(), &body));
+    }
+
+    let boundary = boundary_from_content_type(&content_type).ok_or_else(|| {
+        GmailMcpError::Parse(format!("batch response: not multipart ({})", content_type))
+    })?;
+    Ok(parse_batch_response(&body, &boundary))
+}
+
+/// Fetch a single message outside a batch, with the same format options.
+async fn fetch_one(
+    hub: &GmailHub,
+    id: &str,
+    format: FetchFormat,
+) -> Result<Message, google_gmail1::Error> {
+    let mut backoff = Backoff::new();
+    let mut call = hub
+        .users()
+        .messages_get("me", id)
+        .format(format.api_format())
+        .add_scope(SCOPE_READONLY)
+        .delegate(&mut backoff);
+    if let Some(fields) = format.fields() {
+        call = call.param("fields", fields);
+    }
+    let (_, message) = call.doit().await.map_err(|e| {
+        warn!("Gmail API: Failed to fetch message {}: {}", id, e);
+        e
+    })?;
+    Ok(message)
+}
+
+/// Messages settled so far, fetched or given up on, out of `total`.
+struct Tally {
+    done: AtomicUsize,
+    total: usize,
+}
+
+impl Tally {
+    /// Count `settled` more messages and report the progress of the tool call.
+    fn add(&self, settled: usize) {
+        if settled == 0 {
+            return;
+        }
+        let done = self.done.fetch_add(settled, Ordering::Relaxed) + settled;
+        progress::report(
+            done,
+            self.total,
+            &format!("Fetched {} of {} messages", done, self.total),
+        );
+    }
+}
+
+/// Fetch many messages in `format`, in the order of `ids`.
+///
+/// Up to [`limits::fetch_concurrency`] requests are in flight at a time,
+/// counting batches and single fetches alike. Messages that fail individually
+/// with a rate limit or server error are retried one at a time, with backoff
+/// (see [`crate::retry`]); other failures (such as deleted messages) are logged
+/// and skipped. If a whole batch fails, its messages are fetched one at a time.
+pub async fn fetch_messages(
+    hub: &GmailHub,
+    ids: &[String],
+    format: FetchFormat,
+) -> Result<Vec<Message>, GmailMcpError> {
+    let concurrency = limits::fetch_concurrency();
+    // Shared by every batch and retry, so the retries of concurrent batches
+    // do not multiply the requests in flight
+    let permits = Semaphore::new(concurrency);
+    let tally = Tally {
+        done: AtomicUsize::new(0),
+        total: ids.len(),
+    };
+    let batches: Vec<Vec<Message>> = stream::iter(ids.chunks(MAX_BATCH_SIZE))
+        .map(|chunk| fetch_batch(hub, chunk, format, &permits, &tally))
+        .buffered(concurrency)
+        .collect()
+        .await;
+    Ok(batches.into_iter().flatten().collect())
+}
+
+/// Fetch one batch of at most [`MAX_BATCH_SIZE`] messages, retrying failed
+/// ones individually. Each request holds one of `permits` while it runs, and
+/// progress is added to `tally` as each request completes.
+///
+/// Returns the messages fetched, in order.
+async fn fetch_batch(
+    hub: &GmailHub,
+    chunk: &[String],
+    format: FetchFormat,
+    permits: &Semaphore,
+    tally: &Tally,
+) -> Vec<Message> {
+    let chunk: Vec<&str> = chunk.iter().map(String::as_str).collect();
+    info!("Gmail API: Batch fetching {} messages", chunk.len());
+
+    let parts = {
+        // The semaphore is never closed
+        let _permit = permits.acquire().await.ok();
+        send_batch(hub, &chunk, format)
+            .await
+            .map_err(|e| e.to_string())
+    };
+    let mut fetched: Vec<Option<Message>> = vec![None; chunk.len()];
+    let mut retry = Vec::new();
+    match parts {
+        Ok(parts) => {
+            for part in parts {
+                let Some(index) = part.index.filter(|&i| i < chunk.len()) else {
+                    warn!("Gmail API: Batch response part without a known Content-ID");
+                    continue;
+                };
+                if part.status == 200 {
+                    match serde_json::from_str::<Message>(&part.body) {
+                        Ok(message) => fetched[index] = Some(message),
+                        Err(e) => {
+                            warn!("Gmail API: Could not parse message {}: {}", chunk[index], e)
+                        }
+                    }
+                } else if part.status == 429 || part.status >= 500 {
+                    retry.push(index);
+                } else {
+                    warn!(
+                        "Gmail API: Batch fetch of message {} failed with {}",
+                        chunk[index], part.status
+                    );
+                }
+            }
+        }
+        Err(e) => {
+            warn!(
+                "Gmail API: Batch request failed, fetching one at a time: {}",
+                e
+            );
+            retry.extend(0..chunk.len());
+        }
+    }
+    tally.add(chunk.len() - retry.len());
+
+    let chunk = &chunk;
+    let retried: Vec<(usize, Option<Message>)> = stream::iter(retry)
+        .map(|index| async move {
+            let _permit = permits.acquire().await.ok();
+            // Failures are logged by `fetch_one`
+            let message = fetch_one(hub, chunk[index], format).await;
+            tally.add(1);
+            (index, message.ok())
+        })
+        // At most a batch's worth of retries; the permits bound the requests
+        .buffer_unordered(MAX_BATCH_SIZE)
+        .collect()
+        .await;
+    for (index, message) in retried {
+        fetched[index] = message;
+    }
+    fetched.into_iter().flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_batch_body() {
+        assert_eq!(
+            build_batch_body(&["a1", "b2"], FetchFormat::Full),
+            "--batch_gmail_mcp\r\nContent-Type: application/http\r\nContent-ID: <item-0>\r\n\r\n\
+             GET /gmail/v1/users/me/messages/a1?format=full\r\n\r\n\
+             --batch_gmail_mcp\r\nContent-Type: application/http\r\nContent-ID: <item-1>\r\n\r\n\
+             GET /gmail/v1/users/me/messages/b2?format=full\r\n\r\n\
+             --batch_gmail_mcp--\r\n"
+        );
+    }
+
+    #[test]
+    fn test_build_batch_body_with_fields() {
+        let body = build_batch_body(&["a1"], FetchFormat::Metadata);
+        assert!(body.contains("GET /gmail/v1/users/me/messages/a1?format=full&fields=id,threadId,"));
+        assert!(!body.contains("data"));
+    }
+
+    #[test]
+    fn test_boundary_from_content_type() {
+        assert_eq!(
+            boundary_from_content_type("multipart/mixed; boundary=batch_abc").as_deref(),
+            Some("batch_abc")
+        );
+        assert_eq!(
+            boundary_from_content_type("multipart/mixed; BOUNDARY=\"x y\"").as_deref(),
+            Some("x y")
+        );
+        assert_eq!(boundary_from_content_type("application/json"), None);
+    }
+
+    #[test]
+    fn test_parse_batch_response() {
+        let body = "--batch_xyz\r\n\
+             Content-Type: application/http\r\n\
+             Content-ID: <response-item-1>\r\n\r\n\
+             HTTP/1.1 404 Not Found\r\n\
+             Content-Type: application/json; charset=UTF-8\r\n\r\n\
+             {\"error\": {\"code\": 404}}\r\n\
+             --batch_xyz\r\n\
+             Content-Type: application/http\r\n\
+             Content-ID: <response-item-0>\r\n\r\n\
+             HTTP/1.1 200 OK\r\n\
+             Content-Type: application/json; charset=UTF-8\r\n\r\n\
+             {\"id\": \"a1\", \"threadId\": \"t1\"}\r\n\
+             --batch_xyz--\r\n";
+
+        let parts = parse_batch_response(body, "batch_xyz");
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].index, Some(1));
+        assert_eq!(parts[0].status, 404);
+        assert_eq!(parts[1].index, Some(0));
+        assert_eq!(parts[1].status, 200);
+
+        let message: Message = serde_json::from_str(&parts[1].body).unwrap();
+        assert_eq!(message.id.as_deref(), Some("a1"));
+        assert_eq!(message.thread_id.as_deref(), Some("t1"));
+    }
+}
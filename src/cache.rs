@@ -0,0 +1,486 @@
+//! In-memory cache of summarized messages.
+//!
+//! A message's headers and body never change once it exists, so repeated
+//! listings over the same inbox window reuse the summaries fetched earlier
+//! instead of downloading and parsing the same bodies again. Labels do change,
+//! so cached messages are still fetched in `minimal` format, which carries only
+//! the labels and thread, and their label fields are refreshed from that.
+//!
+//! `GMAIL_CACHE_SIZE` bounds the number of summaries kept, evicting the least
+//! recently used first; `0` disables the cache. With the `sqlite` feature,
+//! `GMAIL_CACHE_DB` names a database that also keeps every summary, and the
+//! message it came from, across restarts.
+
+use crate::{batch, summarize_message, EmailSummary, FetchFormat, GmailHub, GmailMcpError};
+use google_gmail1::api::Message;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use tracing::{info, warn};
+
+/// Environment variable with the most summaries kept.
+pub const CACHE_SIZE_ENV: &str = "GMAIL_CACHE_SIZE";
+/// Summaries kept when [`CACHE_SIZE_ENV`] is not set.
+pub const DEFAULT_CACHE_SIZE: usize = 1000;
+/// Environment variable with the path of the SQLite cache database.
+pub const CACHE_DB_ENV: &str = "GMAIL_CACHE_DB";
+
+/// How many summaries the cache keeps, and where.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheConfig {
+    /// Most summaries kept in memory; 0 disables the in-memory cache.
+    pub capacity: usize,
+    /// SQLite database also keeping every summary, with the `sqlite` feature.
+    pub path: Option<PathBuf>,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            capacity: DEFAULT_CACHE_SIZE,
+            path: None,
+        }
+    }
+}
+
+impl CacheConfig {
+    /// Read the configuration from the environment.
+    pub fn from_env() -> Result<Self, String> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// Read the configuration through `var`, failing on a value that is not a
+    /// number.
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let path = var(CACHE_DB_ENV)
+            .filter(|v| !v.trim().is_empty())
+            .map(|v| PathBuf::from(v.trim()));
+        let Some(value) = var(CACHE_SIZE_ENV).filter(|v| !v.trim().is_empty()) else {
+            return Ok(Self {
+                path,
+                ..Self::default()
+            });
+        };
+        let capacity = value.trim().parse().map_err(|_| {
+            format!(
+                "Invalid {} '{}'; expected a number of messages",
+                CACHE_SIZE_ENV, value
+            )
+        })?;
+        Ok(Self { capacity, path })
+    }
+}
+
+/// A cached summary is only reused for the format it was fetched in.
+type Key = (String, FetchFormat);
+
+/// Summaries by message ID, evicting the least recently used.
+struct Lru {
+    capacity: usize,
+    entries: HashMap<Key, (u64, EmailSummary)>,
+    /// Keys by the tick of their last use, oldest first.
+    recency: BTreeMap<u64, Key>,
+    tick: u64,
+}
+
+impl Lru {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    fn get(&mut self, key: &Key) -> Option<EmailSummary> {
+        let tick = self.next_tick();
+        let (used, summary) = self.entries.get_mut(key)?;
+        self.recency.remove(used);
+        self.recency.insert(tick, key.clone());
+        *used = tick;
+        Some(summary.clone())
+    }
+
+    fn put(&mut self, key: Key, summary: EmailSummary) {
+        if self.capacity == 0 {
+            return;
+        }
+        let tick = self.next_tick();
+        if let Some((used, _)) = self.entries.insert(key.clone(), (tick, summary)) {
+            self.recency.remove(&used);
+        }
+        self.recency.insert(tick, key);
+        while self.entries.len() > self.capacity {
+            let Some((_, oldest)) = self.recency.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+    }
+
+    fn remove(&mut self, key: &Key) {
+        if let Some((used, _)) = self.entries.remove(key) {
+            self.recency.remove(&used);
+        }
+    }
+}
+
+static CACHE: OnceLock<Mutex<Lru>> = OnceLock::new();
+#[cfg(feature = "sqlite")]
+static DISK: OnceLock<crate::disk_cache::DiskCache> = OnceLock::new();
+
+/// Apply `config` to this process; ignored once the cache has been used.
+///
+/// A database that cannot be opened is logged and left out.
+pub fn install(config: CacheConfig) {
+    info!("Message cache: {:?}", config);
+    let _ = CACHE.set(Mutex::new(Lru::new(config.capacity)));
+    let Some(path) = config.path else {
+        return;
+    };
+    #[cfg(feature = "sqlite")]
+    match crate::disk_cache::DiskCache::open(&path) {
+        Ok(disk) => {
+            let _ = DISK.set(disk);
+        }
+        Err(e) => warn!("Message cache: {}", e),
+    }
+    #[cfg(not(feature = "sqlite"))]
+    warn!(
+        "Message cache: Ignoring {}; the sqlite feature is not enabled",
+        path.display()
+    );
+}
+
+/// Run `f` on the database, if one is installed, on a blocking thread, since
+/// SQLite calls block.
+#[cfg(feature = "sqlite")]
+async fn on_disk<T, F>(f: F) -> Option<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&'static crate::disk_cache::DiskCache) -> T + Send + 'static,
+{
+    let disk = DISK.get()?;
+    match tokio::task::spawn_blocking(move || f(disk)).await {
+        Ok(result) => Some(result),
+        Err(e) => {
+            warn!("Message cache: {}", e);
+            None
+        }
+    }
+}
+
+/// Look up the summaries of `ids` in memory, then on disk.
+async fn lookup(ids: &[String], format: FetchFormat) -> HashMap<String, EmailSummary> {
+    let mut found = HashMap::new();
+    let mut missing = Vec::new();
+    {
+        let mut cache = cache();
+        for id in ids {
+            match cache.get(&(id.clone(), format)) {
+                Some(summary) => {
+                    found.insert(id.clone(), summary);
+                }
+                None => missing.push(id.clone()),
+            }
+        }
+    }
+    #[cfg(feature = "sqlite")]
+    if !missing.is_empty() {
+        let on_disk = on_disk(move |disk| {
+            missing
+                .into_iter()
+                .filter_map(|id| disk.get(&id, format).map(|summary| (id, summary)))
+                .collect::<Vec<_>>()
+        })
+        .await
+        .unwrap_or_default();
+        let mut cache = cache();
+        for (id, summary) in on_disk {
+            cache.put((id.clone(), format), summary.clone());
+            found.insert(id, summary);
+        }
+    }
+    #[cfg(not(feature = "sqlite"))]
+    let _ = missing;
+    found
+}
+
+/// Keep the summaries `fresh`, each with the message it was built from, and
+/// forget the messages `gone`, all fetched in `format`.
+async fn update(format: FetchFormat, fresh: Vec<(Message, EmailSummary)>, gone: Vec<String>) {
+    {
+        let mut cache = cache();
+        for id in &gone {
+            cache.remove(&(id.clone(), format));
+        }
+        for (_, summary) in &fresh {
+            cache.put((summary.id.clone(), format), summary.clone());
+        }
+    }
+    #[cfg(feature = "sqlite")]
+    on_disk(move |disk| {
+        for id in &gone {
+            disk.remove(id, format);
+        }
+        for (message, summary) in &fresh {
+            disk.put(format, message, summary);
+        }
+    })
+    .await;
+}
+
+/// Forget messages `ids` in every format, once they have been deleted.
+pub async fn remove(ids: &[String]) {
+    {
+        let mut cache = cache();
+        for id in ids {
+            for format in CACHED_FORMATS {
+                cache.remove(&(id.clone(), format));
+            }
+        }
+    }
+    #[cfg(feature = "sqlite")]
+    {
+        let ids = ids.to_vec();
+        on_disk(move |disk| {
+            for id in &ids {
+                for format in CACHED_FORMATS {
+                    disk.remove(id, format);
+                }
+            }
+        })
+        .await;
+    }
+}
+
+fn cache() -> std::sync::MutexGuard<'static, Lru> {
+    CACHE
+        .get_or_init(|| Mutex::new(Lru::new(DEFAULT_CACHE_SIZE)))
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// The formats whose summaries are cached.
+const CACHED_FORMATS: [FetchFormat; 3] =
+    [FetchFormat::Full, FetchFormat::Metadata, FetchFormat::Raw];
+
+/// Set the labels of each message in `labels`, given by ID, in every cached format.
+async fn relabel(labels: Vec<(String, Vec<String>)>) {
+    {
+        let mut cache = cache();
+        for (id, label_ids) in &labels {
+            for format in CACHED_FORMATS {
+                let key = (id.clone(), format);
+                if let Some(mut summary) = cache.get(&key) {
+                    crate::apply_labels(&mut summary, label_ids);
+                    cache.put(key, summary);
+                }
+            }
+        }
+    }
+    #[cfg(feature = "sqlite")]
+    on_disk(move |disk| {
+        for (id, label_ids) in &labels {
+            for format in CACHED_FORMATS {
+                if let (Some(mut summary), Some(mut message)) =
+                    (disk.get(id, format), disk.message(id, format))
+                {
+                    crate::apply_labels(&mut summary, label_ids);
+                    message.label_ids = Some(label_ids.clone());
+                    disk.put(format, &message, &summary);
+                }
+            }
+        }
+    })
+    .await;
+}
+
+/// Fetch the current labels of messages `ids` and apply them to their cached
+/// summaries, returning how many messages were found.
+pub async fn refresh_labels(hub: &GmailHub, ids: &[String]) -> Result<usize, GmailMcpError> {
+    if ids.is_empty() {
+        return Ok(0);
+    }
+    let messages = batch::fetch_messages(hub, ids, FetchFormat::Minimal).await?;
+    let labels: Vec<(String, Vec<String>)> = messages
+        .into_iter()
+        .filter_map(|message| Some((message.id?, message.label_ids.unwrap_or_default())))
+        .collect();
+    let found = labels.len();
+    relabel(labels).await;
+    Ok(found)
+}
+
+/// Drop every cached summary.
+pub async fn clear() {
+    {
+        let mut cache = cache();
+        let capacity = cache.capacity;
+        *cache = Lru::new(capacity);
+    }
+    #[cfg(feature = "sqlite")]
+    on_disk(|disk| disk.clear()).await;
+}
+
+/// Whether the cache holds nothing though it could keep summaries, as after a
+/// restart without a database.
+pub async fn is_empty() -> bool {
+    let (empty, capacity) = {
+        let cache = cache();
+        (cache.entries.is_empty(), cache.capacity)
+    };
+    if !empty {
+        return false;
+    }
+    #[cfg(feature = "sqlite")]
+    if let Some(empty) = on_disk(|disk| disk.is_empty()).await {
+        return empty;
+    }
+    capacity > 0
+}
+
+/// Fetch and summarize the messages `ids` in `format`, in the order of `ids`,
+/// reusing cached summaries.
+///
+/// Each summary comes with the message it was built or refreshed from, which
+/// for a cached one was fetched in `minimal` format. Messages that could not
+/// be fetched are skipped, as by [`batch::fetch_messages`]. With the `search`
+/// feature, bodies missing from the local index, cached ones included, are
+/// added to it.
+pub async fn fetch_summaries(
+    hub: &GmailHub,
+    ids: &[String],
+    format: FetchFormat,
+) -> Result<Vec<(Message, EmailSummary)>, GmailMcpError> {
+    // Minimal messages carry nothing worth caching
+    let cacheable = format != FetchFormat::Minimal;
+    let mut cached = if cacheable {
+        lookup(ids, format).await
+    } else {
+        HashMap::new()
+    };
+    let (hits, misses): (Vec<String>, Vec<String>) =
+        ids.iter().cloned().partition(|id| cached.contains_key(id));
+    if !hits.is_empty() {
+        info!(
+            "Gmail API: {} of {} messages cached, refreshing their labels",
+            hits.len(),
+            ids.len()
+        );
+    }
+
+    let mut messages: HashMap<String, Message> = HashMap::new();
+    for (ids, format) in [(&misses, format), (&hits, FetchFormat::Minimal)] {
+        if ids.is_empty() {
+            continue;
+        }
+        for message in batch::fetch_messages(hub, ids, format).await? {
+            if let Some(id) = message.id.clone() {
+                messages.insert(id, message);
+            }
+        }
+    }
+
+    let mut summaries = Vec::with_capacity(ids.len());
+    let (mut fresh, mut gone) = (Vec::new(), Vec::new());
+    #[cfg(feature = "search")]
+    let mut to_index = Vec::new();
+    for id in ids {
+        let Some(message) = messages.remove(id) else {
+            // Deleted since it was cached, or failed to fetch
+            if cacheable {
+                gone.push(id.clone());
+            }
+            continue;
+        };
+        let summary = match cached.remove(id) {
+            Some(mut summary) => {
+                crate::apply_labels(
+                    &mut summary,
+                    message.label_ids.as_deref().unwrap_or_default(),
+                );
+                summary
+            }
+            None => {
+                let Some(summary) = summarize_message(&message) else {
+                    continue;
+                };
+                if cacheable {
+                    fresh.push((message.clone(), summary.clone()));
+                }
+                summary
+            }
+        };
+        #[cfg(feature = "search")]
+        if matches!(format, FetchFormat::Full | FetchFormat::Raw) {
+            to_index.push((message.clone(), summary.clone()));
+        }
+        summaries.push((message, summary));
+    }
+    if cacheable {
+        update(format, fresh, gone).await;
+    }
+    #[cfg(feature = "search")]
+    crate::search_index::index_in_background(to_index);
+    Ok(summaries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(id: &str) -> Key {
+        (id.to_string(), FetchFormat::Full)
+    }
+
+    fn summary(id: &str) -> EmailSummary {
+        EmailSummary {
+            id: id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_lru_evicts_least_recently_used() {
+        let mut lru = Lru::new(2);
+        lru.put(key("a"), summary("a"));
+        lru.put(key("b"), summary("b"));
+        assert!(lru.get(&key("a")).is_some());
+        lru.put(key("c"), summary("c"));
+        assert!(lru.get(&key("b")).is_none());
+        assert_eq!(lru.get(&key("a")).unwrap().id, "a");
+        assert_eq!(lru.get(&key("c")).unwrap().id, "c");
+        assert!(lru.get(&("a".to_string(), FetchFormat::Raw)).is_none());
+
+        lru.remove(&key("a"));
+        assert!(lru.get(&key("a")).is_none());
+        assert_eq!(lru.recency.len(), lru.entries.len());
+
+        let mut disabled = Lru::new(0);
+        disabled.put(key("a"), summary("a"));
+        assert!(disabled.get(&key("a")).is_none());
+    }
+
+    #[test]
+    fn test_cache_config_from_vars() {
+        assert_eq!(
+            CacheConfig::from_vars(|_| None).unwrap(),
+            CacheConfig::default()
+        );
+        let config = CacheConfig::from_vars(|name| match name {
+            CACHE_SIZE_ENV => Some("0".to_string()),
+            _ => Some("cache.db".to_string()),
+        })
+        .unwrap();
+        assert_eq!(config.capacity, 0);
+        assert_eq!(config.path, Some(PathBuf::from("cache.db")));
+        assert!(CacheConfig::from_vars(|_| Some("lots".to_string())).is_err());
+    }
+}
@@ -0,0 +1,196 @@
+//! Capabilities the server is allowed to use, and the OAuth scopes they need.
+//!
+//! `GMAIL_CAPABILITIES` lists the enabled capabilities (default: all). Tools for
+//! disabled capabilities are not registered, and the consent screen only asks
+//! for the scopes of the enabled ones.
+
+use crate::GmailMcpError;
+use crate::{
+    modify, SCOPE_COMPOSE, SCOPE_FULL, SCOPE_INSERT, SCOPE_LABELS, SCOPE_MODIFY, SCOPE_READONLY,
+    SCOPE_SEND, SCOPE_SETTINGS_BASIC, SCOPE_SETTINGS_SHARING,
+};
+use std::collections::BTreeSet;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use tracing::warn;
+
+/// Environment variable with a comma-separated list of enabled capabilities.
+pub const CAPABILITIES_ENV: &str = "GMAIL_CAPABILITIES";
+
+/// A group of related operations sharing one OAuth scope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Capability {
+    /// Search and read mail and labels; always enabled.
+    Read,
+    /// Create, rename, and delete labels.
+    Labels,
+    /// Mark read/unread, star, relabel, archive, and trash messages.
+    Modify,
+    /// Manage drafts.
+    Compose,
+    /// Send, reply to, and forward mail.
+    Send,
+    /// Manage filters and signatures.
+    Settings,
+    /// Manage mailbox delegates.
+    Delegation,
+    /// Add existing messages with import and insert.
+    Import,
+}
+
+impl Capability {
+    /// Every capability, in order.
+    pub const ALL: [Capability; 8] = [
+        Self::Read,
+        Self::Labels,
+        Self::Modify,
+        Self::Compose,
+        Self::Send,
+        Self::Settings,
+        Self::Delegation,
+        Self::Import,
+    ];
+
+    /// The name used in [`CAPABILITIES_ENV`].
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::Labels => "labels",
+            Self::Modify => "modify",
+            Self::Compose => "compose",
+            Self::Send => "send",
+            Self::Settings => "settings",
+            Self::Delegation => "delegation",
+            Self::Import => "import",
+        }
+    }
+
+    /// The OAuth scope this capability needs.
+    pub fn scope(self) -> &'static str {
+        match self {
+            Self::Read => SCOPE_READONLY,
+            Self::Labels => SCOPE_LABELS,
+            Self::Modify => SCOPE_MODIFY,
+            Self::Compose => SCOPE_COMPOSE,
+            Self::Send => SCOPE_SEND,
+            Self::Settings => SCOPE_SETTINGS_BASIC,
+            Self::Delegation => SCOPE_SETTINGS_SHARING,
+            Self::Import => SCOPE_INSERT,
+        }
+    }
+}
+
+impl FromStr for Capability {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_ascii_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|c| c.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Self::ALL.iter().map(|c| c.name()).collect();
+                format!(
+                    "Unknown capability '{}'; expected one of {}",
+                    name,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// The set of enabled capabilities.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities(BTreeSet<Capability>);
+
+impl Capabilities {
+    /// Every capability enabled.
+    pub fn all() -> Self {
+        Self(Capability::ALL.into_iter().collect())
+    }
+
+    /// Parse a comma-separated list; empty or `all` enables everything.
+    /// [`Capability::Read`] is always included.
+    pub fn parse(list: &str) -> Result<Self, String> {
+        if list.trim().is_empty() || list.trim().eq_ignore_ascii_case("all") {
+            return Ok(Self::all());
+        }
+        let mut set = list
+            .split(',')
+            .filter(|c| !c.trim().is_empty())
+            .map(str::parse)
+            .collect::<Result<BTreeSet<Capability>, _>>()?;
+        set.insert(Capability::Read);
+        Ok(Self(set))
+    }
+
+    /// Read the capabilities from [`CAPABILITIES_ENV`].
+    pub fn from_env() -> Result<Self, String> {
+        Self::parse(&std::env::var(CAPABILITIES_ENV).unwrap_or_default())
+    }
+
+    pub fn contains(&self, capability: Capability) -> bool {
+        self.0.contains(&capability)
+    }
+
+    /// The OAuth scopes covering every enabled capability.
+    ///
+    /// Full mailbox access is added only when permanent deletion is allowed.
+    pub fn scopes(&self) -> Vec<&'static str> {
+        let mut scopes: Vec<&'static str> = self.0.iter().map(|c| c.scope()).collect();
+        if self.contains(Capability::Modify) && modify::permanent_delete_allowed() {
+            scopes.push(SCOPE_FULL);
+        }
+        scopes
+    }
+}
+
+static ENABLED: OnceLock<Capabilities> = OnceLock::new();
+
+/// The capabilities enabled for this process, read once from the environment.
+///
+/// An invalid [`CAPABILITIES_ENV`] is logged and treated as enabling
+/// everything; servers should validate it at startup with [`Capabilities::from_env`].
+pub fn enabled() -> &'static Capabilities {
+    ENABLED.get_or_init(|| {
+        Capabilities::from_env().unwrap_or_else(|e| {
+            warn!("{}; enabling all capabilities", e);
+            Capabilities::all()
+        })
+    })
+}
+
+/// Fail with a helpful error when `capability` is disabled.
+pub fn require(capability: Capability) -> Result<(), GmailMcpError> {
+    if enabled().contains(capability) {
+        Ok(())
+    } else {
+        Err(GmailMcpError::Invalid(format!(
+            "The '{}' capability is disabled; add it to {} to use this action",
+            capability.name(),
+            CAPABILITIES_ENV
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_capabilities() {
+        assert_eq!(Capabilities::parse("").unwrap(), Capabilities::all());
+        assert_eq!(Capabilities::parse("ALL").unwrap(), Capabilities::all());
+
+        let caps = Capabilities::parse("send, compose").unwrap();
+        assert!(caps.contains(Capability::Read));
+        assert!(caps.contains(Capability::Send));
+        assert!(!caps.contains(Capability::Modify));
+        assert_eq!(
+            caps.scopes(),
+            vec![SCOPE_READONLY, SCOPE_COMPOSE, SCOPE_SEND]
+        );
+
+        assert!(Capabilities::parse("read,everything").is_err());
+    }
+}
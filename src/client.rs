@@ -0,0 +1,189 @@
+//! A Gmail client that authenticates once and is reused across calls.
+//!
+//! Building the authenticator and HTTPS stack means reading the credentials and
+//! token store, so the server connects once with [`shared`] and every tool
+//! call reuses that client. Checks that must not start an OAuth flow, such as
+//! the auth status and health report, reuse the client from [`built`] instead,
+//! which later becomes the shared one. [`reset`] drops both after the cached
+//! tokens are cleared, since the authenticator keeps its tokens in memory.
+
+use crate::auth::{self, AuthFlow};
+use crate::token_store::TokenStore;
+use crate::{
+    capabilities, limits, timeouts, EmailResponse, EmailSummary, FetchOptions, GmailHub,
+    GmailMcpError,
+};
+use google_gmail1::hyper_rustls::HttpsConnectorBuilder;
+use google_gmail1::hyper_util::client::legacy::{connect::HttpConnector, Client};
+use google_gmail1::hyper_util::rt::TokioExecutor;
+use google_gmail1::Gmail;
+use hyper_timeout::TimeoutConnector;
+use tokio::sync::Mutex;
+use tracing::{error, info};
+
+/// How a [`GmailClient`] authenticates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientConfig {
+    /// OAuth flow used when no cached token covers the enabled scopes.
+    pub flow: AuthFlow,
+    /// Where tokens are cached.
+    pub store: TokenStore,
+}
+
+impl ClientConfig {
+    /// Read the flow and token store from the environment.
+    pub fn from_env() -> Result<Self, String> {
+        Ok(Self {
+            flow: AuthFlow::from_env()?,
+            store: TokenStore::from_env()?,
+        })
+    }
+}
+
+/// An authenticated Gmail API hub with the common read operations.
+#[derive(Clone)]
+pub struct GmailClient {
+    hub: GmailHub,
+}
+
+impl GmailClient {
+    /// Build the client and authorize every enabled scope at once, so the first
+    /// consent covers all tools and later calls reuse the token for their
+    /// narrower scopes.
+    pub async fn connect(config: ClientConfig) -> Result<Self, GmailMcpError> {
+        let client = Self::build(config).await?;
+        client.authorize().await?;
+        Ok(client)
+    }
+
+    /// Request a token for every enabled scope, starting an OAuth flow unless
+    /// one is cached.
+    pub async fn authorize(&self) -> Result<(), GmailMcpError> {
+        let scopes = capabilities::enabled().scopes();
+        self.hub.auth.get_token(&scopes).await.map_err(|e| {
+            error!("Gmail API: Failed to authorize scopes {:?}: {}", scopes, e);
+            GmailMcpError::Auth(e.to_string())
+        })?;
+        Ok(())
+    }
+
+    /// Build the client without requesting a token, so no OAuth flow starts until a call needs one.
+    pub async fn build(config: ClientConfig) -> Result<Self, GmailMcpError> {
+        // Load credentials
+        let credentials = auth::Credentials::load().await.map_err(|e| {
+            error!("Gmail API: Failed to load credentials: {}", e);
+            e
+        })?;
+
+        // Set up authenticator
+        let auth = auth::authenticator(credentials, config.flow, config.store)
+            .await
+            .map_err(|e| {
+                error!("Gmail API: Failed to build authenticator: {}", e);
+                e
+            })?;
+
+        // Create HTTPS client
+        info!("Gmail API: Creating HTTPS client");
+        let timeouts = timeouts::current();
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+        http.set_connect_timeout(timeouts.connect);
+        let https = HttpsConnectorBuilder::new()
+            .with_native_roots()?
+            .https_or_http()
+            .enable_http1()
+            .wrap_connector(http);
+        let mut connector = TimeoutConnector::new(https);
+        connector.set_read_timeout(timeouts.read);
+
+        let client = Client::builder(TokioExecutor::new()).build(connector);
+        Ok(Self {
+            hub: Gmail::new(client, limits::Throttled(auth)),
+        })
+    }
+
+    /// The underlying hub, for requests without a method here.
+    pub fn hub(&self) -> &GmailHub {
+        &self.hub
+    }
+
+    /// List up to `max_results` emails matching `query`, or the inbox when it is
+    /// `None` or blank, optionally only the unread ones.
+    pub async fn list(
+        &self,
+        max_results: u32,
+        query: Option<String>,
+        unread_only: bool,
+    ) -> Result<EmailResponse, GmailMcpError> {
+        let mut query = crate::resolve_query(query);
+        if unread_only {
+            query = crate::append_term(&query, "is:unread");
+        }
+        self.fetch(&query, max_results).await
+    }
+
+    /// Fetch one email by ID.
+    pub async fn get(&self, id: &str) -> Result<EmailSummary, GmailMcpError> {
+        crate::fetch_email(&self.hub, id.trim()).await
+    }
+
+    /// Search every folder with a Gmail query, returning up to `max_results` emails.
+    pub async fn search(
+        &self,
+        query: &str,
+        max_results: u32,
+    ) -> Result<EmailResponse, GmailMcpError> {
+        let query = query.trim();
+        if query.is_empty() {
+            return Err(GmailMcpError::Invalid(
+                "A search query is required".to_string(),
+            ));
+        }
+        self.fetch(query, max_results).await
+    }
+
+    async fn fetch(&self, query: &str, max_results: u32) -> Result<EmailResponse, GmailMcpError> {
+        let options = FetchOptions::new().max_results(max_results);
+        crate::fetch_page(&self.hub, query, &options).await
+    }
+}
+
+static SHARED: Mutex<Option<GmailClient>> = Mutex::const_new(None);
+static BUILT: Mutex<Option<GmailClient>> = Mutex::const_new(None);
+
+/// The client shared by every call, connecting on first use.
+///
+/// Concurrent first calls wait for a single connection, so at most one OAuth
+/// flow runs at a time. A failed connection is retried on the next call.
+pub async fn shared() -> Result<GmailClient, GmailMcpError> {
+    let mut shared = SHARED.lock().await;
+    if let Some(client) = shared.as_ref() {
+        return Ok(client.clone());
+    }
+    let client = built().await?;
+    client.authorize().await?;
+    *shared = Some(client.clone());
+    Ok(client)
+}
+
+/// The client shared by calls that must not start an OAuth flow, built without
+/// requesting a token on first use.
+///
+/// Unlike [`shared`], it never waits for an OAuth flow in progress.
+pub async fn built() -> Result<GmailClient, GmailMcpError> {
+    let mut built = BUILT.lock().await;
+    if let Some(client) = built.as_ref() {
+        return Ok(client.clone());
+    }
+    let config = ClientConfig::from_env().map_err(GmailMcpError::Invalid)?;
+    let client = GmailClient::build(config).await?;
+    *built = Some(client.clone());
+    Ok(client)
+}
+
+/// Drop the shared clients, so the next call connects again.
+pub async fn reset() {
+    SHARED.lock().await.take();
+    BUILT.lock().await.take();
+}
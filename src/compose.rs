@@ -0,0 +1,753 @@
+//! RFC 822 message construction for outgoing mail.
+
+use crate::GmailMcpError;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Gmail's limit on the total size of attachments in one message.
+pub const MAX_ATTACHMENT_BYTES: usize = 25 * 1024 * 1024;
+
+/// Environment variable naming the directory clients may attach files from by
+/// path. Unset, attachments must be sent as base64 `data`.
+pub const ATTACHMENTS_DIR_ENV: &str = "GMAIL_ATTACHMENTS_DIR";
+
+/// A file attached to an outgoing email.
+#[derive(Debug, Clone, Default)]
+pub struct Attachment {
+    /// File name shown to the recipient.
+    pub filename: String,
+    /// MIME type, e.g. `application/pdf` or `message/rfc822`.
+    pub mime_type: String,
+    /// Raw file contents.
+    pub data: Vec<u8>,
+}
+
+impl Attachment {
+    /// Read an attachment from disk, guessing the MIME type from the extension
+    /// when none is given. Any readable path is accepted, so paths from clients
+    /// go through [`AttachmentInput::load`] instead.
+    pub fn from_path(
+        path: impl AsRef<Path>,
+        mime_type: Option<&str>,
+    ) -> Result<Self, GmailMcpError> {
+        let path = path.as_ref();
+        let filename = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| {
+                GmailMcpError::Invalid(format!(
+                    "Attachment path has no file name: {}",
+                    path.display()
+                ))
+            })?
+            .to_string();
+        let unreadable = |e: std::io::Error| {
+            GmailMcpError::Invalid(format!("Cannot read attachment {}: {}", path.display(), e))
+        };
+        // Check the size first so an oversized file is never read into memory
+        let size = std::fs::metadata(path).map_err(unreadable)?.len();
+        check_attachment_size(size as usize)?;
+        let data = std::fs::read(path).map_err(unreadable)?;
+
+        Ok(Self {
+            mime_type: mime_type
+                .map(str::to_string)
+                .unwrap_or_else(|| guess_mime_type(&filename).to_string()),
+            filename,
+            data,
+        })
+    }
+}
+
+/// An attachment as supplied by a client: either a file path on the server or
+/// base64-encoded bytes with a file name.
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema)]
+pub struct AttachmentInput {
+    /// Path to a file in the server's attachments directory, if one is configured.
+    pub path: Option<String>,
+    /// Base64-encoded file contents, used when no path is given.
+    pub data: Option<String>,
+    /// File name shown to the recipient; defaults to the path's file name.
+    pub filename: Option<String>,
+    /// MIME type; guessed from the file name when omitted.
+    pub mime_type: Option<String>,
+}
+
+impl AttachmentInput {
+    /// Load the attachment contents from the path or decode the inline data.
+    /// Paths are only read inside the directory named by [`ATTACHMENTS_DIR_ENV`].
+    pub fn load(&self) -> Result<Attachment, GmailMcpError> {
+        self.load_from(dir_from_env(ATTACHMENTS_DIR_ENV).as_deref())
+    }
+
+    /// [`load`](Self::load) with paths read inside `dir`, or refused without one.
+    fn load_from(&self, dir: Option<&Path>) -> Result<Attachment, GmailMcpError> {
+        let mut attachment = match (&self.path, &self.data) {
+            (Some(path), _) => {
+                let path = resolve_in_dir(dir, path, ATTACHMENTS_DIR_ENV)?;
+                Attachment::from_path(path, self.mime_type.as_deref())?
+            }
+            (None, Some(data)) => {
+                let filename = self
+                    .filename
+                    .clone()
+                    .filter(|f| !f.trim().is_empty())
+                    .ok_or_else(|| {
+                        GmailMcpError::Invalid(
+                            "An attachment given as data needs a filename".to_string(),
+                        )
+                    })?;
+                let data: String = data.chars().filter(|c| !c.is_whitespace()).collect();
+                let data = STANDARD.decode(data).map_err(|e| {
+                    GmailMcpError::Invalid(format!(
+                        "Attachment {} is not valid base64: {}",
+                        filename, e
+                    ))
+                })?;
+                check_attachment_size(data.len())?;
+                Attachment {
+                    mime_type: self
+                        .mime_type
+                        .clone()
+                        .unwrap_or_else(|| guess_mime_type(&filename).to_string()),
+                    filename,
+                    data,
+                }
+            }
+            (None, None) => {
+                return Err(GmailMcpError::Invalid(
+                    "An attachment needs either a path or data".to_string(),
+                ))
+            }
+        };
+        if let Some(filename) = self.filename.as_ref().filter(|f| !f.trim().is_empty()) {
+            attachment.filename = filename.clone();
+        }
+        Ok(attachment)
+    }
+}
+
+/// A directory from the environment variable `env`, if it is set.
+pub(crate) fn dir_from_env(env: &str) -> Option<PathBuf> {
+    std::env::var(env)
+        .ok()
+        .filter(|v| !v.trim().is_empty())
+        .map(|v| PathBuf::from(v.trim()))
+}
+
+/// Resolve a client-supplied `path`, relative to `dir` unless absolute, and
+/// refuse it unless it lies inside `dir` once symlinks and `..` are resolved.
+/// Without a `dir`, reading files is disabled until `env` names one.
+pub(crate) fn resolve_in_dir(
+    dir: Option<&Path>,
+    path: &str,
+    env: &str,
+) -> Result<PathBuf, GmailMcpError> {
+    let Some(dir) = dir else {
+        return Err(GmailMcpError::Invalid(format!(
+            "Reading files on the server is disabled; send the contents as base64 data, \
+             or set {} to a directory to allow paths inside it",
+            env
+        )));
+    };
+    let dir = dir.canonicalize().map_err(|e| {
+        GmailMcpError::Invalid(format!("Cannot open {} {}: {}", env, dir.display(), e))
+    })?;
+    let resolved = dir
+        .join(path)
+        .canonicalize()
+        .map_err(|e| GmailMcpError::Invalid(format!("Cannot read {}: {}", path, e)))?;
+    if !resolved.starts_with(&dir) {
+        return Err(GmailMcpError::Invalid(format!(
+            "{} is outside {} ({})",
+            path,
+            env,
+            dir.display()
+        )));
+    }
+    Ok(resolved)
+}
+
+/// Load a list of client-supplied attachments.
+pub fn load_attachments(inputs: &[AttachmentInput]) -> Result<Vec<Attachment>, GmailMcpError> {
+    inputs.iter().map(AttachmentInput::load).collect()
+}
+
+/// An email to be sent or saved as a draft.
+#[derive(Debug, Clone, Default)]
+pub struct OutgoingEmail {
+    /// Primary recipients.
+    pub to: Vec<String>,
+    /// Carbon-copy recipients.
+    pub cc: Vec<String>,
+    /// Blind carbon-copy recipients.
+    pub bcc: Vec<String>,
+    /// The subject line (may contain non-ASCII text).
+    pub subject: String,
+    /// Plain-text body.
+    pub body: String,
+    /// Optional HTML rendering of the body; when set the body is sent as
+    /// `multipart/alternative` with `body` as the plain-text fallback.
+    pub html_body: Option<String>,
+    /// Additional headers such as `In-Reply-To`, in order.
+    pub extra_headers: Vec<(String, String)>,
+    /// Files to attach; when non-empty the message becomes `multipart/mixed`.
+    pub attachments: Vec<Attachment>,
+}
+
+impl OutgoingEmail {
+    /// Create a plain-text email.
+    pub fn new(to: Vec<String>, subject: &str, body: &str) -> Self {
+        Self {
+            to,
+            subject: subject.to_string(),
+            body: body.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Render the email as an RFC 822 message with a base64-encoded UTF-8 body.
+    ///
+    /// The result is bytes rather than a string, since a forwarded message is
+    /// embedded unchanged and may be in any 8-bit charset.
+    pub fn to_rfc822(&self) -> Result<Vec<u8>, GmailMcpError> {
+        if self.to.is_empty() && self.cc.is_empty() && self.bcc.is_empty() {
+            return Err(GmailMcpError::Invalid(
+                "An email needs at least one recipient".to_string(),
+            ));
+        }
+
+        check_attachment_size(self.attachments.iter().map(|a| a.data.len()).sum())?;
+
+        let mut headers = Vec::new();
+        for (name, list) in [("To", &self.to), ("Cc", &self.cc), ("Bcc", &self.bcc)] {
+            if !list.is_empty() {
+                let addresses = list
+                    .iter()
+                    .map(|a| encode_address(a))
+                    .collect::<Result<Vec<_>, _>>()?;
+                headers.push((name.to_string(), addresses.join(", ")));
+            }
+        }
+        headers.push(("Subject".to_string(), encode_header(&self.subject)?));
+        for (name, value) in &self.extra_headers {
+            headers.push((name.clone(), encode_header(value)?));
+        }
+        headers.push(("MIME-Version".to_string(), "1.0".to_string()));
+
+        let mut message = String::new();
+        for (name, value) in headers {
+            message.push_str(&format!("{}: {}\r\n", name, value));
+        }
+
+        if self.attachments.is_empty() {
+            message.push_str(&body_part(self));
+            return Ok(message.into_bytes());
+        }
+
+        let boundary = boundary_for(self, "");
+        message.push_str(&format!(
+            "Content-Type: multipart/mixed; boundary=\"{}\"\r\n\r\n",
+            boundary
+        ));
+        message.push_str(&format!("--{}\r\n", boundary));
+        message.push_str(&body_part(self));
+        let mut message = message.into_bytes();
+        for attachment in &self.attachments {
+            message.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+            message.extend_from_slice(&attachment_part(attachment)?);
+        }
+        message.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+        Ok(message)
+    }
+}
+
+/// Render the body: plain text alone, or a `multipart/alternative` of text and HTML.
+fn body_part(email: &OutgoingEmail) -> String {
+    let Some(html) = &email.html_body else {
+        return text_part(&email.body);
+    };
+
+    let boundary = boundary_for(email, "alt_");
+    format!(
+        "Content-Type: multipart/alternative; boundary=\"{b}\"\r\n\r\n--{b}\r\n{}--{b}\r\n{}--{b}--\r\n",
+        text_part(&email.body),
+        html_part(html),
+        b = boundary
+    )
+}
+
+/// Render a UTF-8 HTML MIME part.
+fn html_part(html: &str) -> String {
+    format!(
+        "Content-Type: text/html; charset=\"UTF-8\"\r\nContent-Transfer-Encoding: base64\r\n\r\n{}",
+        base64_lines(html.as_bytes())
+    )
+}
+
+/// Render a UTF-8 plain-text MIME part (headers, blank line, base64 body).
+fn text_part(body: &str) -> String {
+    format!(
+        "Content-Type: text/plain; charset=\"UTF-8\"\r\nContent-Transfer-Encoding: base64\r\n\r\n{}",
+        base64_lines(body.as_bytes())
+    )
+}
+
+/// Render an attachment MIME part.
+///
+/// Forwarded messages (`message/rfc822`) are embedded byte for byte, since
+/// RFC 2046 does not allow base64 for that type; everything else is
+/// base64-encoded.
+fn attachment_part(attachment: &Attachment) -> Result<Vec<u8>, GmailMcpError> {
+    check_header_value(&attachment.mime_type)?;
+    let filename = encode_header(&attachment.filename)?.replace('"', "");
+    if attachment.mime_type.eq_ignore_ascii_case("message/rfc822") {
+        let mut part = format!(
+            "Content-Type: message/rfc822\r\nContent-Disposition: attachment; filename=\"{}\"\r\n\r\n",
+            filename
+        )
+        .into_bytes();
+        part.extend_from_slice(&attachment.data);
+        if !attachment.data.ends_with(b"\r\n") {
+            part.extend_from_slice(b"\r\n");
+        }
+        return Ok(part);
+    }
+
+    Ok(format!(
+        "Content-Type: {}; name=\"{}\"\r\nContent-Disposition: attachment; filename=\"{}\"\r\nContent-Transfer-Encoding: base64\r\n\r\n{}",
+        attachment.mime_type,
+        filename,
+        filename,
+        base64_lines(&attachment.data)
+    )
+    .into_bytes())
+}
+
+/// Pick a multipart boundary that does not occur in any part of the email.
+///
+/// `kind` distinguishes nested multiparts, so no boundary is a prefix of another.
+fn boundary_for(email: &OutgoingEmail, kind: &str) -> String {
+    let mut counter = 0u32;
+    loop {
+        let boundary = format!(
+            "=_gmail_mcp_{}{}_{}",
+            kind,
+            email.attachments.len(),
+            counter
+        );
+        let clashes = email.body.contains(&boundary)
+            || email
+                .html_body
+                .as_deref()
+                .is_some_and(|html| html.contains(&boundary))
+            || email.attachments.iter().any(|a| {
+                a.data
+                    .windows(boundary.len())
+                    .any(|window| window == boundary.as_bytes())
+            });
+        if !clashes {
+            return boundary;
+        }
+        counter += 1;
+    }
+}
+
+/// Reject attachments larger than Gmail accepts.
+fn check_attachment_size(bytes: usize) -> Result<(), GmailMcpError> {
+    if bytes > MAX_ATTACHMENT_BYTES {
+        return Err(GmailMcpError::Invalid(format!(
+            "Attachments total {:.1} MB, exceeding Gmail's {} MB limit",
+            bytes as f64 / (1024.0 * 1024.0),
+            MAX_ATTACHMENT_BYTES / (1024 * 1024)
+        )));
+    }
+    Ok(())
+}
+
+/// Guess a MIME type from a file name's extension.
+pub fn guess_mime_type(filename: &str) -> &'static str {
+    let extension = filename
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "pdf" => "application/pdf",
+        "txt" | "log" => "text/plain",
+        "csv" => "text/csv",
+        "htm" | "html" => "text/html",
+        "md" => "text/markdown",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "ics" => "text/calendar",
+        "eml" => "message/rfc822",
+        "doc" => "application/msword",
+        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "xls" => "application/vnd.ms-excel",
+        "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "ppt" => "application/vnd.ms-powerpoint",
+        "pptx" => "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Reject header values that could inject extra headers.
+fn check_header_value(value: &str) -> Result<(), GmailMcpError> {
+    if value.contains('\r') || value.contains('\n') {
+        return Err(GmailMcpError::Invalid(format!(
+            "Header value must not contain line breaks: {:?}",
+            value
+        )));
+    }
+    Ok(())
+}
+
+/// Encode a header value as an RFC 2047 encoded-word when it contains non-ASCII text.
+pub fn encode_header(value: &str) -> Result<String, GmailMcpError> {
+    check_header_value(value)?;
+    if value.is_ascii() {
+        Ok(value.to_string())
+    } else {
+        Ok(format!("=?UTF-8?B?{}?=", STANDARD.encode(value)))
+    }
+}
+
+/// Encode an address such as `José <jose@example.com>`, encoding only the display name.
+fn encode_address(address: &str) -> Result<String, GmailMcpError> {
+    let address = address.trim();
+    check_header_value(address)?;
+    if !address.contains('@') {
+        return Err(GmailMcpError::Invalid(format!(
+            "Invalid email address: {}",
+            address
+        )));
+    }
+
+    match address.rfind('<') {
+        Some(start) if address.ends_with('>') => {
+            let name = address[..start].trim().trim_matches('"');
+            let email = &address[start..];
+            if name.is_empty() {
+                Ok(email.to_string())
+            } else if name.is_ascii() {
+                Ok(format!("\"{}\" {}", name.replace('"', "\\\""), email))
+            } else {
+                Ok(format!("{} {}", encode_header(name)?, email))
+            }
+        }
+        _ => Ok(address.to_string()),
+    }
+}
+
+/// Split an address header such as `"Doe, Jane" <jane@example.com>, bob@example.com`
+/// into individual addresses, ignoring commas inside quoted display names.
+pub fn split_addresses(header: &str) -> Vec<String> {
+    let mut addresses = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut escaped = false;
+    for c in header.chars() {
+        match c {
+            _ if escaped => {
+                escaped = false;
+                current.push(c);
+            }
+            '\\' if quoted => {
+                escaped = true;
+                current.push(c);
+            }
+            '"' => {
+                quoted = !quoted;
+                current.push(c);
+            }
+            ',' if !quoted => addresses.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    addresses.push(current);
+    addresses
+        .into_iter()
+        .map(|a| a.trim().to_string())
+        .filter(|a| !a.is_empty())
+        .collect()
+}
+
+/// An address split into its display name and email address.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default, PartialEq)]
+pub struct Mailbox {
+    /// The display name, unquoted and decoded, if the address has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The bare email address, such as `jane@example.com`.
+    pub address: String,
+}
+
+/// Formats as `Name <address>`, quoting the name when it contains characters
+/// such as `,` that would otherwise split or break the address.
+impl std::fmt::Display for Mailbox {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Some(name) = &self.name else {
+            return f.write_str(&self.address);
+        };
+        if name.contains(|c| "()<>[]:;@\\,.\"".contains(c)) {
+            let escaped = name.replace('\\', "\\\\").replace('"', "\\\"");
+            write!(f, "\"{}\" <{}>", escaped, self.address)
+        } else {
+            write!(f, "{} <{}>", name, self.address)
+        }
+    }
+}
+
+/// Parse one address such as `"Doe, Jane" <jane@example.com>`,
+/// `=?UTF-8?B?Sm9zw6k=?= <jose@example.com>`, or `jane@example.com (Jane Doe)`.
+pub fn parse_mailbox(address: &str) -> Mailbox {
+    let address = address.trim();
+    let (name, email) = match address.rfind('<') {
+        Some(start) if address.ends_with('>') => {
+            (&address[..start], &address[start + 1..address.len() - 1])
+        }
+        _ => match address.split_once('(') {
+            Some((email, comment)) if comment.ends_with(')') => {
+                (&comment[..comment.len() - 1], email)
+            }
+            _ => ("", address),
+        },
+    };
+    let name = name.trim();
+    let name = match name.strip_prefix('"').and_then(|n| n.strip_suffix('"')) {
+        Some(quoted) => quoted.replace("\\\"", "\"").replace("\\\\", "\\"),
+        None => name.to_string(),
+    };
+    let name = crate::rfc822::decode_header(&name).trim().to_string();
+    Mailbox {
+        name: (!name.is_empty()).then_some(name),
+        address: email.trim().to_string(),
+    }
+}
+
+/// Base64-encode data, wrapped at 76 characters per line as MIME requires.
+pub(crate) fn base64_lines(data: &[u8]) -> String {
+    let encoded = STANDARD.encode(data);
+    let mut out = String::with_capacity(encoded.len() + encoded.len() / 76 * 2 + 2);
+    for chunk in encoded.as_bytes().chunks(76) {
+        // Base64 output is pure ASCII, so each chunk is valid UTF-8
+        out.push_str(std::str::from_utf8(chunk).unwrap_or_default());
+        out.push_str("\r\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guess_mime_type() {
+        assert_eq!(guess_mime_type("report.PDF"), "application/pdf");
+        assert_eq!(guess_mime_type("photo.jpeg"), "image/jpeg");
+        assert_eq!(guess_mime_type("README"), "application/octet-stream");
+    }
+
+    #[test]
+    fn test_attachment_input_load() {
+        let input = AttachmentInput {
+            data: Some("aGVs\nbG8=".to_string()),
+            filename: Some("hello.txt".to_string()),
+            ..Default::default()
+        };
+        let attachment = input.load().unwrap();
+        assert_eq!(attachment.data, b"hello");
+        assert_eq!(attachment.mime_type, "text/plain");
+
+        assert!(AttachmentInput::default().load().is_err());
+        let unnamed = AttachmentInput {
+            data: Some("aGVsbG8=".to_string()),
+            ..Default::default()
+        };
+        assert!(unnamed.load().is_err());
+    }
+
+    #[test]
+    fn test_attachment_input_path() {
+        let root = std::env::temp_dir().join(format!("gmail-mcp-attach-{}", std::process::id()));
+        let dir = root.join("outbox");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("notes.txt"), "hello").unwrap();
+        std::fs::write(root.join("secret.txt"), "key").unwrap();
+        let input = |path: &str| AttachmentInput {
+            path: Some(path.to_string()),
+            ..Default::default()
+        };
+
+        let attachment = input("notes.txt").load_from(Some(&dir)).unwrap();
+        assert_eq!(attachment.data, b"hello");
+        assert_eq!(attachment.filename, "notes.txt");
+        assert!(input("notes.txt").load_from(None).is_err());
+        assert!(input("../secret.txt").load_from(Some(&dir)).is_err());
+        let outside = root.join("secret.txt");
+        assert!(input(outside.to_str().unwrap())
+            .load_from(Some(&dir))
+            .is_err());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_to_rfc822_with_html_alternative() {
+        let mut email = OutgoingEmail::new(vec!["a@example.com".to_string()], "Hi", "Hello");
+        email.html_body = Some("<p>Hello</p>".to_string());
+        let raw = String::from_utf8(email.to_rfc822().unwrap()).unwrap();
+        assert!(raw.contains(
+            "Content-Type: multipart/alternative; boundary=\"=_gmail_mcp_alt_0_0\"\r\n\r\n--=_gmail_mcp_alt_0_0\r\nContent-Type: text/plain"
+        ));
+        assert!(raw.contains("--=_gmail_mcp_alt_0_0\r\nContent-Type: text/html; charset=\"UTF-8\""));
+        assert!(raw.ends_with("--=_gmail_mcp_alt_0_0--\r\n"));
+    }
+
+    #[test]
+    fn test_attachment_size_limit() {
+        let mut email = OutgoingEmail::new(vec!["a@example.com".to_string()], "Big", "");
+        email.attachments.push(Attachment {
+            filename: "big.bin".to_string(),
+            mime_type: "application/octet-stream".to_string(),
+            data: vec![0; MAX_ATTACHMENT_BYTES + 1],
+        });
+        let err = email.to_rfc822().unwrap_err().to_string();
+        assert!(err.contains("25 MB limit"), "{}", err);
+    }
+
+    #[test]
+    fn test_split_addresses() {
+        assert_eq!(
+            split_addresses(r#""Doe, Jane" <jane@example.com>, bob@example.com,"#),
+            vec![
+                r#""Doe, Jane" <jane@example.com>"#.to_string(),
+                "bob@example.com".to_string()
+            ]
+        );
+        assert!(split_addresses("  ").is_empty());
+    }
+
+    #[test]
+    fn test_encode_header() {
+        assert_eq!(encode_header("Hello").unwrap(), "Hello");
+        assert_eq!(encode_header("Café").unwrap(), "=?UTF-8?B?Q2Fmw6k=?=");
+        assert!(encode_header("Hi\r\nBcc: evil@example.com").is_err());
+    }
+
+    #[test]
+    fn test_encode_address() {
+        assert_eq!(encode_address("a@example.com").unwrap(), "a@example.com");
+        assert_eq!(
+            encode_address("Ann Lee <ann@example.com>").unwrap(),
+            "\"Ann Lee\" <ann@example.com>"
+        );
+        assert_eq!(
+            encode_address("José <jose@example.com>").unwrap(),
+            "=?UTF-8?B?Sm9zw6k=?= <jose@example.com>"
+        );
+        assert!(encode_address("not an address").is_err());
+    }
+
+    #[test]
+    fn test_parse_mailbox() {
+        let mailbox = |name: Option<&str>, address: &str| Mailbox {
+            name: name.map(str::to_string),
+            address: address.to_string(),
+        };
+        assert_eq!(
+            parse_mailbox("jane@example.com"),
+            mailbox(None, "jane@example.com")
+        );
+        assert_eq!(
+            parse_mailbox(" Jane Doe <jane@example.com> "),
+            mailbox(Some("Jane Doe"), "jane@example.com")
+        );
+        assert_eq!(
+            parse_mailbox("\"Doe, \\\"JD\\\" Jane\" <jane@example.com>"),
+            mailbox(Some("Doe, \"JD\" Jane"), "jane@example.com")
+        );
+        assert_eq!(
+            parse_mailbox("=?UTF-8?B?Sm9zw6k=?= <jose@example.com>"),
+            mailbox(Some("José"), "jose@example.com")
+        );
+        assert_eq!(
+            parse_mailbox("jane@example.com (Jane Doe)"),
+            mailbox(Some("Jane Doe"), "jane@example.com")
+        );
+        assert_eq!(parse_mailbox("<>"), mailbox(None, ""));
+    }
+
+    #[test]
+    fn test_mailbox_display() {
+        let display = |address: &str| parse_mailbox(address).to_string();
+        assert_eq!(display("jane@example.com"), "jane@example.com");
+        assert_eq!(
+            display("Jane Doe <jane@example.com>"),
+            "Jane Doe <jane@example.com>"
+        );
+        assert_eq!(
+            display("=?UTF-8?B?RG9lLCBKYW5l?= <jane@example.com>"),
+            "\"Doe, Jane\" <jane@example.com>"
+        );
+        assert_eq!(
+            display("\"Doe, \\\"JD\\\" Jane\" <jane@example.com>"),
+            "\"Doe, \\\"JD\\\" Jane\" <jane@example.com>"
+        );
+        let quoted = display("\"Doe, \\\"JD\\\" Jane\" <jane@example.com>");
+        assert_eq!(split_addresses(&quoted), vec![quoted.clone()]);
+    }
+
+    #[test]
+    fn test_to_rfc822() {
+        let mut email = OutgoingEmail::new(vec!["a@example.com".to_string()], "Hi", "Hello!");
+        email.cc = vec!["b@example.com".to_string()];
+        let raw = String::from_utf8(email.to_rfc822().unwrap()).unwrap();
+
+        assert!(raw.starts_with("To: a@example.com\r\nCc: b@example.com\r\nSubject: Hi\r\n"));
+        assert!(raw.contains("Content-Transfer-Encoding: base64\r\n\r\nSGVsbG8h\r\n"));
+        assert!(OutgoingEmail::default().to_rfc822().is_err());
+    }
+
+    #[test]
+    fn test_to_rfc822_with_forwarded_message() {
+        let mut email = OutgoingEmail::new(vec!["a@example.com".to_string()], "Fwd: Hi", "FYI");
+        email.attachments.push(Attachment {
+            filename: "original.eml".to_string(),
+            mime_type: "message/rfc822".to_string(),
+            data: b"Subject: Hi\r\n\r\nHello".to_vec(),
+        });
+        let raw = String::from_utf8(email.to_rfc822().unwrap()).unwrap();
+
+        assert!(raw.contains("Content-Type: multipart/mixed; boundary=\"=_gmail_mcp_1_0\""));
+        assert!(raw.contains("Content-Type: message/rfc822\r\n"));
+        assert!(raw.contains("Subject: Hi\r\n\r\nHello\r\n"));
+        assert!(raw.ends_with("--=_gmail_mcp_1_0--\r\n"));
+    }
+
+    #[test]
+    fn test_to_rfc822_keeps_8bit_forwarded_message() {
+        // A Latin-1 original that also contains the first boundary candidate
+        let original = b"Subject: Caf\xe9\r\nContent-Type: text/plain; charset=iso-8859-1\r\n\r\n\xe0 --=_gmail_mcp_1_0\r\n".to_vec();
+        let mut email =
+            OutgoingEmail::new(vec!["a@example.com".to_string()], "Fwd: Caf\u{e9}", "FYI");
+        email.attachments.push(Attachment {
+            filename: "original.eml".to_string(),
+            mime_type: "message/rfc822".to_string(),
+            data: original.clone(),
+        });
+        let raw = email.to_rfc822().unwrap();
+
+        assert!(raw.windows(original.len()).any(|window| window == original));
+        assert!(raw.ends_with(b"--=_gmail_mcp_1_1--\r\n"));
+    }
+}
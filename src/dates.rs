@@ -0,0 +1,400 @@
+//! Dates, times, and deadlines mentioned in email bodies.
+//!
+//! [`extract`] finds absolute dates (`2024-05-17`, `May 17th`, `17 May 2024`,
+//! `5/17/2024`), relative ones (`tomorrow`, `next Friday`, `end of the week`),
+//! and times of day (`3pm`, `14:30`, `noon`), joining a time to the date next
+//! to it as in `Friday at 3pm`. Relative dates and dates without a year are
+//! resolved against the day the email was sent. A mention preceded by `by`,
+//! `before`, `due`, `until`, or `no later than` is marked as a deadline.
+
+use crate::markup::pattern;
+use crate::EmailSummary;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, Utc, Weekday};
+use regex::{Captures, Regex};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use std::str::FromStr;
+
+/// Most mentions listed per email.
+const MAX_MENTIONS: usize = 20;
+/// How many days before the reference a date without a year may fall before it
+/// is taken to mean next year, as `Jan 5` does in mail sent in December.
+const PAST_DAYS: i64 = 60;
+
+/// An optional weekday before a date, as in `Fri, May 17`.
+const WEEKDAY_PREFIX: &str = r"(?:(?:monday|tuesday|wednesday|thursday|friday|saturday|sunday|mon|tues?|wed|thu(?:rs?)?|fri|sat|sun)\.?,?\s+)?";
+/// A month name or its abbreviation.
+const MONTH: &str = r"(jan(?:uary)?|feb(?:ruary)?|mar(?:ch)?|apr(?:il)?|may|june?|july?|aug(?:ust)?|sept?(?:ember)?|oct(?:ober)?|nov(?:ember)?|dec(?:ember)?)";
+
+/// A date or time mentioned in an email body.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct DateMention {
+    /// The phrase as written, such as `by Friday` or `May 17 at 3pm`.
+    pub text: String,
+    /// The date it names, as `YYYY-MM-DD`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+    /// The time of day it names, as 24-hour `HH:MM`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time: Option<String>,
+    /// Whether the phrase sets a deadline, as `by Friday` or `due May 17` do.
+    #[serde(default)]
+    pub deadline: bool,
+}
+
+/// A date or time found in the text, with the bytes it spans.
+struct Found<T> {
+    span: Range<usize>,
+    value: T,
+}
+
+/// The date relative dates in `email` are resolved against: the day it was
+/// sent, or today when that is unknown.
+pub(crate) fn reference_date(email: &EmailSummary) -> NaiveDate {
+    [&email.date, &email.internal_date]
+        .into_iter()
+        .flatten()
+        .find_map(|date| DateTime::parse_from_rfc3339(date).ok())
+        .map_or_else(|| Utc::now().date_naive(), |date| date.date_naive())
+}
+
+/// The dates and times mentioned in `text`, in order, resolving relative
+/// dates against `reference`.
+pub fn extract(text: &str, reference: NaiveDate) -> Vec<DateMention> {
+    let dates = find_dates(text, reference);
+    let mut times: Vec<Option<Found<NaiveTime>>> = find_times(text)
+        .into_iter()
+        .filter(|time| !dates.iter().any(|date| overlap(&date.span, &time.span)))
+        .map(Some)
+        .collect();
+    let after_date = pattern(r"(?i)^\s*,?\s*(?:at|@|from)?\s*$");
+    let before_date = pattern(r"(?i)^\s*,?\s*(?:on)?\s*$");
+
+    let mut mentions: Vec<(Range<usize>, Option<NaiveDate>, Option<NaiveTime>)> = Vec::new();
+    for date in dates {
+        let joins = |time: &Found<NaiveTime>| {
+            if time.span.start >= date.span.end {
+                after_date.is_match(&text[date.span.end..time.span.start])
+            } else {
+                time.span.end <= date.span.start
+                    && before_date.is_match(&text[time.span.end..date.span.start])
+            }
+        };
+        let time = times
+            .iter_mut()
+            .find(|time| time.as_ref().is_some_and(joins))
+            .and_then(Option::take);
+        let span = match &time {
+            Some(time) => date.span.start.min(time.span.start)..date.span.end.max(time.span.end),
+            None => date.span,
+        };
+        mentions.push((span, Some(date.value), time.map(|time| time.value)));
+    }
+    mentions.extend(
+        times
+            .into_iter()
+            .flatten()
+            .map(|time| (time.span, None, Some(time.value))),
+    );
+    mentions.sort_by_key(|(span, _, _)| span.start);
+
+    let deadline = pattern(
+        r"(?i)\b(?:by|before|until|till|due(?:\s+(?:on|by))?|no\s+later\s+than|(?:deadline|due\s+date)(?:\s+is)?:?)\s*$",
+    );
+    mentions
+        .into_iter()
+        .take(MAX_MENTIONS)
+        .map(|(span, date, time)| {
+            let keyword = deadline_keyword(&deadline, text, span.start);
+            let start = keyword.unwrap_or(span.start);
+            DateMention {
+                text: text[start..span.end]
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                date: date.map(|date| date.format("%Y-%m-%d").to_string()),
+                time: time.map(|time| time.format("%H:%M").to_string()),
+                deadline: keyword.is_some(),
+            }
+        })
+        .collect()
+}
+
+/// Where a deadline keyword such as `by` or `due` starts right before `start`.
+fn deadline_keyword(deadline: &Regex, text: &str, start: usize) -> Option<usize> {
+    let mut window = start.saturating_sub(30);
+    while !text.is_char_boundary(window) {
+        window += 1;
+    }
+    // Searching from the window keeps the text before it as context for `\b`
+    deadline
+        .find_at(&text[..start], window)
+        .map(|keyword| keyword.start())
+}
+
+fn overlap(a: &Range<usize>, b: &Range<usize>) -> bool {
+    a.start < b.end && b.start < a.end
+}
+
+/// Capture group `i` parsed as a number.
+fn group<T: FromStr>(caps: &Captures, i: usize) -> Option<T> {
+    caps.get(i)?.as_str().parse().ok()
+}
+
+/// The number of the month named `name`, from 1 for January.
+fn month_number(name: &str) -> Option<u32> {
+    let name = name.to_ascii_lowercase();
+    [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ]
+    .iter()
+    .position(|month| name.starts_with(month))
+    .map(|i| i as u32 + 1)
+}
+
+/// Whether the word ending just before byte `at` of `text` is `word`, ignoring case.
+fn word_before(text: &str, at: usize, word: &str) -> bool {
+    text[..at]
+        .split_whitespace()
+        .next_back()
+        .is_some_and(|last| last.eq_ignore_ascii_case(word))
+}
+
+/// The date `month`/`day` of `year`, or when no year is given, of the year
+/// around `reference` it most likely falls in.
+fn in_year(reference: NaiveDate, month: u32, day: u32, year: Option<i32>) -> Option<NaiveDate> {
+    if let Some(year) = year {
+        return NaiveDate::from_ymd_opt(year, month, day);
+    }
+    let date = NaiveDate::from_ymd_opt(reference.year(), month, day)?;
+    if (reference - date).num_days() > PAST_DAYS {
+        NaiveDate::from_ymd_opt(reference.year() + 1, month, day)
+    } else {
+        Some(date)
+    }
+}
+
+/// The next `weekday` on or after `reference`, or with `next_week`, the one in
+/// the week (starting Monday) after that of `reference`.
+fn weekday_date(reference: NaiveDate, weekday: Weekday, next_week: bool) -> NaiveDate {
+    let today = i64::from(reference.weekday().num_days_from_monday());
+    let target = i64::from(weekday.num_days_from_monday());
+    let days = if next_week {
+        7 - today + target
+    } else {
+        (target - today).rem_euclid(7)
+    };
+    reference + Duration::days(days)
+}
+
+/// The last day of the month of `reference`.
+fn end_of_month(reference: NaiveDate) -> Option<NaiveDate> {
+    let (year, month) = match reference.month() {
+        12 => (reference.year() + 1, 1),
+        month => (reference.year(), month + 1),
+    };
+    NaiveDate::from_ymd_opt(year, month, 1)?.pred_opt()
+}
+
+/// The dates in `text`, longest first where they overlap.
+fn find_dates(text: &str, reference: NaiveDate) -> Vec<Found<NaiveDate>> {
+    let mut found = Vec::new();
+    let mut scan = |re: &str, resolve: &dyn Fn(&Captures) -> Option<NaiveDate>| {
+        for caps in pattern(re).captures_iter(text) {
+            if let (Some(whole), Some(value)) = (caps.get(0), resolve(&caps)) {
+                found.push(Found {
+                    span: whole.range(),
+                    value,
+                });
+            }
+        }
+    };
+
+    scan(r"\b(\d{4})-(\d{2})-(\d{2})\b", &|caps| {
+        NaiveDate::from_ymd_opt(group(caps, 1)?, group(caps, 2)?, group(caps, 3)?)
+    });
+    scan(
+        &format!(
+            r"(?i)\b{WEEKDAY_PREFIX}{MONTH}\.?\s+(\d{{1,2}})(?:st|nd|rd|th)?\b(?:,?\s+(\d{{4}})\b)?"
+        ),
+        &|caps| {
+            in_year(
+                reference,
+                month_number(&caps[1])?,
+                group(caps, 2)?,
+                group(caps, 3),
+            )
+        },
+    );
+    scan(
+        &format!(
+            r"(?i)\b{WEEKDAY_PREFIX}(\d{{1,2}})(?:st|nd|rd|th)?\s+(?:of\s+)?{MONTH}\b\.?(?:,?\s+(\d{{4}})\b)?"
+        ),
+        &|caps| {
+            // In "these 3 may need review" the modal verb is not a month, so
+            // a lowercase "may" needs a year or "of" or "on" before it
+            let month = caps.get(2)?;
+            if month.as_str() == "may"
+                && caps.get(3).is_none()
+                && !word_before(text, month.start(), "of")
+                && !word_before(text, caps.get(0)?.start(), "on")
+            {
+                return None;
+            }
+            in_year(
+                reference,
+                month_number(month.as_str())?,
+                group(caps, 1)?,
+                group(caps, 3),
+            )
+        },
+    );
+    // Numeric dates need a year, so fractions and scores are not taken for
+    // dates; they are read month first unless the first number cannot be a month
+    scan(r"\b(\d{1,2})/(\d{1,2})/(\d{4}|\d{2})\b", &|caps| {
+        let (first, second): (u32, u32) = (group(caps, 1)?, group(caps, 2)?);
+        let (month, day) = if first > 12 {
+            (second, first)
+        } else {
+            (first, second)
+        };
+        let year: i32 = group(caps, 3)?;
+        let year = if year < 100 { 2000 + year } else { year };
+        NaiveDate::from_ymd_opt(year, month, day)
+    });
+    scan(r"(?i)\b(today|tonight|tomorrow)\b", &|caps| {
+        if caps[1].eq_ignore_ascii_case("tomorrow") {
+            reference.succ_opt()
+        } else {
+            Some(reference)
+        }
+    });
+    scan(
+        r"(?i)\b(?:(next|this|coming)\s+)?(monday|tuesday|wednesday|thursday|friday|saturday|sunday)\b",
+        &|caps| {
+            let next_week = caps
+                .get(1)
+                .is_some_and(|word| word.as_str().eq_ignore_ascii_case("next"));
+            Some(weekday_date(reference, caps[2].parse().ok()?, next_week))
+        },
+    );
+    scan(
+        r"(?i)\b(?:end\s+of\s+(?:the\s+)?(day|week|month)|(eod|eow|eom))\b",
+        &|caps| match caps
+            .get(1)
+            .or_else(|| caps.get(2))?
+            .as_str()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "day" | "eod" => Some(reference),
+            "week" | "eow" => Some(weekday_date(reference, Weekday::Fri, false)),
+            _ => end_of_month(reference),
+        },
+    );
+
+    found.sort_by_key(|date| (date.span.start, std::cmp::Reverse(date.span.end)));
+    let mut dates: Vec<Found<NaiveDate>> = Vec::new();
+    for date in found {
+        if !dates
+            .last()
+            .is_some_and(|last| date.span.start < last.span.end)
+        {
+            dates.push(date);
+        }
+    }
+    dates
+}
+
+/// The times of day in `text`.
+fn find_times(text: &str) -> Vec<Found<NaiveTime>> {
+    let time = pattern(
+        r"(?i)\b(\d{1,2})(?::([0-5]\d))?\s*([ap])\.?m\b\.?|\b([01]?\d|2[0-3]):([0-5]\d)\b|\b(noon|midday|midnight)\b",
+    );
+    time.captures_iter(text)
+        .filter_map(|caps| {
+            let span = caps.get(0)?.range();
+            let value = if let Some(hour) = group::<u32>(&caps, 1) {
+                if !(1..=12).contains(&hour) {
+                    return None;
+                }
+                let pm = caps[3].eq_ignore_ascii_case("p");
+                let hour = hour % 12 + if pm { 12 } else { 0 };
+                NaiveTime::from_hms_opt(hour, group(&caps, 2).unwrap_or(0), 0)?
+            } else if let Some(hour) = group::<u32>(&caps, 4) {
+                NaiveTime::from_hms_opt(hour, group(&caps, 5)?, 0)?
+            } else if caps.get(6)?.as_str().eq_ignore_ascii_case("midnight") {
+                NaiveTime::from_hms_opt(0, 0, 0)?
+            } else {
+                NaiveTime::from_hms_opt(12, 0, 0)?
+            };
+            Some(Found { span, value })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mention(text: &str, date: Option<&str>, time: Option<&str>, deadline: bool) -> DateMention {
+        DateMention {
+            text: text.to_string(),
+            date: date.map(str::to_string),
+            time: time.map(str::to_string),
+            deadline,
+        }
+    }
+
+    #[test]
+    fn test_extract() {
+        // A Wednesday
+        let reference = NaiveDate::from_ymd_opt(2024, 5, 15).unwrap();
+        let text = "Can you send the report by Friday? Let's meet tomorrow at 3pm.\n\
+            The launch is on June 3rd, 2024 and the invoice is due 2024-06-30.\n\
+            Call me at 10:30.";
+        assert_eq!(
+            extract(text, reference),
+            vec![
+                mention("by Friday", Some("2024-05-17"), None, true),
+                mention("tomorrow at 3pm", Some("2024-05-16"), Some("15:00"), false),
+                mention("June 3rd, 2024", Some("2024-06-03"), None, false),
+                mention("due 2024-06-30", Some("2024-06-30"), None, true),
+                mention("10:30", None, Some("10:30"), false),
+            ]
+        );
+        assert!(extract("Thanks for the update!", reference).is_empty());
+    }
+
+    #[test]
+    fn test_extract_resolves_dates() {
+        // A Friday in December
+        let reference = NaiveDate::from_ymd_opt(2024, 12, 20).unwrap();
+        let dates = |text: &str| -> Vec<Option<String>> {
+            extract(text, reference)
+                .into_iter()
+                .map(|mention| mention.date)
+                .collect()
+        };
+        let date = |date: &str| Some(date.to_string());
+        assert_eq!(dates("Kickoff Jan 5"), vec![date("2025-01-05")]);
+        assert_eq!(dates("We met on 2 Dec"), vec![date("2024-12-02")]);
+        assert_eq!(dates("Paid 12/24/2024"), vec![date("2024-12-24")]);
+        assert_eq!(dates("Half done: 1/2"), Vec::<Option<String>>::new());
+        assert_eq!(dates("by end of the month"), vec![date("2024-12-31")]);
+        assert_eq!(
+            dates("this Friday or next Monday"),
+            vec![date("2024-12-20"), date("2024-12-23")]
+        );
+        assert_eq!(dates("Mon, Dec 30"), vec![date("2024-12-30")]);
+        assert_eq!(
+            dates("these 3 may need review"),
+            Vec::<Option<String>>::new()
+        );
+        assert_eq!(dates("Due 3 May"), vec![date("2025-05-03")]);
+        assert_eq!(dates("due on 3 may"), vec![date("2025-05-03")]);
+        assert_eq!(dates("the 3rd of may"), vec![date("2025-05-03")]);
+        assert_eq!(dates("sent 3 may 2024"), vec![date("2024-05-03")]);
+    }
+}
@@ -0,0 +1,208 @@
+//! SQLite store behind the message cache, surviving restarts.
+//!
+//! Each row holds a message as Gmail returned it and the summary built from
+//! it, keyed by message ID and fetch format. The in-memory cache in
+//! [`crate::cache`] consults it on a miss and writes every new summary through
+//! to it. Failures are logged and treated as misses, since the cache only ever
+//! saves work.
+//!
+//! The database holds mail in the clear, so on Unix it is created readable
+//! and writable by its owner only (mode `0600`), as are the journal files
+//! SQLite creates next to it.
+
+use crate::{EmailSummary, FetchFormat, GmailMcpError};
+use google_gmail1::api::Message;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::Path;
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS messages (
+    id TEXT NOT NULL,
+    format TEXT NOT NULL,
+    message TEXT NOT NULL,
+    summary TEXT NOT NULL,
+    cached_at INTEGER NOT NULL,
+    PRIMARY KEY (id, format)
+)";
+
+/// Messages and their summaries in an SQLite database.
+pub struct DiskCache {
+    conn: Mutex<Connection>,
+}
+
+impl DiskCache {
+    /// Open the database at `path`, creating it if needed.
+    ///
+    /// The calls block, so async code reaches the database through
+    /// [`tokio::task::spawn_blocking`].
+    pub fn open(path: &Path) -> Result<Self, GmailMcpError> {
+        let fail = |e: &dyn std::fmt::Display| {
+            GmailMcpError::Io(std::io::Error::other(format!(
+                "Cannot open the message cache {}: {}",
+                path.display(),
+                e
+            )))
+        };
+        #[cfg(unix)]
+        restrict_to_owner(path).map_err(|e| fail(&e))?;
+        let open = || -> rusqlite::Result<Connection> {
+            let conn = Connection::open(path)?;
+            conn.execute_batch(SCHEMA)?;
+            Ok(conn)
+        };
+        let conn = open().map_err(|e| fail(&e))?;
+        info!("Message cache: Using {}", path.display());
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn conn(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The cached summary of message `id` fetched in `format`.
+    pub fn get(&self, id: &str, format: FetchFormat) -> Option<EmailSummary> {
+        let summary = self
+            .conn()
+            .query_row(
+                "SELECT summary FROM messages WHERE id = ?1 AND format = ?2",
+                params![id, format.as_str()],
+                |row| row.get::<_, String>(0),
+            )
+            .optional();
+        match summary {
+            Ok(summary) => serde_json::from_str(&summary?).ok(),
+            Err(e) => {
+                warn!("Message cache: Cannot read message {}: {}", id, e);
+                None
+            }
+        }
+    }
+
+    /// The cached message `id` as Gmail returned it in `format`.
+    pub fn message(&self, id: &str, format: FetchFormat) -> Option<Message> {
+        let message = self
+            .conn()
+            .query_row(
+                "SELECT message FROM messages WHERE id = ?1 AND format = ?2",
+                params![id, format.as_str()],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()
+            .ok()??;
+        serde_json::from_str(&message).ok()
+    }
+
+    /// Store `message`, fetched in `format`, and its summary.
+    pub fn put(&self, format: FetchFormat, message: &Message, summary: &EmailSummary) {
+        let (Ok(message), Ok(json)) = (
+            serde_json::to_string(message),
+            serde_json::to_string(summary),
+        ) else {
+            return;
+        };
+        if let Err(e) = self.conn().execute(
+            "INSERT OR REPLACE INTO messages (id, format, message, summary, cached_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                summary.id,
+                format.as_str(),
+                message,
+                json,
+                crate::state::now()
+            ],
+        ) {
+            warn!("Message cache: Cannot store message {}: {}", summary.id, e);
+        }
+    }
+
+    /// Forget message `id` in `format`.
+    pub fn remove(&self, id: &str, format: FetchFormat) {
+        if let Err(e) = self.conn().execute(
+            "DELETE FROM messages WHERE id = ?1 AND format = ?2",
+            params![id, format.as_str()],
+        ) {
+            warn!("Message cache: Cannot remove message {}: {}", id, e);
+        }
+    }
+
+    /// Whether no message is stored.
+    pub fn is_empty(&self) -> bool {
+        let any = self
+            .conn()
+            .query_row("SELECT EXISTS (SELECT 1 FROM messages)", [], |row| {
+                row.get::<_, bool>(0)
+            });
+        !any.unwrap_or(false)
+    }
+
+    /// Forget every message.
+    pub fn clear(&self) {
+        if let Err(e) = self.conn().execute("DELETE FROM messages", []) {
+            warn!("Message cache: Cannot clear: {}", e);
+        }
+    }
+}
+
+/// Create the database file at `path` with mode `0600`, or narrow an existing
+/// one to it; SQLite gives its journal files the same mode.
+#[cfg(unix)]
+fn restrict_to_owner(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+    if path == Path::new(":memory:") {
+        return Ok(());
+    }
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .mode(0o600)
+        .open(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disk_cache_round_trip() {
+        let cache = DiskCache::open(Path::new(":memory:")).unwrap();
+        let message = Message {
+            id: Some("abc".to_string()),
+            snippet: Some("Hello".to_string()),
+            ..Default::default()
+        };
+        let summary = crate::summarize_message(&message).unwrap();
+        assert!(cache.is_empty());
+        cache.put(FetchFormat::Full, &message, &summary);
+        assert!(!cache.is_empty());
+
+        assert_eq!(
+            cache.get("abc", FetchFormat::Full).unwrap().snippet,
+            "Hello"
+        );
+        assert_eq!(
+            cache.message("abc", FetchFormat::Full).unwrap().snippet,
+            message.snippet
+        );
+        assert!(cache.get("abc", FetchFormat::Raw).is_none());
+
+        cache.remove("abc", FetchFormat::Full);
+        assert!(cache.get("abc", FetchFormat::Full).is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_disk_cache_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = std::env::temp_dir().join(format!("gmail-mcp-cache-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        DiskCache::open(&path).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
@@ -0,0 +1,524 @@
+//! Draft management so an agent can stage mail for human review.
+
+use crate::actions::{self, Action};
+use crate::capabilities::{self, Capability};
+use crate::compose::{split_addresses, Attachment, OutgoingEmail};
+use crate::markup::{render_body, BodyFormat};
+use crate::reademail::convert_html_to_text;
+use crate::send::SendResponse;
+use crate::{EmailSummary, GmailHub, GmailMcpError, SCOPE_COMPOSE, SCOPE_READONLY};
+use google_gmail1::api::{Draft, Message, MessagePart};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use std::str::FromStr;
+use tracing::{error, info};
+
+/// Identifiers of a saved draft.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DraftInfo {
+    /// The draft ID, used to update, delete, or send it.
+    pub id: String,
+    /// The ID of the draft's current message.
+    pub message_id: Option<String>,
+    /// The thread the draft belongs to.
+    pub thread_id: Option<String>,
+}
+
+impl From<Draft> for DraftInfo {
+    fn from(draft: Draft) -> Self {
+        let message = draft.message.unwrap_or_default();
+        Self {
+            id: draft.id.unwrap_or_default(),
+            message_id: message.id,
+            thread_id: message.thread_id,
+        }
+    }
+}
+
+/// A draft together with its processed message.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DraftSummary {
+    /// The draft ID.
+    pub id: String,
+    /// The draft's message, processed like an inbox email.
+    pub message: EmailSummary,
+}
+
+/// A page of drafts.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DraftListResponse {
+    pub drafts: Vec<DraftSummary>,
+    pub count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_page_token: Option<String>,
+}
+
+/// Save a composed email as a new draft, optionally inside an existing thread.
+pub async fn save_draft(
+    hub: &GmailHub,
+    email: &OutgoingEmail,
+    thread_id: Option<&str>,
+) -> Result<DraftInfo, GmailMcpError> {
+    let raw = email.to_rfc822()?;
+    info!("Gmail API: Creating draft '{}'", email.subject);
+
+    let request = Draft {
+        message: Some(Message {
+            thread_id: thread_id.map(str::to_string),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    crate::quota::record("drafts.create", 1);
+    let (_, draft) = hub
+        .users()
+        .drafts_create(request, "me")
+        .add_scope(SCOPE_COMPOSE)
+        .upload(Cursor::new(raw), "message/rfc822".parse()?)
+        .await
+        .map_err(|e| {
+            error!("Gmail API: Failed to create draft: {}", e);
+            e
+        })?;
+
+    Ok(draft.into())
+}
+
+/// Replace the content of an existing draft.
+pub async fn update_draft(
+    hub: &GmailHub,
+    id: &str,
+    email: &OutgoingEmail,
+    thread_id: Option<&str>,
+) -> Result<DraftInfo, GmailMcpError> {
+    let raw = email.to_rfc822()?;
+    info!("Gmail API: Updating draft {}", id);
+
+    let request = Draft {
+        id: Some(id.to_string()),
+        message: Some(Message {
+            thread_id: thread_id.map(str::to_string),
+            ..Default::default()
+        }),
+    };
+    crate::quota::record("drafts.update", 1);
+    let (_, draft) = hub
+        .users()
+        .drafts_update(request, "me", id)
+        .add_scope(SCOPE_COMPOSE)
+        .upload(Cursor::new(raw), "message/rfc822".parse()?)
+        .await
+        .map_err(|e| {
+            error!("Gmail API: Failed to update draft {}: {}", id, e);
+            e
+        })?;
+
+    Ok(draft.into())
+}
+
+/// Permanently delete a draft.
+pub async fn delete_draft(hub: &GmailHub, id: &str) -> Result<(), google_gmail1::Error> {
+    info!("Gmail API: Deleting draft {}", id);
+    crate::quota::record("drafts.delete", 1);
+    hub.users()
+        .drafts_delete("me", id)
+        .add_scope(SCOPE_COMPOSE)
+        .doit()
+        .await
+        .map_err(|e| {
+            error!("Gmail API: Failed to delete draft {}: {}", id, e);
+            e
+        })?;
+    Ok(())
+}
+
+/// Fields to change on an existing draft; `None` keeps the current value.
+#[derive(Debug, Default)]
+pub struct DraftChanges {
+    pub to: Option<Vec<String>>,
+    pub cc: Option<Vec<String>>,
+    pub bcc: Option<Vec<String>>,
+    pub subject: Option<String>,
+    pub body: Option<String>,
+    /// How to interpret `body` when it is given.
+    pub format: BodyFormat,
+    pub attachments: Option<Vec<Attachment>>,
+}
+
+impl DraftChanges {
+    fn is_empty(&self) -> bool {
+        self.to.is_none()
+            && self.cc.is_none()
+            && self.bcc.is_none()
+            && self.subject.is_none()
+            && self.body.is_none()
+            && self.attachments.is_none()
+    }
+}
+
+/// Collect the parts of a message that carry a file name.
+fn attachment_parts<'a>(parts: &'a [MessagePart], out: &mut Vec<&'a MessagePart>) {
+    for part in parts {
+        if part.filename.as_deref().is_some_and(|f| !f.is_empty()) {
+            out.push(part);
+        }
+        if let Some(sub_parts) = &part.parts {
+            attachment_parts(sub_parts, out);
+        }
+    }
+}
+
+/// The body part of a message with the given MIME type, if it has one.
+fn existing_part(message: &Message, mime_type: &str) -> Option<String> {
+    let payload = message.payload.as_ref()?;
+    if payload.mime_type.as_deref() == Some(mime_type) {
+        let data = payload.body.as_ref()?.data.as_ref()?;
+        return String::from_utf8(data.clone()).ok();
+    }
+    crate::find_text_part(payload.parts.as_deref()?, mime_type)
+}
+
+/// Download the attachments of an existing draft message so they survive an update.
+async fn existing_attachments(
+    hub: &GmailHub,
+    message: &Message,
+) -> Result<Vec<Attachment>, GmailMcpError> {
+    let mut parts = Vec::new();
+    if let Some(sub_parts) = message.payload.as_ref().and_then(|p| p.parts.as_deref()) {
+        attachment_parts(sub_parts, &mut parts);
+    }
+
+    let mut attachments = Vec::new();
+    for part in parts {
+        let body = part.body.clone().unwrap_or_default();
+        let data = match (body.data, body.attachment_id, message.id.as_deref()) {
+            (Some(data), _, _) => data,
+            (None, Some(attachment_id), Some(message_id)) => {
+                crate::fetch_attachment(hub, message_id, &attachment_id).await?
+            }
+            _ => continue,
+        };
+        attachments.push(Attachment {
+            filename: part.filename.clone().unwrap_or_default(),
+            mime_type: part
+                .mime_type
+                .clone()
+                .unwrap_or_else(|| "application/octet-stream".to_string()),
+            data,
+        });
+    }
+    Ok(attachments)
+}
+
+/// Apply `changes` to a draft, keeping its thread, reply headers, attachments, and any
+/// unchanged fields. Without a new body the existing text and HTML parts are kept as-is.
+pub async fn revise_draft(
+    hub: &GmailHub,
+    id: &str,
+    changes: DraftChanges,
+) -> Result<DraftInfo, GmailMcpError> {
+    let draft = fetch_draft(hub, id).await?;
+    let message = draft
+        .message
+        .ok_or_else(|| GmailMcpError::Parse(format!("draft {}: no message", id)))?;
+    let headers = message
+        .payload
+        .as_ref()
+        .and_then(|p| p.headers.clone())
+        .unwrap_or_default();
+    let existing = |name: &str| {
+        crate::header_value(&headers, name)
+            .map(|v| split_addresses(&v))
+            .unwrap_or_default()
+    };
+
+    let (body, html_body) = match changes.body {
+        Some(body) => render_body(&body, changes.format),
+        // Keep the saved parts verbatim; HTML-only drafts get a plain-text rendering
+        // that keeps their links.
+        None => {
+            let text = existing_part(&message, "text/plain")
+                .or_else(|| crate::summarize_message(&message).and_then(|s| s.body_text))
+                .unwrap_or_default();
+            (text, existing_part(&message, "text/html"))
+        }
+    };
+    let attachments = match changes.attachments {
+        Some(attachments) => attachments,
+        None => existing_attachments(hub, &message).await?,
+    };
+    let email = OutgoingEmail {
+        to: changes.to.unwrap_or_else(|| existing("To")),
+        cc: changes.cc.unwrap_or_else(|| existing("Cc")),
+        bcc: changes.bcc.unwrap_or_else(|| existing("Bcc")),
+        subject: changes
+            .subject
+            .or_else(|| crate::header_value(&headers, "Subject"))
+            .unwrap_or_default(),
+        body,
+        extra_headers: ["In-Reply-To", "References"]
+            .into_iter()
+            .filter_map(|name| {
+                crate::header_value(&headers, name).map(|value| (name.to_string(), value))
+            })
+            .collect(),
+        attachments,
+        html_body,
+    };
+
+    update_draft(hub, id, &email, message.thread_id.as_deref()).await
+}
+
+/// Send an existing draft exactly as saved, returning the sent message's identifiers.
+pub async fn send_draft(hub: &GmailHub, id: &str) -> Result<SendResponse, GmailMcpError> {
+    info!("Gmail API: Fetching raw draft {}", id);
+    crate::quota::record("drafts.get", 1);
+    let (_, draft) = hub
+        .users()
+        .drafts_get("me", id)
+        .format("raw")
+        .add_scope(SCOPE_READONLY)
+        .doit()
+        .await
+        .map_err(|e| {
+            error!("Gmail API: Failed to fetch draft {}: {}", id, e);
+            e
+        })?;
+    let message = draft
+        .message
+        .ok_or_else(|| GmailMcpError::Parse(format!("draft {}: no message", id)))?;
+    let raw = message
+        .raw
+        .ok_or_else(|| GmailMcpError::Parse(format!("draft {}: no raw content", id)))?;
+
+    // drafts.send only accepts a media upload, so re-upload the draft's own bytes
+    info!("Gmail API: Sending draft {}", id);
+    let request = Draft {
+        id: Some(id.to_string()),
+        message: Some(Message {
+            thread_id: message.thread_id,
+            ..Default::default()
+        }),
+    };
+    crate::quota::record("drafts.send", 1);
+    let (_, sent) = hub
+        .users()
+        .drafts_send(request, "me")
+        .add_scope(SCOPE_COMPOSE)
+        .upload(Cursor::new(raw), "message/rfc822".parse()?)
+        .await
+        .map_err(|e| {
+            error!("Gmail API: Failed to send draft {}: {}", id, e);
+            e
+        })?;
+
+    Ok(SendResponse {
+        id: sent.id.unwrap_or_default(),
+        thread_id: sent.thread_id,
+    })
+}
+
+/// Fetch a single draft with its full message.
+pub async fn fetch_draft(hub: &GmailHub, id: &str) -> Result<Draft, google_gmail1::Error> {
+    info!("Gmail API: Fetching draft {}", id);
+    crate::quota::record("drafts.get", 1);
+    let (_, draft) = hub
+        .users()
+        .drafts_get("me", id)
+        .format("full")
+        .add_scope(SCOPE_READONLY)
+        .doit()
+        .await
+        .map_err(|e| {
+            error!("Gmail API: Failed to fetch draft {}: {}", id, e);
+            e
+        })?;
+    Ok(draft)
+}
+
+/// Summarize a fetched draft, converting HTML bodies to text.
+async fn summarize_draft(draft: Draft) -> Option<DraftSummary> {
+    let id = draft.id?;
+    let mut message = crate::summarize_message(draft.message.as_ref()?)?;
+    convert_html_to_text(&mut message).await;
+    Some(DraftSummary { id, message })
+}
+
+/// List up to `max_results` drafts, fetching and summarizing each one.
+pub async fn list_drafts(
+    hub: &GmailHub,
+    max_results: u32,
+    page_token: Option<String>,
+) -> Result<DraftListResponse, GmailMcpError> {
+    info!("Gmail API: Listing up to {} drafts", max_results);
+    crate::quota::record("drafts.list", 1);
+    let mut call = hub
+        .users()
+        .drafts_list("me")
+        .max_results(max_results)
+        .add_scope(SCOPE_READONLY);
+    if let Some(token) = page_token.as_deref() {
+        call = call.page_token(token);
+    }
+    let (_, response) = call.doit().await.map_err(|e| {
+        error!("Gmail API: Failed to list drafts: {}", e);
+        e
+    })?;
+
+    let mut drafts = Vec::new();
+    for draft in response.drafts.unwrap_or_default() {
+        let Some(id) = draft.id else { continue };
+        let draft = fetch_draft(hub, &id).await?;
+        if let Some(summary) = summarize_draft(draft).await {
+            drafts.push(summary);
+        }
+    }
+
+    Ok(DraftListResponse {
+        count: drafts.len(),
+        drafts,
+        next_page_token: response.next_page_token,
+    })
+}
+
+/// Connect to Gmail and save a plain-text draft, returning JSON with its ID.
+pub async fn create_draft(
+    to: Vec<String>,
+    subject: &str,
+    body: &str,
+) -> Result<String, GmailMcpError> {
+    let email = OutgoingEmail::new(to, subject, body);
+    // Validate before connecting so a malformed draft never triggers an OAuth prompt
+    email.to_rfc822()?;
+
+    let hub = crate::connect().await?;
+    let draft = save_draft(&hub, &email, None).await?;
+    info!("Gmail API: Created draft {}", draft.id);
+    Ok(serde_json::to_string_pretty(&draft)?)
+}
+
+/// Actions of the `gmail_drafts` tool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DraftAction {
+    List,
+    Get,
+    Create,
+    Update,
+    Delete,
+    Send,
+}
+
+impl Action for DraftAction {
+    const SUBJECT: &'static str = "draft";
+    const ALL: &'static [(Self, &'static str, &'static str)] = &[
+        (
+            DraftAction::List,
+            "list",
+            "optional max_results and page_token",
+        ),
+        (DraftAction::Get, "get", "requires id"),
+        (
+            DraftAction::Create,
+            "create",
+            "requires body; optional to, cc, bcc, subject, format, and attachments",
+        ),
+        (
+            DraftAction::Update,
+            "update",
+            "requires id and at least one of to, cc, bcc, subject, or body",
+        ),
+        (DraftAction::Delete, "delete", "requires id"),
+        (DraftAction::Send, "send", "requires id"),
+    ];
+}
+
+impl FromStr for DraftAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        actions::parse(s)
+    }
+}
+
+/// Dispatch a draft action, returning JSON.
+pub async fn manage_drafts(
+    action: DraftAction,
+    id: Option<String>,
+    changes: DraftChanges,
+    max_results: u32,
+    page_token: Option<String>,
+) -> Result<String, GmailMcpError> {
+    capabilities::require(Capability::Compose)?;
+    let require = |value: Option<String>, field: &str| {
+        value.filter(|v| !v.trim().is_empty()).ok_or_else(|| {
+            GmailMcpError::Invalid(format!(
+                "Draft action '{}' requires '{}'",
+                action.name(),
+                field
+            ))
+        })
+    };
+
+    match action {
+        DraftAction::List => {
+            let max_results = max_results.clamp(1, 500);
+            let hub = crate::connect().await?;
+            let drafts = list_drafts(&hub, max_results, page_token).await?;
+            Ok(serde_json::to_string_pretty(&drafts)?)
+        }
+        DraftAction::Get => {
+            let id = require(id, "id")?;
+            let hub = crate::connect().await?;
+            let draft = fetch_draft(&hub, id.trim()).await?;
+            let summary = summarize_draft(draft)
+                .await
+                .ok_or_else(|| GmailMcpError::Parse(format!("draft {}: no message", id.trim())))?;
+            Ok(serde_json::to_string_pretty(&summary)?)
+        }
+        DraftAction::Create => {
+            let body = require(changes.body, "body")?;
+            let (body, html_body) = render_body(&body, changes.format);
+            let email = OutgoingEmail {
+                to: changes.to.unwrap_or_default(),
+                cc: changes.cc.unwrap_or_default(),
+                bcc: changes.bcc.unwrap_or_default(),
+                subject: changes.subject.unwrap_or_default(),
+                body,
+                html_body,
+                attachments: changes.attachments.unwrap_or_default(),
+                ..Default::default()
+            };
+            email.to_rfc822()?;
+            let hub = crate::connect().await?;
+            let draft = save_draft(&hub, &email, None).await?;
+            Ok(serde_json::to_string_pretty(&draft)?)
+        }
+        DraftAction::Update => {
+            let id = require(id, "id")?;
+            if changes.is_empty() {
+                return Err(GmailMcpError::Invalid(
+                    "Draft action 'update' requires at least one of to, cc, bcc, subject, or body"
+                        .to_string(),
+                ));
+            }
+            let hub = crate::connect().await?;
+            let draft = revise_draft(&hub, id.trim(), changes).await?;
+            Ok(serde_json::to_string_pretty(&draft)?)
+        }
+        DraftAction::Delete => {
+            let id = require(id, "id")?;
+            let hub = crate::connect().await?;
+            delete_draft(&hub, id.trim()).await?;
+            Ok(serde_json::to_string_pretty(
+                &serde_json::json!({ "deleted": id.trim() }),
+            )?)
+        }
+        DraftAction::Send => {
+            let id = require(id, "id")?;
+            let hub = crate::connect().await?;
+            let sent = send_draft(&hub, id.trim()).await?;
+            info!("Gmail API: Sent draft {} as message {}", id.trim(), sent.id);
+            Ok(serde_json::to_string_pretty(&sent)?)
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! Error type returned by the library.
+//!
+//! Gmail API failures are classified by their HTTP status and reason, so
+//! callers can tell an expired grant from a quota problem or a bad request
+//! without parsing messages.
+
+use serde_json::Value;
+use thiserror::Error;
+
+/// Everything that can go wrong talking to Gmail or handling its data.
+#[derive(Debug, Error)]
+pub enum GmailMcpError {
+    /// The OAuth client credentials could not be found or read.
+    #[error("Missing credentials: {0}")]
+    MissingCredentials(String),
+    /// No usable token: the OAuth flow failed, or Gmail rejected the grant.
+    #[error("Authentication failed: {0}")]
+    Auth(String),
+    /// Gmail answered with an error status.
+    #[error("Gmail API error {status}: {message}")]
+    Api { status: u16, message: String },
+    /// Gmail refused the request because a quota or rate limit was exceeded.
+    #[error("Gmail API rate limit exceeded: {0}")]
+    RateLimited(String),
+    /// The request never got an answer from Gmail.
+    #[error("Network error: {0}")]
+    Network(String),
+    /// Data from Gmail or a file could not be decoded.
+    #[error("Failed to parse {0}")]
+    Parse(String),
+    /// A parameter or configuration value was rejected.
+    #[error("{0}")]
+    Invalid(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl GmailMcpError {
+    /// HTTP status returned by Gmail, when the error came from a response.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            Self::Api { status, .. } => Some(*status),
+            Self::RateLimited(_) => Some(429),
+            _ => None,
+        }
+    }
+
+    /// Classify an error response from its status and body, which is usually
+    /// JSON in the Gmail API error format.
+    pub(crate) fn from_status(status: u16, body: &str) -> Self {
+        match serde_json::from_str::<Value>(body) {
+            Ok(json) if json["error"].is_object() => Self::from_body(Some(status), &json),
+            _ => Self::from_response(status, body.trim().to_string(), &[]),
+        }
+    }
+
+    /// Classify a Gmail API error body, whose `code` is used when `status` is unknown.
+    pub(crate) fn from_body(status: Option<u16>, body: &Value) -> Self {
+        let error = &body["error"];
+        let status = status.unwrap_or_else(|| error["code"].as_u64().unwrap_or(400) as u16);
+        let message = error["message"]
+            .as_str()
+            .map(str::to_string)
+            .unwrap_or_else(|| body.to_string());
+        let reasons: Vec<&str> = error["errors"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e["reason"].as_str())
+            .collect();
+        Self::from_response(status, message, &reasons)
+    }
+
+    /// Classify an error response by its status and the reasons in its body.
+    fn from_response(status: u16, message: String, reasons: &[&str]) -> Self {
+        let rate_limited = reasons.iter().any(|reason| {
+            matches!(
+                *reason,
+                "rateLimitExceeded" | "userRateLimitExceeded" | "quotaExceeded"
+            )
+        });
+        match status {
+            429 => Self::RateLimited(message),
+            403 if rate_limited => Self::RateLimited(message),
+            401 => Self::Auth(message),
+            _ => Self::Api { status, message },
+        }
+    }
+}
+
+impl From<google_gmail1::Error> for GmailMcpError {
+    fn from(e: google_gmail1::Error) -> Self {
+        use google_gmail1::Error;
+        match e {
+            Error::BadRequest(body) => Self::from_body(None, &body),
+            Error::Failure(response) => {
+                let status = response.status();
+                let message = status.canonical_reason().unwrap_or("Request failed");
+                Self::from_response(status.as_u16(), message.to_string(), &[])
+            }
+            Error::MissingToken(e) => Self::Auth(e.to_string()),
+            Error::MissingAPIKey => Self::Auth(e.to_string().trim().to_string()),
+            Error::HttpError(e) => Self::Network(e.to_string()),
+            Error::Cancelled => Self::Network("The request was cancelled".to_string()),
+            Error::JsonDecodeError(body, e) => {
+                Self::Parse(format!("Gmail API response ({}): {}", e, body))
+            }
+            Error::Io(e) => Self::Io(e),
+            e @ (Error::UploadSizeLimitExceeded(..) | Error::FieldClash(_)) => {
+                Self::Invalid(e.to_string().trim().to_string())
+            }
+        }
+    }
+}
+
+impl From<serde_json::Error> for GmailMcpError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Parse(format!("JSON: {}", e))
+    }
+}
+
+impl From<base64::DecodeError> for GmailMcpError {
+    fn from(e: base64::DecodeError) -> Self {
+        Self::Parse(format!("base64 data: {}", e))
+    }
+}
+
+impl From<google_gmail1::hyper::http::Error> for GmailMcpError {
+    fn from(e: google_gmail1::hyper::http::Error) -> Self {
+        Self::Invalid(format!("Invalid HTTP request: {}", e))
+    }
+}
+
+impl From<google_gmail1::hyper_util::client::legacy::Error> for GmailMcpError {
+    fn from(e: google_gmail1::hyper_util::client::legacy::Error) -> Self {
+        Self::Network(e.to_string())
+    }
+}
+
+impl From<mime::FromStrError> for GmailMcpError {
+    fn from(e: mime::FromStrError) -> Self {
+        Self::Parse(format!("MIME type: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_classify_gmail_errors() {
+        let body = |code: u16, reason: &str| {
+            google_gmail1::Error::BadRequest(json!({
+                "error": { "code": code, "message": "Nope", "errors": [{ "reason": reason }] }
+            }))
+        };
+        assert!(matches!(
+            GmailMcpError::from(body(403, "userRateLimitExceeded")),
+            GmailMcpError::RateLimited(_)
+        ));
+        assert!(matches!(
+            GmailMcpError::from(body(401, "authError")),
+            GmailMcpError::Auth(_)
+        ));
+        let not_found = GmailMcpError::from(body(404, "notFound"));
+        assert_eq!(not_found.status(), Some(404));
+        assert_eq!(not_found.to_string(), "Gmail API error 404: Nope");
+        let unavailable = GmailMcpError::from_status(503, "Try later");
+        assert_eq!(unavailable.to_string(), "Gmail API error 503: Try later");
+    }
+}
@@ -0,0 +1,143 @@
+//! New-mail events raised by the mailbox watchers.
+//!
+//! Events are broadcast to in-process subscribers and queued, up to
+//! [`MAX_PENDING_EVENTS`], until a client collects them. Events still queued at
+//! shutdown are saved and queued again on the next start.
+
+use crate::{state, EmailSummary, GmailMcpError};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Mutex, OnceLock};
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+/// How many uncollected events are kept; older ones are dropped first.
+pub const MAX_PENDING_EVENTS: usize = 100;
+
+/// New mail arrived in the watched mailbox.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct NewMailEvent {
+    /// What detected the mail, e.g. `push` or `poll`.
+    pub source: String,
+    /// The mailbox history ID after the change, when known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub history_id: Option<String>,
+    /// IDs of the new messages.
+    pub message_ids: Vec<String>,
+    /// Summaries of the new messages that could be fetched.
+    #[serde(default)]
+    pub emails: Vec<EmailSummary>,
+    /// When the event was raised (Unix epoch seconds).
+    pub received_at: i64,
+}
+
+/// Uncollected events and their count.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EventResponse {
+    pub events: Vec<NewMailEvent>,
+    pub count: usize,
+}
+
+static PENDING: Mutex<VecDeque<NewMailEvent>> = Mutex::new(VecDeque::new());
+static CHANNEL: OnceLock<broadcast::Sender<NewMailEvent>> = OnceLock::new();
+
+fn channel() -> &'static broadcast::Sender<NewMailEvent> {
+    CHANNEL.get_or_init(|| broadcast::channel(MAX_PENDING_EVENTS).0)
+}
+
+/// Queue an event and broadcast it to any subscribers.
+pub fn publish(event: NewMailEvent) {
+    info!(
+        "New mail event from {}: {} message(s)",
+        event.source,
+        event.message_ids.len()
+    );
+    match PENDING.lock() {
+        Ok(mut pending) => {
+            if pending.len() >= MAX_PENDING_EVENTS {
+                warn!("Dropping oldest uncollected new-mail event");
+                pending.pop_front();
+            }
+            pending.push_back(event.clone());
+        }
+        Err(e) => warn!("New-mail event queue is unavailable: {}", e),
+    }
+    // Sending only fails when nobody is subscribed, which is fine
+    let _ = channel().send(event);
+}
+
+/// Receive every event published from now on.
+pub fn subscribe() -> broadcast::Receiver<NewMailEvent> {
+    channel().subscribe()
+}
+
+/// Queue the events saved at the previous shutdown, ahead of any new ones.
+pub fn restore() {
+    let saved = state::take_saved_events();
+    if saved.is_empty() {
+        return;
+    }
+    info!("Restored {} uncollected new-mail event(s)", saved.len());
+    if let Ok(mut pending) = PENDING.lock() {
+        for event in saved.into_iter().rev() {
+            if pending.len() >= MAX_PENDING_EVENTS {
+                break;
+            }
+            pending.push_front(event);
+        }
+    }
+}
+
+/// Save the queued events for the next run; called at shutdown.
+pub fn flush() {
+    let pending: Vec<NewMailEvent> = PENDING
+        .lock()
+        .map(|pending| pending.iter().cloned().collect())
+        .unwrap_or_default();
+    if !pending.is_empty() {
+        state::save_pending_events(pending);
+    }
+}
+
+/// Remove and return all queued events, oldest first.
+pub fn take_pending() -> Vec<NewMailEvent> {
+    PENDING
+        .lock()
+        .map(|mut pending| pending.drain(..).collect())
+        .unwrap_or_default()
+}
+
+/// Collect queued events as JSON.
+pub fn collect_events() -> Result<String, GmailMcpError> {
+    let events = take_pending();
+    let response = EventResponse {
+        count: events.len(),
+        events,
+    };
+    Ok(serde_json::to_string_pretty(&response)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_publish_queues_and_broadcasts() {
+        let mut receiver = subscribe();
+        for i in 0..MAX_PENDING_EVENTS + 2 {
+            publish(NewMailEvent {
+                source: "test".to_string(),
+                message_ids: vec![i.to_string()],
+                ..Default::default()
+            });
+        }
+
+        let pending = take_pending();
+        assert_eq!(pending.len(), MAX_PENDING_EVENTS);
+        assert_eq!(pending[0].message_ids, vec!["2"]);
+        assert!(take_pending().is_empty());
+
+        // Subscribers see every event, including those dropped from the queue
+        assert_eq!(receiver.try_recv().unwrap().message_ids, vec!["0"]);
+    }
+}
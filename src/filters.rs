@@ -0,0 +1,170 @@
+//! Translation of structured filters into Gmail search operators.
+
+use crate::GmailMcpError;
+use chrono::DateTime;
+
+/// Query matching all mail except spam and trash, as Gmail's own search does.
+pub const ALL_MAIL_QUERY: &str = "in:anywhere -in:spam -in:trash";
+
+/// Search criteria given as fields rather than Gmail query syntax.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchFilters {
+    /// Sender address or name.
+    pub from: Option<String>,
+    /// Recipient address or name.
+    pub to: Option<String>,
+    /// Words or phrase the subject must contain.
+    pub subject_contains: Option<String>,
+    /// `true` matches only mail with attachments, `false` only mail without.
+    pub has_attachment: Option<bool>,
+    /// Only mail received in this many days.
+    pub newer_than_days: Option<u32>,
+}
+
+/// Quote a value for a Gmail operator when it holds spaces or grouping characters.
+fn quote(value: &str) -> String {
+    let value = value.trim().replace('"', "");
+    if value.contains(|c: char| c.is_whitespace() || "(){}".contains(c)) {
+        format!("\"{}\"", value)
+    } else {
+        value
+    }
+}
+
+impl SearchFilters {
+    /// Compile the filters into Gmail search operators, joined by spaces.
+    ///
+    /// Blank values are ignored; an empty string means no filter was given.
+    pub fn to_query(&self) -> Result<String, String> {
+        let mut terms = Vec::new();
+        let fields = [
+            ("from", &self.from),
+            ("to", &self.to),
+            ("subject", &self.subject_contains),
+        ];
+        for (op, value) in fields {
+            if let Some(value) = value.as_deref().filter(|v| !v.trim().is_empty()) {
+                terms.push(format!("{}:{}", op, quote(value)));
+            }
+        }
+        match self.has_attachment {
+            Some(true) => terms.push("has:attachment".to_string()),
+            Some(false) => terms.push("-has:attachment".to_string()),
+            None => {}
+        }
+        match self.newer_than_days {
+            Some(0) => return Err("newer_than_days must be at least 1".to_string()),
+            Some(days) => terms.push(format!("newer_than:{}d", days)),
+            None => {}
+        }
+        Ok(terms.join(" "))
+    }
+}
+
+/// Parse a date bound given as RFC3339 (`2024-05-01T09:00:00Z`) or Unix epoch seconds.
+pub fn parse_date_bound(value: &str) -> Result<i64, GmailMcpError> {
+    let value = value.trim();
+    if !value.is_empty() && value.chars().all(|c| c.is_ascii_digit()) {
+        return value.parse().map_err(|e| {
+            GmailMcpError::Invalid(format!("Invalid epoch seconds '{}': {}", value, e))
+        });
+    }
+
+    DateTime::parse_from_rfc3339(value)
+        .map(|dt| dt.timestamp())
+        .map_err(|e| {
+            GmailMcpError::Invalid(format!(
+                "Invalid date '{}': expected RFC3339 (e.g. 2024-05-01T09:00:00Z) or epoch seconds ({})",
+                value, e
+            ))
+        })
+}
+
+/// Build the Gmail `after:`/`before:` operators for an optional date range.
+///
+/// Returns `None` when neither bound is set, and an error when a bound is
+/// malformed or `after` is not earlier than `before`.
+pub fn date_range_query(
+    after: Option<&str>,
+    before: Option<&str>,
+) -> Result<Option<String>, GmailMcpError> {
+    let after = after.map(parse_date_bound).transpose()?;
+    let before = before.map(parse_date_bound).transpose()?;
+
+    if let (Some(a), Some(b)) = (after, before) {
+        if a >= b {
+            return Err(GmailMcpError::Invalid(format!(
+                "Date range is empty: after ({}) must be earlier than before ({})",
+                a, b
+            )));
+        }
+    }
+
+    let terms: Vec<String> = [("after", after), ("before", before)]
+        .into_iter()
+        .filter_map(|(op, bound)| bound.map(|ts| format!("{}:{}", op, ts)))
+        .collect();
+    Ok((!terms.is_empty()).then(|| terms.join(" ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_date_bound() {
+        assert_eq!(parse_date_bound("1714554000").unwrap(), 1714554000);
+        assert_eq!(
+            parse_date_bound("2024-05-01T09:00:00Z").unwrap(),
+            1714554000
+        );
+        assert_eq!(
+            parse_date_bound("2024-05-01T11:00:00+02:00").unwrap(),
+            1714554000
+        );
+        assert!(parse_date_bound("yesterday").is_err());
+        assert!(parse_date_bound("").is_err());
+    }
+
+    #[test]
+    fn test_search_filters_to_query() {
+        assert_eq!(SearchFilters::default().to_query().unwrap(), "");
+        let filters = SearchFilters {
+            from: Some("boss@corp.com".to_string()),
+            to: Some(" ".to_string()),
+            subject_contains: Some("quarterly \"Q3\" report".to_string()),
+            has_attachment: Some(true),
+            newer_than_days: Some(7),
+        };
+        assert_eq!(
+            filters.to_query().unwrap(),
+            "from:boss@corp.com subject:\"quarterly Q3 report\" has:attachment newer_than:7d"
+        );
+        let filters = SearchFilters {
+            has_attachment: Some(false),
+            ..Default::default()
+        };
+        assert_eq!(filters.to_query().unwrap(), "-has:attachment");
+        let filters = SearchFilters {
+            newer_than_days: Some(0),
+            ..Default::default()
+        };
+        assert!(filters.to_query().is_err());
+    }
+
+    #[test]
+    fn test_date_range_query() {
+        assert_eq!(date_range_query(None, None).unwrap(), None);
+        assert_eq!(
+            date_range_query(Some("100"), None).unwrap().as_deref(),
+            Some("after:100")
+        );
+        assert_eq!(
+            date_range_query(Some("100"), Some("200"))
+                .unwrap()
+                .as_deref(),
+            Some("after:100 before:200")
+        );
+        assert!(date_range_query(Some("200"), Some("100")).is_err());
+    }
+}
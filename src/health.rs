@@ -0,0 +1,94 @@
+//! Health report for container orchestration and uptime monitoring.
+//!
+//! The HTTP transports serve it at [`HEALTH_PATH`] to callers without the
+//! token, so the report carries only the status; the problems found are kept
+//! for the server log. The server is healthy when the cached token can be used
+//! to read the account profile, which shows both that the token store works and
+//! that the Gmail API is reachable. The check never starts an OAuth flow, and
+//! its outcome is reused for [`CHECK_TTL`] so frequent probes do not each cost
+//! a Gmail API call.
+
+use crate::auth;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Route of the health report.
+pub const HEALTH_PATH: &str = "/healthz";
+
+/// How long the outcome of a check is reused before Gmail is asked again.
+pub const CHECK_TTL: Duration = Duration::from_secs(30);
+
+/// The last check and when it ran; held while checking, so concurrent probes
+/// share one check.
+static LAST: Mutex<Option<(Instant, Health)>> = Mutex::const_new(None);
+
+/// Outcome of a health check.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Health {
+    /// `ok` or `unavailable`.
+    pub status: &'static str,
+    /// Problems found while checking, for the server log only.
+    #[serde(skip)]
+    pub errors: Vec<String>,
+}
+
+impl Health {
+    /// Whether the server can read the account through the Gmail API.
+    pub fn is_healthy(&self) -> bool {
+        self.status == "ok"
+    }
+}
+
+/// Check the credentials, the token store, and the Gmail API, reusing the
+/// outcome of a check made less than [`CHECK_TTL`] ago.
+pub async fn check() -> Health {
+    let mut last = LAST.lock().await;
+    if let Some((at, health)) = last.as_ref() {
+        if at.elapsed() < CHECK_TTL {
+            return health.clone();
+        }
+    }
+    let health = check_now().await;
+    *last = Some((Instant::now(), health.clone()));
+    health
+}
+
+async fn check_now() -> Health {
+    let status = match auth::auth_status().await {
+        Ok(status) => status,
+        Err(e) => {
+            return Health {
+                status: "unavailable",
+                errors: vec![e.to_string()],
+            }
+        }
+    };
+    let gmail_reachable = status.email.is_some();
+    let mut errors = status.errors;
+    if !gmail_reachable && errors.is_empty() {
+        errors.push("No cached token can read the account; authorize the server first".to_string());
+    }
+    Health {
+        status: if gmail_reachable { "ok" } else { "unavailable" },
+        errors,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health_reports_only_status() {
+        let health = Health {
+            status: "unavailable",
+            errors: vec!["Cannot read cached tokens: /secret/path".to_string()],
+        };
+        assert!(!health.is_healthy());
+        assert_eq!(
+            serde_json::to_value(&health).unwrap(),
+            serde_json::json!({ "status": "unavailable" })
+        );
+    }
+}
@@ -0,0 +1,258 @@
+//! Incremental mailbox changes using the Gmail History API.
+
+use crate::reademail::convert_html_to_text;
+use crate::{EmailSummary, GmailHub, GmailMcpError, SCOPE_READONLY};
+use google_gmail1::api::{History, Message};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+/// Labels added to or removed from a message.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct LabelChange {
+    /// The message ID.
+    pub id: String,
+    /// The label IDs that changed.
+    pub label_ids: Vec<String>,
+}
+
+/// Everything that changed in the mailbox since a given history ID.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct MailboxChanges {
+    /// Pass this to the next call to receive only later changes.
+    pub history_id: String,
+    /// IDs of messages added since the start point.
+    pub added: Vec<String>,
+    /// IDs of messages permanently deleted since the start point.
+    pub deleted: Vec<String>,
+    pub labels_added: Vec<LabelChange>,
+    pub labels_removed: Vec<LabelChange>,
+    /// Summaries of the added messages that still exist.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub emails: Vec<EmailSummary>,
+}
+
+/// Fetch the mailbox's current history ID, the starting point for later change requests.
+pub async fn current_history_id(hub: &GmailHub) -> Result<u64, GmailMcpError> {
+    crate::quota::record("getProfile", 1);
+    let (_, profile) = hub
+        .users()
+        .get_profile("me")
+        .add_scope(SCOPE_READONLY)
+        .doit()
+        .await
+        .map_err(|e| {
+            error!("Gmail API: Failed to fetch profile: {}", e);
+            e
+        })?;
+    profile
+        .history_id
+        .ok_or_else(|| GmailMcpError::Parse("Gmail profile: no history ID".to_string()))
+}
+
+/// Fetch all history records after `since_history_id`, following pagination.
+///
+/// Gmail keeps history for about a week; an older start point fails and the
+/// caller should fall back to a full read.
+pub async fn fetch_changes(
+    hub: &GmailHub,
+    since_history_id: u64,
+) -> Result<MailboxChanges, GmailMcpError> {
+    info!(
+        "Gmail API: Fetching changes since history {}",
+        since_history_id
+    );
+    let mut records = Vec::new();
+    let mut latest = since_history_id;
+    let mut page_token: Option<String> = None;
+
+    loop {
+        crate::quota::record("history.list", 1);
+        let mut call = hub
+            .users()
+            .history_list("me")
+            .start_history_id(since_history_id)
+            .max_results(500)
+            .add_scope(SCOPE_READONLY);
+        if let Some(token) = page_token.as_deref() {
+            call = call.page_token(token);
+        }
+        let (_, response) = call.doit().await.map_err(|e| {
+            error!(
+                "Gmail API: Failed to list history since {} (it may have expired; do a full read to resync): {}",
+                since_history_id, e
+            );
+            e
+        })?;
+
+        records.extend(response.history.unwrap_or_default());
+        latest = latest.max(response.history_id.unwrap_or_default());
+        page_token = response.next_page_token;
+        if page_token.is_none() {
+            break;
+        }
+    }
+
+    let mut changes = collect_changes(&records);
+    changes.history_id = latest.to_string();
+    info!(
+        "Gmail API: {} added, {} deleted, {} relabeled since history {}",
+        changes.added.len(),
+        changes.deleted.len(),
+        changes.labels_added.len() + changes.labels_removed.len(),
+        since_history_id
+    );
+    Ok(changes)
+}
+
+/// Flatten history records into deduplicated lists of changes.
+///
+/// Messages that were deleted within the window are dropped from the added and
+/// relabeled lists, since there is nothing left to fetch.
+fn collect_changes(records: &[History]) -> MailboxChanges {
+    let message_id = |message: &Option<Message>| message.as_ref().and_then(|m| m.id.clone());
+    let mut changes = MailboxChanges::default();
+
+    for record in records {
+        for added in record.messages_added.iter().flatten() {
+            if let Some(id) = message_id(&added.message) {
+                if !changes.added.contains(&id) {
+                    changes.added.push(id);
+                }
+            }
+        }
+        for deleted in record.messages_deleted.iter().flatten() {
+            if let Some(id) = message_id(&deleted.message) {
+                if !changes.deleted.contains(&id) {
+                    changes.deleted.push(id);
+                }
+            }
+        }
+        for change in record.labels_added.iter().flatten() {
+            if let Some(id) = message_id(&change.message) {
+                changes.labels_added.push(LabelChange {
+                    id,
+                    label_ids: change.label_ids.clone().unwrap_or_default(),
+                });
+            }
+        }
+        for change in record.labels_removed.iter().flatten() {
+            if let Some(id) = message_id(&change.message) {
+                changes.labels_removed.push(LabelChange {
+                    id,
+                    label_ids: change.label_ids.clone().unwrap_or_default(),
+                });
+            }
+        }
+    }
+
+    let deleted = changes.deleted.clone();
+    changes.added.retain(|id| !deleted.contains(id));
+    changes.labels_added.retain(|c| !deleted.contains(&c.id));
+    changes.labels_removed.retain(|c| !deleted.contains(&c.id));
+    changes
+}
+
+/// Connect to Gmail and report changes since `since_history_id`, with summaries of
+/// newly added messages.
+///
+/// Without a start point this returns the current history ID and no changes, so a
+/// client can establish a baseline and then poll for deltas.
+pub async fn read_changes(since_history_id: Option<String>) -> Result<String, GmailMcpError> {
+    let since = since_history_id
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            s.parse::<u64>()
+                .map_err(|_| GmailMcpError::Invalid(format!("Invalid history ID '{}'", s)))
+        })
+        .transpose()?;
+
+    let hub = crate::connect().await?;
+    let Some(since) = since else {
+        let history_id = current_history_id(&hub).await?;
+        info!("Gmail API: Current history ID is {}", history_id);
+        let baseline = MailboxChanges {
+            history_id: history_id.to_string(),
+            ..Default::default()
+        };
+        return Ok(serde_json::to_string_pretty(&baseline)?);
+    };
+
+    let mut changes = fetch_changes(&hub, since).await?;
+    for id in &changes.added {
+        // A message can be trashed or deleted between the history read and this fetch
+        let mut email = match crate::fetch_email(&hub, id).await {
+            Ok(email) => email,
+            Err(e) => {
+                warn!("Gmail API: Skipping added message {}: {}", id, e);
+                continue;
+            }
+        };
+        convert_html_to_text(&mut email).await;
+        changes.emails.push(email);
+    }
+
+    Ok(serde_json::to_string_pretty(&changes)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use google_gmail1::api::{HistoryLabelAdded, HistoryMessageAdded, HistoryMessageDeleted};
+
+    fn message(id: &str) -> Option<Message> {
+        Some(Message {
+            id: Some(id.to_string()),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_collect_changes() {
+        let records = vec![
+            History {
+                messages_added: Some(vec![
+                    HistoryMessageAdded {
+                        message: message("a"),
+                    },
+                    HistoryMessageAdded {
+                        message: message("b"),
+                    },
+                ]),
+                ..Default::default()
+            },
+            History {
+                messages_added: Some(vec![HistoryMessageAdded {
+                    message: message("a"),
+                }]),
+                labels_added: Some(vec![
+                    HistoryLabelAdded {
+                        message: message("a"),
+                        label_ids: Some(vec!["STARRED".to_string()]),
+                    },
+                    HistoryLabelAdded {
+                        message: message("b"),
+                        label_ids: Some(vec!["STARRED".to_string()]),
+                    },
+                ]),
+                messages_deleted: Some(vec![HistoryMessageDeleted {
+                    message: message("b"),
+                }]),
+                ..Default::default()
+            },
+        ];
+
+        let changes = collect_changes(&records);
+        assert_eq!(changes.added, vec!["a"]);
+        assert_eq!(changes.deleted, vec!["b"]);
+        assert_eq!(
+            changes.labels_added,
+            vec![LabelChange {
+                id: "a".to_string(),
+                label_ids: vec!["STARRED".to_string()],
+            }]
+        );
+        assert!(changes.labels_removed.is_empty());
+    }
+}
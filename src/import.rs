@@ -0,0 +1,250 @@
+//! Adding existing messages to the mailbox with `messages.import` and `messages.insert`.
+//!
+//! Both take the full RFC 822 source, for example an `.eml` file exported from
+//! another mail system. Import runs the message through Gmail's normal delivery
+//! scanning and classification; insert stores it as-is, like IMAP `APPEND`.
+
+use crate::capabilities::{self, Capability};
+use crate::compose::{dir_from_env, resolve_in_dir};
+use crate::{labels, GmailHub, GmailMcpError, SCOPE_INSERT};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use google_gmail1::api::Message;
+use google_gmail1::common::Delegate;
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use std::path::Path;
+use tracing::{error, info};
+
+/// Largest message the Gmail upload endpoints accept (50 MB).
+pub const MAX_IMPORT_BYTES: usize = 50 * 1024 * 1024;
+
+/// Environment variable naming the directory clients may import messages from
+/// by path. Unset, messages must be sent as base64 `data`.
+pub const IMPORT_DIR_ENV: &str = "GMAIL_IMPORT_DIR";
+
+/// Messages above this size use a chunked, resumable upload.
+const RESUMABLE_THRESHOLD: usize = 5 * 1024 * 1024;
+
+/// Size of each chunk in a resumable upload; must be a power of two.
+const UPLOAD_CHUNK_SIZE: u64 = 1 << 22;
+
+/// Sets the chunk size used by resumable uploads.
+struct ChunkedUpload;
+
+impl Delegate for ChunkedUpload {
+    fn chunk_size(&mut self) -> u64 {
+        UPLOAD_CHUNK_SIZE
+    }
+}
+
+/// How a message is added to the mailbox.
+#[derive(Debug, Clone, Default)]
+pub struct ImportOptions {
+    /// Use `messages.insert`, skipping scanning and classification.
+    pub insert: bool,
+    /// Label names or IDs to apply to the message.
+    pub labels: Vec<String>,
+    /// Take the internal date from the `Date` header rather than the time of import.
+    pub use_date_header: bool,
+    /// Never let the spam classifier send the message to Spam (import only).
+    pub never_mark_spam: bool,
+    /// Add invitations found in the message to Google Calendar (import only).
+    pub process_for_calendar: bool,
+}
+
+/// Result of an import, returned to MCP clients as JSON.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ImportResponse {
+    /// `import` or `insert`.
+    pub method: String,
+    /// The ID of the new message.
+    pub id: String,
+    /// The conversation the message was added to.
+    pub thread_id: String,
+    /// The label IDs now on the message.
+    pub label_ids: Vec<String>,
+    /// Size of the uploaded message in bytes.
+    pub size: usize,
+    /// Whether the message was uploaded in chunks.
+    pub chunked: bool,
+}
+
+/// Read a message from a file on the server or decode it from base64. Paths
+/// are only read inside the directory named by [`IMPORT_DIR_ENV`].
+pub fn load_message(path: Option<&str>, data: Option<&str>) -> Result<Vec<u8>, GmailMcpError> {
+    load_message_from(dir_from_env(IMPORT_DIR_ENV).as_deref(), path, data)
+}
+
+/// [`load_message`] with paths read inside `dir`, or refused without one.
+fn load_message_from(
+    dir: Option<&Path>,
+    path: Option<&str>,
+    data: Option<&str>,
+) -> Result<Vec<u8>, GmailMcpError> {
+    let raw = match (path, data) {
+        (Some(path), _) => {
+            let file = resolve_in_dir(dir, path, IMPORT_DIR_ENV)?;
+            let unreadable = |e: std::io::Error| {
+                GmailMcpError::Invalid(format!("Cannot read message {}: {}", path, e))
+            };
+            // Check the size first so an oversized file is never read into memory
+            let size = std::fs::metadata(&file).map_err(unreadable)?.len();
+            check_message_size(size as usize)?;
+            std::fs::read(&file).map_err(unreadable)?
+        }
+        (None, Some(data)) => {
+            let data: String = data.chars().filter(|c| !c.is_whitespace()).collect();
+            STANDARD.decode(data).map_err(|e| {
+                GmailMcpError::Invalid(format!("Message data is not valid base64: {}", e))
+            })?
+        }
+        (None, None) => {
+            return Err(GmailMcpError::Invalid(
+                "A message needs either a path or data".to_string(),
+            ))
+        }
+    };
+    check_message_size(raw.len())?;
+    if raw.is_empty() {
+        return Err(GmailMcpError::Invalid("The message is empty".to_string()));
+    }
+    Ok(raw)
+}
+
+fn check_message_size(size: usize) -> Result<(), GmailMcpError> {
+    if size > MAX_IMPORT_BYTES {
+        return Err(GmailMcpError::Invalid(format!(
+            "Message is {} bytes; Gmail accepts at most {} bytes",
+            size, MAX_IMPORT_BYTES
+        )));
+    }
+    Ok(())
+}
+
+/// Add a raw RFC 822 message to the mailbox, uploading it in chunks when large.
+pub async fn import_message(
+    hub: &GmailHub,
+    raw: Vec<u8>,
+    options: &ImportOptions,
+) -> Result<ImportResponse, GmailMcpError> {
+    check_message_size(raw.len())?;
+    let label_ids = labels::resolve_label_ids(hub, &options.labels).await?;
+    let request = Message {
+        label_ids: (!label_ids.is_empty()).then_some(label_ids),
+        ..Default::default()
+    };
+    let method = if options.insert { "insert" } else { "import" };
+    let date_source = if options.use_date_header {
+        "dateHeader"
+    } else {
+        "receivedTime"
+    };
+    let size = raw.len();
+    let chunked = size > RESUMABLE_THRESHOLD;
+    info!(
+        "Gmail API: Uploading message via {} ({} bytes{})",
+        method,
+        size,
+        if chunked { ", chunked" } else { "" }
+    );
+
+    let stream = Cursor::new(raw);
+    let mime_type = "message/rfc822".parse()?;
+    let mut delegate = ChunkedUpload;
+    let result = if options.insert {
+        crate::quota::record("messages.insert", 1);
+        let call = hub
+            .users()
+            .messages_insert(request, "me")
+            .internal_date_source(date_source)
+            .add_scope(SCOPE_INSERT);
+        if chunked {
+            call.delegate(&mut delegate)
+                .upload_resumable(stream, mime_type)
+                .await
+        } else {
+            call.upload(stream, mime_type).await
+        }
+    } else {
+        crate::quota::record("messages.import", 1);
+        let call = hub
+            .users()
+            .messages_import(request, "me")
+            .internal_date_source(date_source)
+            .never_mark_spam(options.never_mark_spam)
+            .process_for_calendar(options.process_for_calendar)
+            .add_scope(SCOPE_INSERT);
+        if chunked {
+            call.delegate(&mut delegate)
+                .upload_resumable(stream, mime_type)
+                .await
+        } else {
+            call.upload(stream, mime_type).await
+        }
+    };
+    let (_, message) = result.map_err(|e| {
+        error!("Gmail API: Failed to {} message: {}", method, e);
+        e
+    })?;
+
+    Ok(ImportResponse {
+        method: method.to_string(),
+        id: message.id.unwrap_or_default(),
+        thread_id: message.thread_id.unwrap_or_default(),
+        label_ids: message.label_ids.unwrap_or_default(),
+        size,
+        chunked,
+    })
+}
+
+/// Load a message from `path` or base64 `data` and add it to the mailbox.
+pub async fn import_email(
+    path: Option<String>,
+    data: Option<String>,
+    options: ImportOptions,
+) -> Result<String, GmailMcpError> {
+    capabilities::require(Capability::Import)?;
+    let raw = load_message(path.as_deref(), data.as_deref())?;
+    let hub = crate::connect().await?;
+    let response = import_message(&hub, raw, &options).await?;
+    info!(
+        "Gmail API: Added message {} via {}",
+        response.id, response.method
+    );
+    Ok(serde_json::to_string_pretty(&response)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_message() {
+        let raw = load_message(None, Some("RnJvbTogYUBiLmNvbQ0KDQpIaQ==\n")).unwrap();
+        assert_eq!(raw, b"From: a@b.com\r\n\r\nHi");
+
+        assert!(load_message(None, None).is_err());
+        assert!(load_message(None, Some("")).is_err());
+        assert!(load_message(None, Some("not base64!")).is_err());
+        assert!(load_message(Some("/nonexistent/message.eml"), None).is_err());
+    }
+
+    #[test]
+    fn test_load_message_path() {
+        let dir = std::env::temp_dir().join(format!("gmail-mcp-import-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("old.eml"), "From: a@b.com\r\n\r\nHi").unwrap();
+
+        let raw = load_message_from(Some(&dir), Some("old.eml"), None).unwrap();
+        assert_eq!(raw, b"From: a@b.com\r\n\r\nHi");
+        assert!(load_message_from(None, Some("old.eml"), None).is_err());
+        assert!(load_message_from(Some(&dir), Some("/etc/hostname"), None).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_message_size_limit() {
+        assert!(check_message_size(MAX_IMPORT_BYTES).is_ok());
+        assert!(check_message_size(MAX_IMPORT_BYTES + 1).is_err());
+    }
+}
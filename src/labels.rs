@@ -0,0 +1,280 @@
+//! Gmail label lookup and management helpers.
+
+use crate::actions::{self, Action};
+use crate::capabilities::{self, Capability};
+use crate::{GmailHub, GmailMcpError, SCOPE_LABELS};
+use google_gmail1::api::Label;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use tracing::{error, info};
+
+/// Summary of a Gmail label as exposed to MCP clients.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LabelInfo {
+    /// The immutable label ID (e.g. `INBOX`, `Label_123`).
+    pub id: String,
+    /// The display name, with `/` separating nested labels.
+    pub name: String,
+    /// `system` for built-in labels, `user` for labels the user created.
+    pub label_type: String,
+    /// Whether messages with this label show in the message list (`show`/`hide`).
+    pub message_list_visibility: Option<String>,
+    /// Whether the label shows in the label list (`labelShow`, `labelShowIfUnread`, `labelHide`).
+    pub label_list_visibility: Option<String>,
+}
+
+impl From<Label> for LabelInfo {
+    fn from(label: Label) -> Self {
+        Self {
+            id: label.id.unwrap_or_default(),
+            name: label.name.unwrap_or_default(),
+            label_type: label.type_.unwrap_or_else(|| "user".to_string()),
+            message_list_visibility: label.message_list_visibility,
+            label_list_visibility: label.label_list_visibility,
+        }
+    }
+}
+
+/// Response structure that wraps the label list
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LabelResponse {
+    /// The labels in the mailbox
+    pub labels: Vec<LabelInfo>,
+    /// The number of labels returned
+    pub count: usize,
+}
+
+/// Fetch every label in the mailbox.
+pub async fn list_labels(hub: &GmailHub) -> Result<Vec<LabelInfo>, GmailMcpError> {
+    info!("Gmail API: Requesting label list");
+    crate::quota::record("labels.list", 1);
+    let (_, response) = hub.users().labels_list("me").doit().await.map_err(|e| {
+        error!("Gmail API: Failed to list labels: {}", e);
+        e
+    })?;
+
+    Ok(response
+        .labels
+        .unwrap_or_default()
+        .into_iter()
+        .map(LabelInfo::from)
+        .collect())
+}
+
+/// Connect to Gmail and return the mailbox labels as pretty-printed JSON.
+///
+/// System labels (`INBOX`, `SENT`, ...) are omitted unless `include_system` is set.
+pub async fn read_labels(include_system: bool) -> Result<String, GmailMcpError> {
+    let hub = crate::connect().await?;
+    let mut labels = list_labels(&hub).await?;
+    if !include_system {
+        labels.retain(|l| l.label_type != "system");
+    }
+    labels.sort_by(|a, b| a.name.cmp(&b.name));
+
+    info!("Gmail API: Returning {} labels", labels.len());
+    let response = LabelResponse {
+        count: labels.len(),
+        labels,
+    };
+    Ok(serde_json::to_string_pretty(&response)?)
+}
+
+/// Resolve a label name (e.g. `Work/Invoices`) to its Gmail label ID.
+///
+/// Matching is case-insensitive. A value that is already a label ID
+/// (such as `INBOX` or `Label_123`) is accepted as-is.
+pub async fn resolve_label_id(hub: &GmailHub, label: &str) -> Result<String, GmailMcpError> {
+    info!("Gmail API: Resolving label '{}'", label);
+    let labels = list_labels(hub).await?;
+    find_label_id(&labels, label)
+}
+
+/// Resolve several label names or IDs with a single `labels.list` call.
+pub async fn resolve_label_ids(
+    hub: &GmailHub,
+    wanted: &[String],
+) -> Result<Vec<String>, GmailMcpError> {
+    if wanted.is_empty() {
+        return Ok(Vec::new());
+    }
+    info!("Gmail API: Resolving labels {:?}", wanted);
+    let labels = list_labels(hub).await?;
+    wanted
+        .iter()
+        .map(|label| find_label_id(&labels, label))
+        .collect()
+}
+
+/// Find the ID of `wanted` among already-fetched labels.
+fn find_label_id(labels: &[LabelInfo], wanted: &str) -> Result<String, GmailMcpError> {
+    labels
+        .iter()
+        .find(|l| label_matches(wanted, &l.id, &l.name))
+        .map(|l| l.id.clone())
+        .ok_or_else(|| GmailMcpError::Invalid(format!("Gmail label not found: {}", wanted)))
+}
+
+/// Create a new user label. Nested labels use `/` in the name (e.g. `Work/Invoices`).
+pub async fn create_label(hub: &GmailHub, name: &str) -> Result<LabelInfo, GmailMcpError> {
+    info!("Gmail API: Creating label '{}'", name);
+    let request = Label {
+        name: Some(name.to_string()),
+        label_list_visibility: Some("labelShow".to_string()),
+        message_list_visibility: Some("show".to_string()),
+        ..Default::default()
+    };
+
+    crate::quota::record("labels.create", 1);
+    let (_, label) = hub
+        .users()
+        .labels_create(request, "me")
+        .add_scope(SCOPE_LABELS)
+        .doit()
+        .await
+        .map_err(|e| {
+            error!("Gmail API: Failed to create label '{}': {}", name, e);
+            e
+        })?;
+    Ok(label.into())
+}
+
+/// Rename the label identified by `label` (name or ID) to `new_name`.
+pub async fn rename_label(
+    hub: &GmailHub,
+    label: &str,
+    new_name: &str,
+) -> Result<LabelInfo, GmailMcpError> {
+    let id = resolve_label_id(hub, label).await?;
+    info!("Gmail API: Renaming label {} to '{}'", id, new_name);
+    let request = Label {
+        name: Some(new_name.to_string()),
+        ..Default::default()
+    };
+
+    crate::quota::record("labels.patch", 1);
+    let (_, label) = hub
+        .users()
+        .labels_patch(request, "me", &id)
+        .add_scope(SCOPE_LABELS)
+        .doit()
+        .await
+        .map_err(|e| {
+            error!("Gmail API: Failed to rename label {}: {}", id, e);
+            e
+        })?;
+    Ok(label.into())
+}
+
+/// Delete the label identified by `label` (name or ID), returning its ID.
+///
+/// Messages keep existing; only the label is removed from them.
+pub async fn delete_label(hub: &GmailHub, label: &str) -> Result<String, GmailMcpError> {
+    let id = resolve_label_id(hub, label).await?;
+    info!("Gmail API: Deleting label {}", id);
+    crate::quota::record("labels.delete", 1);
+    hub.users()
+        .labels_delete("me", &id)
+        .add_scope(SCOPE_LABELS)
+        .doit()
+        .await
+        .map_err(|e| {
+            error!("Gmail API: Failed to delete label {}: {}", id, e);
+            e
+        })?;
+    Ok(id)
+}
+
+/// Actions of the `gmail_labels` tool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LabelAction {
+    List,
+    Create,
+    Rename,
+    Delete,
+}
+
+impl Action for LabelAction {
+    const SUBJECT: &'static str = "label";
+    const ALL: &'static [(Self, &'static str, &'static str)] = &[
+        (LabelAction::List, "list", "optional include_system"),
+        (LabelAction::Create, "create", "requires name"),
+        (LabelAction::Rename, "rename", "requires name and new_name"),
+        (LabelAction::Delete, "delete", "requires name"),
+    ];
+}
+
+impl FromStr for LabelAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        actions::parse(s)
+    }
+}
+
+/// Connect to Gmail and apply a label management action, returning JSON.
+pub async fn manage_labels(
+    action: LabelAction,
+    name: Option<String>,
+    new_name: Option<String>,
+    include_system: bool,
+) -> Result<String, GmailMcpError> {
+    let require = |value: Option<String>, field: &str| {
+        value.filter(|v| !v.trim().is_empty()).ok_or_else(|| {
+            GmailMcpError::Invalid(format!(
+                "Label action '{}' requires '{}'",
+                action.name(),
+                field
+            ))
+        })
+    };
+
+    if action != LabelAction::List {
+        capabilities::require(Capability::Labels)?;
+    }
+
+    match action {
+        LabelAction::List => read_labels(include_system).await,
+        LabelAction::Create => {
+            let name = require(name, "name")?;
+            let hub = crate::connect().await?;
+            let label = create_label(&hub, &name).await?;
+            Ok(serde_json::to_string_pretty(&label)?)
+        }
+        LabelAction::Rename => {
+            let name = require(name, "name")?;
+            let new_name = require(new_name, "new_name")?;
+            let hub = crate::connect().await?;
+            let label = rename_label(&hub, &name, &new_name).await?;
+            Ok(serde_json::to_string_pretty(&label)?)
+        }
+        LabelAction::Delete => {
+            let name = require(name, "name")?;
+            let hub = crate::connect().await?;
+            let id = delete_label(&hub, &name).await?;
+            Ok(serde_json::to_string_pretty(
+                &serde_json::json!({ "deleted": id }),
+            )?)
+        }
+    }
+}
+
+/// Whether a user-supplied label matches a label's ID or (case-insensitive) name.
+fn label_matches(wanted: &str, id: &str, name: &str) -> bool {
+    let wanted = wanted.trim();
+    wanted == id || wanted.eq_ignore_ascii_case(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_label_matches() {
+        assert!(label_matches("Work/Invoices", "Label_42", "Work/Invoices"));
+        assert!(label_matches("work/invoices ", "Label_42", "Work/Invoices"));
+        assert!(label_matches("INBOX", "INBOX", "INBOX"));
+        assert!(label_matches("Label_42", "Label_42", "Work/Invoices"));
+        assert!(!label_matches("Work", "Label_42", "Work/Invoices"));
+    }
+}
@@ -1,41 +1,258 @@
 //! Gmail API Client Library
 //! Simple library for fetching Gmail emails with OAuth2 authentication.
 
+pub mod actions;
+pub mod auth;
+pub mod batch;
+pub mod cache;
+pub mod capabilities;
+pub mod client;
+pub mod compose;
+pub mod dates;
+#[cfg(feature = "sqlite")]
+pub mod disk_cache;
+pub mod drafts;
+pub mod error;
+pub mod events;
+pub mod filters;
+pub mod health;
+pub mod history;
+pub mod import;
+pub mod labels;
+pub mod limits;
+pub mod markup;
+pub mod mock;
+pub mod modify;
+pub mod poll;
+pub mod progress;
+#[cfg(feature = "server")]
+pub mod prompts;
+pub mod provider;
+pub mod quota;
 pub mod reademail;
+pub mod retry;
+pub mod rfc822;
+pub mod risk;
+#[cfg(feature = "search")]
+pub mod search_index;
+pub mod send;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod settings;
+pub mod shutdown;
+pub mod state;
+pub mod stream;
+pub mod sync;
+pub mod threads;
+pub mod timeouts;
+#[cfg(feature = "server")]
+pub mod tls;
+pub mod token_store;
+#[cfg(feature = "server")]
+pub mod transport;
+pub mod unsubscribe;
+mod util;
+#[cfg(feature = "server")]
+pub mod watch;
+#[cfg(feature = "server")]
+pub mod web_auth;
 
-use gmail1::hyper_rustls::HttpsConnectorBuilder;
-use gmail1::hyper_util::{client::legacy::Client, rt::TokioExecutor};
+pub use error::GmailMcpError;
+pub use provider::MailProvider;
+pub use stream::fetch_stream;
+
+use chrono::SecondsFormat;
+use gmail1::hyper_rustls::HttpsConnector;
+use gmail1::hyper_util::client::legacy::connect::HttpConnector;
 use gmail1::{
-    api::{ListMessagesResponse, MessagePart},
+    api::{ListMessagesResponse, Message, MessagePart, MessagePartHeader},
     Gmail,
 };
 use google_gmail1 as gmail1;
+use hyper_timeout::TimeoutConnector;
+use schemars::gen::SchemaSettings;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
 use tracing::{error, info, warn};
-use yup_oauth2::{InstalledFlowAuthenticator, InstalledFlowReturnMethod};
+
+/// Metadata for a file attached to a message.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default, PartialEq)]
+pub struct AttachmentInfo {
+    /// The attachment's file name, empty for an inline part known only by its `content_id`.
+    pub filename: String,
+    /// The MIME type of the attachment (e.g. `application/pdf`).
+    pub mime_type: String,
+    /// Size of the attachment data in bytes.
+    pub size: u64,
+    /// ID for `messages.attachments.get`, when the data is stored separately.
+    pub attachment_id: Option<String>,
+    /// The part's `Content-ID`, by which `cid:` URLs in the HTML body show it
+    /// inline.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_id: Option<String>,
+}
 
 /// Lightweight representation of an email message that our API returns.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
 pub struct EmailSummary {
     /// The unique Gmail message ID.
     pub id: String,
+    /// ID of the conversation the message belongs to, for the thread tools.
+    #[serde(default)]
+    pub thread_id: String,
     /// The value of the `From` header.
     pub from: String,
+    /// The `From` header split into display name and address.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sender: Option<compose::Mailbox>,
     /// The value of the `Subject` header.
     pub subject: String,
+    /// The addresses in the `To` headers, decoded, with display names quoted where needed.
+    #[serde(default)]
+    pub to: Vec<String>,
+    /// The addresses in the `Cc` headers.
+    #[serde(default)]
+    pub cc: Vec<String>,
+    /// The addresses in the `Bcc` headers, present on mail sent from this account.
+    #[serde(default)]
+    pub bcc: Vec<String>,
+    /// The addresses in the `Reply-To` header, where replies should go instead of `from`.
+    #[serde(default)]
+    pub reply_to: Vec<String>,
+    /// The `Message-ID` header, for the `In-Reply-To` and `References` of a reply.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<String>,
+    /// Every header by name, with each value in message order, when requested
+    /// with [`FetchOptions::include_headers`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headers: Option<HashMap<String, Vec<String>>>,
     /// A short snippet of the message body.
     pub snippet: String,
-    /// Raw body (HTML or plain text).
+    /// Raw body (HTML or plain text); see `body_text` and `body_html` for each
+    /// version on its own.
     pub body_raw: String,
+    /// The plain-text body, converted from the HTML body when the message has
+    /// no plain-text part. Left out of listings unless requested with
+    /// [`FetchOptions::include_parts`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_text: Option<String>,
+    /// The original HTML body, when the message has one. Left out of listings
+    /// unless requested with [`FetchOptions::include_parts`], and of text and
+    /// Markdown ones even then, since it keeps every URL of the body.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_html: Option<String>,
+    /// URLs taken out of the body under [`reademail::UrlPolicy::Extract`], in
+    /// order of first appearance.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<String>,
+    /// Email addresses mentioned in the body, when requested with
+    /// [`FetchOptions::extract_addresses`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub addresses_mentioned: Vec<String>,
+    /// Dates, times, and deadlines mentioned in the body, when requested with
+    /// [`FetchOptions::extract_dates`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dates_mentioned: Vec<dates::DateMention>,
+    /// How likely the message is phishing or spam, from 0 to 100, judged from
+    /// its sender, links, wording, and authentication results (see [`risk::assess`]).
+    #[serde(default)]
+    pub risk_score: u32,
+    /// The signs of phishing behind `risk_score`, one per entry.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub risk_reasons: Vec<String>,
+    /// ID of an earlier email in the same listing that this one repeats, by
+    /// `Message-ID` or identical subject and body (see [`reademail::mark_duplicates`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duplicate_of: Option<String>,
+    /// Files attached to the message.
+    #[serde(default)]
+    pub attachments: Vec<AttachmentInfo>,
+    /// Gmail's estimate of the message size in bytes, attachments included.
+    #[serde(default)]
+    pub size_estimate: u64,
+    /// IDs of the message's labels, such as `INBOX`, `SENT`, or `Label_12`.
+    #[serde(default)]
+    pub label_ids: Vec<String>,
+    /// Whether the message carries the `UNREAD` label.
+    #[serde(default)]
+    pub is_unread: bool,
+    /// Whether the message carries the `STARRED` label.
+    #[serde(default)]
+    pub is_starred: bool,
+    /// Whether the message carries the `IMPORTANT` label.
+    #[serde(default)]
+    pub is_important: bool,
+    /// The inbox tab Gmail sorted the message into, from its `CATEGORY_*` label.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<Category>,
+    /// Where to unsubscribe from the mailing list the message came from, from
+    /// its `List-Unsubscribe` headers and unsubscribe links in the body.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unsubscribe: Option<unsubscribe::Unsubscribe>,
+    /// When the message was sent, from its `Date` header, as RFC 3339.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+    /// When Gmail received the message, as RFC 3339.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub internal_date: Option<String>,
+}
+
+/// The inbox tab Gmail sorts a message into.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+    /// The Primary tab, from the `CATEGORY_PERSONAL` label.
+    Primary,
+    /// Deals, offers, and other marketing mail.
+    Promotions,
+    /// Messages from social networks.
+    Social,
+    /// Receipts, bills, statements, and notifications.
+    Updates,
+    /// Mailing lists and discussion groups.
+    Forums,
+}
+
+impl Category {
+    /// The category named by a label ID such as `CATEGORY_PROMOTIONS`.
+    pub fn from_label_id(label_id: &str) -> Option<Self> {
+        match label_id {
+            "CATEGORY_PERSONAL" => Some(Self::Primary),
+            "CATEGORY_PROMOTIONS" => Some(Self::Promotions),
+            "CATEGORY_SOCIAL" => Some(Self::Social),
+            "CATEGORY_UPDATES" => Some(Self::Updates),
+            "CATEGORY_FORUMS" => Some(Self::Forums),
+            _ => None,
+        }
+    }
 }
 
 /// Response structure that wraps the email summaries
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct EmailResponse {
     /// The list of email summaries
     pub emails: Vec<EmailSummary>,
     /// The total number of emails fetched
     pub count: usize,
+    /// Token to pass back to fetch the next page, if more results exist
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_page_token: Option<String>,
+    /// Emails grouped by thread, when grouping was requested (`emails` is then empty)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threads: Option<Vec<threads::EmailThread>>,
+}
+
+/// JSON Schema of [`EmailResponse`], published as the output schema of the listing tools.
+///
+/// Subschemas are inlined so the schema stands alone without `definitions`.
+pub fn email_response_schema() -> serde_json::Value {
+    let schema = SchemaSettings::draft07()
+        .with(|s| s.inline_subschemas = true)
+        .into_generator()
+        .into_root_schema_for::<EmailResponse>();
+    serde_json::to_value(schema).unwrap_or_default()
 }
 
 /// Extract the plain-text body from a `Message`. Falls back to empty string.
@@ -56,7 +273,7 @@ fn extract_body(msg: &gmail1::api::Message) -> String {
 
         // Search parts for text/plain
         if let Some(parts) = &payload.parts {
-            if let Some(txt) = find_plain_text(parts) {
+            if let Some(txt) = find_text_part(parts, "text/plain") {
                 return txt;
             }
         }
@@ -64,10 +281,275 @@ fn extract_body(msg: &gmail1::api::Message) -> String {
     String::new()
 }
 
-/// Recursively traverse message parts to find the first `text/plain` body.
-fn find_plain_text(parts: &[MessagePart]) -> Option<String> {
+/// The HTML body of a payload: its own body if it is `text/html`, or else its
+/// first `text/html` part.
+fn extract_html(payload: &MessagePart) -> Option<String> {
+    if payload.mime_type.as_deref() == Some("text/html") {
+        return payload
+            .body
+            .as_ref()
+            .and_then(|body| body.data.as_deref())
+            .and_then(bytes_to_string);
+    }
+    find_text_part(payload.parts.as_deref()?, "text/html")
+}
+
+/// Split a message body into its plain text and HTML versions, given the
+/// HTML part found alongside `body_raw`, if any.
+///
+/// A `body_raw` that is itself HTML counts as the HTML version, and when there
+/// is no plain text it is converted from the HTML.
+fn split_bodies(body_raw: &str, html: Option<String>) -> (Option<String>, Option<String>) {
+    let is_html = body_raw.starts_with('<');
+    let html = html.or_else(|| is_html.then(|| body_raw.to_string()));
+    let text = if body_raw.is_empty() || is_html {
+        html.as_deref()
+            .map(|html| html2text::from_read(html.as_bytes(), 100))
+    } else {
+        Some(body_raw.to_string())
+    };
+    (text, html)
+}
+
+/// Look up the first header with the given name (case-insensitive).
+pub(crate) fn header_value(headers: &[MessagePartHeader], name: &str) -> Option<String> {
+    headers
+        .iter()
+        .find(|h| {
+            h.name
+                .as_deref()
+                .is_some_and(|n| n.eq_ignore_ascii_case(name))
+        })
+        .and_then(|h| h.value.clone())
+}
+
+/// Convert a Gmail `Message` into an [`EmailSummary`], using whichever fields
+/// its [`FetchFormat`] returned.
+///
+/// `full` and `metadata` messages are read from their parsed payload and `raw`
+/// messages from their RFC 822 source. `minimal` messages carry neither, so
+/// only the ID, snippet, and labels are filled in.
+///
+/// Returns `None` (and logs a warning) when the message lacks an ID.
+pub fn summarize_message(msg: &Message) -> Option<EmailSummary> {
+    let Some(id) = msg.id.clone() else {
+        warn!("Gmail API: Message has no ID");
+        return None;
+    };
+
+    let mut attachments = Vec::new();
+    let (headers, body_raw, html) = if let Some(payload) = &msg.payload {
+        let headers = payload.headers.as_deref().unwrap_or_default();
+        if headers.is_empty() {
+            warn!("Gmail API: Message {} has no headers", id);
+        }
+        collect_attachments(std::slice::from_ref(payload), &mut attachments);
+        let headers = headers
+            .iter()
+            .filter_map(|h| Some((h.name.clone()?, h.value.clone()?)))
+            .collect();
+        (headers, extract_body(msg), extract_html(payload))
+    } else if let Some(raw) = &msg.raw {
+        let parsed = rfc822::parse(raw);
+        attachments.extend(parsed.attachments.iter().map(|a| AttachmentInfo {
+            filename: a.filename.clone(),
+            mime_type: a.mime_type.clone(),
+            size: a.size,
+            attachment_id: None,
+            content_id: a.content_id.clone(),
+        }));
+        (parsed.headers, parsed.body, parsed.html)
+    } else {
+        (Vec::new(), String::new(), None)
+    };
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    };
+    // Addresses are split before their encoded-words are decoded, since a
+    // decoded display name may contain commas; such names are quoted again
+    let recipients = |name: &str| -> Vec<String> {
+        headers
+            .iter()
+            .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+            .flat_map(|(_, value)| compose::split_addresses(value))
+            .map(|address| compose::parse_mailbox(&address).to_string())
+            .collect()
+    };
+
+    let subject = header("Subject").map_or_else(|| "No Subject".to_string(), rfc822::decode_header);
+    let from = header("From").map_or_else(|| "Unknown Sender".to_string(), rfc822::decode_header);
+    let sender = header("From")
+        .and_then(|from| compose::split_addresses(from).into_iter().next())
+        .map(|from| compose::parse_mailbox(&from));
+    let (body_text, body_html) = split_bodies(&body_raw, html);
+    let unsubscribe = unsubscribe::find(
+        &headers,
+        body_html.as_deref(),
+        body_text.as_deref().unwrap_or_default(),
+    );
+
+    let mut summary = EmailSummary {
+        id,
+        thread_id: msg.thread_id.clone().unwrap_or_default(),
+        from,
+        sender,
+        subject,
+        to: recipients("To"),
+        cc: recipients("Cc"),
+        bcc: recipients("Bcc"),
+        reply_to: recipients("Reply-To"),
+        message_id: header("Message-ID").map(|id| id.trim().to_string()),
+        snippet: msg
+            .snippet
+            .as_deref()
+            .map(clean_snippet)
+            .unwrap_or_default(),
+        body_raw,
+        body_text,
+        body_html,
+        attachments,
+        unsubscribe,
+        size_estimate: msg.size_estimate.unwrap_or_default().max(0) as u64,
+        date: header("Date").and_then(parse_date_header),
+        internal_date: msg
+            .internal_date
+            .and_then(chrono::DateTime::from_timestamp_millis)
+            .map(|date| date.to_rfc3339_opts(SecondsFormat::Secs, true)),
+        ..Default::default()
+    };
+    let risk = risk::assess(&summary, &headers);
+    summary.risk_score = risk.score;
+    summary.risk_reasons = risk.reasons;
+    apply_labels(&mut summary, msg.label_ids.as_deref().unwrap_or_default());
+    Some(summary)
+}
+
+/// Every header of `msg` by name, or `None` if it was fetched without headers.
+fn header_map(msg: &Message) -> Option<HashMap<String, Vec<String>>> {
+    let headers: Vec<(String, String)> = match (&msg.payload, &msg.raw) {
+        (Some(payload), _) => payload
+            .headers
+            .as_deref()?
+            .iter()
+            .filter_map(|h| Some((h.name.clone()?, h.value.clone()?)))
+            .collect(),
+        (None, Some(raw)) => rfc822::parse(raw).headers,
+        (None, None) => return None,
+    };
+    let mut map: HashMap<String, Vec<String>> = HashMap::new();
+    for (name, value) in headers {
+        map.entry(name).or_default().push(value);
+    }
+    Some(map)
+}
+
+/// Set [`EmailSummary::headers`] from each fetched message, first fetching
+/// the headers of messages whose summaries came from the cache.
+pub(crate) async fn attach_headers(
+    hub: &GmailHub,
+    fetched: &mut [(Message, EmailSummary)],
+) -> Result<(), GmailMcpError> {
+    let missing: Vec<String> = fetched
+        .iter()
+        .filter(|(msg, _)| msg.payload.is_none() && msg.raw.is_none())
+        .map(|(_, summary)| summary.id.clone())
+        .collect();
+    let mut refetched: HashMap<String, Message> = HashMap::new();
+    if !missing.is_empty() {
+        for msg in batch::fetch_messages(hub, &missing, FetchFormat::Metadata).await? {
+            if let Some(id) = msg.id.clone() {
+                refetched.insert(id, msg);
+            }
+        }
+    }
+    for (msg, summary) in fetched.iter_mut() {
+        summary.headers = header_map(refetched.get(&summary.id).unwrap_or(msg));
+    }
+    Ok(())
+}
+
+/// Convert a `Date` header to RFC 3339, keeping its UTC offset.
+///
+/// A trailing comment such as `(UTC)` is ignored; other dates that are not
+/// valid RFC 2822 give `None`.
+fn parse_date_header(value: &str) -> Option<String> {
+    let value = match value.trim().rsplit_once(" (") {
+        Some((date, comment)) if comment.ends_with(')') => date,
+        _ => value.trim(),
+    };
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some(date.to_rfc3339_opts(SecondsFormat::Secs, true))
+}
+
+/// Decode the HTML entities Gmail leaves in snippets, such as `&amp;` and
+/// `&#39;`, and collapse their whitespace, dropping the zero-width characters
+/// newsletters pad their preview text with.
+fn clean_snippet(snippet: &str) -> String {
+    markup::decode_entities(snippet)
+        .replace(
+            ['\u{200b}', '\u{200c}', '\u{200d}', '\u{034f}', '\u{feff}'],
+            "",
+        )
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Set the fields of `summary` derived from the message's label IDs.
+pub(crate) fn apply_labels(summary: &mut EmailSummary, label_ids: &[String]) {
+    let has_label = |label: &str| label_ids.iter().any(|id| id == label);
+    summary.label_ids = label_ids.to_vec();
+    summary.is_unread = has_label("UNREAD");
+    summary.is_starred = has_label("STARRED");
+    summary.is_important = has_label("IMPORTANT");
+    summary.category = label_ids.iter().find_map(|id| Category::from_label_id(id));
+}
+
+/// Recursively collect every part that carries a file name as an attachment.
+fn collect_attachments(parts: &[MessagePart], out: &mut Vec<AttachmentInfo>) {
     for part in parts {
-        if part.mime_type.as_deref() == Some("text/plain") {
+        let headers = part.headers.as_deref().unwrap_or_default();
+        let mime_type = part
+            .mime_type
+            .clone()
+            .unwrap_or_else(|| "application/octet-stream".to_string());
+        let content_id = header_value(headers, "Content-ID")
+            .as_deref()
+            .and_then(rfc822::content_id);
+        let filename = part.filename.clone().filter(|f| !f.is_empty()).or_else(|| {
+            header_value(headers, "Content-Type")
+                .and_then(|t| rfc822::header_param(&t, "name"))
+                .filter(|f| !f.is_empty())
+        });
+        // Inline images are often sent with only a Content-ID to refer to them by
+        let inline = content_id.is_some()
+            && !mime_type.starts_with("text/")
+            && !mime_type.starts_with("multipart/");
+        if filename.is_some() || inline {
+            let body = part.body.as_ref();
+            out.push(AttachmentInfo {
+                filename: filename.unwrap_or_default(),
+                mime_type,
+                size: body.and_then(|b| b.size).unwrap_or_default().max(0) as u64,
+                attachment_id: body.and_then(|b| b.attachment_id.clone()),
+                content_id,
+            });
+        }
+
+        if let Some(sub_parts) = &part.parts {
+            collect_attachments(sub_parts, out);
+        }
+    }
+}
+
+/// Recursively traverse message parts to find the first body of the given type,
+/// such as `text/plain` or `text/html`.
+pub(crate) fn find_text_part(parts: &[MessagePart], mime_type: &str) -> Option<String> {
+    for part in parts {
+        if part.mime_type.as_deref() == Some(mime_type) {
             if let Some(body) = &part.body {
                 if let Some(data) = &body.data {
                     if let Some(txt) = bytes_to_string(data) {
@@ -79,7 +561,7 @@ fn find_plain_text(parts: &[MessagePart]) -> Option<String> {
 
         // Recurse into sub-parts
         if let Some(sub_parts) = &part.parts {
-            if let Some(txt) = find_plain_text(sub_parts) {
+            if let Some(txt) = find_text_part(sub_parts, mime_type) {
                 return Some(txt);
             }
         }
@@ -87,150 +569,956 @@ fn find_plain_text(parts: &[MessagePart]) -> Option<String> {
     None
 }
 
-/// Fetch Gmail emails using OAuth2 authentication
-pub async fn run(max_results: u32) -> Result<String, Box<dyn std::error::Error>> {
-    let max_results = max_results.clamp(1, 500);
-    info!("Gmail API: Starting to fetch {} emails", max_results);
+/// OAuth scope for read-only access to messages and labels.
+pub const SCOPE_READONLY: &str = "https://www.googleapis.com/auth/gmail.readonly";
+/// OAuth scope for creating, renaming, and deleting labels.
+pub const SCOPE_LABELS: &str = "https://www.googleapis.com/auth/gmail.labels";
+/// OAuth scope for changing message state (read/unread, labels).
+pub const SCOPE_MODIFY: &str = "https://www.googleapis.com/auth/gmail.modify";
+/// OAuth scope for managing drafts.
+pub const SCOPE_COMPOSE: &str = "https://www.googleapis.com/auth/gmail.compose";
+/// OAuth scope for managing filters and other basic mail settings.
+pub const SCOPE_SETTINGS_BASIC: &str = "https://www.googleapis.com/auth/gmail.settings.basic";
+/// OAuth scope for adding existing messages with import and insert.
+pub const SCOPE_INSERT: &str = "https://www.googleapis.com/auth/gmail.insert";
+/// OAuth scope for managing mailbox delegates.
+pub const SCOPE_SETTINGS_SHARING: &str = "https://www.googleapis.com/auth/gmail.settings.sharing";
+/// OAuth scope for sending mail.
+pub const SCOPE_SEND: &str = "https://www.googleapis.com/auth/gmail.send";
+/// Full mailbox scope; only needed for permanent deletion.
+pub const SCOPE_FULL: &str = "https://mail.google.com/";
+
+/// Default Gmail search query used when the caller does not provide one.
+pub const DEFAULT_QUERY: &str = "in:inbox";
+
+/// Partial-response mask for [`FetchFormat::Metadata`]: the message with its
+/// headers and MIME structure down to five levels deep, but no body data.
+///
+/// Gmail's own `metadata` format leaves out the MIME parts, and with them the
+/// attachments.
+const METADATA_FIELDS: &str = "id,threadId,labelIds,snippet,historyId,internalDate,sizeEstimate,\
+    payload(partId,mimeType,filename,headers,body(size,attachmentId),\
+    parts(partId,mimeType,filename,body(size,attachmentId),\
+    parts(partId,mimeType,filename,body(size,attachmentId),\
+    parts(partId,mimeType,filename,body(size,attachmentId),\
+    parts(partId,mimeType,filename,body(size,attachmentId))))))";
+
+/// How much of each message to download, mirroring the Gmail API `format` values.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum FetchFormat {
+    /// Parsed headers and bodies.
+    #[default]
+    Full,
+    /// Headers, the snippet, and the attachment list, skipping bodies.
+    Metadata,
+    /// Only the ID, labels, and snippet.
+    Minimal,
+    /// The RFC 822 source, parsed locally.
+    Raw,
+}
+
+impl FetchFormat {
+    /// The name of this format, as accepted by [`FromStr`].
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Full => "full",
+            Self::Metadata => "metadata",
+            Self::Minimal => "minimal",
+            Self::Raw => "raw",
+        }
+    }
+
+    /// The value sent as the API's `format` parameter.
+    ///
+    /// [`Self::Metadata`] asks for `full`, trimmed by [`Self::fields`].
+    pub fn api_format(self) -> &'static str {
+        match self {
+            Self::Metadata => "full",
+            _ => self.as_str(),
+        }
+    }
+
+    /// Partial-response mask limiting what Gmail returns, if any.
+    pub fn fields(self) -> Option<&'static str> {
+        match self {
+            Self::Metadata => Some(METADATA_FIELDS),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for FetchFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "" | "full" => Ok(Self::Full),
+            "metadata" => Ok(Self::Metadata),
+            "minimal" => Ok(Self::Minimal),
+            "raw" => Ok(Self::Raw),
+            other => Err(format!(
+                "Unknown fetch format '{}'; expected full, metadata, minimal, or raw",
+                other
+            )),
+        }
+    }
+}
+
+/// What [`run_from_page`] and [`reademail::read_emails`] fetch, and how.
+///
+/// Start from [`FetchOptions::new`] and chain the setters for what differs
+/// from the defaults: ten inbox emails in [`FetchFormat::Full`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchOptions {
+    pub(crate) query: Option<String>,
+    pub(crate) max_results: u32,
+    pub(crate) label: Option<String>,
+    pub(crate) page_token: Option<String>,
+    pub(crate) group_by_thread: bool,
+    pub(crate) unread_only: bool,
+    pub(crate) since_last_check: bool,
+    pub(crate) after: Option<String>,
+    pub(crate) before: Option<String>,
+    pub(crate) format: FetchFormat,
+    pub(crate) include_spam_trash: bool,
+    pub(crate) truncate_body_at: Option<usize>,
+    pub(crate) include_headers: bool,
+    pub(crate) strip_quoted: bool,
+    pub(crate) body_format: markup::BodyFormat,
+    pub(crate) url_policy: Option<reademail::UrlPolicy>,
+    pub(crate) extract_addresses: bool,
+    pub(crate) extract_dates: bool,
+    pub(crate) collapse_duplicates: bool,
+    pub(crate) inline_images: markup::InlineImages,
+    pub(crate) include_parts: bool,
+}
+
+impl Default for FetchOptions {
+    fn default() -> Self {
+        Self {
+            query: None,
+            max_results: 10,
+            label: None,
+            page_token: None,
+            group_by_thread: false,
+            unread_only: false,
+            since_last_check: false,
+            after: None,
+            before: None,
+            format: FetchFormat::Full,
+            include_spam_trash: false,
+            truncate_body_at: None,
+            include_headers: false,
+            strip_quoted: false,
+            body_format: markup::BodyFormat::Text,
+            url_policy: None,
+            extract_addresses: false,
+            extract_dates: false,
+            collapse_duplicates: false,
+            inline_images: markup::InlineImages::Marker,
+            include_parts: false,
+        }
+    }
+}
+
+impl FetchOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gmail search expression; without one (or a label) the inbox is read.
+    pub fn query(mut self, query: impl Into<Option<String>>) -> Self {
+        self.query = query.into();
+        self
+    }
+
+    /// Most emails to return, clamped to 1-500.
+    pub fn max_results(mut self, max_results: u32) -> Self {
+        self.max_results = max_results;
+        self
+    }
+
+    /// Only fetch messages carrying this label, by name or ID. The query then
+    /// no longer defaults to [`DEFAULT_QUERY`].
+    pub fn label(mut self, label: impl Into<Option<String>>) -> Self {
+        self.label = label.into();
+        self
+    }
+
+    /// Resume from [`EmailResponse::next_page_token`] of a previous call.
+    pub fn page_token(mut self, page_token: impl Into<Option<String>>) -> Self {
+        self.page_token = page_token.into();
+        self
+    }
+
+    /// Return the emails under [`EmailResponse::threads`] instead.
+    pub fn group_by_thread(mut self, group_by_thread: bool) -> Self {
+        self.group_by_thread = group_by_thread;
+        self
+    }
+
+    /// Only fetch unread messages.
+    pub fn unread_only(mut self, unread_only: bool) -> Self {
+        self.unread_only = unread_only;
+        self
+    }
+
+    /// With [`unread_only`](Self::unread_only), only fetch mail received after
+    /// the previous such call (see [`state`]).
+    pub fn since_last_check(mut self, since_last_check: bool) -> Self {
+        self.since_last_check = since_last_check;
+        self
+    }
+
+    /// Only mail received after this RFC3339 timestamp or epoch second.
+    pub fn after(mut self, after: impl Into<Option<String>>) -> Self {
+        self.after = after.into();
+        self
+    }
+
+    /// Only mail received before this RFC3339 timestamp or epoch second.
+    pub fn before(mut self, before: impl Into<Option<String>>) -> Self {
+        self.before = before.into();
+        self
+    }
+
+    /// How much of each message to download.
+    pub fn format(mut self, format: FetchFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Shorthand for [`FetchFormat::Metadata`] when `headers_only` is set.
+    pub fn headers_only(self, headers_only: bool) -> Self {
+        if headers_only {
+            self.format(FetchFormat::Metadata)
+        } else {
+            self
+        }
+    }
+
+    /// Also match messages in Spam and Trash.
+    pub fn include_spam_trash(mut self, include_spam_trash: bool) -> Self {
+        self.include_spam_trash = include_spam_trash;
+        self
+    }
+
+    /// Cut each body to at most this many characters, at a word boundary and
+    /// with a marker saying how much was cut counted in the limit, once
+    /// [`reademail::read_emails`] has converted it to text.
+    pub fn truncate_body_at(mut self, chars: impl Into<Option<usize>>) -> Self {
+        self.truncate_body_at = chars.into();
+        self
+    }
+
+    /// Attach every header to each email as [`EmailSummary::headers`].
+    pub fn include_headers(mut self, include_headers: bool) -> Self {
+        self.include_headers = include_headers;
+        self
+    }
+
+    /// Drop the quoted earlier messages from each text body, keeping only
+    /// what the sender wrote (see [`reademail::strip_quoted_reply`]).
+    pub fn strip_quoted(mut self, strip_quoted: bool) -> Self {
+        self.strip_quoted = strip_quoted;
+        self
+    }
+
+    /// How [`reademail::read_emails`] returns HTML bodies: converted to plain
+    /// text (the default), converted to Markdown, or as sent.
+    pub fn body_format(mut self, body_format: markup::BodyFormat) -> Self {
+        self.body_format = body_format;
+        self
+    }
+
+    /// What happens to the URLs in text and Markdown bodies; by default they
+    /// are removed from text and kept in Markdown.
+    pub fn url_policy(mut self, url_policy: impl Into<Option<reademail::UrlPolicy>>) -> Self {
+        self.url_policy = url_policy.into();
+        self
+    }
+
+    /// List the email addresses mentioned in each body as
+    /// [`EmailSummary::addresses_mentioned`] (see [`reademail::extract_addresses`]).
+    pub fn extract_addresses(mut self, extract_addresses: bool) -> Self {
+        self.extract_addresses = extract_addresses;
+        self
+    }
+
+    /// List the dates, times, and deadlines mentioned in each body as
+    /// [`EmailSummary::dates_mentioned`] (see [`dates::extract`]).
+    pub fn extract_dates(mut self, extract_dates: bool) -> Self {
+        self.extract_dates = extract_dates;
+        self
+    }
+
+    /// Drop the emails [`reademail::read_emails`] finds to repeat an earlier
+    /// one, instead of only marking them with [`EmailSummary::duplicate_of`].
+    pub fn collapse_duplicates(mut self, collapse_duplicates: bool) -> Self {
+        self.collapse_duplicates = collapse_duplicates;
+        self
+    }
+
+    /// Whether converted bodies show inline images, the `cid:` references to
+    /// parts of the message, as `[image: filename]` markers (the default) or
+    /// drop them.
+    pub fn inline_images(mut self, inline_images: markup::InlineImages) -> Self {
+        self.inline_images = inline_images;
+        self
+    }
+
+    /// Also return [`EmailSummary::body_text`] and [`EmailSummary::body_html`]
+    /// next to the processed body, instead of only `body_raw`.
+    pub fn include_parts(mut self, include_parts: bool) -> Self {
+        self.include_parts = include_parts;
+        self
+    }
+}
 
-    // Load credentials
-    info!("Gmail API: Loading credentials from client_secret.json");
-    let secret = yup_oauth2::read_application_secret("client_secret.json")
+/// Normalize an optional user query, substituting the default for missing or blank input.
+pub(crate) fn resolve_query(query: Option<String>) -> String {
+    query
+        .map(|q| q.trim().to_string())
+        .filter(|q| !q.is_empty())
+        .unwrap_or_else(|| DEFAULT_QUERY.to_string())
+}
+
+/// Authenticated Gmail API hub used by every request in this crate.
+pub type GmailHub = Gmail<TimeoutConnector<HttpsConnector<HttpConnector>>>;
+
+/// Append a search term to a Gmail query, separated by a space.
+pub(crate) fn append_term(query: &str, term: &str) -> String {
+    if query.is_empty() {
+        term.to_string()
+    } else {
+        format!("{} {}", query, term)
+    }
+}
+
+/// Fetch Gmail emails using OAuth2 authentication.
+///
+/// `query` accepts any Gmail search expression (e.g. `from:boss@corp.com is:unread`)
+/// and falls back to [`DEFAULT_QUERY`] when `None` or blank.
+/// Set `unread_only` to restrict the results to unread messages.
+pub async fn run(
+    max_results: u32,
+    query: Option<String>,
+    unread_only: bool,
+) -> Result<String, GmailMcpError> {
+    let response = client::shared()
+        .await?
+        .list(max_results, query, unread_only)
+        .await?;
+    Ok(serde_json::to_string_pretty(&response)?)
+}
+
+/// The hub of the shared [`client::GmailClient`], connecting on first use.
+pub async fn connect() -> Result<GmailHub, GmailMcpError> {
+    Ok(client::shared().await?.hub().clone())
+}
+
+/// The hub of the shared client built without requesting a token, so no OAuth
+/// flow starts until a call needs one.
+pub(crate) async fn build_hub() -> Result<GmailHub, GmailMcpError> {
+    Ok(client::built().await?.hub().clone())
+}
+
+/// Fetch a single message in `full` format.
+pub async fn fetch_message(hub: &GmailHub, id: &str) -> Result<Message, gmail1::Error> {
+    let mut backoff = retry::Backoff::new();
+    match hub
+        .users()
+        .messages_get("me", id)
+        .format("full")
+        .add_scope(SCOPE_READONLY)
+        .delegate(&mut backoff)
+        .doit()
         .await
-        .map_err(|e| {
-            error!("Gmail API: Failed to read client_secret.json: {}", e);
-            e
-        })?;
+    {
+        Ok((_, msg)) => Ok(msg),
+        Err(e) => {
+            error!("Gmail API: Failed to fetch message {}: {}", id, e);
+            // Check if it's an authentication error
+            if e.to_string().contains("403") || e.to_string().contains("PERMISSION_DENIED") {
+                error!("Gmail API: This appears to be an authentication issue");
+                warn!("Gmail API: Run the gmail_auth tool with action 'reauth' to sign in again");
+            }
+            Err(e)
+        }
+    }
+}
 
-    // Set up authenticator
-    info!("Gmail API: Setting up OAuth2 authenticator");
-    let auth = InstalledFlowAuthenticator::builder(secret, InstalledFlowReturnMethod::HTTPRedirect)
-        .persist_tokens_to_disk("token_cache.json")
-        .build()
+/// Fetch the original RFC 822 bytes of a message (`format=raw`, base64url-decoded).
+pub async fn fetch_raw_message(hub: &GmailHub, id: &str) -> Result<Vec<u8>, GmailMcpError> {
+    info!("Gmail API: Fetching raw message {}", id);
+    let mut backoff = retry::Backoff::new();
+    let (_, msg) = hub
+        .users()
+        .messages_get("me", id)
+        .format("raw")
+        .add_scope(SCOPE_READONLY)
+        .delegate(&mut backoff)
+        .doit()
         .await
         .map_err(|e| {
-            error!("Gmail API: Failed to build authenticator: {}", e);
+            error!("Gmail API: Failed to fetch raw message {}: {}", id, e);
             e
         })?;
 
-    // Create HTTPS client
-    info!("Gmail API: Creating HTTPS client");
-    let https = HttpsConnectorBuilder::new()
-        .with_native_roots()?
-        .https_or_http()
-        .enable_http1()
-        .build();
-
-    let client = Client::builder(TokioExecutor::new()).build(https);
-    let hub = Gmail::new(client, auth);
+    msg.raw
+        .ok_or_else(|| GmailMcpError::Parse(format!("message {}: no raw content", id)))
+}
 
-    // Fetch messages
-    info!("Gmail API: Requesting message list from inbox");
-    let result = hub
+/// Download the contents of a message attachment.
+pub async fn fetch_attachment(
+    hub: &GmailHub,
+    message_id: &str,
+    attachment_id: &str,
+) -> Result<Vec<u8>, GmailMcpError> {
+    info!("Gmail API: Fetching attachment of message {}", message_id);
+    let mut backoff = retry::Backoff::new();
+    let (_, body) = hub
         .users()
-        .messages_list("me")
-        .q("in:inbox")
-        .max_results(max_results)
+        .messages_attachments_get("me", message_id, attachment_id)
+        .add_scope(SCOPE_READONLY)
+        .delegate(&mut backoff)
         .doit()
         .await
         .map_err(|e| {
+            error!(
+                "Gmail API: Failed to fetch attachment of message {}: {}",
+                message_id, e
+            );
+            e
+        })?;
+
+    body.data.ok_or_else(|| {
+        GmailMcpError::Parse(format!("attachment of message {}: no data", message_id))
+    })
+}
+
+/// Fetch a single message by ID and summarize it.
+pub async fn fetch_email(hub: &GmailHub, id: &str) -> Result<EmailSummary, GmailMcpError> {
+    info!("Gmail API: Fetching message {}", id);
+    let msg = fetch_message(hub, id).await?;
+    summarize_message(&msg).ok_or_else(|| GmailMcpError::Parse(format!("message {}: no ID", id)))
+}
+
+/// List message stubs matching `query`, following `next_page_token` until
+/// `max_results` messages are collected or the result set is exhausted.
+///
+/// Returns the collected messages and the token to resume from, if any remain.
+pub(crate) async fn list_messages(
+    hub: &GmailHub,
+    query: &str,
+    label_id: Option<&str>,
+    max_results: u32,
+    page_token: Option<String>,
+    include_spam_trash: bool,
+) -> Result<(Vec<Message>, Option<String>), GmailMcpError> {
+    let mut messages = Vec::new();
+    let mut page_token = page_token;
+
+    loop {
+        let remaining = max_results.saturating_sub(messages.len() as u32);
+        info!(
+            "Gmail API: Requesting up to {} messages for query '{}'",
+            remaining, query
+        );
+
+        let mut call = hub.users().messages_list("me").max_results(remaining);
+        if !query.is_empty() {
+            call = call.q(query);
+        }
+        if let Some(label_id) = label_id {
+            call = call.add_label_ids(label_id);
+        }
+        if let Some(token) = &page_token {
+            call = call.page_token(token);
+        }
+        if include_spam_trash {
+            call = call.include_spam_trash(true);
+        }
+
+        let mut backoff = retry::Backoff::new();
+        let (
+            _,
+            ListMessagesResponse {
+                messages: page,
+                next_page_token,
+                ..
+            },
+        ) = call.delegate(&mut backoff).doit().await.map_err(|e| {
             error!("Gmail API: Failed to list messages: {}", e);
             e
         })?;
 
+        let mut page = page.unwrap_or_default();
+        page.truncate(remaining as usize);
+        messages.extend(page);
+        page_token = next_page_token;
+
+        if page_token.is_none() || messages.len() as u32 >= max_results {
+            break;
+        }
+    }
+
+    Ok((messages, page_token))
+}
+
+/// Fetch the Gmail emails selected by `options`, with bodies as Gmail returns them.
+///
+/// See [`FetchOptions`] for what can be selected.
+pub async fn run_from_page(options: &FetchOptions) -> Result<String, GmailMcpError> {
+    let check_started = state::now();
+    let query = build_query(options)?;
+    let response = provider::current().await?.list(&query, options).await?;
+
+    if options.unread_only && options.since_last_check {
+        state::record_check(check_started);
+    }
+    Ok(serde_json::to_string_pretty(&response)?)
+}
+
+/// The Gmail query for `options`, with the date range and unread terms added.
+pub(crate) fn build_query(options: &FetchOptions) -> Result<String, GmailMcpError> {
+    let date_range =
+        filters::date_range_query(options.after.as_deref(), options.before.as_deref())?;
+    let mut query = match options.label {
+        Some(_) => options.query.clone().unwrap_or_default().trim().to_string(),
+        None => resolve_query(options.query.clone()),
+    };
+    if let Some(date_range) = date_range {
+        query = append_term(&query, &date_range);
+    }
+    if options.unread_only {
+        query = append_term(&query, "is:unread");
+        if options.since_last_check {
+            if let Some(last) = state::last_check() {
+                query = append_term(&query, &format!("after:{}", last));
+            }
+        }
+    }
+    Ok(query)
+}
+
+/// Fetch one page of emails matching `query`, which replaces the query of
+/// `options`, within the fetch timeout (see [`timeouts`]).
+pub(crate) async fn fetch_page(
+    hub: &GmailHub,
+    query: &str,
+    options: &FetchOptions,
+) -> Result<EmailResponse, GmailMcpError> {
+    timeouts::fetch(read_page(hub, query, options)).await
+}
+
+/// [`fetch_page`] without the timeout.
+async fn read_page(
+    hub: &GmailHub,
+    query: &str,
+    options: &FetchOptions,
+) -> Result<EmailResponse, GmailMcpError> {
+    let max_results = options.max_results.clamp(1, 500);
+    let format = options.format;
+    info!(
+        "Gmail API: Starting to fetch {} emails matching '{}'",
+        max_results, query
+    );
+
+    let label_id = match &options.label {
+        Some(label) => Some(labels::resolve_label_id(hub, label).await?),
+        None => None,
+    };
+
+    // Fetch messages
+    let (messages, next_page_token) = list_messages(
+        hub,
+        query,
+        label_id.as_deref(),
+        max_results,
+        options.page_token.clone(),
+        options.include_spam_trash,
+    )
+    .await?;
+
     let mut summaries = Vec::new();
+    let mut thread_keys = Vec::new();
 
-    if let (
-        _,
-        ListMessagesResponse {
-            messages: Some(messages),
-            ..
-        },
-    ) = result
-    {
+    if !messages.is_empty() {
         let message_count = messages.len();
         info!(
             "Gmail API: Found {} messages, fetching details",
             message_count
         );
 
-        for (i, message) in messages.into_iter().enumerate() {
-            if let Some(id) = message.id {
-                info!(
-                    "Gmail API: Fetching message {}/{}: {}",
-                    i + 1,
-                    message_count,
-                    id
-                );
-
-                match hub
-                    .users()
-                    .messages_get("me", &id)
-                    .format("full")
-                    .add_scope("https://www.googleapis.com/auth/gmail.readonly")
-                    .doit()
-                    .await
-                {
-                    Ok((_, msg)) => {
-                        if let Some(payload) = &msg.payload {
-                            if let Some(headers) = &payload.headers {
-                                let subject = headers
-                                    .iter()
-                                    .find(|h| h.name.as_deref() == Some("Subject"))
-                                    .and_then(|h| h.value.clone())
-                                    .unwrap_or_else(|| "No Subject".to_string());
-
-                                let from = headers
-                                    .iter()
-                                    .find(|h| h.name.as_deref() == Some("From"))
-                                    .and_then(|h| h.value.clone())
-                                    .unwrap_or_else(|| "Unknown Sender".to_string());
-
-                                let snippet = msg.snippet.clone().unwrap_or_default();
-                                let body_raw = extract_body(&msg);
-
-                                summaries.push(EmailSummary {
-                                    id: id.clone(),
-                                    from,
-                                    subject: subject.clone(),
-                                    snippet,
-                                    body_raw,
-                                });
-
-                                info!("Gmail API: Successfully processed email: {}", subject);
-                            } else {
-                                warn!("Gmail API: Message {} has no headers", id);
-                            }
-                        } else {
-                            warn!("Gmail API: Message {} has no payload", id);
-                        }
-                    }
-                    Err(e) => {
-                        error!("Gmail API: Failed to fetch message {}: {}", id, e);
-                        // Check if it's an authentication error
-                        if e.to_string().contains("403")
-                            || e.to_string().contains("PERMISSION_DENIED")
-                        {
-                            error!("Gmail API: This appears to be an authentication issue");
-                            warn!("Gmail API: Consider deleting token_cache.json and restarting");
-                        }
-                    }
-                }
-            } else {
-                warn!("Gmail API: Message has no ID");
-            }
+        let ids: Vec<String> = messages.into_iter().filter_map(|m| m.id).collect();
+        if ids.len() < message_count {
+            warn!(
+                "Gmail API: Skipping {} messages without an ID",
+                message_count - ids.len()
+            );
+        }
+
+        // Failures are logged by `fetch_messages`; missing messages are skipped
+        let mut fetched = cache::fetch_summaries(hub, &ids, format).await?;
+        if options.include_headers {
+            attach_headers(hub, &mut fetched).await?;
+        }
+        for (msg, summary) in fetched {
+            info!(
+                "Gmail API: Successfully processed email: {}",
+                summary.subject
+            );
+            thread_keys.push((
+                msg.thread_id.clone().unwrap_or_else(|| summary.id.clone()),
+                msg.internal_date.unwrap_or_default(),
+            ));
+            summaries.push(summary);
         }
     } else {
         warn!("Gmail API: No messages found in response");
     }
 
+    let count = summaries.len();
+    let (emails, threads) = if options.group_by_thread {
+        let keyed = thread_keys
+            .into_iter()
+            .zip(summaries)
+            .map(|((thread_id, date), summary)| (thread_id, date, summary))
+            .collect();
+        (Vec::new(), Some(threads::group_by_thread(keyed)))
+    } else {
+        (summaries, None)
+    };
+
     let response = EmailResponse {
-        count: summaries.len(),
-        emails: summaries,
+        count,
+        emails,
+        next_page_token,
+        threads,
     };
 
     info!(
         "Gmail API: Completed successfully, returning {} emails",
         response.count
     );
-    Ok(serde_json::to_string_pretty(&response)?)
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_term() {
+        assert_eq!(append_term("", "is:unread"), "is:unread");
+        assert_eq!(append_term("in:inbox", "is:unread"), "in:inbox is:unread");
+    }
+
+    #[test]
+    fn test_fetch_options_builder() {
+        let options = FetchOptions::new();
+        assert_eq!(options.max_results, 10);
+        assert_eq!(options.format, FetchFormat::Full);
+
+        let options = FetchOptions::new()
+            .label("Work".to_string())
+            .format(FetchFormat::Raw)
+            .headers_only(false)
+            .truncate_body_at(200)
+            .include_headers(true)
+            .strip_quoted(true)
+            .body_format(markup::BodyFormat::Markdown)
+            .url_policy(reademail::UrlPolicy::Extract)
+            .extract_addresses(true)
+            .extract_dates(true)
+            .collapse_duplicates(true)
+            .inline_images(markup::InlineImages::Drop)
+            .include_parts(true);
+        assert_eq!(options.label.as_deref(), Some("Work"));
+        assert_eq!(options.body_format, markup::BodyFormat::Markdown);
+        assert_eq!(options.url_policy, Some(reademail::UrlPolicy::Extract));
+        assert!(options.include_headers);
+        assert!(options.strip_quoted);
+        assert!(options.extract_addresses);
+        assert!(options.extract_dates);
+        assert!(options.collapse_duplicates);
+        assert_eq!(options.inline_images, markup::InlineImages::Drop);
+        assert!(options.include_parts);
+        assert_eq!(options.format, FetchFormat::Raw);
+        assert_eq!(options.truncate_body_at, Some(200));
+        assert_eq!(options.headers_only(true).format, FetchFormat::Metadata);
+    }
+
+    #[test]
+    fn test_resolve_query() {
+        assert_eq!(resolve_query(None), DEFAULT_QUERY);
+        assert_eq!(resolve_query(Some("   ".to_string())), DEFAULT_QUERY);
+        assert_eq!(
+            resolve_query(Some(" from:boss@corp.com is:unread ".to_string())),
+            "from:boss@corp.com is:unread"
+        );
+    }
+
+    fn header(name: &str, value: &str) -> MessagePartHeader {
+        MessagePartHeader {
+            name: Some(name.to_string()),
+            value: Some(value.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_summarize_message() {
+        let msg = Message {
+            id: Some("abc".to_string()),
+            thread_id: Some("t-abc".to_string()),
+            snippet: Some("Hello".to_string()),
+            label_ids: Some(vec!["INBOX".to_string(), "STARRED".to_string()]),
+            payload: Some(MessagePart {
+                headers: Some(vec![
+                    header("subject", "Quarterly report"),
+                    header("From", "Boss <boss@corp.com>"),
+                    header("Date", "Wed, 1 May 2024 09:30:00 -0700 (PDT)"),
+                    header("To", "\"Doe, Jane\" <jane@corp.com>, bob@corp.com"),
+                    header("Cc", "carol@corp.com"),
+                    header("cc", "dave@corp.com"),
+                    header("Reply-To", "assistant@corp.com"),
+                    header("Message-Id", " <report-1@corp.com>"),
+                ]),
+                ..Default::default()
+            }),
+            internal_date: Some(1_714_581_000_000),
+            size_estimate: Some(5120),
+            ..Default::default()
+        };
+
+        let summary = summarize_message(&msg).unwrap();
+        assert_eq!(summary.id, "abc");
+        assert_eq!(summary.thread_id, "t-abc");
+        assert_eq!(summary.subject, "Quarterly report");
+        assert_eq!(summary.from, "Boss <boss@corp.com>");
+        assert_eq!(
+            summary.sender,
+            Some(compose::Mailbox {
+                name: Some("Boss".to_string()),
+                address: "boss@corp.com".to_string(),
+            })
+        );
+        assert_eq!(summary.snippet, "Hello");
+        assert_eq!(summary.size_estimate, 5120);
+        assert_eq!(summary.label_ids, vec!["INBOX", "STARRED"]);
+        assert!(!summary.is_unread);
+        assert!(summary.is_starred);
+        assert!(!summary.is_important);
+        assert_eq!(summary.category, None);
+        assert_eq!(summary.date.as_deref(), Some("2024-05-01T09:30:00-07:00"));
+        assert_eq!(
+            summary.to,
+            vec!["\"Doe, Jane\" <jane@corp.com>", "bob@corp.com"]
+        );
+        assert_eq!(summary.cc, vec!["carol@corp.com", "dave@corp.com"]);
+        assert!(summary.bcc.is_empty());
+        assert_eq!(summary.reply_to, vec!["assistant@corp.com"]);
+        assert_eq!(summary.message_id.as_deref(), Some("<report-1@corp.com>"));
+        assert_eq!(
+            summary.internal_date.as_deref(),
+            Some("2024-05-01T16:30:00Z")
+        );
+
+        assert!(summarize_message(&Message::default()).is_none());
+    }
+
+    #[test]
+    fn test_summarize_message_without_payload() {
+        let minimal = Message {
+            id: Some("abc".to_string()),
+            snippet: Some("Hello".to_string()),
+            ..Default::default()
+        };
+        let summary = summarize_message(&minimal).unwrap();
+        assert_eq!(summary.subject, "No Subject");
+        assert_eq!(summary.from, "Unknown Sender");
+        assert_eq!(summary.sender, None);
+        assert_eq!(summary.snippet, "Hello");
+        assert!(summary.body_raw.is_empty());
+        assert_eq!(summary.date, None);
+
+        let raw = Message {
+            raw: Some(b"From: boss@corp.com\r\nSubject: Report\r\n\r\nSee attached.".to_vec()),
+            ..minimal
+        };
+        let summary = summarize_message(&raw).unwrap();
+        assert_eq!(summary.subject, "Report");
+        assert_eq!(summary.from, "boss@corp.com");
+        assert_eq!(summary.body_raw, "See attached.");
+        assert_eq!(summary.body_text.as_deref(), Some("See attached."));
+        assert_eq!(summary.body_html, None);
+    }
+
+    #[test]
+    fn test_summarize_message_decodes_headers() {
+        let raw = Message {
+            id: Some("abc".to_string()),
+            raw: Some(
+                b"From: =?UTF-8?Q?Jos=C3=A9_P=C3=A9rez?= <jose@example.com>\r\n\
+                To: =?UTF-8?B?RG9lLCBKYW5l?= <jane@example.com>, bob@example.com\r\n\
+                Subject: =?UTF-8?B?8J+Ygg==?= Launch\r\n\r\nHi"
+                    .to_vec(),
+            ),
+            ..Default::default()
+        };
+        let summary = summarize_message(&raw).unwrap();
+        assert_eq!(summary.subject, "\u{1F602} Launch");
+        assert_eq!(summary.from, "José Pérez <jose@example.com>");
+        assert_eq!(summary.sender.unwrap().name.as_deref(), Some("José Pérez"));
+        assert_eq!(
+            summary.to,
+            vec!["\"Doe, Jane\" <jane@example.com>", "bob@example.com"]
+        );
+    }
+
+    #[test]
+    fn test_clean_snippet() {
+        assert_eq!(
+            clean_snippet("Tom &amp; Jerry&#39;s   offer&nbsp;ends \u{200c}\u{034f} today &lt;3"),
+            "Tom & Jerry's offer ends today <3"
+        );
+        assert_eq!(
+            clean_snippet("AT&T &unknown; deals"),
+            "AT&T &unknown; deals"
+        );
+    }
+
+    #[test]
+    fn test_apply_labels_category() {
+        let mut summary = EmailSummary::default();
+        let labels = ["INBOX", "CATEGORY_PROMOTIONS", "UNREAD"].map(String::from);
+        apply_labels(&mut summary, &labels);
+        assert_eq!(summary.category, Some(Category::Promotions));
+        assert!(summary.is_unread);
+        assert_eq!(
+            serde_json::to_value(summary.category).unwrap(),
+            serde_json::json!("promotions")
+        );
+        apply_labels(&mut summary, &["CATEGORY_PERSONAL".to_string()]);
+        assert_eq!(summary.category, Some(Category::Primary));
+        apply_labels(&mut summary, &[]);
+        assert_eq!(summary.category, None);
+    }
+
+    #[test]
+    fn test_split_bodies() {
+        assert_eq!(split_bodies("", None), (None, None));
+        assert_eq!(
+            split_bodies("Hi", Some("<p>Hi</p>".to_string())),
+            (Some("Hi".to_string()), Some("<p>Hi</p>".to_string()))
+        );
+        let (text, html) = split_bodies("<p>Only <b>HTML</b></p>", None);
+        assert_eq!(html.as_deref(), Some("<p>Only <b>HTML</b></p>"));
+        assert!(text.unwrap().contains("Only"));
+        let (text, html) = split_bodies("", Some("<p>Alternative</p>".to_string()));
+        assert!(text.unwrap().contains("Alternative"));
+        assert!(html.is_some());
+    }
+
+    #[test]
+    fn test_fetch_format_from_str() {
+        assert_eq!("".parse::<FetchFormat>(), Ok(FetchFormat::Full));
+        assert_eq!("Metadata".parse::<FetchFormat>(), Ok(FetchFormat::Metadata));
+        assert_eq!("raw".parse::<FetchFormat>(), Ok(FetchFormat::Raw));
+        assert!("headers".parse::<FetchFormat>().is_err());
+        assert_eq!(FetchFormat::Metadata.api_format(), "full");
+        assert_eq!(FetchFormat::Minimal.api_format(), "minimal");
+        assert!(FetchFormat::Metadata
+            .fields()
+            .unwrap()
+            .contains("attachmentId"));
+        assert_eq!(FetchFormat::Full.fields(), None);
+    }
+
+    #[test]
+    fn test_collect_attachments_walks_nested_parts() {
+        let payload = MessagePart {
+            mime_type: Some("multipart/mixed".to_string()),
+            filename: Some(String::new()),
+            parts: Some(vec![
+                MessagePart {
+                    mime_type: Some("text/plain".to_string()),
+                    filename: Some(String::new()),
+                    ..Default::default()
+                },
+                MessagePart {
+                    mime_type: Some("multipart/related".to_string()),
+                    parts: Some(vec![MessagePart {
+                        mime_type: Some("application/pdf".to_string()),
+                        filename: Some("invoice.pdf".to_string()),
+                        body: Some(gmail1::api::MessagePartBody {
+                            attachment_id: Some("att-1".to_string()),
+                            size: Some(2048),
+                            data: None,
+                        }),
+                        ..Default::default()
+                    }]),
+                    ..Default::default()
+                },
+            ]),
+            ..Default::default()
+        };
+
+        let mut attachments = Vec::new();
+        collect_attachments(std::slice::from_ref(&payload), &mut attachments);
+        assert_eq!(
+            attachments,
+            vec![AttachmentInfo {
+                filename: "invoice.pdf".to_string(),
+                mime_type: "application/pdf".to_string(),
+                size: 2048,
+                attachment_id: Some("att-1".to_string()),
+                content_id: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_collect_attachments_keeps_inline_parts() {
+        let inline = |mime_type: &str, headers: Vec<MessagePartHeader>| MessagePart {
+            mime_type: Some(mime_type.to_string()),
+            filename: Some(String::new()),
+            headers: Some(headers),
+            body: Some(gmail1::api::MessagePartBody {
+                attachment_id: Some("att-1".to_string()),
+                size: Some(512),
+                data: None,
+            }),
+            ..Default::default()
+        };
+        let parts = vec![
+            inline(
+                "text/html",
+                vec![header("Content-ID", "<body@example.com>")],
+            ),
+            inline(
+                "image/png",
+                vec![
+                    header("Content-Type", "image/png; name=\"logo.png\""),
+                    header("Content-ID", "<logo@example.com>"),
+                ],
+            ),
+            inline("image/gif", vec![header("Content-ID", "<dot@example.com>")]),
+        ];
+
+        let mut attachments = Vec::new();
+        collect_attachments(&parts, &mut attachments);
+        let found: Vec<(&str, Option<&str>)> = attachments
+            .iter()
+            .map(|a| (a.filename.as_str(), a.content_id.as_deref()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("logo.png", Some("logo@example.com")),
+                ("", Some("dot@example.com")),
+            ]
+        );
+    }
 }
@@ -0,0 +1,221 @@
+//! Server-wide caps on tool calls and Gmail API requests.
+//!
+//! `GMAIL_MAX_CONCURRENT_CALLS` bounds the tool calls running at once across
+//! all clients; further calls wait for a free slot. `GMAIL_MAX_REQUESTS_PER_SECOND`
+//! paces Gmail API requests, allowing bursts of up to one second's worth. Both
+//! are off unless set. `GMAIL_FETCH_CONCURRENCY` bounds the requests a single
+//! fetch keeps in flight at once, [`DEFAULT_FETCH_CONCURRENCY`] unless set.
+//!
+//! Gmail asks the authenticator for a token once per API request, so requests
+//! are paced by wrapping the hub's authenticator in [`Throttled`]. Each part of
+//! a batch request counts against the quota, so batches reserve extra slots
+//! with [`throttle`]. The same wrapper applies the daily quota budget, if any
+//! (see [`crate::quota`]).
+
+use google_gmail1::common::GetToken;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+use tracing::info;
+
+/// Environment variable with the most tool calls handled at once.
+pub const MAX_CALLS_ENV: &str = "GMAIL_MAX_CONCURRENT_CALLS";
+/// Environment variable with the most Gmail API requests sent per second.
+pub const MAX_RATE_ENV: &str = "GMAIL_MAX_REQUESTS_PER_SECOND";
+/// Environment variable with the most requests one fetch sends at once.
+pub const FETCH_CONCURRENCY_ENV: &str = "GMAIL_FETCH_CONCURRENCY";
+/// Requests one fetch sends at once when [`FETCH_CONCURRENCY_ENV`] is not set.
+pub const DEFAULT_FETCH_CONCURRENCY: usize = 8;
+
+/// Configured limits; `None` means unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LimitsConfig {
+    pub max_concurrent_calls: Option<usize>,
+    pub max_requests_per_second: Option<u32>,
+    /// Requests one fetch sends at once; `None` means [`DEFAULT_FETCH_CONCURRENCY`].
+    pub fetch_concurrency: Option<usize>,
+}
+
+impl LimitsConfig {
+    /// Read the limits from the environment.
+    pub fn from_env() -> Result<Self, String> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// Read the limits through `var`, failing on values that are not positive integers.
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        fn positive<T: std::str::FromStr + Default + PartialEq>(
+            name: &str,
+            value: Option<String>,
+        ) -> Result<Option<T>, String> {
+            let Some(value) = value.filter(|v| !v.trim().is_empty()) else {
+                return Ok(None);
+            };
+            match value.trim().parse::<T>() {
+                Ok(n) if n != T::default() => Ok(Some(n)),
+                _ => Err(format!(
+                    "Invalid {} '{}'; expected a positive integer",
+                    name, value
+                )),
+            }
+        }
+        Ok(Self {
+            max_concurrent_calls: positive(MAX_CALLS_ENV, var(MAX_CALLS_ENV))?,
+            max_requests_per_second: positive(MAX_RATE_ENV, var(MAX_RATE_ENV))?,
+            fetch_concurrency: positive(FETCH_CONCURRENCY_ENV, var(FETCH_CONCURRENCY_ENV))?,
+        })
+    }
+}
+
+/// Paces requests with the generic cell rate algorithm.
+struct RateLimiter {
+    /// Time between requests at the sustained rate.
+    interval: Duration,
+    /// How far ahead of the sustained rate a burst may run.
+    burst: Duration,
+    /// When the next request would be due at the sustained rate.
+    due: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    fn new(per_second: u32) -> Self {
+        let interval = Duration::from_secs(1) / per_second;
+        Self {
+            interval,
+            burst: interval * (per_second - 1),
+            due: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Reserve `requests` slots, returning when the last of them may be sent.
+    fn reserve(&self, due: &mut Instant, now: Instant, requests: u32) -> Instant {
+        let start = (*due).max(now);
+        let last = start + self.interval * requests.saturating_sub(1);
+        *due = start + self.interval * requests;
+        last.checked_sub(self.burst).unwrap_or(now).max(now)
+    }
+
+    async fn acquire(&self, requests: u32) {
+        let ready = {
+            let mut due = self.due.lock().await;
+            self.reserve(&mut due, Instant::now(), requests)
+        };
+        tokio::time::sleep_until(ready).await;
+    }
+}
+
+struct Limits {
+    calls: Option<Arc<Semaphore>>,
+    rate: Option<RateLimiter>,
+    fetch_concurrency: usize,
+}
+
+static LIMITS: OnceLock<Limits> = OnceLock::new();
+
+/// Apply `config` to this process; later calls are ignored.
+pub fn install(config: LimitsConfig) {
+    info!("Server limits: {:?}", config);
+    let _ = LIMITS.set(Limits {
+        calls: config
+            .max_concurrent_calls
+            .map(|n| Arc::new(Semaphore::new(n))),
+        rate: config.max_requests_per_second.map(RateLimiter::new),
+        fetch_concurrency: config
+            .fetch_concurrency
+            .unwrap_or(DEFAULT_FETCH_CONCURRENCY),
+    });
+}
+
+/// Wait for a tool call slot; the call may run while the permit is held.
+pub async fn call_permit() -> Option<OwnedSemaphorePermit> {
+    let calls = LIMITS.get()?.calls.clone()?;
+    if calls.available_permits() == 0 {
+        info!("Server limits: Waiting for a free tool call slot");
+    }
+    calls.acquire_owned().await.ok()
+}
+
+/// How many requests one fetch may have in flight at once.
+pub fn fetch_concurrency() -> usize {
+    LIMITS
+        .get()
+        .map_or(DEFAULT_FETCH_CONCURRENCY, |limits| limits.fetch_concurrency)
+}
+
+/// Wait until `requests` more Gmail API requests may be sent.
+pub async fn throttle(requests: u32) {
+    if let Some(rate) = LIMITS.get().and_then(|limits| limits.rate.as_ref()) {
+        rate.acquire(requests).await;
+    }
+}
+
+/// An authenticator whose every token request waits for a request slot and
+/// for the daily quota budget.
+#[derive(Clone)]
+pub struct Throttled<A>(pub A);
+
+impl<A: GetToken + Clone + 'static> GetToken for Throttled<A> {
+    #[allow(clippy::type_complexity)]
+    fn get_token<'a>(
+        &'a self,
+        scopes: &'a [&str],
+    ) -> Pin<
+        Box<
+            dyn Future<Output = Result<Option<String>, Box<dyn std::error::Error + Send + Sync>>>
+                + Send
+                + 'a,
+        >,
+    > {
+        Box::pin(async move {
+            throttle(1).await;
+            crate::quota::pace().await;
+            self.0.get_token(scopes).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limits_from_vars() {
+        let config = LimitsConfig::from_vars(|name| match name {
+            MAX_CALLS_ENV => Some("4".to_string()),
+            FETCH_CONCURRENCY_ENV => Some("16".to_string()),
+            _ => None,
+        })
+        .unwrap();
+        assert_eq!(config.max_concurrent_calls, Some(4));
+        assert_eq!(config.max_requests_per_second, None);
+        assert_eq!(config.fetch_concurrency, Some(16));
+        assert!(LimitsConfig::from_vars(|_| Some("0".to_string())).is_err());
+        assert!(LimitsConfig::from_vars(|_| Some("fast".to_string())).is_err());
+        assert_eq!(
+            LimitsConfig::from_vars(|_| Some(" ".to_string())).unwrap(),
+            LimitsConfig::default()
+        );
+    }
+
+    #[test]
+    fn test_rate_limiter_allows_one_second_burst() {
+        let limiter = RateLimiter::new(4);
+        let now = Instant::now();
+        let mut due = now;
+        for _ in 0..4 {
+            assert_eq!(limiter.reserve(&mut due, now, 1), now);
+        }
+        assert_eq!(
+            limiter.reserve(&mut due, now, 1),
+            now + Duration::from_millis(250)
+        );
+        // A batch waits until its last part fits
+        assert_eq!(
+            limiter.reserve(&mut due, now, 3),
+            now + Duration::from_millis(1000)
+        );
+    }
+}
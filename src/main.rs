@@ -1,61 +1,1397 @@
 use anyhow::Result;
-use gmail_mcp_server::reademail::read_emails;
+use gmail_mcp_server::actions::Action;
+use gmail_mcp_server::auth::{manage_auth, AuthAction, AuthFlow};
+use gmail_mcp_server::cache::{self, CacheConfig};
+use gmail_mcp_server::capabilities::{Capabilities, Capability};
+use gmail_mcp_server::compose::{load_attachments, AttachmentInput};
+use gmail_mcp_server::drafts::{manage_drafts, DraftAction, DraftChanges};
+use gmail_mcp_server::events::{self, collect_events};
+use gmail_mcp_server::filters::{SearchFilters, ALL_MAIL_QUERY};
+use gmail_mcp_server::history::read_changes;
+use gmail_mcp_server::import::{import_email, ImportOptions};
+use gmail_mcp_server::labels::{manage_labels, LabelAction};
+use gmail_mcp_server::limits::{self, LimitsConfig};
+use gmail_mcp_server::markup::BodyFormat;
+use gmail_mcp_server::modify::{apply_labels, archive, mark, remove};
+use gmail_mcp_server::poll::{self, PollConfig};
+use gmail_mcp_server::progress;
+use gmail_mcp_server::prompts;
+use gmail_mcp_server::quota::{self, quota_report, QuotaConfig};
+use gmail_mcp_server::reademail::{get_email, get_raw_email, read_emails, UrlPolicy};
+use gmail_mcp_server::retry::{self, RetryConfig};
+#[cfg(feature = "search")]
+use gmail_mcp_server::search_index::{self, local_search};
+use gmail_mcp_server::send::{forward_email, reply_to, send_email};
+use gmail_mcp_server::server::ServerBuilder;
+use gmail_mcp_server::settings::{
+    manage_delegates, manage_filters, manage_signatures, DelegateAction, FilterActionInfo,
+    FilterCriteriaInfo, FilterToolAction, SignatureAction,
+};
+use gmail_mcp_server::shutdown;
+use gmail_mcp_server::sync::{self, SyncConfig};
+use gmail_mcp_server::threads::fetch_threads;
+use gmail_mcp_server::timeouts::{self, TimeoutConfig};
+use gmail_mcp_server::tls::{self, TlsConfig};
+use gmail_mcp_server::transport::{
+    auth_token, check_exposure, host, SseTransport, StreamableHttpTransport, TransportKind,
+    HTTP_PATH, PORT, SSE_PATH,
+};
+use gmail_mcp_server::unsubscribe::unsubscribe_candidates;
+use gmail_mcp_server::watch::{self, WatchConfig};
+use gmail_mcp_server::web_auth::{self, WebAuthConfig};
+use gmail_mcp_server::{email_response_schema, FetchOptions};
 use mcp_core::{
     server::Server,
     tool_text_content,
-    transport::ServerSseTransport,
-    types::{ServerCapabilities, ToolCapabilities, ToolResponseContent},
+    transport::ServerStdioTransport,
+    types::{ProtocolVersion, ToolResponseContent},
 };
 use mcp_core_macros::{tool, tool_param};
-use tracing::info;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tracing::{error, info, warn};
+
+/// Wrapper that inlines a parameter's schema, since tool schemas are sent
+/// without the `definitions` a `$ref` would point to.
+#[derive(Debug, Deserialize)]
+#[serde(transparent)]
+struct Inline<T>(T);
+
+impl<T: JsonSchema> JsonSchema for Inline<T> {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        T::schema_name()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        T::json_schema(gen)
+    }
+}
+
+/// Unwrap a list of inlined attachment parameters.
+fn attachment_inputs(attachments: Option<Vec<Inline<AttachmentInput>>>) -> Vec<AttachmentInput> {
+    attachments
+        .unwrap_or_default()
+        .into_iter()
+        .map(|a| a.0)
+        .collect()
+}
+
+/// The options shaping the output of `gmail_list` and `gmail_search`, as the
+/// tools received them.
+struct OutputParams {
+    group_by_thread: Option<bool>,
+    headers_only: Option<bool>,
+    format: Option<String>,
+    include_headers: Option<bool>,
+    strip_quoted: Option<bool>,
+    body_format: Option<String>,
+    url_policy: Option<String>,
+    extract_addresses: Option<bool>,
+    extract_dates: Option<bool>,
+    collapse_duplicates: Option<bool>,
+    max_body_chars: Option<u32>,
+    inline_images: Option<String>,
+    include_parts: Option<bool>,
+}
+
+impl OutputParams {
+    /// Set the options on `options`, defaulting the ones the client left out.
+    fn apply(self, options: FetchOptions) -> Result<FetchOptions, Box<dyn std::error::Error>> {
+        Ok(options
+            .group_by_thread(self.group_by_thread.unwrap_or(false))
+            .format(self.format.as_deref().unwrap_or_default().parse()?)
+            .headers_only(self.headers_only.unwrap_or(false))
+            .include_headers(self.include_headers.unwrap_or(false))
+            .strip_quoted(self.strip_quoted.unwrap_or(false))
+            .body_format(self.body_format.as_deref().unwrap_or_default().parse()?)
+            .url_policy(
+                self.url_policy
+                    .as_deref()
+                    .map(str::parse::<UrlPolicy>)
+                    .transpose()?,
+            )
+            .extract_addresses(self.extract_addresses.unwrap_or(false))
+            .extract_dates(self.extract_dates.unwrap_or(false))
+            .collapse_duplicates(self.collapse_duplicates.unwrap_or(false))
+            .truncate_body_at(
+                self.max_body_chars
+                    .filter(|&chars| chars > 0)
+                    .map(|chars| chars as usize),
+            )
+            .inline_images(self.inline_images.as_deref().unwrap_or_default().parse()?)
+            .include_parts(self.include_parts.unwrap_or(false)))
+    }
+}
 
 #[tool(
-    name = "gmail_reader",
-    description = "Read Gmail emails with automatic authentication."
+    name = "gmail_list",
+    description = "List recent emails in the inbox, or under a label, newest first. Use gmail_search to find emails matching a query."
 )]
-async fn gmail(
-    action: tool_param!(String, description = "Action to perform on emails"),
-    max_results: tool_param!(Option<u32>, description = "Max emails to fetch (1-500)"),
+#[allow(clippy::too_many_arguments)]
+async fn gmail_list(
+    max_results: tool_param!(
+        Option<u32>,
+        description = "Max emails to fetch (1-500, default: 10)"
+    ),
+    label: tool_param!(
+        Option<String>,
+        description = "List this label instead of the inbox, by name or ID, e.g. 'Work/Invoices'"
+    ),
+    page_token: tool_param!(
+        Option<String>,
+        description = "next_page_token from a previous call, to continue where it stopped"
+    ),
+    group_by_thread: tool_param!(
+        Option<bool>,
+        description = "Group emails by conversation, oldest message first (default: false)"
+    ),
+    unread_only: tool_param!(
+        Option<bool>,
+        description = "Only fetch unread emails (default: false)"
+    ),
+    since_last_check: tool_param!(
+        Option<bool>,
+        description = "With unread_only, only return mail received since the previous unread check (default: false)"
+    ),
+    headers_only: tool_param!(
+        Option<bool>,
+        description = "Fetch only the headers, snippet, and attachment list, skipping bodies, for a quick overview (default: false)"
+    ),
+    format: tool_param!(
+        Option<String>,
+        description = "How much of each email to download: 'full' (default), 'metadata' (headers, snippet, and attachments, same as headers_only), 'minimal' (id, labels, and snippet), or 'raw' (RFC 822 source, parsed locally)"
+    ),
+    include_headers: tool_param!(
+        Option<bool>,
+        description = "Attach every header of each email under 'headers', e.g. Received or Authentication-Results, for deliverability or routing analysis (default: false)"
+    ),
+    strip_quoted: tool_param!(
+        Option<bool>,
+        description = "Drop quoted earlier messages ('> ' lines, 'On ... wrote:' and Outlook 'Original Message' blocks) from each body, keeping only the new content (default: false)"
+    ),
+    body_format: tool_param!(
+        Option<String>,
+        description = "How to return HTML bodies: 'text' (default, flattened plain text without URLs), 'markdown' (keeping headings, lists, tables, emphasis, and links), or 'html' (as sent)"
+    ),
+    url_policy: tool_param!(
+        Option<String>,
+        description = "What to do with URLs in text and markdown bodies: 'keep', 'remove' (default for text), 'placeholder' (replace each with [link: domain]), 'extract' (move them to a 'links' list), or 'clean' (keep them without tracking parameters and click-tracking redirects); markdown keeps them by default"
+    ),
+    extract_addresses: tool_param!(
+        Option<bool>,
+        description = "List the email addresses mentioned in each body under 'addresses_mentioned', e.g. for contact graphs or routing (default: false)"
+    ),
+    extract_dates: tool_param!(
+        Option<bool>,
+        description = "List the dates, times, and deadlines mentioned in each body (e.g. 'by Friday', 'May 17 at 3pm') under 'dates_mentioned', resolved to YYYY-MM-DD and HH:MM, for creating calendar events or reminders (default: false)"
+    ),
+    collapse_duplicates: tool_param!(
+        Option<bool>,
+        description = "Drop emails repeating an earlier one in the results (same Message-ID, or same subject and body, e.g. mail sent to several aliases) instead of marking them with 'duplicate_of' (default: false)"
+    ),
+    max_body_chars: tool_param!(
+        Option<u32>,
+        description = "Cut each processed body to at most this many characters, marker included, at a word boundary (HTML only between tags), ending it with a '[truncated, N more characters]' marker; keeps large fetches within context (default: no limit)"
+    ),
+    inline_images: tool_param!(
+        Option<String>,
+        description = "What to do with inline images (cid: references to parts of the email) in text and markdown bodies: 'marker' (default, replace each with [image: filename]) or 'drop'"
+    ),
+    include_parts: tool_param!(
+        Option<bool>,
+        description = "Also return the plain-text part as 'body_text' and, with body_format 'html', the HTML part as 'body_html', next to the processed 'body_raw' (default: false)"
+    ),
 ) -> Result<ToolResponseContent, Box<dyn std::error::Error>> {
     let max_results = max_results.unwrap_or(10);
+    info!(
+        "Gmail list tool called with max_results: {}, label: {:?}",
+        max_results, label
+    );
 
+    let options = FetchOptions::new()
+        .max_results(max_results)
+        .label(label)
+        .page_token(page_token)
+        .unread_only(unread_only.unwrap_or(false))
+        .since_last_check(since_last_check.unwrap_or(false));
+    let options = OutputParams {
+        group_by_thread,
+        headers_only,
+        format,
+        include_headers,
+        strip_quoted,
+        body_format,
+        url_policy,
+        extract_addresses,
+        extract_dates,
+        collapse_duplicates,
+        max_body_chars,
+        inline_images,
+        include_parts,
+    }
+    .apply(options)?;
+    match read_emails(options).await {
+        Ok(emails) => Ok(tool_text_content!(emails)),
+        Err(e) => {
+            info!("Error listing emails: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tool(
+    name = "gmail_search",
+    description = "Search all mail and return the matching emails, newest first. Use the structured fields (from, to, subject_contains, has_attachment, newer_than_days, label) instead of Gmail query syntax; they combine with each other and with an optional query."
+)]
+#[allow(clippy::too_many_arguments)]
+async fn gmail_search(
+    query: tool_param!(
+        Option<String>,
+        description = "Gmail search query, e.g. 'from:boss@corp.com is:unread newer_than:7d'"
+    ),
+    from: tool_param!(
+        Option<String>,
+        description = "Only mail from this sender address or name"
+    ),
+    to: tool_param!(
+        Option<String>,
+        description = "Only mail to this recipient address or name"
+    ),
+    subject_contains: tool_param!(
+        Option<String>,
+        description = "Only mail whose subject contains these words"
+    ),
+    has_attachment: tool_param!(
+        Option<bool>,
+        description = "true for only mail with attachments, false for only mail without"
+    ),
+    newer_than_days: tool_param!(
+        Option<u32>,
+        description = "Only mail received in the last this many days"
+    ),
+    max_results: tool_param!(
+        Option<u32>,
+        description = "Max emails to fetch (1-500, default: 10)"
+    ),
+    label: tool_param!(
+        Option<String>,
+        description = "Only match emails with this label name or ID"
+    ),
+    after: tool_param!(
+        Option<String>,
+        description = "Only mail received after this time (RFC3339, e.g. 2024-05-01T00:00:00Z, or epoch seconds)"
+    ),
+    before: tool_param!(
+        Option<String>,
+        description = "Only mail received before this time (RFC3339 or epoch seconds)"
+    ),
+    page_token: tool_param!(
+        Option<String>,
+        description = "next_page_token from a previous call, to continue where it stopped"
+    ),
+    group_by_thread: tool_param!(
+        Option<bool>,
+        description = "Group emails by conversation, oldest message first (default: false)"
+    ),
+    headers_only: tool_param!(
+        Option<bool>,
+        description = "Fetch only the headers, snippet, and attachment list, skipping bodies, for a quick overview (default: false)"
+    ),
+    format: tool_param!(
+        Option<String>,
+        description = "How much of each email to download: 'full' (default), 'metadata' (headers, snippet, and attachments, same as headers_only), 'minimal' (id, labels, and snippet), or 'raw' (RFC 822 source, parsed locally)"
+    ),
+    include_headers: tool_param!(
+        Option<bool>,
+        description = "Attach every header of each email under 'headers', e.g. Received or Authentication-Results, for deliverability or routing analysis (default: false)"
+    ),
+    strip_quoted: tool_param!(
+        Option<bool>,
+        description = "Drop quoted earlier messages ('> ' lines, 'On ... wrote:' and Outlook 'Original Message' blocks) from each body, keeping only the new content (default: false)"
+    ),
+    body_format: tool_param!(
+        Option<String>,
+        description = "How to return HTML bodies: 'text' (default, flattened plain text without URLs), 'markdown' (keeping headings, lists, tables, emphasis, and links), or 'html' (as sent)"
+    ),
+    url_policy: tool_param!(
+        Option<String>,
+        description = "What to do with URLs in text and markdown bodies: 'keep', 'remove' (default for text), 'placeholder' (replace each with [link: domain]), 'extract' (move them to a 'links' list), or 'clean' (keep them without tracking parameters and click-tracking redirects); markdown keeps them by default"
+    ),
+    extract_addresses: tool_param!(
+        Option<bool>,
+        description = "List the email addresses mentioned in each body under 'addresses_mentioned', e.g. for contact graphs or routing (default: false)"
+    ),
+    extract_dates: tool_param!(
+        Option<bool>,
+        description = "List the dates, times, and deadlines mentioned in each body (e.g. 'by Friday', 'May 17 at 3pm') under 'dates_mentioned', resolved to YYYY-MM-DD and HH:MM, for creating calendar events or reminders (default: false)"
+    ),
+    collapse_duplicates: tool_param!(
+        Option<bool>,
+        description = "Drop emails repeating an earlier one in the results (same Message-ID, or same subject and body, e.g. mail sent to several aliases) instead of marking them with 'duplicate_of' (default: false)"
+    ),
+    max_body_chars: tool_param!(
+        Option<u32>,
+        description = "Cut each processed body to at most this many characters, marker included, at a word boundary (HTML only between tags), ending it with a '[truncated, N more characters]' marker; keeps large fetches within context (default: no limit)"
+    ),
+    inline_images: tool_param!(
+        Option<String>,
+        description = "What to do with inline images (cid: references to parts of the email) in text and markdown bodies: 'marker' (default, replace each with [image: filename]) or 'drop'"
+    ),
+    include_parts: tool_param!(
+        Option<bool>,
+        description = "Also return the plain-text part as 'body_text' and, with body_format 'html', the HTML part as 'body_html', next to the processed 'body_raw' (default: false)"
+    ),
+) -> Result<ToolResponseContent, Box<dyn std::error::Error>> {
+    let max_results = max_results.unwrap_or(10);
+    let filters = SearchFilters {
+        from,
+        to,
+        subject_contains,
+        has_attachment,
+        newer_than_days,
+    };
+    let query = search_query(
+        query,
+        &filters,
+        label.is_some() || after.is_some() || before.is_some(),
+    )?;
     info!(
-        "Gmail tool called with action: '{}', max_results: {}",
-        action, max_results
+        "Gmail search tool called with query: '{}', max_results: {}",
+        query, max_results
     );
 
-    match read_emails(max_results).await {
+    let options = FetchOptions::new()
+        .query(query)
+        .max_results(max_results)
+        .label(label)
+        .after(after)
+        .before(before)
+        .page_token(page_token);
+    let options = OutputParams {
+        group_by_thread,
+        headers_only,
+        format,
+        include_headers,
+        strip_quoted,
+        body_format,
+        url_policy,
+        extract_addresses,
+        extract_dates,
+        collapse_duplicates,
+        max_body_chars,
+        inline_images,
+        include_parts,
+    }
+    .apply(options)?;
+    match read_emails(options).await {
         Ok(emails) => Ok(tool_text_content!(emails)),
         Err(e) => {
-            info!("Error fetching emails: {}", e);
-            Err(e)
+            info!("Error searching emails: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+/// Combine a search's free-form query with its structured filters.
+///
+/// Fails when nothing narrows the search; `other_criteria` tells whether a
+/// label or date bound does. Without a query the search covers all mail.
+fn search_query(
+    query: Option<String>,
+    filters: &SearchFilters,
+    other_criteria: bool,
+) -> Result<String, String> {
+    let query = query.unwrap_or_default().trim().to_string();
+    let terms = filters.to_query()?;
+    match (query.is_empty(), terms.is_empty()) {
+        (true, true) if !other_criteria => Err(
+            "gmail_search needs a query or at least one filter; use gmail_list to list recent mail"
+                .to_string(),
+        ),
+        (true, true) => Ok(ALL_MAIL_QUERY.to_string()),
+        (true, false) => Ok(terms),
+        (false, true) => Ok(query),
+        (false, false) => Ok(format!("{} {}", query, terms)),
+    }
+}
+
+#[cfg(feature = "search")]
+#[tool(
+    name = "gmail_local_search",
+    description = "Search the emails downloaded so far in the local full-text index, without contacting Gmail, so it also works offline. Only finds mail fetched by earlier calls."
+)]
+async fn gmail_local_search(
+    query: tool_param!(
+        String,
+        description = "Words to match in the sender, subject, or body; 'from:', 'subject:', and 'body:' restrict a term to one field, quotes match a phrase, and AND, OR, NOT combine terms, e.g. 'from:alice invoice' or 'date:[2024-05-01T00:00:00Z TO *]'"
+    ),
+    max_results: tool_param!(
+        Option<u32>,
+        description = "Max emails to return, most relevant first (1-500, default: 10)"
+    ),
+) -> Result<ToolResponseContent, Box<dyn std::error::Error>> {
+    let max_results = max_results.unwrap_or(10);
+    info!(
+        "Gmail local search tool called with query: '{}', max_results: {}",
+        query, max_results
+    );
+
+    match local_search(&query, max_results).await {
+        Ok(emails) => Ok(tool_text_content!(emails)),
+        Err(e) => {
+            info!("Error searching the local index: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tool(
+    name = "gmail_get",
+    description = "Read one email by ID with its full body, or its original RFC 822 source."
+)]
+async fn gmail_get(
+    id: tool_param!(String, description = "Email ID"),
+    raw: tool_param!(
+        Option<bool>,
+        description =
+            "Return the original RFC 822 source instead of the parsed email (default: false)"
+    ),
+) -> Result<ToolResponseContent, Box<dyn std::error::Error>> {
+    info!("Gmail get tool called for email {}", id);
+
+    let result = if raw.unwrap_or(false) {
+        get_raw_email(&id).await
+    } else {
+        get_email(&id).await
+    };
+    match result {
+        Ok(email) => Ok(tool_text_content!(email)),
+        Err(e) => {
+            info!("Error reading email: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tool(
+    name = "gmail_threads",
+    description = "Return whole conversations, each with all of its messages, oldest first."
+)]
+async fn gmail_threads(
+    query: tool_param!(
+        Option<String>,
+        description = "Gmail search query selecting the threads (default: in:inbox)"
+    ),
+    max_results: tool_param!(
+        Option<u32>,
+        description = "Max threads to fetch (1-500, default: 10)"
+    ),
+) -> Result<ToolResponseContent, Box<dyn std::error::Error>> {
+    let max_results = max_results.unwrap_or(10);
+    info!(
+        "Gmail threads tool called with query: {:?}, max_results: {}",
+        query, max_results
+    );
+
+    match fetch_threads(max_results, query).await {
+        Ok(threads) => Ok(tool_text_content!(threads)),
+        Err(e) => {
+            info!("Error fetching threads: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tool(
+    name = "gmail_changes",
+    description = "Report mail added, deleted, or relabeled since a history ID, for incremental sync."
+)]
+async fn gmail_changes(
+    history_id: tool_param!(
+        Option<String>,
+        description = "The history ID returned by the previous call; omit to get a starting point"
+    ),
+) -> Result<ToolResponseContent, Box<dyn std::error::Error>> {
+    info!(
+        "Gmail changes tool called with history_id: {:?}",
+        history_id
+    );
+
+    match read_changes(history_id).await {
+        Ok(changes) => Ok(tool_text_content!(changes)),
+        Err(e) => {
+            info!("Error reading changes: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tool(
+    name = "gmail_mark",
+    description = "Mark emails read or unread, starred or unstarred, and important or not important."
+)]
+async fn gmail_mark(
+    ids: tool_param!(Vec<String>, description = "Email IDs to change"),
+    read: tool_param!(
+        Option<bool>,
+        description = "true to mark read, false to mark unread; omit to leave unchanged"
+    ),
+    starred: tool_param!(
+        Option<bool>,
+        description = "true to star, false to unstar; omit to leave unchanged"
+    ),
+    important: tool_param!(
+        Option<bool>,
+        description =
+            "true to mark important, false to mark not important; omit to leave unchanged"
+    ),
+) -> Result<ToolResponseContent, Box<dyn std::error::Error>> {
+    info!("Gmail mark tool called for {} email(s)", ids.len());
+
+    match mark(ids, read, starred, important).await {
+        Ok(marked) => Ok(tool_text_content!(marked)),
+        Err(e) => {
+            info!("Error marking emails: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tool(
+    name = "gmail_modify_labels",
+    description = "Add labels to and remove labels from emails."
+)]
+async fn gmail_modify_labels(
+    ids: tool_param!(Vec<String>, description = "Email IDs to change"),
+    add_labels: tool_param!(
+        Option<Vec<String>>,
+        description = "Label names or IDs to apply"
+    ),
+    remove_labels: tool_param!(
+        Option<Vec<String>>,
+        description = "Label names or IDs to remove"
+    ),
+) -> Result<ToolResponseContent, Box<dyn std::error::Error>> {
+    info!("Gmail modify labels tool called for {} email(s)", ids.len());
+
+    match apply_labels(
+        ids,
+        add_labels.unwrap_or_default(),
+        remove_labels.unwrap_or_default(),
+    )
+    .await
+    {
+        Ok(modified) => Ok(tool_text_content!(modified)),
+        Err(e) => {
+            info!("Error modifying labels: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tool(
+    name = "gmail_archive",
+    description = "Archive emails by removing them from the inbox, by ID or every inbox email matching a query."
+)]
+async fn gmail_archive(
+    ids: tool_param!(Option<Vec<String>>, description = "Email IDs to archive"),
+    query: tool_param!(
+        Option<String>,
+        description = "Archive every inbox email matching this Gmail query, e.g. 'older_than:30d'"
+    ),
+) -> Result<ToolResponseContent, Box<dyn std::error::Error>> {
+    info!("Gmail archive tool called with query: {:?}", query);
+
+    match archive(ids.unwrap_or_default(), query).await {
+        Ok(archived) => Ok(tool_text_content!(archived)),
+        Err(e) => {
+            info!("Error archiving emails: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tool(
+    name = "gmail_trash",
+    description = "Move emails to the trash, where Gmail deletes them after 30 days, or restore them from it."
+)]
+async fn gmail_trash(
+    ids: tool_param!(Vec<String>, description = "Email IDs to trash or restore"),
+    restore: tool_param!(
+        Option<bool>,
+        description = "Take the emails back out of the trash instead (default: false)"
+    ),
+) -> Result<ToolResponseContent, Box<dyn std::error::Error>> {
+    let action = if restore.unwrap_or(false) {
+        "untrash"
+    } else {
+        "trash"
+    };
+    info!(
+        "Gmail trash tool called with action: '{}' for {} email(s)",
+        action,
+        ids.len()
+    );
+
+    match remove(action, ids).await {
+        Ok(removed) => Ok(tool_text_content!(removed)),
+        Err(e) => {
+            info!("Error trashing emails: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tool(
+    name = "gmail_delete",
+    description = "Permanently delete emails, skipping the trash. Cannot be undone; disabled unless the server allows it."
+)]
+async fn gmail_delete(
+    ids: tool_param!(Vec<String>, description = "Email IDs to delete permanently"),
+) -> Result<ToolResponseContent, Box<dyn std::error::Error>> {
+    info!("Gmail delete tool called for {} email(s)", ids.len());
+
+    match remove("delete", ids).await {
+        Ok(removed) => Ok(tool_text_content!(removed)),
+        Err(e) => {
+            info!("Error deleting emails: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tool(
+    name = "gmail_labels",
+    description = "List, create, rename, or delete Gmail labels. Listing returns IDs, names, types, and visibility."
+)]
+async fn gmail_labels(
+    action: tool_param!(
+        Option<String>,
+        description = "One of: list (default), create, rename, delete"
+    ),
+    name: tool_param!(
+        Option<String>,
+        description = "Label name or ID; required for create, rename, and delete"
+    ),
+    new_name: tool_param!(
+        Option<String>,
+        description = "New label name; required for rename"
+    ),
+    include_system: tool_param!(
+        Option<bool>,
+        description = "Include system labels such as INBOX and SENT when listing (default: true)"
+    ),
+) -> Result<ToolResponseContent, Box<dyn std::error::Error>> {
+    let action: LabelAction = action.as_deref().unwrap_or_default().parse()?;
+    let include_system = include_system.unwrap_or(true);
+    info!(
+        "Gmail labels tool called with action: '{}', name: {:?}",
+        action.name(),
+        name
+    );
+
+    match manage_labels(action, name, new_name, include_system).await {
+        Ok(labels) => Ok(tool_text_content!(labels)),
+        Err(e) => {
+            info!("Error fetching labels: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tool(
+    name = "gmail_send",
+    description = "Send an email written as plain text, HTML, or Markdown, optionally with attachments, from the authenticated Gmail account."
+)]
+async fn gmail_send(
+    to: tool_param!(
+        Vec<String>,
+        description = "Recipient addresses, e.g. 'Ann <ann@example.com>'"
+    ),
+    subject: tool_param!(String, description = "Subject line"),
+    body: tool_param!(String, description = "Message body"),
+    format: tool_param!(
+        Option<String>,
+        description = "Body format: text (default), html, or markdown; HTML and Markdown are sent with a plain-text alternative"
+    ),
+    cc: tool_param!(Option<Vec<String>>, description = "Cc recipient addresses"),
+    bcc: tool_param!(Option<Vec<String>>, description = "Bcc recipient addresses"),
+    attachments: tool_param!(
+        Option<Vec<Inline<AttachmentInput>>>,
+        description = "Files to attach, each given as base64 data with a filename, or as a path inside the server's attachments directory (GMAIL_ATTACHMENTS_DIR; disabled when unset); 25 MB total"
+    ),
+) -> Result<ToolResponseContent, Box<dyn std::error::Error>> {
+    info!(
+        "Gmail send tool called for {} recipient(s), subject: '{}'",
+        to.len(),
+        subject
+    );
+
+    match send_email(
+        to,
+        cc.unwrap_or_default(),
+        bcc.unwrap_or_default(),
+        &subject,
+        &body,
+        format
+            .as_deref()
+            .unwrap_or_default()
+            .parse::<BodyFormat>()?,
+        &attachment_inputs(attachments),
+    )
+    .await
+    {
+        Ok(sent) => Ok(tool_text_content!(sent)),
+        Err(e) => {
+            info!("Error sending email: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tool(
+    name = "gmail_reply",
+    description = "Reply to an email within its thread, addressed to the original sender."
+)]
+async fn gmail_reply(
+    message_id: tool_param!(String, description = "ID of the email being replied to"),
+    body: tool_param!(String, description = "Plain-text reply body"),
+) -> Result<ToolResponseContent, Box<dyn std::error::Error>> {
+    info!("Gmail reply tool called for message {}", message_id);
+
+    match reply_to(&message_id, &body).await {
+        Ok(sent) => Ok(tool_text_content!(sent)),
+        Err(e) => {
+            info!("Error sending reply: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tool(
+    name = "gmail_forward",
+    description = "Forward an email to new recipients, inline with its original headers or as an attachment."
+)]
+async fn gmail_forward(
+    message_id: tool_param!(String, description = "ID of the email to forward"),
+    to: tool_param!(Vec<String>, description = "Recipient addresses"),
+    note: tool_param!(
+        Option<String>,
+        description = "Optional text placed above the forwarded message"
+    ),
+    as_attachment: tool_param!(
+        Option<bool>,
+        description =
+            "Attach the original as a .eml file instead of quoting it inline (default: false)"
+    ),
+) -> Result<ToolResponseContent, Box<dyn std::error::Error>> {
+    info!(
+        "Gmail forward tool called for message {} to {} recipient(s)",
+        message_id,
+        to.len()
+    );
+
+    match forward_email(
+        &message_id,
+        to,
+        note.as_deref(),
+        as_attachment.unwrap_or(false),
+    )
+    .await
+    {
+        Ok(sent) => Ok(tool_text_content!(sent)),
+        Err(e) => {
+            info!("Error forwarding email: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tool(
+    name = "gmail_drafts",
+    description = "List, read, create, update, delete, or send Gmail drafts, so an email can be staged and refined for human review and sent once approved."
+)]
+#[allow(clippy::too_many_arguments)]
+async fn gmail_drafts(
+    action: tool_param!(
+        Option<String>,
+        description = "One of: list (default), get, create, update, delete, send"
+    ),
+    id: tool_param!(
+        Option<String>,
+        description = "Draft ID; required for get, update, delete, and send"
+    ),
+    to: tool_param!(
+        Option<Vec<String>>,
+        description = "Recipient addresses for create; replaces the recipients on update"
+    ),
+    cc: tool_param!(
+        Option<Vec<String>>,
+        description = "Cc recipient addresses for create; replaces the Cc list on update"
+    ),
+    bcc: tool_param!(
+        Option<Vec<String>>,
+        description = "Bcc recipient addresses for create; replaces the Bcc list on update"
+    ),
+    subject: tool_param!(
+        Option<String>,
+        description = "Subject line for create; replaces the subject on update"
+    ),
+    body: tool_param!(
+        Option<String>,
+        description = "Message body; required for create, replaces the body on update"
+    ),
+    format: tool_param!(
+        Option<String>,
+        description = "Body format: text (default), html, or markdown"
+    ),
+    attachments: tool_param!(
+        Option<Vec<Inline<AttachmentInput>>>,
+        description = "Files to attach, each given as base64 data with a filename, or as a path inside the server's attachments directory (GMAIL_ATTACHMENTS_DIR; disabled when unset); 25 MB total; replaces the attachments on update"
+    ),
+    max_results: tool_param!(
+        Option<u32>,
+        description = "Max drafts to list (1-500, default: 10)"
+    ),
+    page_token: tool_param!(
+        Option<String>,
+        description = "Token from a previous list response to fetch the next page"
+    ),
+) -> Result<ToolResponseContent, Box<dyn std::error::Error>> {
+    let action: DraftAction = action.as_deref().unwrap_or_default().parse()?;
+    info!(
+        "Gmail drafts tool called with action: '{}', id: {:?}",
+        action.name(),
+        id
+    );
+
+    let attachments = match attachments {
+        Some(inputs) => Some(load_attachments(&attachment_inputs(Some(inputs)))?),
+        None => None,
+    };
+    let changes = DraftChanges {
+        to,
+        cc,
+        bcc,
+        subject,
+        body,
+        format: format.as_deref().unwrap_or_default().parse()?,
+        attachments,
+    };
+    match manage_drafts(action, id, changes, max_results.unwrap_or(10), page_token).await {
+        Ok(draft) => Ok(tool_text_content!(draft)),
+        Err(e) => {
+            info!("Error managing drafts: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tool(
+    name = "gmail_filters",
+    description = "List, create, or delete server-side Gmail filters that label, archive, or forward matching mail."
+)]
+#[allow(clippy::too_many_arguments)]
+async fn gmail_filters(
+    action: tool_param!(
+        Option<String>,
+        description = "One of: list (default), create, delete"
+    ),
+    id: tool_param!(
+        Option<String>,
+        description = "Filter ID; required for delete"
+    ),
+    from: tool_param!(Option<String>, description = "Match mail from this sender"),
+    to: tool_param!(Option<String>, description = "Match mail to this recipient"),
+    subject: tool_param!(
+        Option<String>,
+        description = "Match mail whose subject contains this text"
+    ),
+    query: tool_param!(
+        Option<String>,
+        description = "Match mail for this Gmail search query"
+    ),
+    negated_query: tool_param!(
+        Option<String>,
+        description = "Exclude mail matching this Gmail search query"
+    ),
+    has_attachment: tool_param!(
+        Option<bool>,
+        description = "Match only mail with attachments"
+    ),
+    add_labels: tool_param!(
+        Option<Vec<String>>,
+        description = "Label names or IDs to apply to matching mail"
+    ),
+    remove_labels: tool_param!(
+        Option<Vec<String>>,
+        description = "Label names or IDs to remove, e.g. INBOX to archive or UNREAD to mark read"
+    ),
+    forward: tool_param!(
+        Option<String>,
+        description = "Verified forwarding address to send matching mail to"
+    ),
+) -> Result<ToolResponseContent, Box<dyn std::error::Error>> {
+    let action: FilterToolAction = action.as_deref().unwrap_or_default().parse()?;
+    info!(
+        "Gmail filters tool called with action: '{}', id: {:?}",
+        action.name(),
+        id
+    );
+
+    let criteria = FilterCriteriaInfo {
+        from,
+        to,
+        subject,
+        query,
+        negated_query,
+        has_attachment,
+        ..Default::default()
+    };
+    let filter_action = FilterActionInfo {
+        add_labels: add_labels.unwrap_or_default(),
+        remove_labels: remove_labels.unwrap_or_default(),
+        forward,
+    };
+    match manage_filters(action, id, criteria, filter_action).await {
+        Ok(filters) => Ok(tool_text_content!(filters)),
+        Err(e) => {
+            info!("Error managing filters: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tool(
+    name = "gmail_signatures",
+    description = "List send-as aliases and read or update their signatures, optionally setting one signature on every alias."
+)]
+async fn gmail_signatures(
+    action: tool_param!(
+        Option<String>,
+        description = "One of: list (default), get, set"
+    ),
+    email: tool_param!(
+        Option<String>,
+        description = "Send-as address; required for get, and for set unless all_aliases is true"
+    ),
+    signature: tool_param!(
+        Option<String>,
+        description = "HTML signature for set; an empty string removes the signature"
+    ),
+    all_aliases: tool_param!(
+        Option<bool>,
+        description = "With set, apply the signature to every send-as alias (default: false)"
+    ),
+) -> Result<ToolResponseContent, Box<dyn std::error::Error>> {
+    let action: SignatureAction = action.as_deref().unwrap_or_default().parse()?;
+    info!(
+        "Gmail signatures tool called with action: '{}', email: {:?}",
+        action.name(),
+        email
+    );
+
+    match manage_signatures(action, email, signature, all_aliases.unwrap_or(false)).await {
+        Ok(aliases) => Ok(tool_text_content!(aliases)),
+        Err(e) => {
+            info!("Error managing signatures: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tool(
+    name = "gmail_delegates",
+    description = "List, add, or remove delegates who can read, send, and delete mail on this account's behalf (Google Workspace only)."
+)]
+async fn gmail_delegates(
+    action: tool_param!(
+        Option<String>,
+        description = "One of: list (default), create, delete"
+    ),
+    email: tool_param!(
+        Option<String>,
+        description = "Delegate address; required for create and delete"
+    ),
+) -> Result<ToolResponseContent, Box<dyn std::error::Error>> {
+    let action: DelegateAction = action.as_deref().unwrap_or_default().parse()?;
+    info!(
+        "Gmail delegates tool called with action: '{}', email: {:?}",
+        action.name(),
+        email
+    );
+
+    match manage_delegates(action, email).await {
+        Ok(delegates) => Ok(tool_text_content!(delegates)),
+        Err(e) => {
+            info!("Error managing delegates: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tool(
+    name = "gmail_import",
+    description = "Add an existing RFC 822 message (e.g. an .eml file migrated from another system) to the mailbox with labels. Large messages are uploaded in chunks."
+)]
+#[allow(clippy::too_many_arguments)]
+async fn gmail_import(
+    path: tool_param!(
+        Option<String>,
+        description = "Path to the .eml file inside the server's import directory (GMAIL_IMPORT_DIR; disabled when unset); give this or data"
+    ),
+    data: tool_param!(
+        Option<String>,
+        description = "Base64-encoded RFC 822 message; give this or path"
+    ),
+    labels: tool_param!(
+        Option<Vec<String>>,
+        description = "Label names or IDs to apply, e.g. ['INBOX', 'UNREAD', 'Archive/2019']"
+    ),
+    insert: tool_param!(
+        Option<bool>,
+        description = "Use messages.insert to store the message as-is, skipping spam scanning and classification (default: false, import)"
+    ),
+    use_date_header: tool_param!(
+        Option<bool>,
+        description =
+            "Date the message by its Date header instead of the time of import (default: false)"
+    ),
+    never_mark_spam: tool_param!(
+        Option<bool>,
+        description = "Import only: never send the message to Spam (default: false)"
+    ),
+    process_for_calendar: tool_param!(
+        Option<bool>,
+        description = "Import only: add calendar invitations in the message to Google Calendar (default: false)"
+    ),
+) -> Result<ToolResponseContent, Box<dyn std::error::Error>> {
+    info!(
+        "Gmail import tool called with path: {:?}, labels: {:?}",
+        path, labels
+    );
+
+    let options = ImportOptions {
+        insert: insert.unwrap_or(false),
+        labels: labels.unwrap_or_default(),
+        use_date_header: use_date_header.unwrap_or(false),
+        never_mark_spam: never_mark_spam.unwrap_or(false),
+        process_for_calendar: process_for_calendar.unwrap_or(false),
+    };
+    match import_email(path, data, options).await {
+        Ok(imported) => Ok(tool_text_content!(imported)),
+        Err(e) => {
+            info!("Error importing message: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tool(
+    name = "gmail_auth",
+    description = "Diagnose authentication (credentials, cached tokens with scopes and expiry, missing scopes, account address), or clear the cached tokens and sign in again."
+)]
+async fn gmail_auth(
+    action: tool_param!(
+        Option<String>,
+        description = "One of: status (default), logout (delete cached tokens), reauth (delete cached tokens and sign in again)"
+    ),
+    revoke: tool_param!(
+        Option<bool>,
+        description = "With logout or reauth, also revoke the tokens with Google (default: false)"
+    ),
+) -> Result<ToolResponseContent, Box<dyn std::error::Error>> {
+    let action: AuthAction = action.as_deref().unwrap_or_default().parse()?;
+    info!("Gmail auth tool called with action: '{}'", action.name());
+
+    match manage_auth(action, revoke.unwrap_or(false)).await {
+        Ok(status) => Ok(tool_text_content!(status)),
+        Err(e) => {
+            info!("Error checking authentication: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tool(
+    name = "gmail_events",
+    description = "Collect new-mail events raised since the last call by the push or polling mailbox watcher."
+)]
+async fn gmail_events() -> Result<ToolResponseContent, Box<dyn std::error::Error>> {
+    info!("Gmail events tool called");
+
+    match collect_events() {
+        Ok(events) => Ok(tool_text_content!(events)),
+        Err(e) => {
+            info!("Error collecting events: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tool(
+    name = "gmail_quota",
+    description = "Report the Gmail API quota units this server has spent today (UTC), per API method, and the daily budget left if one is set."
+)]
+async fn gmail_quota() -> Result<ToolResponseContent, Box<dyn std::error::Error>> {
+    info!("Gmail quota tool called");
+
+    match quota_report() {
+        Ok(report) => Ok(tool_text_content!(report)),
+        Err(e) => {
+            info!("Error reporting quota: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tool(
+    name = "gmail_unsubscribe_candidates",
+    description = "List the senders of recent mailing-list mail that can be unsubscribed from through the List-Unsubscribe header, one-click (RFC 8058) senders first, with message counts and the unsubscribe links and mailto addresses of each sender's newest message."
+)]
+async fn gmail_unsubscribe_candidates(
+    query: tool_param!(
+        Option<String>,
+        description =
+            "Gmail search query selecting the mail to scan (default: unsubscribe newer_than:90d)"
+    ),
+    max_results: tool_param!(
+        Option<u32>,
+        description = "Max emails to scan (1-500, default: 200)"
+    ),
+) -> Result<ToolResponseContent, Box<dyn std::error::Error>> {
+    let max_results = max_results.unwrap_or(200);
+    info!(
+        "Gmail unsubscribe candidates tool called with query: {:?}, max_results: {}",
+        query, max_results
+    );
+
+    match unsubscribe_candidates(query, max_results).await {
+        Ok(candidates) => Ok(tool_text_content!(candidates)),
+        Err(e) => {
+            info!("Error finding unsubscribe candidates: {}", e);
+            Err(e.into())
         }
     }
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize simple logging
-    tracing_subscriber::fmt::init();
+    let transport =
+        TransportKind::from_args(std::env::args().skip(1)).map_err(anyhow::Error::msg)?;
+
+    // Log to stderr: with the stdio transport, stdout carries the protocol
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
+
+    // The interactive flow reads the pasted code from stdin, which stdio clients own
+    let flow = AuthFlow::from_env().map_err(anyhow::Error::msg)?;
+    if transport == TransportKind::Stdio && flow == AuthFlow::Interactive {
+        anyhow::bail!("The interactive auth flow cannot be used with the stdio transport");
+    }
+
+    // Only register tools for the enabled capabilities
+    let capabilities = Capabilities::from_env().map_err(anyhow::Error::msg)?;
+    info!("Enabled capabilities: {:?}", capabilities);
+    limits::install(LimitsConfig::from_env().map_err(anyhow::Error::msg)?);
+    retry::install(RetryConfig::from_env().map_err(anyhow::Error::msg)?);
+    quota::install(QuotaConfig::from_env().map_err(anyhow::Error::msg)?);
+    timeouts::install(TimeoutConfig::from_env().map_err(anyhow::Error::msg)?);
+    cache::install(CacheConfig::from_env().map_err(anyhow::Error::msg)?);
+    #[cfg(feature = "search")]
+    if let Some(dir) = search_index::dir_from_env() {
+        search_index::install(&dir)?;
+    }
 
     // Create MCP server
-    let server = Server::builder(
-        "gmail-server".to_string(),
-        "1.0.0".to_string(),
-        mcp_core::types::ProtocolVersion::V2025_03_26,
-    )
-    .set_capabilities(ServerCapabilities {
-        tools: Some(ToolCapabilities::default()),
-        ..Default::default()
-    })
-    .register_tool(Gmail::tool(), Gmail::call())
-    .build();
+    let mut builder = ServerBuilder::new("gmail-server", "1.0.0", ProtocolVersion::V2025_03_26)
+        .register_tool_with_output(
+            GmailList::tool(),
+            email_response_schema(),
+            GmailList::call(),
+        )
+        .register_tool_with_output(
+            GmailSearch::tool(),
+            email_response_schema(),
+            GmailSearch::call(),
+        )
+        .register_tool(GmailGet::tool(), GmailGet::call())
+        .register_tool(GmailThreads::tool(), GmailThreads::call())
+        .register_tool(GmailChanges::tool(), GmailChanges::call())
+        .register_tool(GmailLabels::tool(), GmailLabels::call())
+        .register_tool(GmailEvents::tool(), GmailEvents::call())
+        .register_tool(GmailQuota::tool(), GmailQuota::call())
+        .register_tool(
+            GmailUnsubscribeCandidates::tool(),
+            GmailUnsubscribeCandidates::call(),
+        )
+        .register_tool(GmailAuth::tool(), GmailAuth::call());
+    #[cfg(feature = "search")]
+    if search_index::is_enabled() {
+        builder = builder.register_tool(GmailLocalSearch::tool(), GmailLocalSearch::call());
+    }
+    if capabilities.contains(Capability::Modify) {
+        builder = builder
+            .register_tool(GmailMark::tool(), GmailMark::call())
+            .register_tool(GmailModifyLabels::tool(), GmailModifyLabels::call())
+            .register_tool(GmailArchive::tool(), GmailArchive::call())
+            .register_tool(GmailTrash::tool(), GmailTrash::call())
+            .register_tool(GmailDelete::tool(), GmailDelete::call());
+    }
+    if capabilities.contains(Capability::Send) {
+        builder = builder
+            .register_tool(GmailSend::tool(), GmailSend::call())
+            .register_tool(GmailReply::tool(), GmailReply::call())
+            .register_tool(GmailForward::tool(), GmailForward::call());
+    }
+    if capabilities.contains(Capability::Compose) {
+        builder = builder.register_tool(GmailDrafts::tool(), GmailDrafts::call());
+    }
+    if capabilities.contains(Capability::Settings) {
+        builder = builder
+            .register_tool(GmailFilters::tool(), GmailFilters::call())
+            .register_tool(GmailSignatures::tool(), GmailSignatures::call());
+    }
+    if capabilities.contains(Capability::Delegation) {
+        builder = builder.register_tool(GmailDelegates::tool(), GmailDelegates::call());
+    }
+    if capabilities.contains(Capability::Import) {
+        builder = builder.register_tool(GmailImport::tool(), GmailImport::call());
+    }
+    builder = prompts::register(builder);
+    let server = builder.build();
+
+    // The web flow completes consent through the server's own OAuth routes
+    if flow == AuthFlow::Web {
+        let config = WebAuthConfig::from_env();
+        tokio::spawn(async move {
+            if let Err(e) = web_auth::run(config).await {
+                error!("Gmail OAuth routes stopped: {}", e);
+            }
+        });
+    }
+
+    // Events left uncollected by the previous run are still delivered
+    events::restore();
+
+    // Push notifications are opt-in, since they need a Pub/Sub topic
+    if let Some(config) = WatchConfig::from_env() {
+        tokio::spawn(async move {
+            if let Err(e) = watch::run(config).await {
+                error!("Gmail push notifications stopped: {}", e);
+            }
+        });
+    }
+    if let Some(config) = PollConfig::from_env() {
+        tokio::spawn(async move {
+            if let Err(e) = poll::run(config).await {
+                error!("Gmail polling stopped: {}", e);
+            }
+        });
+    }
+    if let Some(config) = SyncConfig::from_env() {
+        tokio::spawn(async move {
+            if let Err(e) = sync::run(config).await {
+                error!("Local cache sync stopped: {}", e);
+            }
+        });
+    }
 
-    // Start server transport
-    let transport = ServerSseTransport::new("0.0.0.0".to_string(), 3003, server);
+    let host = host();
+    if transport != TransportKind::Stdio {
+        check_exposure(&host, auth_token().as_deref()).map_err(anyhow::Error::msg)?;
+    }
 
-    println!("Gmail MCP Server running on http://0.0.0.0:3003/sse");
+    // With TLS the HTTP transports serve a loopback port behind the TLS relay
+    let (scheme, listener) = match TlsConfig::from_env().map_err(anyhow::Error::msg)? {
+        Some(_) if transport == TransportKind::Stdio => {
+            warn!("Ignoring the TLS configuration with the stdio transport");
+            ("http", None)
+        }
+        Some(config) => {
+            let acceptor = config
+                .acceptor()
+                .map_err(|e| anyhow::anyhow!("Cannot load the TLS certificate: {}", e))?;
+            let listener = std::net::TcpListener::bind(("127.0.0.1", 0))?;
+            let backend = listener.local_addr()?;
+            let public = (host.clone(), PORT);
+            tokio::spawn(async move {
+                if let Err(e) = tls::serve(acceptor, public, backend).await {
+                    error!("TLS relay stopped: {}", e);
+                }
+            });
+            ("https", Some(listener))
+        }
+        None => ("http", None),
+    };
 
-    Server::start(transport).await?;
+    // Serve on a task of its own, so the signals are watched alongside
+    let serve = tokio::spawn(async move {
+        match transport {
+            TransportKind::Sse => {
+                let transport = SseTransport::new(host.clone(), PORT, server)
+                    .with_token(auth_token())
+                    .with_listener(listener);
+                info!(
+                    "Gmail MCP Server running on {}://{}:{}{}",
+                    scheme, host, PORT, SSE_PATH
+                );
+                Server::start(transport).await?;
+            }
+            TransportKind::Http => {
+                let transport = StreamableHttpTransport::new(host.clone(), PORT, server)
+                    .with_token(auth_token())
+                    .with_listener(listener);
+                info!(
+                    "Gmail MCP Server running on {}://{}:{}{}",
+                    scheme, host, PORT, HTTP_PATH
+                );
+                Server::start(transport).await?;
+            }
+            TransportKind::Stdio => {
+                info!("Gmail MCP Server running on stdio");
+                // The transport reads stdin synchronously; keep it on a thread of
+                // its own so a single-worker runtime still sees signals, and a read
+                // still blocking at shutdown does not hold up the runtime
+                let runtime = tokio::runtime::Handle::current();
+                let (done, served) = tokio::sync::oneshot::channel();
+                std::thread::spawn(move || {
+                    // Progress notifications go to stdout between the responses
+                    let _ = done.send(runtime.block_on(progress::with_sink(
+                        progress::stdout_sink(),
+                        Server::start(ServerStdioTransport::new(server)),
+                    )));
+                });
+                served.await??;
+            }
+        }
+        Ok::<(), anyhow::Error>(())
+    });
+
+    tokio::select! {
+        served = serve => served??,
+        signal = shutdown::signal() => {
+            info!(
+                "Received {}; waiting for {} tool call(s) to finish",
+                signal,
+                shutdown::in_flight()
+            );
+            if !shutdown::drain(shutdown::GRACE_PERIOD).await {
+                warn!(
+                    "Gave up waiting for {} tool call(s)",
+                    shutdown::in_flight()
+                );
+            }
+        }
+    }
+    events::flush();
+    info!("Gmail MCP Server stopped");
     Ok(())
 }
@@ -0,0 +1,739 @@
+//! HTML and Markdown bodies for outgoing mail, and Markdown for incoming HTML.
+//!
+//! Bodies are rendered to a `multipart/alternative` pair: a plain-text part for
+//! clients that prefer it and a sanitized HTML part with inline styles, which is
+//! what Gmail and Outlook render most consistently. Markdown is rendered with
+//! `pulldown-cmark` and HTML is cleaned with `ammonia`, with an inline-CSS
+//! allow-list on top. In the other direction,
+//! [`html_to_markdown`] turns the HTML of received mail into Markdown.
+
+use crate::AttachmentInfo;
+use pulldown_cmark::html::push_html;
+use pulldown_cmark::{Event, Options, Parser};
+use regex::{Captures, Regex};
+use std::str::FromStr;
+
+/// How the body text supplied by a client should be interpreted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BodyFormat {
+    /// Plain text, sent as a single `text/plain` part.
+    #[default]
+    Text,
+    /// HTML, sanitized and sent alongside a text rendering.
+    Html,
+    /// Markdown, converted to HTML and sent alongside the Markdown source.
+    Markdown,
+}
+
+impl FromStr for BodyFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "" | "text" | "plain" => Ok(Self::Text),
+            "html" => Ok(Self::Html),
+            "markdown" | "md" => Ok(Self::Markdown),
+            other => Err(format!(
+                "Unknown body format '{}'; expected text, html, or markdown",
+                other
+            )),
+        }
+    }
+}
+
+/// What happens to inline images, which an HTML body shows through `cid:`
+/// references to parts of the message, when the body is converted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InlineImages {
+    /// Replace each with an `[image: filename]` marker.
+    #[default]
+    Marker,
+    /// Remove them.
+    Drop,
+}
+
+impl FromStr for InlineImages {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "" | "marker" => Ok(Self::Marker),
+            "drop" => Ok(Self::Drop),
+            other => Err(format!(
+                "Unknown inline image handling '{}'; expected marker or drop",
+                other
+            )),
+        }
+    }
+}
+
+/// Render a body as a plain-text part and, for HTML or Markdown, an HTML part.
+pub fn render_body(body: &str, format: BodyFormat) -> (String, Option<String>) {
+    match format {
+        BodyFormat::Text => (body.to_string(), None),
+        BodyFormat::Html => {
+            let html = sanitize_html(body);
+            let text = html2text::from_read(html.as_bytes(), 100);
+            (text, Some(html))
+        }
+        BodyFormat::Markdown => {
+            let html = format!(
+                "<html><body style=\"font-family: Arial, Helvetica, sans-serif; font-size: 14px; line-height: 1.5; color: #222222;\">\n{}</body></html>\n",
+                sanitize_html(&markdown_to_html(body))
+            );
+            (body.to_string(), Some(html))
+        }
+    }
+}
+
+/// Build a regex from a pattern known to be valid.
+pub(crate) fn pattern(re: &str) -> Regex {
+    Regex::new(re).unwrap_or_else(|e| panic!("invalid built-in pattern {:?}: {}", re, e))
+}
+
+/// Reduce an HTML fragment or document to an allow-list of tags, attributes,
+/// and URL schemes, keeping inline CSS that passes [`sanitize_style`].
+///
+/// The markup is parsed with `html5ever` by `ammonia`, so scripts, frames,
+/// forms, SVG and MathML, meta refreshes, event handlers, and `javascript:`
+/// URLs are removed however they are quoted or entity-encoded.
+pub fn sanitize_html(html: &str) -> String {
+    ammonia::Builder::default()
+        .add_generic_attributes(["style", "align", "valign", "width", "height", "bgcolor"])
+        .add_url_schemes(["cid"])
+        .link_rel(None)
+        .attribute_filter(|_element, attribute, value| match attribute {
+            "style" => {
+                let css = sanitize_style(value);
+                (!css.is_empty()).then(|| css.into())
+            }
+            _ => Some(value.into()),
+        })
+        .clean(html)
+        .to_string()
+}
+
+/// Keep only inline CSS declarations that cannot run code, load remote resources,
+/// or escape the message layout.
+pub fn sanitize_style(css: &str) -> String {
+    const BLOCKED_VALUES: [&str; 6] = [
+        "expression(",
+        "javascript:",
+        "url(",
+        "behavior",
+        "-moz-binding",
+        "@import",
+    ];
+    const BLOCKED_PROPERTIES: [&str; 3] = ["position", "behavior", "z-index"];
+
+    css.split(';')
+        .filter_map(|declaration| {
+            let (property, value) = declaration.split_once(':')?;
+            let property = property.trim().to_ascii_lowercase();
+            let value = value.trim();
+            let lower = value.to_ascii_lowercase();
+            let valid_property = !property.is_empty()
+                && property
+                    .chars()
+                    .all(|c| c.is_ascii_alphabetic() || c == '-');
+            if !valid_property
+                || value.is_empty()
+                || BLOCKED_PROPERTIES.contains(&property.as_str())
+                || BLOCKED_VALUES.iter().any(|blocked| lower.contains(blocked))
+            {
+                return None;
+            }
+            Some(format!("{}: {}", property, value))
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Escape text for inclusion in HTML.
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Convert CommonMark, with tables and strikethrough, to HTML with the inline
+/// styles mail clients need for code and quotes. HTML in the source is shown
+/// as text rather than passed through.
+pub fn markdown_to_html(markdown: &str) -> String {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH;
+    let events = Parser::new_ext(markdown, options).map(|event| match event {
+        Event::Html(raw) | Event::InlineHtml(raw) => Event::Text(raw),
+        Event::Code(code) => Event::InlineHtml(
+            format!(
+                "<code style=\"font-family: Consolas, monospace; background-color: #f4f4f4;\">{}</code>",
+                escape_html(&code)
+            )
+            .into(),
+        ),
+        event => event,
+    });
+    let mut html = String::new();
+    push_html(&mut html, events);
+    html.replace(
+        "<pre><code",
+        "<pre style=\"font-family: Consolas, monospace; background-color: #f4f4f4; padding: 8px;\"><code",
+    )
+    .replace(
+        "<blockquote>",
+        "<blockquote style=\"margin: 0 0 0 8px; padding-left: 8px; border-left: 3px solid #cccccc; color: #555555;\">",
+    )
+}
+
+/// Replace the `<img>` tags of `html` whose source is a `cid:` reference under
+/// `policy`, naming each image by the part in `attachments` with that
+/// `Content-ID`, or else by its alt text.
+pub fn replace_inline_images(
+    html: &str,
+    attachments: &[AttachmentInfo],
+    policy: InlineImages,
+) -> String {
+    pattern(r"(?i)<img\b[^>]*>")
+        .replace_all(html, |caps: &Captures| {
+            let tag = &caps[0];
+            let src = attribute(tag, "src").unwrap_or_default();
+            match src
+                .get(..4)
+                .filter(|scheme| scheme.eq_ignore_ascii_case("cid:"))
+            {
+                Some(_) => escape_html(&inline_image(
+                    &src[4..],
+                    attribute(tag, "alt"),
+                    attachments,
+                    policy,
+                )),
+                None => tag.to_string(),
+            }
+        })
+        .into_owned()
+}
+
+/// Replace the `[cid:…]` references that plain-text bodies show in place of
+/// inline images, as [`replace_inline_images`] does for HTML.
+pub fn replace_inline_image_refs(
+    text: &str,
+    attachments: &[AttachmentInfo],
+    policy: InlineImages,
+) -> String {
+    pattern(r"(?i)\[cid:([^\]\s]+)\]")
+        .replace_all(text, |caps: &Captures| {
+            inline_image(&caps[1], None, attachments, policy)
+        })
+        .into_owned()
+}
+
+/// What stands in for the inline image with Content-ID `id` under `policy`.
+fn inline_image(
+    id: &str,
+    alt: Option<String>,
+    attachments: &[AttachmentInfo],
+    policy: InlineImages,
+) -> String {
+    if policy == InlineImages::Drop {
+        return String::new();
+    }
+    // `cid:` URLs percent-encode characters not allowed in URLs (RFC 2392)
+    let id = crate::util::percent_decode(id);
+    let name = attachments
+        .iter()
+        .find(|part| {
+            part.content_id
+                .as_deref()
+                .is_some_and(|content_id| content_id.eq_ignore_ascii_case(&id))
+        })
+        .map(|part| part.filename.clone())
+        .filter(|name| !name.trim().is_empty())
+        .or(alt)
+        .filter(|name| !name.trim().is_empty());
+    match name {
+        Some(name) => format!("[image: {}]", name.trim()),
+        None => "[image]".to_string(),
+    }
+}
+
+/// Marks the place of a set-aside code block in [`html_to_markdown`].
+const CODE_MARK: char = '\u{1}';
+/// Stands for indentation in [`html_to_markdown`] until lines are trimmed.
+const INDENT: char = '\u{2}';
+
+/// Convert the HTML body of a received email to Markdown, keeping headings,
+/// paragraphs, lists, tables, block quotes, code, links, images with alt text,
+/// and bold, italic, and struck-out text.
+///
+/// Layout tables, which most newsletters are built from, are unwrapped into
+/// paragraphs; only tables whose cells each hold one line become Markdown
+/// tables.
+pub fn html_to_markdown(html: &str) -> String {
+    let mut html = pattern(r"(?s)<!--.*?-->")
+        .replace_all(html, "")
+        .into_owned();
+    for tag in ["head", "title", "style", "script"] {
+        let block = pattern(&format!(r"(?is)<{0}\b.*?</{0}\s*>", tag));
+        html = block.replace_all(&html, "").into_owned();
+    }
+
+    // Preformatted text keeps its whitespace, so set it aside until the end
+    let mut code_blocks = Vec::new();
+    html = pattern(r"(?is)<pre\b[^>]*>(.*?)</pre\s*>")
+        .replace_all(&html, |caps: &Captures| {
+            let code = decode_entities(&strip_tags(&caps[1]));
+            code_blocks.push(format!("```\n{}\n```", code.trim_matches('\n')));
+            format!("<p>{0}{1}{0}</p>", CODE_MARK, code_blocks.len() - 1)
+        })
+        .into_owned();
+    html = pattern(r"\s+").replace_all(&html, " ").into_owned();
+
+    html = pattern(r"(?i)<br\b[^>]*>")
+        .replace_all(&html, "\n")
+        .into_owned();
+    html = pattern(r"(?i)<hr\b[^>]*>")
+        .replace_all(&html, "\n\n---\n\n")
+        .into_owned();
+    html = pattern(r"(?i)<img\b[^>]*>")
+        .replace_all(&html, |caps: &Captures| {
+            let tag = &caps[0];
+            match (attribute(tag, "alt"), attribute(tag, "src")) {
+                (Some(alt), Some(src)) if !alt.trim().is_empty() => {
+                    format!("![{}]({})", alt.trim(), src)
+                }
+                _ => String::new(),
+            }
+        })
+        .into_owned();
+    for (tags, marker) in [
+        ("code|tt|kbd", "`"),
+        ("strong|b", "**"),
+        ("em|i", "*"),
+        ("del|s|strike", "~~"),
+    ] {
+        html = pattern(&format!(r"(?is)<(?:{0})\b[^>]*>(.*?)</(?:{0})\s*>", tags))
+            .replace_all(&html, |caps: &Captures| emphasize(&caps[1], marker))
+            .into_owned();
+    }
+    html = pattern(r"(?is)<a\b([^>]*)>(.*?)</a\s*>")
+        .replace_all(&html, |caps: &Captures| {
+            let text = caps[2].trim();
+            match attribute(&caps[1], "href") {
+                Some(href) if !text.is_empty() && !href.starts_with('#') => {
+                    format!("[{}]({})", text, href)
+                }
+                _ => text.to_string(),
+            }
+        })
+        .into_owned();
+    html = pattern(r"(?is)<h([1-6])\b[^>]*>(.*?)</h[1-6]\s*>")
+        .replace_all(&html, |caps: &Captures| {
+            let level = caps[1].parse().unwrap_or(1);
+            format!(
+                "\n\n{} {}\n\n",
+                "#".repeat(level),
+                strip_tags(&caps[2]).trim()
+            )
+        })
+        .into_owned();
+    html = pattern(
+        r"(?i)</?(?:p|div|section|article|header|footer|main|aside|nav|center|address|figure|figcaption|dl|dt|dd)\b[^>]*>",
+    )
+    .replace_all(&html, "\n\n")
+    .into_owned();
+
+    html = replace_nested(&html, "table", |_, inner| table_to_markdown(inner));
+    html = replace_nested(&html, "ul|ol", list_to_markdown);
+    html = replace_nested(&html, "blockquote", |_, inner| {
+        let quoted: Vec<String> = tidy_lines(inner)
+            .lines()
+            .map(|line| format!("> {}", line).trim_end().to_string())
+            .collect();
+        format!("\n\n{}\n\n", quoted.join("\n"))
+    });
+
+    let text = decode_entities(&strip_tags(&html));
+    let text = tidy_lines(&text).replace(INDENT, " ");
+    pattern(&format!(r"{0}(\d+){0}", CODE_MARK))
+        .replace_all(&text, |caps: &Captures| {
+            let index: usize = caps[1].parse().unwrap_or_default();
+            code_blocks.get(index).cloned().unwrap_or_default()
+        })
+        .into_owned()
+}
+
+/// Wrap `text` in an emphasis `marker` such as `**`, keeping the whitespace
+/// around it outside, since Markdown ignores markers next to spaces.
+fn emphasize(text: &str, marker: &str) -> String {
+    let core = text.trim();
+    if core.is_empty() {
+        return text.to_string();
+    }
+    let start = text.len() - text.trim_start().len();
+    let end = text.trim_end().len();
+    format!(
+        "{}{}{}{}{}",
+        &text[..start],
+        marker,
+        core,
+        marker,
+        &text[end..]
+    )
+}
+
+/// Remove every tag, leaving the text between them.
+pub(crate) fn strip_tags(html: &str) -> String {
+    pattern(r"(?s)<[^>]*>").replace_all(html, "").into_owned()
+}
+
+/// Read the value of attribute `name` from a tag or its attribute list.
+pub(crate) fn attribute(tag: &str, name: &str) -> Option<String> {
+    let attribute = pattern(&format!(
+        r#"(?i)\b{}\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+))"#,
+        name
+    ));
+    let caps = attribute.captures(tag)?;
+    let value = caps
+        .get(1)
+        .or_else(|| caps.get(2))
+        .or_else(|| caps.get(3))?;
+    Some(decode_entities(value.as_str()))
+}
+
+/// Replace every element named by the `tags` alternation, such as `ul|ol`, with
+/// `convert(tag, inner_html)`, innermost first, so nested elements are already
+/// converted when their parent is.
+fn replace_nested(html: &str, tags: &str, convert: impl Fn(&str, &str) -> String) -> String {
+    let open = pattern(&format!(r"(?i)<({})\b[^>]*>", tags));
+    let close = pattern(&format!(r"(?i)</(?:{})\s*>", tags));
+    let mut html = html.to_string();
+    while let Some(end) = close.find(&html).map(|m| m.range()) {
+        let innermost = open.captures_iter(&html[..end.start]).last().map(|caps| {
+            let tag = caps[1].to_ascii_lowercase();
+            (caps.get(0).map_or(0..0, |m| m.range()), tag)
+        });
+        let Some((start, tag)) = innermost else {
+            // A closing tag that was never opened
+            html.replace_range(end, "");
+            continue;
+        };
+        let converted = convert(&tag, &html[start.end..end.start]);
+        html.replace_range(start.start..end.end, &converted);
+    }
+    open.replace_all(&html, "").into_owned()
+}
+
+/// Convert the items of a `ul` or `ol` list to Markdown, indenting their
+/// continuation lines and nested lists under the marker.
+fn list_to_markdown(tag: &str, inner: &str) -> String {
+    let items = pattern(r"(?i)<li\b[^>]*>").split(inner).skip(1);
+    let mut lines = Vec::new();
+    for (i, item) in items.enumerate() {
+        let marker = if tag == "ol" {
+            format!("{}. ", i + 1)
+        } else {
+            "- ".to_string()
+        };
+        let indent = INDENT.to_string().repeat(marker.len());
+        let item = tidy_lines(&strip_tags(item));
+        for (j, line) in item.lines().filter(|l| !l.is_empty()).enumerate() {
+            let prefix = if j == 0 { &marker } else { &indent };
+            lines.push(format!("{}{}", prefix, line));
+        }
+    }
+    format!("\n\n{}\n\n", lines.join("\n"))
+}
+
+/// Convert a table to Markdown when every cell holds one line, or else unwrap
+/// its cells into paragraphs.
+fn table_to_markdown(inner: &str) -> String {
+    let cell = pattern(r"(?i)<t[dh]\b[^>]*>");
+    let rows: Vec<Vec<String>> = pattern(r"(?i)<tr\b[^>]*>")
+        .split(inner)
+        .skip(1)
+        .map(|row| {
+            cell.split(row)
+                .skip(1)
+                .map(|c| tidy_lines(&strip_tags(c)))
+                .collect()
+        })
+        .filter(|row: &Vec<String>| !row.is_empty())
+        .collect();
+    let columns = rows.iter().map(Vec::len).max().unwrap_or_default();
+    let tabular = rows.len() >= 2
+        && columns >= 2
+        && rows.iter().flatten().all(|c| !c.contains('\n'))
+        && rows.iter().flatten().any(|c| !c.is_empty());
+    if !tabular {
+        let cells: Vec<&str> = rows
+            .iter()
+            .flatten()
+            .map(String::as_str)
+            .filter(|c| !c.is_empty())
+            .collect();
+        return format!("\n\n{}\n\n", cells.join("\n\n"));
+    }
+
+    let line = |row: &[String]| {
+        let mut cells: Vec<String> = row.iter().map(|c| c.replace('|', "\\|")).collect();
+        cells.resize(columns, String::new());
+        format!("| {} |", cells.join(" | "))
+    };
+    let mut lines = vec![line(&rows[0]), format!("|{}", " --- |".repeat(columns))];
+    lines.extend(rows[1..].iter().map(|row| line(row)));
+    format!("\n\n{}\n\n", lines.join("\n"))
+}
+
+/// Trim spaces around each line and collapse runs of blank lines into one.
+fn tidy_lines(text: &str) -> String {
+    let mut out: Vec<&str> = Vec::new();
+    for line in text.lines().map(|line| line.trim_matches(' ')) {
+        if line.is_empty() && matches!(out.last(), None | Some(&"")) {
+            continue;
+        }
+        out.push(line);
+    }
+    while out.last() == Some(&"") {
+        out.pop();
+    }
+    out.join("\n")
+}
+
+/// Decode HTML character references: numeric ones and the named ones common
+/// in email. Unknown names are kept as they are.
+pub fn decode_entities(text: &str) -> String {
+    pattern(r"&(#[0-9]{1,7}|#[xX][0-9a-fA-F]{1,6}|[a-zA-Z][a-zA-Z0-9]{1,31});")
+        .replace_all(text, |caps: &Captures| {
+            let name = &caps[1];
+            let hex = name.strip_prefix("#x").or_else(|| name.strip_prefix("#X"));
+            let decoded = if let Some(hex) = hex {
+                u32::from_str_radix(hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .map(String::from)
+            } else if let Some(decimal) = name.strip_prefix('#') {
+                decimal
+                    .parse()
+                    .ok()
+                    .and_then(char::from_u32)
+                    .map(String::from)
+            } else {
+                named_entity(name).map(str::to_string)
+            };
+            decoded.unwrap_or_else(|| caps[0].to_string())
+        })
+        .into_owned()
+}
+
+/// The text of a named character reference such as `amp` or `rsquo`.
+fn named_entity(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "amp" => "&",
+        "lt" => "<",
+        "gt" => ">",
+        "quot" => "\"",
+        "apos" => "'",
+        "nbsp" | "ensp" | "emsp" | "thinsp" => " ",
+        "zwnj" | "zwj" | "shy" => "",
+        "ndash" => "\u{2013}",
+        "mdash" => "\u{2014}",
+        "lsquo" => "\u{2018}",
+        "rsquo" => "\u{2019}",
+        "ldquo" => "\u{201C}",
+        "rdquo" => "\u{201D}",
+        "laquo" => "\u{AB}",
+        "raquo" => "\u{BB}",
+        "hellip" => "\u{2026}",
+        "bull" => "\u{2022}",
+        "middot" => "\u{B7}",
+        "copy" => "\u{A9}",
+        "reg" => "\u{AE}",
+        "trade" => "\u{2122}",
+        "euro" => "\u{20AC}",
+        "pound" => "\u{A3}",
+        "yen" => "\u{A5}",
+        "cent" => "\u{A2}",
+        "deg" => "\u{B0}",
+        "times" => "\u{D7}",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_body_format_from_str() {
+        assert_eq!("".parse::<BodyFormat>(), Ok(BodyFormat::Text));
+        assert_eq!("HTML".parse::<BodyFormat>(), Ok(BodyFormat::Html));
+        assert_eq!("md".parse::<BodyFormat>(), Ok(BodyFormat::Markdown));
+        assert!("rtf".parse::<BodyFormat>().is_err());
+    }
+
+    #[test]
+    fn test_markdown_to_html() {
+        let html = markdown_to_html(
+            "# Plan\n\nShip **v2** on _Friday_.\nSee [docs](https://example.com).\n\n- one\n- two\n\n1. first\n\n```\nlet x = 1 < 2;\n```",
+        );
+        assert_eq!(
+            html,
+            "<h1>Plan</h1>\n\
+             <p>Ship <strong>v2</strong> on <em>Friday</em>.\nSee <a href=\"https://example.com\">docs</a>.</p>\n\
+             <ul>\n<li>one</li>\n<li>two</li>\n</ul>\n\
+             <ol>\n<li>first</li>\n</ol>\n\
+             <pre style=\"font-family: Consolas, monospace; background-color: #f4f4f4; padding: 8px;\"><code>let x = 1 &lt; 2;\n</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn test_markdown_escapes_html() {
+        assert_eq!(
+            markdown_to_html("<b>hi</b> & bye"),
+            "<p>&lt;b&gt;hi&lt;/b&gt; &amp; bye</p>\n"
+        );
+    }
+
+    #[test]
+    fn test_sanitize_html() {
+        let html = sanitize_html(
+            "<p onclick=\"steal()\" style=\"color: red; position: fixed; background: url(http://x/t.gif)\">Hi</p>\
+             <script>alert(1)</script><a href='javascript:alert(1)'>x</a>",
+        );
+        assert_eq!(html, "<p style=\"color: red\">Hi</p><a>x</a>");
+    }
+
+    #[test]
+    fn test_sanitize_html_blocks_evasions() {
+        let html = sanitize_html(
+            "<a href=javascript:alert(1)>a</a>\
+             <a href=\"&#106;avascript:alert(1)\">b</a>\
+             <svg><script>alert(1)</script></svg>\
+             <math><mi>c</mi></math>\
+             <form action=\"https://evil.example\"><input name=\"password\"></form>\
+             <meta http-equiv=\"refresh\" content=\"0;url=https://evil.example\">\
+             <a href=\"https://example.com\">d</a><img src=\"cid:logo\">",
+        );
+        for blocked in [
+            "javascript",
+            "alert",
+            "<svg",
+            "<math",
+            "<form",
+            "<input",
+            "<meta",
+        ] {
+            assert!(!html.contains(blocked), "{} survived in {}", blocked, html);
+        }
+        assert!(html.starts_with("<a>a</a><a>b</a>"));
+        assert!(html.ends_with("<a href=\"https://example.com\">d</a><img src=\"cid:logo\">"));
+    }
+
+    #[test]
+    fn test_markdown_quotes_and_inline_code() {
+        assert_eq!(
+            markdown_to_html("> Run `a<b`"),
+            "<blockquote style=\"margin: 0 0 0 8px; padding-left: 8px; border-left: 3px solid #cccccc; color: #555555;\">\n\
+             <p>Run <code style=\"font-family: Consolas, monospace; background-color: #f4f4f4;\">a&lt;b</code></p>\n\
+             </blockquote>\n"
+        );
+    }
+
+    #[test]
+    fn test_sanitize_style() {
+        assert_eq!(
+            sanitize_style("COLOR: blue; width: expression(alert(1)); ;font-weight:bold"),
+            "color: blue; font-weight: bold"
+        );
+        assert_eq!(sanitize_style("behavior: url(x.htc)"), "");
+    }
+
+    #[test]
+    fn test_html_to_markdown() {
+        let html = "<html><head><style>p { color: red }</style></head><body>\
+            <h1>Release   notes</h1>\
+            <p>Version <b>2.0</b> is <em>out</em>, see <a href=\"https://example.com/v2\">the docs</a>.<br>Thanks!</p>\
+            <ul><li>Faster</li><li>Smaller<ol><li>50% less memory</li><li>No &lt;deps&gt;</li></ol></li></ul>\
+            <table><tr><th>Plan</th><th>Price</th></tr><tr><td>Pro</td><td>&euro;10</td></tr></table>\
+            <blockquote><p>Best release yet</p></blockquote>\
+            <pre>let  x = 1;\n  x + 1</pre>\
+            <img src=\"https://example.com/pixel.gif\" width=\"1\"><img src=\"logo.png\" alt=\"Logo\">\
+            </body></html>";
+        assert_eq!(
+            html_to_markdown(html),
+            "# Release notes\n\n\
+             Version **2.0** is *out*, see [the docs](https://example.com/v2).\nThanks!\n\n\
+             - Faster\n\
+             - Smaller\n  1. 50% less memory\n  2. No <deps>\n\n\
+             | Plan | Price |\n| --- | --- |\n| Pro | \u{20AC}10 |\n\n\
+             > Best release yet\n\n\
+             ```\nlet  x = 1;\n  x + 1\n```\n\n\
+             ![Logo](logo.png)"
+        );
+    }
+
+    #[test]
+    fn test_html_to_markdown_unwraps_layout_tables() {
+        let html = "<table><tr><td><table><tr><td><h2>News</h2><p>First story</p></td></tr></table></td></tr>\
+            <tr><td><p>Second story</p></td></tr></table>";
+        assert_eq!(
+            html_to_markdown(html),
+            "## News\n\nFirst story\n\nSecond story"
+        );
+    }
+
+    #[test]
+    fn test_decode_entities() {
+        assert_eq!(
+            decode_entities("Tom &amp; Jerry&#39;s &#x1F600; &rsquo;&nbsp;&bogus;"),
+            "Tom & Jerry's \u{1F600} \u{2019} &bogus;"
+        );
+    }
+
+    #[test]
+    fn test_replace_inline_images() {
+        let attachments = vec![AttachmentInfo {
+            filename: "chart.png".to_string(),
+            mime_type: "image/png".to_string(),
+            content_id: Some("chart@example.com".to_string()),
+            ..Default::default()
+        }];
+        let html = "<p>Sales:<img src=\"cid:chart%40example.com\"> \
+            <img src=\"CID:missing\" alt=\"Logo\"><img src=\"cid:other\">\
+            <img src=\"https://example.com/a.png\"></p>";
+        assert_eq!(
+            replace_inline_images(html, &attachments, InlineImages::Marker),
+            "<p>Sales:[image: chart.png] [image: Logo][image]\
+            <img src=\"https://example.com/a.png\"></p>"
+        );
+        assert_eq!(
+            replace_inline_images(html, &attachments, InlineImages::Drop),
+            "<p>Sales: <img src=\"https://example.com/a.png\"></p>"
+        );
+        assert_eq!(
+            replace_inline_image_refs(
+                "See [cid:chart@example.com] below",
+                &attachments,
+                InlineImages::Marker
+            ),
+            "See [image: chart.png] below"
+        );
+        assert_eq!("drop".parse::<InlineImages>(), Ok(InlineImages::Drop));
+        assert!("hide".parse::<InlineImages>().is_err());
+    }
+
+    #[test]
+    fn test_render_body() {
+        let (text, html) = render_body("Hello", BodyFormat::Text);
+        assert_eq!(text, "Hello");
+        assert!(html.is_none());
+
+        let (text, html) = render_body("<p>Hello <b>there</b></p>", BodyFormat::Html);
+        assert!(text.contains("Hello"));
+        assert_eq!(html.as_deref(), Some("<p>Hello <b>there</b></p>"));
+
+        let (text, html) = render_body("**Hi**", BodyFormat::Markdown);
+        assert_eq!(text, "**Hi**");
+        assert!(html.unwrap().contains("<strong>Hi</strong>"));
+    }
+}
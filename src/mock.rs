@@ -0,0 +1,579 @@
+//! An in-memory [`MailProvider`] serving fixture messages.
+//!
+//! [`MockMailProvider`] keeps Gmail API messages in memory and answers list,
+//! get, modify, archive, trash, delete, send, and the other [`MailProvider`]
+//! calls from them, so tools and the processing pipeline
+//! can be developed and tested without a Google account or network access.
+//! [`fixtures`] covers plain, multipart, HTML-only, raw, and malformed messages.
+
+use crate::compose::OutgoingEmail;
+use crate::modify::ARCHIVE_QUERY_LIMIT;
+use crate::send::SendResponse;
+use crate::{summarize_message, threads, EmailResponse, EmailSummary, FetchOptions};
+use crate::{GmailMcpError, MailProvider};
+use async_trait::async_trait;
+use google_gmail1::api::{Message, MessagePart, MessagePartBody, MessagePartHeader};
+use std::sync::Mutex;
+
+/// A mailbox held in memory, searched with a small subset of Gmail's query syntax.
+///
+/// Queries understand `in:`, `is:`, `label:`, `from:`, and `subject:` terms and
+/// plain words, which must appear in the sender, subject, snippet, or body.
+/// Other operators, such as date ranges, are ignored.
+#[derive(Debug, Default)]
+pub struct MockMailProvider {
+    messages: Mutex<Vec<Message>>,
+    sent: Mutex<Vec<OutgoingEmail>>,
+}
+
+impl MockMailProvider {
+    /// A mailbox holding `messages`.
+    pub fn new(messages: Vec<Message>) -> Self {
+        Self {
+            messages: Mutex::new(messages),
+            sent: Mutex::default(),
+        }
+    }
+
+    /// A mailbox holding the [`fixtures`].
+    pub fn with_fixtures() -> Self {
+        Self::new(fixtures())
+    }
+
+    /// Label IDs currently on a message, or `None` when there is no such message.
+    pub fn labels(&self, id: &str) -> Option<Vec<String>> {
+        self.messages
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .find(|m| m.id.as_deref() == Some(id))
+            .map(|m| m.label_ids.clone().unwrap_or_default())
+    }
+
+    /// Every email sent so far, oldest first.
+    pub fn sent(&self) -> Vec<OutgoingEmail> {
+        self.sent.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+#[async_trait]
+impl MailProvider for MockMailProvider {
+    async fn list(
+        &self,
+        query: &str,
+        options: &FetchOptions,
+    ) -> Result<EmailResponse, GmailMcpError> {
+        let mut matching: Vec<Message> = self
+            .messages
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .filter(|m| {
+                options.include_spam_trash || !(has_label(m, "SPAM") || has_label(m, "TRASH"))
+            })
+            .filter(|m| {
+                options
+                    .label
+                    .as_deref()
+                    .is_none_or(|label| has_label(m, label))
+            })
+            .filter(|m| matches_query(m, query))
+            .cloned()
+            .collect();
+        matching.sort_by_key(|m| std::cmp::Reverse(m.internal_date.unwrap_or_default()));
+
+        let start = match options.page_token.as_deref() {
+            Some(token) => token
+                .parse()
+                .map_err(|_| GmailMcpError::Invalid(format!("Invalid page token '{}'", token)))?,
+            None => 0,
+        };
+        let end = matching
+            .len()
+            .min(start + options.max_results.clamp(1, 500) as usize);
+        let next_page_token = (end < matching.len()).then(|| end.to_string());
+
+        let mut entries = Vec::new();
+        for message in matching.get(start..end).unwrap_or_default() {
+            if let Some(summary) = summarize_message(message) {
+                let thread_id = message
+                    .thread_id
+                    .clone()
+                    .unwrap_or_else(|| summary.id.clone());
+                entries.push((
+                    thread_id,
+                    message.internal_date.unwrap_or_default(),
+                    summary,
+                ));
+            }
+        }
+
+        let count = entries.len();
+        let (emails, threads) = if options.group_by_thread {
+            (Vec::new(), Some(threads::group_by_thread(entries)))
+        } else {
+            (
+                entries.into_iter().map(|(_, _, summary)| summary).collect(),
+                None,
+            )
+        };
+        Ok(EmailResponse {
+            count,
+            emails,
+            next_page_token,
+            threads,
+        })
+    }
+
+    async fn get(&self, id: &str) -> Result<EmailSummary, GmailMcpError> {
+        let messages = self.messages.lock().unwrap_or_else(|e| e.into_inner());
+        messages
+            .iter()
+            .find(|m| m.id.as_deref() == Some(id))
+            .and_then(summarize_message)
+            .ok_or_else(|| not_found(id))
+    }
+
+    async fn message(&self, id: &str) -> Result<Message, GmailMcpError> {
+        let messages = self.messages.lock().unwrap_or_else(|e| e.into_inner());
+        messages
+            .iter()
+            .find(|m| m.id.as_deref() == Some(id))
+            .cloned()
+            .ok_or_else(|| not_found(id))
+    }
+
+    /// Only messages stored with their source, such as the `raw` fixture, have one.
+    async fn raw(&self, id: &str) -> Result<Vec<u8>, GmailMcpError> {
+        self.message(id)
+            .await?
+            .raw
+            .ok_or_else(|| GmailMcpError::Parse(format!("message {}: no raw source", id)))
+    }
+
+    /// Labels are only known by ID, so names are returned unchanged.
+    async fn label_ids(&self, labels: &[String]) -> Result<Vec<String>, GmailMcpError> {
+        Ok(labels.to_vec())
+    }
+
+    async fn modify(
+        &self,
+        ids: &[String],
+        add_label_ids: &[String],
+        remove_label_ids: &[String],
+    ) -> Result<usize, GmailMcpError> {
+        if ids.is_empty() {
+            return Err(GmailMcpError::Invalid(
+                "At least one message ID is required".to_string(),
+            ));
+        }
+        if add_label_ids.is_empty() && remove_label_ids.is_empty() {
+            return Err(GmailMcpError::Invalid(
+                "Nothing to modify: no labels to add or remove".to_string(),
+            ));
+        }
+
+        let mut messages = self.messages.lock().unwrap_or_else(|e| e.into_inner());
+        for id in ids {
+            let message = messages
+                .iter_mut()
+                .find(|m| m.id.as_deref() == Some(id.as_str()))
+                .ok_or_else(|| not_found(id))?;
+            let labels = message.label_ids.get_or_insert_with(Vec::new);
+            labels.retain(|label| !remove_label_ids.contains(label));
+            for label in add_label_ids {
+                if !labels.contains(label) {
+                    labels.push(label.clone());
+                }
+            }
+        }
+        Ok(ids.len())
+    }
+
+    async fn archive(&self, ids: &[String]) -> Result<usize, GmailMcpError> {
+        self.modify(ids, &[], &["INBOX".to_string()]).await
+    }
+
+    async fn archive_matching(&self, query: &str) -> Result<Vec<String>, GmailMcpError> {
+        let query = query.trim();
+        if query.is_empty() {
+            return Err(GmailMcpError::Invalid(
+                "Archiving by query requires a non-empty query".to_string(),
+            ));
+        }
+        let ids: Vec<String> = self
+            .messages
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .filter(|m| has_label(m, "INBOX") && matches_query(m, query))
+            .filter_map(|m| m.id.clone())
+            .take(ARCHIVE_QUERY_LIMIT as usize)
+            .collect();
+        if !ids.is_empty() {
+            self.archive(&ids).await?;
+        }
+        Ok(ids)
+    }
+
+    /// Trashed messages keep their labels and gain `TRASH`.
+    async fn trash(&self, ids: &[String]) -> Result<usize, GmailMcpError> {
+        self.modify(ids, &["TRASH".to_string()], &[]).await
+    }
+
+    async fn untrash(&self, ids: &[String]) -> Result<usize, GmailMcpError> {
+        self.modify(ids, &[], &["TRASH".to_string()]).await
+    }
+
+    /// Deletes every message or, when an ID is unknown, none.
+    async fn delete(&self, ids: &[String]) -> Result<usize, GmailMcpError> {
+        if ids.is_empty() {
+            return Err(GmailMcpError::Invalid(
+                "At least one message ID is required".to_string(),
+            ));
+        }
+        let mut messages = self.messages.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(missing) = ids.iter().find(|id| {
+            !messages
+                .iter()
+                .any(|m| m.id.as_deref() == Some(id.as_str()))
+        }) {
+            return Err(not_found(missing));
+        }
+        messages.retain(|m| !m.id.as_ref().is_some_and(|id| ids.contains(id)));
+        Ok(ids.len())
+    }
+
+    async fn send(
+        &self,
+        email: &OutgoingEmail,
+        thread_id: Option<&str>,
+    ) -> Result<SendResponse, GmailMcpError> {
+        email.to_rfc822()?;
+        let mut sent = self.sent.lock().unwrap_or_else(|e| e.into_inner());
+        sent.push(email.clone());
+        let id = format!("sent-{}", sent.len());
+        Ok(SendResponse {
+            thread_id: Some(thread_id.map_or_else(|| id.clone(), str::to_string)),
+            id,
+        })
+    }
+}
+
+/// The error Gmail returns for an unknown message ID.
+fn not_found(id: &str) -> GmailMcpError {
+    GmailMcpError::Api {
+        status: 404,
+        message: format!("Requested entity was not found: {}", id),
+    }
+}
+
+fn has_label(message: &Message, label: &str) -> bool {
+    message
+        .label_ids
+        .iter()
+        .flatten()
+        .any(|id| id.eq_ignore_ascii_case(label))
+}
+
+/// Whether a message matches every supported term of `query`.
+fn matches_query(message: &Message, query: &str) -> bool {
+    let Some(summary) = summarize_message(message) else {
+        return false;
+    };
+    let contains = |field: &str, needle: &str| field.to_lowercase().contains(needle);
+    query.split_whitespace().all(|term| {
+        let term = term.to_lowercase();
+        match term.split_once(':') {
+            Some(("in", "anywhere")) => true,
+            Some(("in" | "label", label)) => has_label(message, label),
+            Some(("is", "read")) => !has_label(message, "UNREAD"),
+            Some(("is", flag)) => has_label(message, flag),
+            Some(("from", from)) => contains(&summary.from, from),
+            Some(("subject", subject)) => contains(&summary.subject, subject),
+            Some(_) => true,
+            None => [
+                &summary.from,
+                &summary.subject,
+                &summary.snippet,
+                &summary.body_raw,
+            ]
+            .iter()
+            .any(|field| contains(field, &term)),
+        }
+    })
+}
+
+fn header(name: &str, value: &str) -> MessagePartHeader {
+    MessagePartHeader {
+        name: Some(name.to_string()),
+        value: Some(value.to_string()),
+    }
+}
+
+fn part(mime_type: &str, data: &[u8]) -> MessagePart {
+    MessagePart {
+        mime_type: Some(mime_type.to_string()),
+        filename: Some(String::new()),
+        body: Some(MessagePartBody {
+            size: Some(data.len() as i32),
+            data: Some(data.to_vec()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+fn message(id: &str, thread_id: &str, date: i64, labels: &[&str], snippet: &str) -> Message {
+    Message {
+        id: Some(id.to_string()),
+        thread_id: Some(thread_id.to_string()),
+        internal_date: Some(date),
+        label_ids: Some(labels.iter().map(|l| l.to_string()).collect()),
+        snippet: Some(snippet.to_string()),
+        ..Default::default()
+    }
+}
+
+/// Deterministic messages covering the shapes Gmail returns, newest last:
+///
+/// - `plain`: a single `text/plain` body, unread in the inbox.
+/// - `multipart`: `multipart/mixed` with text and HTML alternatives and a PDF
+///   attachment, replying in the `plain` thread.
+/// - `html`: a `text/html` body only, starred and important.
+/// - `raw`: only the RFC 822 source, as fetched in `raw` format.
+/// - `malformed`: no headers and a body that is not valid UTF-8.
+/// - `spam`: a message in spam, hidden unless spam and trash are included.
+pub fn fixtures() -> Vec<Message> {
+    let plain = Message {
+        payload: Some(MessagePart {
+            headers: Some(vec![
+                header("From", "Alice <alice@example.com>"),
+                header("Subject", "Lunch on Friday?"),
+            ]),
+            ..part(
+                "text/plain",
+                b"Are you free for lunch on Friday? See https://example.com/menu",
+            )
+        }),
+        ..message(
+            "plain",
+            "thread-lunch",
+            1_700_000_000_000,
+            &["INBOX", "UNREAD"],
+            "Are you free for lunch on Friday?",
+        )
+    };
+
+    let multipart = Message {
+        payload: Some(MessagePart {
+            mime_type: Some("multipart/mixed".to_string()),
+            headers: Some(vec![
+                header("From", "Bob <bob@example.com>"),
+                header("Subject", "Re: Lunch on Friday?"),
+            ]),
+            parts: Some(vec![
+                MessagePart {
+                    mime_type: Some("multipart/alternative".to_string()),
+                    parts: Some(vec![
+                        part("text/plain", b"Friday works. Menu attached."),
+                        part("text/html", b"<p>Friday works. <b>Menu</b> attached.</p>"),
+                    ]),
+                    ..Default::default()
+                },
+                MessagePart {
+                    filename: Some("menu.pdf".to_string()),
+                    mime_type: Some("application/pdf".to_string()),
+                    body: Some(MessagePartBody {
+                        attachment_id: Some("attachment-menu".to_string()),
+                        size: Some(2048),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            ]),
+            ..Default::default()
+        }),
+        ..message(
+            "multipart",
+            "thread-lunch",
+            1_700_000_100_000,
+            &["INBOX"],
+            "Friday works. Menu attached.",
+        )
+    };
+
+    let html = Message {
+        payload: Some(MessagePart {
+            headers: Some(vec![
+                header("From", "Newsletter <news@example.org>"),
+                header("Subject", "This week in Rust"),
+            ]),
+            ..part(
+                "text/html",
+                b"<html><body><h1>This week</h1><p>Read more at <a href=\"https://example.org/rust\">example.org</a></p></body></html>",
+            )
+        }),
+        ..message("html", "thread-news", 1_700_000_200_000, &["INBOX", "STARRED", "IMPORTANT"], "This week")
+    };
+
+    let raw = Message {
+        raw: Some(
+            b"From: carol@example.com\r\nSubject: Invoice 42\r\nContent-Type: text/plain\r\n\r\nYour invoice is ready."
+                .to_vec(),
+        ),
+        ..message("raw", "thread-invoice", 1_700_000_300_000, &["INBOX"], "Your invoice is ready.")
+    };
+
+    let malformed = Message {
+        payload: Some(part("text/plain", &[0xff, 0xfe, 0x00, 0x80])),
+        ..message(
+            "malformed",
+            "thread-malformed",
+            1_700_000_400_000,
+            &["INBOX", "UNREAD"],
+            "",
+        )
+    };
+
+    let spam = Message {
+        payload: Some(MessagePart {
+            headers: Some(vec![
+                header("From", "Prize <win@spam.example>"),
+                header("Subject", "You won"),
+            ]),
+            ..part("text/plain", b"Claim your prize")
+        }),
+        ..message(
+            "spam",
+            "thread-spam",
+            1_700_000_500_000,
+            &["SPAM"],
+            "Claim your prize",
+        )
+    };
+
+    vec![plain, multipart, html, raw, malformed, spam]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> FetchOptions {
+        FetchOptions::new().max_results(10)
+    }
+
+    #[tokio::test]
+    async fn test_list_fixtures() {
+        let mock = MockMailProvider::with_fixtures();
+        let inbox = mock.list("in:inbox", &options()).await.unwrap();
+        let ids: Vec<&str> = inbox.emails.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, ["malformed", "raw", "html", "multipart", "plain"]);
+
+        let unread = mock.list("is:unread lunch", &options()).await.unwrap();
+        assert_eq!(unread.count, 1);
+        assert_eq!(unread.emails[0].subject, "Lunch on Friday?");
+
+        let spam = options().include_spam_trash(true);
+        assert_eq!(mock.list("prize", &spam).await.unwrap().count, 1);
+        assert_eq!(mock.list("prize", &options()).await.unwrap().count, 0);
+
+        let first = mock.list("", &options().max_results(2)).await.unwrap();
+        assert_eq!(first.next_page_token.as_deref(), Some("2"));
+        let rest = options().max_results(10).page_token("2".to_string());
+        assert_eq!(mock.list("", &rest).await.unwrap().count, 3);
+
+        let threaded = options().group_by_thread(true);
+        let threads = mock
+            .list("lunch", &threaded)
+            .await
+            .unwrap()
+            .threads
+            .unwrap();
+        assert_eq!(threads.len(), 1);
+        assert_eq!(threads[0].emails.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_get_fixture_shapes() {
+        let mock = MockMailProvider::with_fixtures();
+
+        let multipart = mock.get("multipart").await.unwrap();
+        assert_eq!(multipart.body_raw, "Friday works. Menu attached.");
+        assert_eq!(
+            multipart.body_text.as_deref(),
+            Some("Friday works. Menu attached.")
+        );
+        assert_eq!(
+            multipart.body_html.as_deref(),
+            Some("<p>Friday works. <b>Menu</b> attached.</p>")
+        );
+        assert_eq!(multipart.attachments[0].filename, "menu.pdf");
+
+        let html = mock.get("html").await.unwrap();
+        assert!(html.body_raw.starts_with("<html>"));
+        assert_eq!(html.body_html.as_deref(), Some(html.body_raw.as_str()));
+        assert!(html.body_text.unwrap().contains("This week"));
+        assert!(html.is_starred && html.is_important);
+
+        let raw = mock.get("raw").await.unwrap();
+        assert_eq!(raw.from, "carol@example.com");
+        assert_eq!(raw.body_raw, "Your invoice is ready.");
+
+        let malformed = mock.get("malformed").await.unwrap();
+        assert_eq!(malformed.subject, "No Subject");
+        assert_eq!(malformed.from, "Unknown Sender");
+        assert!(malformed.body_raw.is_empty());
+        assert!(malformed.is_unread);
+
+        let missing = mock.get("nope").await.unwrap_err();
+        assert_eq!(missing.status(), Some(404));
+    }
+
+    #[tokio::test]
+    async fn test_modify_and_send() {
+        let mock = MockMailProvider::with_fixtures();
+        let ids = vec!["plain".to_string()];
+        let count = mock
+            .modify(&ids, &["STARRED".to_string()], &["UNREAD".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(mock.labels("plain").unwrap(), ["INBOX", "STARRED"]);
+        assert!(mock
+            .modify(&[], &["STARRED".to_string()], &[])
+            .await
+            .is_err());
+
+        let email = OutgoingEmail::new(vec!["alice@example.com".to_string()], "Hi", "Hello");
+        let sent = mock.send(&email, Some("thread-lunch")).await.unwrap();
+        assert_eq!(sent.id, "sent-1");
+        assert_eq!(sent.thread_id.as_deref(), Some("thread-lunch"));
+        assert_eq!(mock.sent()[0].subject, "Hi");
+    }
+
+    #[tokio::test]
+    async fn test_archive_trash_and_delete() {
+        let mock = MockMailProvider::with_fixtures();
+        let ids = vec!["plain".to_string()];
+        assert_eq!(mock.archive(&ids).await.unwrap(), 1);
+        assert_eq!(mock.labels("plain").unwrap(), ["UNREAD"]);
+
+        assert_eq!(mock.archive_matching("invoice").await.unwrap(), ["raw"]);
+        assert!(mock.archive_matching("invoice").await.unwrap().is_empty());
+        assert!(mock.archive_matching(" ").await.is_err());
+
+        let ids = vec!["multipart".to_string()];
+        mock.trash(&ids).await.unwrap();
+        assert_eq!(mock.list("lunch", &options()).await.unwrap().count, 1);
+        mock.untrash(&ids).await.unwrap();
+        assert_eq!(mock.list("lunch", &options()).await.unwrap().count, 2);
+
+        let ids = vec!["multipart".to_string(), "nope".to_string()];
+        assert_eq!(mock.delete(&ids).await.unwrap_err().status(), Some(404));
+        assert!(mock.labels("multipart").is_some());
+        assert_eq!(mock.delete(&ids[..1]).await.unwrap(), 1);
+        assert!(mock.labels("multipart").is_none());
+    }
+}
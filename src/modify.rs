@@ -0,0 +1,423 @@
+//! Message state changes (read/unread, labels) via `messages.batchModify`, and
+//! archiving, trashing, and deleting messages.
+
+use crate::capabilities::{self, Capability};
+use crate::{provider, GmailHub, GmailMcpError, SCOPE_FULL, SCOPE_MODIFY};
+use google_gmail1::api::{BatchDeleteMessagesRequest, BatchModifyMessagesRequest};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+/// Gmail accepts at most 1000 message IDs per `batchModify` request.
+const BATCH_LIMIT: usize = 1000;
+
+/// Result of a modify operation, returned to MCP clients as JSON.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ModifyResponse {
+    /// The action that was applied (e.g. `mark_read`).
+    pub action: String,
+    /// The message IDs that were modified.
+    pub ids: Vec<String>,
+    /// The number of messages modified.
+    pub count: usize,
+}
+
+/// Add and remove label IDs on a set of messages, batching as needed.
+///
+/// Returns the number of messages modified.
+pub async fn modify_messages(
+    hub: &GmailHub,
+    ids: &[String],
+    add_label_ids: &[String],
+    remove_label_ids: &[String],
+) -> Result<usize, GmailMcpError> {
+    if ids.is_empty() {
+        return Err(GmailMcpError::Invalid(
+            "At least one message ID is required".to_string(),
+        ));
+    }
+    if add_label_ids.is_empty() && remove_label_ids.is_empty() {
+        return Err(GmailMcpError::Invalid(
+            "Nothing to modify: no labels to add or remove".to_string(),
+        ));
+    }
+
+    for chunk in ids.chunks(BATCH_LIMIT) {
+        info!(
+            "Gmail API: Modifying {} messages (+{:?} -{:?})",
+            chunk.len(),
+            add_label_ids,
+            remove_label_ids
+        );
+        let request = BatchModifyMessagesRequest {
+            ids: Some(chunk.to_vec()),
+            add_label_ids: Some(add_label_ids.to_vec()),
+            remove_label_ids: Some(remove_label_ids.to_vec()),
+        };
+        crate::quota::record("messages.batchModify", 1);
+        hub.users()
+            .messages_batch_modify(request, "me")
+            .add_scope(SCOPE_MODIFY)
+            .doit()
+            .await
+            .map_err(|e| {
+                error!("Gmail API: Failed to modify messages: {}", e);
+                e
+            })?;
+    }
+
+    Ok(ids.len())
+}
+
+/// Add (`on = true`) or remove a single system label such as `STARRED`.
+pub async fn set_flag(
+    hub: &GmailHub,
+    ids: &[String],
+    label: &str,
+    on: bool,
+) -> Result<usize, GmailMcpError> {
+    let label = vec![label.to_string()];
+    if on {
+        modify_messages(hub, ids, &label, &[]).await
+    } else {
+        modify_messages(hub, ids, &[], &label).await
+    }
+}
+
+/// Mark messages as read (`read = true`) or unread by toggling the `UNREAD` label.
+pub async fn set_read(hub: &GmailHub, ids: &[String], read: bool) -> Result<usize, GmailMcpError> {
+    set_flag(hub, ids, "UNREAD", !read).await
+}
+
+/// Labels to add and remove so messages end up read or unread, starred or not,
+/// and important or not; `None` leaves that state unchanged.
+fn flag_changes(
+    read: Option<bool>,
+    starred: Option<bool>,
+    important: Option<bool>,
+) -> (Vec<String>, Vec<String>) {
+    let mut add = Vec::new();
+    let mut remove = Vec::new();
+    for (label, on) in [
+        ("UNREAD", read.map(|r| !r)),
+        ("STARRED", starred),
+        ("IMPORTANT", important),
+    ] {
+        match on {
+            Some(true) => add.push(label.to_string()),
+            Some(false) => remove.push(label.to_string()),
+            None => {}
+        }
+    }
+    (add, remove)
+}
+
+/// Connect to Gmail and set the read, starred, and important states of messages
+/// in one request, returning JSON.
+pub async fn mark(
+    ids: Vec<String>,
+    read: Option<bool>,
+    starred: Option<bool>,
+    important: Option<bool>,
+) -> Result<String, GmailMcpError> {
+    capabilities::require(Capability::Modify)?;
+    let (add, remove) = flag_changes(read, starred, important);
+    if add.is_empty() && remove.is_empty() {
+        return Err(GmailMcpError::Invalid(
+            "Nothing to change; set read, starred, or important".to_string(),
+        ));
+    }
+    let ids = clean_ids(ids);
+    let count = provider::current()
+        .await?
+        .modify(&ids, &add, &remove)
+        .await?;
+
+    let response = ModifyResponse {
+        action: "mark".to_string(),
+        ids,
+        count,
+    };
+    Ok(serde_json::to_string_pretty(&response)?)
+}
+
+/// Connect to Gmail and add/remove labels (by name or ID) on messages, returning JSON.
+pub async fn apply_labels(
+    ids: Vec<String>,
+    add_labels: Vec<String>,
+    remove_labels: Vec<String>,
+) -> Result<String, GmailMcpError> {
+    capabilities::require(Capability::Modify)?;
+    let ids = clean_ids(ids);
+    let provider = provider::current().await?;
+    let add = provider.label_ids(&clean_ids(add_labels)).await?;
+    let remove = provider.label_ids(&clean_ids(remove_labels)).await?;
+    let count = provider.modify(&ids, &add, &remove).await?;
+
+    let response = ModifyResponse {
+        action: "modify_labels".to_string(),
+        ids,
+        count,
+    };
+    Ok(serde_json::to_string_pretty(&response)?)
+}
+
+/// Upper bound on how many messages a single query-based archive will touch.
+pub const ARCHIVE_QUERY_LIMIT: u32 = 5000;
+
+/// Archive messages by removing the `INBOX` label.
+pub async fn archive_messages(hub: &GmailHub, ids: &[String]) -> Result<usize, GmailMcpError> {
+    modify_messages(hub, ids, &[], &["INBOX".to_string()]).await
+}
+
+/// Archive every inbox message matching `query`, up to [`ARCHIVE_QUERY_LIMIT`].
+///
+/// Returns the archived message IDs.
+pub async fn archive_matching(hub: &GmailHub, query: &str) -> Result<Vec<String>, GmailMcpError> {
+    let query = query.trim();
+    if query.is_empty() {
+        return Err(GmailMcpError::Invalid(
+            "Archiving by query requires a non-empty query".to_string(),
+        ));
+    }
+
+    let (messages, _) =
+        crate::list_messages(hub, query, Some("INBOX"), ARCHIVE_QUERY_LIMIT, None, false).await?;
+    let ids: Vec<String> = messages.into_iter().filter_map(|m| m.id).collect();
+    info!(
+        "Gmail API: Archiving {} messages matching '{}'",
+        ids.len(),
+        query
+    );
+    if !ids.is_empty() {
+        archive_messages(hub, &ids).await?;
+    }
+    Ok(ids)
+}
+
+/// Connect to Gmail and archive the given messages, or those matching `query`
+/// when no IDs are given, returning JSON.
+pub async fn archive(ids: Vec<String>, query: Option<String>) -> Result<String, GmailMcpError> {
+    capabilities::require(Capability::Modify)?;
+    let ids = clean_ids(ids);
+    let provider = provider::current().await?;
+    let ids = match (ids.is_empty(), query) {
+        (false, _) => {
+            provider.archive(&ids).await?;
+            ids
+        }
+        (true, Some(query)) => provider.archive_matching(&query).await?,
+        (true, None) => {
+            return Err(GmailMcpError::Invalid(
+                "Archive requires message IDs or a query".to_string(),
+            ))
+        }
+    };
+
+    let response = ModifyResponse {
+        action: "archive".to_string(),
+        count: ids.len(),
+        ids,
+    };
+    Ok(serde_json::to_string_pretty(&response)?)
+}
+
+/// Environment variable that must be `true` or `1` for the server to permanently delete mail.
+pub const ALLOW_DELETE_ENV: &str = "GMAIL_ALLOW_PERMANENT_DELETE";
+
+/// Whether permanent deletion has been enabled through [`ALLOW_DELETE_ENV`].
+pub fn permanent_delete_allowed() -> bool {
+    std::env::var(ALLOW_DELETE_ENV)
+        .map(|v| matches!(v.trim().to_ascii_lowercase().as_str(), "1" | "true"))
+        .unwrap_or(false)
+}
+
+fn delete_disabled_error() -> GmailMcpError {
+    GmailMcpError::Invalid(format!(
+        "Permanent deletion is disabled; set {}=true to enable it, or use 'trash' instead",
+        ALLOW_DELETE_ENV
+    ))
+}
+
+/// Move messages to the trash (recoverable for 30 days).
+pub async fn trash_messages(hub: &GmailHub, ids: &[String]) -> Result<usize, GmailMcpError> {
+    if ids.is_empty() {
+        return Err(GmailMcpError::Invalid(
+            "At least one message ID is required".to_string(),
+        ));
+    }
+    for id in ids {
+        info!("Gmail API: Trashing message {}", id);
+        crate::quota::record("messages.trash", 1);
+        hub.users()
+            .messages_trash("me", id)
+            .add_scope(SCOPE_MODIFY)
+            .doit()
+            .await
+            .map_err(|e| {
+                error!("Gmail API: Failed to trash message {}: {}", id, e);
+                e
+            })?;
+    }
+    Ok(ids.len())
+}
+
+/// Restore messages from the trash.
+pub async fn untrash_messages(hub: &GmailHub, ids: &[String]) -> Result<usize, GmailMcpError> {
+    if ids.is_empty() {
+        return Err(GmailMcpError::Invalid(
+            "At least one message ID is required".to_string(),
+        ));
+    }
+    for id in ids {
+        info!("Gmail API: Untrashing message {}", id);
+        crate::quota::record("messages.untrash", 1);
+        hub.users()
+            .messages_untrash("me", id)
+            .add_scope(SCOPE_MODIFY)
+            .doit()
+            .await
+            .map_err(|e| {
+                error!("Gmail API: Failed to untrash message {}: {}", id, e);
+                e
+            })?;
+    }
+    Ok(ids.len())
+}
+
+/// Permanently delete messages, bypassing the trash. This cannot be undone.
+///
+/// Refuses to run unless `allow_permanent` is set, so callers must opt in explicitly.
+pub async fn delete_messages(
+    hub: &GmailHub,
+    ids: &[String],
+    allow_permanent: bool,
+) -> Result<usize, GmailMcpError> {
+    if !allow_permanent {
+        return Err(delete_disabled_error());
+    }
+    if ids.is_empty() {
+        return Err(GmailMcpError::Invalid(
+            "At least one message ID is required".to_string(),
+        ));
+    }
+
+    for chunk in ids.chunks(BATCH_LIMIT) {
+        info!("Gmail API: Permanently deleting {} messages", chunk.len());
+        let request = BatchDeleteMessagesRequest {
+            ids: Some(chunk.to_vec()),
+        };
+        crate::quota::record("messages.batchDelete", 1);
+        hub.users()
+            .messages_batch_delete(request, "me")
+            .add_scope(SCOPE_FULL)
+            .doit()
+            .await
+            .map_err(|e| {
+                error!("Gmail API: Failed to delete messages: {}", e);
+                e
+            })?;
+    }
+    Ok(ids.len())
+}
+
+/// Connect to Gmail and trash, untrash, or permanently delete messages, returning JSON.
+///
+/// `action` is one of `trash`, `untrash`, or `delete`; `delete` additionally
+/// requires [`permanent_delete_allowed`].
+pub async fn remove(action: &str, ids: Vec<String>) -> Result<String, GmailMcpError> {
+    capabilities::require(Capability::Modify)?;
+    let ids = clean_ids(ids);
+    if action == "delete" && !permanent_delete_allowed() {
+        // Check before connecting so a disabled delete never triggers an OAuth prompt
+        return Err(delete_disabled_error());
+    }
+
+    let provider = provider::current().await?;
+    let count = match action {
+        "trash" => provider.trash(&ids).await?,
+        "untrash" => provider.untrash(&ids).await?,
+        "delete" => provider.delete(&ids).await?,
+        other => {
+            return Err(GmailMcpError::Invalid(format!(
+                "Unknown removal action '{}'",
+                other
+            )))
+        }
+    };
+
+    let response = ModifyResponse {
+        action: action.to_string(),
+        ids,
+        count,
+    };
+    Ok(serde_json::to_string_pretty(&response)?)
+}
+
+/// Trim IDs (or label names) and drop blanks and duplicates, preserving order.
+pub(crate) fn clean_ids(ids: Vec<String>) -> Vec<String> {
+    let mut cleaned: Vec<String> = Vec::with_capacity(ids.len());
+    for id in ids {
+        let id = id.trim();
+        if !id.is_empty() && !cleaned.iter().any(|c| c == id) {
+            cleaned.push(id.to_string());
+        }
+    }
+    cleaned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flag_changes() {
+        assert_eq!(
+            flag_changes(Some(true), Some(true), None),
+            (vec!["STARRED".to_string()], vec!["UNREAD".to_string()])
+        );
+        assert_eq!(
+            flag_changes(Some(false), None, Some(false)),
+            (vec!["UNREAD".to_string()], vec!["IMPORTANT".to_string()])
+        );
+        assert_eq!(flag_changes(None, None, None), (vec![], vec![]));
+    }
+
+    #[test]
+    fn test_clean_ids() {
+        let ids = vec![
+            " a ".to_string(),
+            "".to_string(),
+            "b".to_string(),
+            "a".to_string(),
+        ];
+        assert_eq!(clean_ids(ids), vec!["a".to_string(), "b".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_archive_and_remove_through_provider() {
+        use crate::mock::MockMailProvider;
+        use std::sync::Arc;
+
+        let mock = Arc::new(MockMailProvider::with_fixtures());
+        let _mock = provider::install_scoped(mock.clone()).await;
+
+        let json = archive(vec![" plain ".to_string()], None).await.unwrap();
+        let response: ModifyResponse = serde_json::from_str(&json).unwrap();
+        assert_eq!(response.ids, ["plain"]);
+        assert_eq!(mock.labels("plain").unwrap(), ["UNREAD"]);
+
+        let json = archive(Vec::new(), Some("subject:rust".to_string()))
+            .await
+            .unwrap();
+        let response: ModifyResponse = serde_json::from_str(&json).unwrap();
+        assert_eq!(response.ids, ["html"]);
+        assert!(!mock.labels("html").unwrap().contains(&"INBOX".to_string()));
+        assert!(archive(Vec::new(), None).await.is_err());
+
+        remove("trash", vec!["raw".to_string()]).await.unwrap();
+        assert!(mock.labels("raw").unwrap().contains(&"TRASH".to_string()));
+        remove("untrash", vec!["raw".to_string()]).await.unwrap();
+        assert!(!mock.labels("raw").unwrap().contains(&"TRASH".to_string()));
+        assert!(remove("shred", vec!["raw".to_string()]).await.is_err());
+    }
+}
@@ -0,0 +1,136 @@
+//! Polling-based new-mail watcher, for mailboxes without a Pub/Sub topic.
+//!
+//! A background task lists the newest matching messages on an interval and
+//! raises a [`NewMailEvent`](crate::events::NewMailEvent) for IDs it has not
+//! seen before.
+
+use crate::events::{self, NewMailEvent};
+use crate::reademail::convert_html_to_text;
+use crate::{GmailHub, GmailMcpError};
+use std::collections::HashSet;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// Environment variable with the poll interval in seconds; setting it enables polling.
+pub const INTERVAL_ENV: &str = "GMAIL_POLL_INTERVAL";
+/// Environment variable with the Gmail query to watch.
+pub const QUERY_ENV: &str = "GMAIL_POLL_QUERY";
+
+/// Shortest allowed interval, to stay well within Gmail's quota.
+const MIN_INTERVAL: Duration = Duration::from_secs(10);
+/// How many of the newest messages each poll compares.
+const POLL_WINDOW: u32 = 50;
+
+/// Polling settings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PollConfig {
+    /// Time between polls.
+    pub interval: Duration,
+    /// Gmail search query selecting the messages to watch.
+    pub query: String,
+}
+
+impl PollConfig {
+    /// Read the configuration from the environment; `None` when polling is not enabled.
+    pub fn from_env() -> Option<Self> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+        let seconds = var(INTERVAL_ENV)?;
+        let seconds = match seconds.trim().parse::<u64>() {
+            Ok(seconds) => seconds,
+            Err(_) => {
+                warn!("Ignoring invalid {}: {}", INTERVAL_ENV, seconds);
+                return None;
+            }
+        };
+        Some(Self {
+            interval: Duration::from_secs(seconds).max(MIN_INTERVAL),
+            query: crate::resolve_query(var(QUERY_ENV)),
+        })
+    }
+}
+
+/// IDs listed ahead of every known message, newest first.
+///
+/// Gmail lists newest first, so new mail appears before the first known ID.
+/// Unknown IDs further down are older messages sliding into the window after
+/// others were archived or deleted, and are not reported.
+fn unseen_ids(known: &HashSet<String>, current: &[String]) -> Vec<String> {
+    current
+        .iter()
+        .take_while(|id| !known.contains(*id))
+        .cloned()
+        .collect()
+}
+
+/// List the IDs of the newest messages matching `query`.
+async fn newest_ids(hub: &GmailHub, query: &str) -> Result<Vec<String>, GmailMcpError> {
+    let (messages, _) = crate::list_messages(hub, query, None, POLL_WINDOW, None, false).await?;
+    Ok(messages.into_iter().filter_map(|m| m.id).collect())
+}
+
+/// Poll forever, publishing an event whenever unseen messages appear.
+///
+/// The first poll only records what is already there, so existing mail is not
+/// reported as new.
+pub async fn run(config: PollConfig) -> Result<(), GmailMcpError> {
+    let hub = crate::connect().await?;
+    let mut known: HashSet<String> = newest_ids(&hub, &config.query).await?.into_iter().collect();
+    info!(
+        "Polling '{}' every {}s ({} messages already present)",
+        config.query,
+        config.interval.as_secs(),
+        known.len()
+    );
+
+    loop {
+        tokio::time::sleep(config.interval).await;
+        let current = match newest_ids(&hub, &config.query).await {
+            Ok(current) => current,
+            Err(e) => {
+                error!("Failed to poll for new mail: {}", e);
+                continue;
+            }
+        };
+
+        let added = unseen_ids(&known, &current);
+        // Only remember the current window so the set cannot grow without bound
+        known = current.into_iter().collect();
+        if added.is_empty() {
+            continue;
+        }
+
+        let mut emails = Vec::new();
+        for id in &added {
+            let mut email = match crate::fetch_email(&hub, id).await {
+                Ok(email) => email,
+                Err(e) => {
+                    warn!("Gmail API: Skipping new message {}: {}", id, e);
+                    continue;
+                }
+            };
+            convert_html_to_text(&mut email).await;
+            emails.push(email);
+        }
+
+        events::publish(NewMailEvent {
+            source: "poll".to_string(),
+            history_id: None,
+            message_ids: added,
+            emails,
+            received_at: crate::state::now(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unseen_ids() {
+        let known: HashSet<String> = ["b", "c"].iter().map(|s| s.to_string()).collect();
+        let current: Vec<String> = ["a", "e", "b", "d"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(unseen_ids(&known, &current), vec!["a", "e"]);
+        assert!(unseen_ids(&known, &[]).is_empty());
+    }
+}
@@ -0,0 +1,113 @@
+//! MCP progress notifications for long-running tool calls.
+//!
+//! A client asks for progress by sending a `progressToken` in a request's
+//! `_meta`. Transports that can deliver notifications while a request is still
+//! running install a [`Sink`] around request handling with [`with_sink`]; the
+//! `tools/call` handler scopes the call's token with [`track`], and long fetches
+//! call [`report`]. Without a token or a sink, reporting does nothing.
+
+use serde_json::{json, Value};
+use std::future::Future;
+use std::io::Write;
+use std::sync::Arc;
+use tracing::warn;
+
+/// Delivers a JSON-RPC notification to the client of the current request.
+pub type Sink = Arc<dyn Fn(Value) + Send + Sync>;
+
+/// Progress target of the current tool call.
+#[derive(Clone)]
+struct Reporter {
+    token: Value,
+    sink: Sink,
+}
+
+tokio::task_local! {
+    static SINK: Sink;
+    static REPORTER: Reporter;
+}
+
+/// Run `f` with notifications of its requests delivered to `sink`.
+pub async fn with_sink<F: Future>(sink: Sink, f: F) -> F::Output {
+    SINK.scope(sink, f).await
+}
+
+/// A sink writing notifications to standard output, for the stdio transport.
+pub fn stdout_sink() -> Sink {
+    Arc::new(|notification| {
+        let Ok(line) = serde_json::to_string(&notification) else {
+            return;
+        };
+        let mut stdout = std::io::stdout().lock();
+        if let Err(e) = writeln!(stdout, "{}", line).and_then(|_| stdout.flush()) {
+            warn!("Could not write progress notification: {}", e);
+        }
+    })
+}
+
+/// The `progressToken` in a request's `_meta`, if the client sent one.
+pub fn token(meta: Option<&Value>) -> Option<Value> {
+    meta?
+        .get("progressToken")
+        .filter(|t| t.is_string() || t.is_number())
+        .cloned()
+}
+
+/// Run `f` reporting progress against `token`, when the transport can deliver it.
+pub async fn track<F: Future>(token: Option<Value>, f: F) -> F::Output {
+    let sink = SINK.try_with(|sink| sink.clone()).ok();
+    match (token, sink) {
+        (Some(token), Some(sink)) => REPORTER.scope(Reporter { token, sink }, f).await,
+        _ => f.await,
+    }
+}
+
+/// Report that `progress` of `total` items are done in the current tool call.
+pub fn report(progress: usize, total: usize, message: &str) {
+    // Outside a tracked call there is nobody to tell
+    let _ = REPORTER.try_with(|reporter| {
+        (reporter.sink)(notification(&reporter.token, progress, total, message))
+    });
+}
+
+fn notification(token: &Value, progress: usize, total: usize, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "notifications/progress",
+        "params": {
+            "progressToken": token,
+            "progress": progress,
+            "total": total,
+            "message": message,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[tokio::test]
+    async fn test_report_reaches_sink_only_when_tracked() {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let collected = sent.clone();
+        let sink: Sink = Arc::new(move |n: Value| {
+            collected.lock().unwrap().push(n["params"].clone());
+        });
+
+        report(1, 2, "untracked");
+        with_sink(sink, async {
+            track(None, async { report(1, 2, "no token") }).await;
+            let token = token(Some(&json!({ "progressToken": "t1" })));
+            track(token, async { report(1, 2, "fetched 1 of 2") }).await;
+        })
+        .await;
+
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0]["progressToken"], "t1");
+        assert_eq!(sent[0]["progress"], 1);
+        assert_eq!(sent[0]["total"], 2);
+    }
+}
@@ -0,0 +1,189 @@
+//! Built-in MCP prompts for common mail workflows.
+//!
+//! Each prompt fetches the mail it is about and embeds it in the message, so a
+//! client gets a ready-to-run request in one step instead of first having the
+//! model call the listing tools.
+
+use crate::capabilities::{self, Capability};
+use crate::reademail::{get_email, read_emails};
+use crate::server::{GetPromptResponse, PromptHandlerFn, ServerBuilder};
+use crate::FetchOptions;
+use chrono::{Local, TimeZone};
+use mcp_core::types::{
+    Prompt, PromptArgument, PromptMessage, PromptMessageContent, Role, TextContent,
+};
+use std::collections::HashMap;
+use tracing::info;
+
+/// Most unread emails embedded in the summary prompt.
+const MAX_UNREAD: u32 = 25;
+/// Most of today's emails embedded in the action item prompt.
+const MAX_TODAY: u32 = 50;
+
+pub const SUMMARIZE_UNREAD: &str = "summarize_unread";
+pub const DRAFT_REPLY: &str = "draft_reply";
+pub const ACTION_ITEMS_TODAY: &str = "action_items_today";
+
+fn argument(name: &str, description: &str, required: bool) -> PromptArgument {
+    PromptArgument {
+        name: name.to_string(),
+        description: Some(description.to_string()),
+        required: Some(required),
+    }
+}
+
+/// Every built-in prompt, in the order clients should show them.
+pub fn prompts() -> Vec<Prompt> {
+    vec![
+        Prompt {
+            name: SUMMARIZE_UNREAD.to_string(),
+            description: Some("Summarize my unread email".to_string()),
+            arguments: None,
+        },
+        Prompt {
+            name: DRAFT_REPLY.to_string(),
+            description: Some("Draft a reply to an email".to_string()),
+            arguments: Some(vec![
+                argument("id", "ID of the email to reply to", true),
+                argument(
+                    "instructions",
+                    "What the reply should say, e.g. 'accept, but suggest Friday'",
+                    false,
+                ),
+            ]),
+        },
+        Prompt {
+            name: ACTION_ITEMS_TODAY.to_string(),
+            description: Some("Find action items in today's email".to_string()),
+            arguments: None,
+        },
+    ]
+}
+
+/// Register every built-in prompt with `builder`.
+pub fn register(mut builder: ServerBuilder) -> ServerBuilder {
+    let handlers: [PromptHandlerFn; 3] = [
+        |arguments| Box::pin(summarize_unread(arguments)),
+        |arguments| Box::pin(draft_reply(arguments)),
+        |arguments| Box::pin(action_items_today(arguments)),
+    ];
+    for (prompt, handler) in prompts().into_iter().zip(handlers) {
+        builder = builder.register_prompt(prompt, handler);
+    }
+    builder
+}
+
+/// A single user message holding `text`.
+fn user_message(description: &str, text: String) -> GetPromptResponse {
+    GetPromptResponse {
+        description: Some(description.to_string()),
+        messages: vec![PromptMessage {
+            role: Role::User,
+            content: PromptMessageContent::Text(TextContent {
+                content_type: "text".to_string(),
+                text,
+                annotations: None,
+            }),
+        }],
+    }
+}
+
+/// Ask for a summary of the unread inbox, with the emails attached.
+pub async fn summarize_unread(
+    _arguments: HashMap<String, String>,
+) -> Result<GetPromptResponse, String> {
+    info!("Building the {} prompt", SUMMARIZE_UNREAD);
+    let emails = read_emails(
+        FetchOptions::new()
+            .max_results(MAX_UNREAD)
+            .unread_only(true),
+    )
+    .await
+    .map_err(|e| format!("Cannot read unread email: {}", e))?;
+
+    Ok(user_message(
+        "Summarize my unread email",
+        format!(
+            "Summarize my unread email. Group related messages, lead with anything urgent or \
+             waiting on me, and keep each summary to a line or two. Mention each email's ID so \
+             I can ask for details.\n\nUnread emails (JSON):\n{}",
+            emails
+        ),
+    ))
+}
+
+/// Ask for a reply to one email, with the email attached.
+pub async fn draft_reply(arguments: HashMap<String, String>) -> Result<GetPromptResponse, String> {
+    let id = arguments
+        .get("id")
+        .map(|id| id.trim())
+        .filter(|id| !id.is_empty())
+        .ok_or("The draft_reply prompt needs an email 'id'")?;
+    info!("Building the {} prompt for email {}", DRAFT_REPLY, id);
+    let email = get_email(id)
+        .await
+        .map_err(|e| format!("Cannot read email {}: {}", id, e))?;
+
+    let instructions = match arguments.get("instructions").map(|i| i.trim()) {
+        Some(i) if !i.is_empty() => format!("The reply should: {}\n\n", i),
+        _ => String::new(),
+    };
+    // Only point at tools the server actually offers
+    let next_step = if capabilities::enabled().contains(Capability::Compose) {
+        "Then save it with gmail_drafts (action 'create', addressed to the sender, subject \
+         prefixed with 'Re:') so I can review it before sending."
+            .to_string()
+    } else {
+        "Show me the text; do not send it.".to_string()
+    };
+    Ok(user_message(
+        "Draft a reply",
+        format!(
+            "Draft a reply to the email below, matching its language and tone.\n\n{}{}\n\n\
+             Email (JSON):\n{}",
+            instructions, next_step, email
+        ),
+    ))
+}
+
+/// Ask for the action items in mail received since local midnight, with the emails attached.
+pub async fn action_items_today(
+    _arguments: HashMap<String, String>,
+) -> Result<GetPromptResponse, String> {
+    info!("Building the {} prompt", ACTION_ITEMS_TODAY);
+    let midnight = Local::now()
+        .date_naive()
+        .and_hms_opt(0, 0, 0)
+        .and_then(|t| Local.from_local_datetime(&t).earliest())
+        .ok_or("Cannot determine local midnight")?;
+    let options = FetchOptions::new()
+        .query("in:anywhere -in:sent -in:spam -in:trash".to_string())
+        .max_results(MAX_TODAY)
+        .after(midnight.timestamp().to_string());
+    let emails = read_emails(options)
+        .await
+        .map_err(|e| format!("Cannot read today's email: {}", e))?;
+
+    Ok(user_message(
+        "Find action items from today",
+        format!(
+            "List the action items in today's email: requests, questions waiting on me, \
+             deadlines, and meetings to confirm. For each, give the task, who asked, any due \
+             date, and the email ID. Skip newsletters and notifications that need nothing.\n\n\
+             Today's emails (JSON):\n{}",
+            emails
+        ),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_draft_reply_requires_id() {
+        assert!(draft_reply(HashMap::new()).await.is_err());
+        let blank = HashMap::from([("id".to_string(), " ".to_string())]);
+        assert!(draft_reply(blank).await.is_err());
+    }
+}
@@ -0,0 +1,190 @@
+//! The mail backend behind the processing pipeline and tools.
+//!
+//! [`MailProvider`] covers the operations the pipeline needs: listing, getting,
+//! modifying, archiving, trashing, deleting, and sending messages, plus
+//! resolving labels and fetching the originals that replies and forwards are
+//! built from. [`GmailClient`] implements it over the
+//! Gmail API and is used unless another provider has been [`install`]ed, so a
+//! different backend or a mock can stand in without touching the tools.
+
+use crate::client::{self, GmailClient};
+use crate::compose::OutgoingEmail;
+use crate::send::{self, SendResponse};
+use crate::{labels, modify, EmailResponse, EmailSummary, FetchOptions, GmailMcpError};
+use async_trait::async_trait;
+use google_gmail1::api::Message;
+use std::sync::{Arc, RwLock};
+use tokio::sync::{Mutex, MutexGuard};
+
+/// A mailbox that can list, get, modify, remove, and send messages.
+#[async_trait]
+pub trait MailProvider: Send + Sync {
+    /// One page of emails matching `query`, a Gmail search expression already
+    /// built from `options`, with the label, page, and limits of `options`.
+    async fn list(
+        &self,
+        query: &str,
+        options: &FetchOptions,
+    ) -> Result<EmailResponse, GmailMcpError>;
+
+    /// One email by ID.
+    async fn get(&self, id: &str) -> Result<EmailSummary, GmailMcpError>;
+
+    /// One message by ID as the Gmail API returns it, with headers and parts.
+    async fn message(&self, id: &str) -> Result<Message, GmailMcpError>;
+
+    /// The RFC 822 source of one message by ID.
+    async fn raw(&self, id: &str) -> Result<Vec<u8>, GmailMcpError>;
+
+    /// Label IDs for label names or IDs, in the same order.
+    async fn label_ids(&self, labels: &[String]) -> Result<Vec<String>, GmailMcpError>;
+
+    /// Add and remove label IDs on messages, returning how many were modified.
+    async fn modify(
+        &self,
+        ids: &[String],
+        add_label_ids: &[String],
+        remove_label_ids: &[String],
+    ) -> Result<usize, GmailMcpError>;
+
+    /// Remove messages from the inbox, returning how many were archived.
+    async fn archive(&self, ids: &[String]) -> Result<usize, GmailMcpError>;
+
+    /// Archive every inbox message matching `query`, up to
+    /// [`modify::ARCHIVE_QUERY_LIMIT`], returning the archived IDs.
+    async fn archive_matching(&self, query: &str) -> Result<Vec<String>, GmailMcpError>;
+
+    /// Move messages to the trash, returning how many were moved.
+    async fn trash(&self, ids: &[String]) -> Result<usize, GmailMcpError>;
+
+    /// Restore messages from the trash, returning how many were restored.
+    async fn untrash(&self, ids: &[String]) -> Result<usize, GmailMcpError>;
+
+    /// Permanently delete messages, bypassing the trash, returning how many
+    /// were deleted. Callers check [`modify::permanent_delete_allowed`] first.
+    async fn delete(&self, ids: &[String]) -> Result<usize, GmailMcpError>;
+
+    /// Send an email, optionally into an existing thread.
+    async fn send(
+        &self,
+        email: &OutgoingEmail,
+        thread_id: Option<&str>,
+    ) -> Result<SendResponse, GmailMcpError>;
+}
+
+#[async_trait]
+impl MailProvider for GmailClient {
+    async fn list(
+        &self,
+        query: &str,
+        options: &FetchOptions,
+    ) -> Result<EmailResponse, GmailMcpError> {
+        crate::fetch_page(self.hub(), query, options).await
+    }
+
+    async fn get(&self, id: &str) -> Result<EmailSummary, GmailMcpError> {
+        GmailClient::get(self, id).await
+    }
+
+    async fn message(&self, id: &str) -> Result<Message, GmailMcpError> {
+        Ok(crate::fetch_message(self.hub(), id).await?)
+    }
+
+    async fn raw(&self, id: &str) -> Result<Vec<u8>, GmailMcpError> {
+        crate::fetch_raw_message(self.hub(), id).await
+    }
+
+    async fn label_ids(&self, labels: &[String]) -> Result<Vec<String>, GmailMcpError> {
+        labels::resolve_label_ids(self.hub(), labels).await
+    }
+
+    async fn modify(
+        &self,
+        ids: &[String],
+        add_label_ids: &[String],
+        remove_label_ids: &[String],
+    ) -> Result<usize, GmailMcpError> {
+        modify::modify_messages(self.hub(), ids, add_label_ids, remove_label_ids).await
+    }
+
+    async fn archive(&self, ids: &[String]) -> Result<usize, GmailMcpError> {
+        modify::archive_messages(self.hub(), ids).await
+    }
+
+    async fn archive_matching(&self, query: &str) -> Result<Vec<String>, GmailMcpError> {
+        modify::archive_matching(self.hub(), query).await
+    }
+
+    async fn trash(&self, ids: &[String]) -> Result<usize, GmailMcpError> {
+        modify::trash_messages(self.hub(), ids).await
+    }
+
+    async fn untrash(&self, ids: &[String]) -> Result<usize, GmailMcpError> {
+        modify::untrash_messages(self.hub(), ids).await
+    }
+
+    async fn delete(&self, ids: &[String]) -> Result<usize, GmailMcpError> {
+        modify::delete_messages(self.hub(), ids, modify::permanent_delete_allowed()).await
+    }
+
+    async fn send(
+        &self,
+        email: &OutgoingEmail,
+        thread_id: Option<&str>,
+    ) -> Result<SendResponse, GmailMcpError> {
+        send::send_message(self.hub(), email, thread_id).await
+    }
+}
+
+static INSTALLED: RwLock<Option<Arc<dyn MailProvider>>> = RwLock::new(None);
+
+/// Use `provider` for every later call instead of Gmail.
+pub fn install(provider: Arc<dyn MailProvider>) {
+    *INSTALLED.write().unwrap_or_else(|e| e.into_inner()) = Some(provider);
+}
+
+/// Go back to the shared Gmail client.
+pub fn uninstall() {
+    INSTALLED.write().unwrap_or_else(|e| e.into_inner()).take();
+}
+
+/// Held by the one [`install_scoped`] provider active at a time.
+static SCOPE: Mutex<()> = Mutex::const_new(());
+
+/// A provider installed by [`install_scoped`]; dropping it puts back the
+/// provider that was installed before.
+pub struct Installed {
+    previous: Option<Arc<dyn MailProvider>>,
+    _scope: MutexGuard<'static, ()>,
+}
+
+impl Drop for Installed {
+    fn drop(&mut self) {
+        *INSTALLED.write().unwrap_or_else(|e| e.into_inner()) = self.previous.take();
+    }
+}
+
+/// Use `provider` until the returned guard is dropped, even by a panic.
+///
+/// Callers wait for each other, so tests running in parallel each see the
+/// provider they installed.
+pub async fn install_scoped(provider: Arc<dyn MailProvider>) -> Installed {
+    let scope = SCOPE.lock().await;
+    let previous = INSTALLED
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .replace(provider);
+    Installed {
+        previous,
+        _scope: scope,
+    }
+}
+
+/// The installed provider, or the shared Gmail client when none is.
+pub async fn current() -> Result<Arc<dyn MailProvider>, GmailMcpError> {
+    let installed = INSTALLED.read().unwrap_or_else(|e| e.into_inner()).clone();
+    match installed {
+        Some(provider) => Ok(provider),
+        None => Ok(Arc::new(client::shared().await?)),
+    }
+}
@@ -0,0 +1,294 @@
+//! Accounting of the Gmail API quota units spent today.
+//!
+//! Gmail charges every method a number of quota units per call (see [`cost`])
+//! against a daily allowance per user. Each request is recorded with
+//! [`record`] before it is sent, and retries are recorded by the
+//! [`Backoff`](crate::retry::Backoff) that sends them, so the `gmail_quota` tool
+//! can report the units spent per method since midnight UTC.
+//!
+//! With `GMAIL_DAILY_QUOTA` set, requests slow down once most of that budget is
+//! spent: the [`Throttled`](crate::limits::Throttled) authenticator spreads the
+//! rest of the budget over the rest of the day, waiting at most a minute per
+//! request.
+
+use crate::GmailMcpError;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::Duration;
+use tracing::info;
+
+/// Environment variable with the quota units the server may spend per day.
+pub const DAILY_QUOTA_ENV: &str = "GMAIL_DAILY_QUOTA";
+
+/// Share of the daily budget spent before requests are paced.
+const PACE_FROM: f64 = 0.8;
+/// Longest a request waits for budget.
+const MAX_WAIT: Duration = Duration::from_secs(60);
+/// Units assumed per request when pacing, the cost of most reads.
+const TYPICAL_COST: u64 = 5;
+
+/// Quota units Gmail charges for one call to `method`, named as in the API
+/// reference without the `gmail.users.` prefix (e.g. `messages.get`).
+///
+/// Methods missing from the table are charged 5 units, the cost of most.
+pub fn cost(method: &str) -> u32 {
+    match method {
+        "getProfile"
+        | "labels.list"
+        | "labels.get"
+        | "settings.filters.list"
+        | "settings.filters.get"
+        | "settings.sendAs.list"
+        | "settings.sendAs.get"
+        | "settings.delegates.list"
+        | "settings.delegates.get" => 1,
+        "history.list" => 2,
+        "drafts.create" | "drafts.delete" | "messages.delete" | "threads.get" | "threads.list"
+        | "threads.modify" | "threads.trash" | "threads.untrash" => 10,
+        "drafts.update" => 15,
+        "threads.delete" => 20,
+        "messages.import" | "messages.insert" => 25,
+        "messages.batchModify" | "messages.batchDelete" | "stop" | "settings.delegates.delete" => {
+            50
+        }
+        "messages.send"
+        | "drafts.send"
+        | "watch"
+        | "settings.sendAs.create"
+        | "settings.sendAs.update"
+        | "settings.sendAs.patch"
+        | "settings.delegates.create" => 100,
+        _ => 5,
+    }
+}
+
+/// Daily budget of quota units.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QuotaConfig {
+    /// Units the server may spend per day; `None` means unlimited.
+    pub daily_budget: Option<u64>,
+}
+
+impl QuotaConfig {
+    /// Read the budget from the environment.
+    pub fn from_env() -> Result<Self, String> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// Read the budget through `var`, failing on a value that is not a
+    /// positive integer.
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let Some(value) = var(DAILY_QUOTA_ENV).filter(|v| !v.trim().is_empty()) else {
+            return Ok(Self::default());
+        };
+        match value.trim().parse::<u64>() {
+            Ok(budget) if budget > 0 => Ok(Self {
+                daily_budget: Some(budget),
+            }),
+            _ => Err(format!(
+                "Invalid {} '{}'; expected a positive integer",
+                DAILY_QUOTA_ENV, value
+            )),
+        }
+    }
+}
+
+static CONFIG: OnceLock<QuotaConfig> = OnceLock::new();
+
+/// Apply `config` to this process; later calls are ignored.
+pub fn install(config: QuotaConfig) {
+    info!("Gmail quota: {:?}", config);
+    let _ = CONFIG.set(config);
+}
+
+/// Calls to one method and the units they cost.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MethodUsage {
+    pub calls: u64,
+    pub units: u64,
+}
+
+/// Quota spent on one day.
+#[derive(Debug)]
+struct Usage {
+    day: NaiveDate,
+    methods: BTreeMap<&'static str, MethodUsage>,
+}
+
+impl Usage {
+    fn new(day: NaiveDate) -> Self {
+        Self {
+            day,
+            methods: BTreeMap::new(),
+        }
+    }
+
+    /// Start over when `day` has begun since the last request.
+    fn roll_over(&mut self, day: NaiveDate) {
+        if self.day != day {
+            *self = Self::new(day);
+        }
+    }
+
+    fn record(&mut self, method: &'static str, calls: u32) {
+        let usage = self.methods.entry(method).or_default();
+        usage.calls += u64::from(calls);
+        usage.units += u64::from(cost(method) * calls);
+    }
+
+    fn units(&self) -> u64 {
+        self.methods.values().map(|usage| usage.units).sum()
+    }
+}
+
+static USAGE: OnceLock<Mutex<Usage>> = OnceLock::new();
+
+/// Today's usage, reset first if the day has changed.
+fn usage(now: DateTime<Utc>) -> MutexGuard<'static, Usage> {
+    let mut usage = USAGE
+        .get_or_init(|| Mutex::new(Usage::new(now.date_naive())))
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    usage.roll_over(now.date_naive());
+    usage
+}
+
+/// Count `calls` requests to `method` against today's quota.
+pub fn record(method: &'static str, calls: u32) {
+    if calls > 0 {
+        usage(Utc::now()).record(method, calls);
+    }
+}
+
+/// Time left until the quota day ends at midnight UTC.
+fn rest_of_day(now: DateTime<Utc>) -> Duration {
+    let midnight = now
+        .date_naive()
+        .succ_opt()
+        .and_then(|day| day.and_hms_opt(0, 0, 0))
+        .map(|midnight| midnight.and_utc());
+    midnight
+        .and_then(|midnight| (midnight - now).to_std().ok())
+        .unwrap_or_default()
+}
+
+/// How long the next request waits with `used` of `budget` units spent and
+/// `rest` of the day left: nothing below [`PACE_FROM`] of the budget, then the
+/// rest of the day shared evenly among the requests the rest of the budget
+/// allows, up to [`MAX_WAIT`].
+fn pace_delay(used: u64, budget: u64, rest: Duration) -> Duration {
+    if (used as f64) < budget as f64 * PACE_FROM {
+        return Duration::ZERO;
+    }
+    match budget.saturating_sub(used) / TYPICAL_COST {
+        0 => MAX_WAIT,
+        requests => rest.div_f64(requests as f64).min(MAX_WAIT),
+    }
+}
+
+/// Wait as long as the daily budget, if any, asks before the next request.
+pub(crate) async fn pace() {
+    let Some(budget) = CONFIG.get().and_then(|config| config.daily_budget) else {
+        return;
+    };
+    let now = Utc::now();
+    let used = usage(now).units();
+    let delay = pace_delay(used, budget, rest_of_day(now));
+    if !delay.is_zero() {
+        info!(
+            "Gmail quota: {} of {} units spent today, waiting {:?}",
+            used, budget, delay
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Quota spent today, as reported by the `gmail_quota` tool.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct QuotaReport {
+    /// The UTC day the units were spent on.
+    pub day: String,
+    pub units_used: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub daily_budget: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub units_remaining: Option<u64>,
+    /// Usage per method, such as `messages.get`.
+    pub methods: BTreeMap<&'static str, MethodUsage>,
+}
+
+/// Report the quota spent today.
+pub fn report() -> QuotaReport {
+    let usage = usage(Utc::now());
+    let units_used = usage.units();
+    let daily_budget = CONFIG.get().and_then(|config| config.daily_budget);
+    QuotaReport {
+        day: usage.day.to_string(),
+        units_used,
+        daily_budget,
+        units_remaining: daily_budget.map(|budget| budget.saturating_sub(units_used)),
+        methods: usage.methods.clone(),
+    }
+}
+
+/// Report the quota spent today as pretty-printed JSON.
+pub fn quota_report() -> Result<String, GmailMcpError> {
+    Ok(serde_json::to_string_pretty(&report())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quota_config_from_vars() {
+        assert_eq!(QuotaConfig::from_vars(|_| None).unwrap().daily_budget, None);
+        let config = QuotaConfig::from_vars(|_| Some("1000000".to_string())).unwrap();
+        assert_eq!(config.daily_budget, Some(1_000_000));
+        assert!(QuotaConfig::from_vars(|_| Some("0".to_string())).is_err());
+        assert!(QuotaConfig::from_vars(|_| Some("lots".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_usage_per_method_and_day() {
+        let day = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        let mut usage = Usage::new(day);
+        usage.record("messages.list", 1);
+        usage.record("messages.get", 3);
+        usage.record("messages.send", 1);
+        assert_eq!(
+            usage.methods["messages.get"],
+            MethodUsage {
+                calls: 3,
+                units: 15
+            }
+        );
+        assert_eq!(usage.units(), 5 + 15 + 100);
+
+        usage.roll_over(day);
+        assert_eq!(usage.units(), 120);
+        usage.roll_over(day.succ_opt().unwrap());
+        assert_eq!(usage.units(), 0);
+    }
+
+    #[test]
+    fn test_pace_delay() {
+        let hour = Duration::from_secs(3600);
+        assert_eq!(pace_delay(0, 1000, hour), Duration::ZERO);
+        assert_eq!(pace_delay(799, 1000, hour), Duration::ZERO);
+        // 200 units left allow 40 requests over the hour
+        assert_eq!(pace_delay(800, 1000, hour), Duration::from_secs(60));
+        assert_eq!(
+            pace_delay(800, 1000, Duration::from_secs(400)),
+            Duration::from_secs(10)
+        );
+        assert_eq!(pace_delay(1000, 1000, Duration::from_secs(10)), MAX_WAIT);
+
+        let now = DateTime::parse_from_rfc3339("2024-05-01T23:59:30Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(rest_of_day(now), Duration::from_secs(30));
+    }
+}
@@ -1,14 +1,22 @@
-use crate::{EmailResponse, EmailSummary};
+use crate::dates;
+use crate::markup::{
+    html_to_markdown, pattern, replace_inline_image_refs, replace_inline_images, BodyFormat,
+    InlineImages,
+};
+use crate::{util, EmailResponse, EmailSummary, FetchOptions, GmailMcpError};
 use html2text::from_read as html_to_text;
-use regex::Regex;
+use regex::Captures;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
 use tokio::task;
 use tracing::{error, info, warn};
 
-/// Reads emails from Gmail and processes them
-pub async fn read_emails(max_results: u32) -> Result<String, Box<dyn std::error::Error>> {
-    info!("Starting to read {} emails from Gmail", max_results);
+/// Reads the emails selected by `options` from Gmail and converts their bodies to text
+pub async fn read_emails(options: FetchOptions) -> Result<String, GmailMcpError> {
+    info!("Starting to read {} emails from Gmail", options.max_results);
 
-    let json = match crate::run(max_results).await {
+    let json = match crate::run_from_page(&options).await {
         Ok(json) => {
             info!("Gmail API response received ({} bytes)", json.len());
             json
@@ -21,44 +29,546 @@ pub async fn read_emails(max_results: u32) -> Result<String, Box<dyn std::error:
 
     let mut response: EmailResponse = serde_json::from_str(&json)?;
 
-    if response.emails.is_empty() {
+    if response.count == 0 {
         warn!("No emails found in Gmail response");
         return Ok(serde_json::to_string_pretty(&response)?);
     }
 
-    info!("Processing {} emails", response.emails.len());
-    for email in response.emails.iter_mut() {
-        convert_html_to_text(email).await;
+    let duplicates = mark_duplicates(
+        response.emails.iter_mut().chain(
+            response
+                .threads
+                .iter_mut()
+                .flatten()
+                .flat_map(|thread| thread.emails.iter_mut()),
+        ),
+    );
+    if duplicates > 0 && options.collapse_duplicates {
+        info!("Dropping {} duplicate emails", duplicates);
+        response.emails.retain(|email| email.duplicate_of.is_none());
+        for thread in response.threads.iter_mut().flatten() {
+            thread.emails.retain(|email| email.duplicate_of.is_none());
+        }
+        response.count = response.count.saturating_sub(duplicates);
+    }
+
+    info!("Processing {} emails", response.count);
+    let threaded = response
+        .threads
+        .iter_mut()
+        .flatten()
+        .flat_map(|thread| thread.emails.iter_mut());
+    for email in response.emails.iter_mut().chain(threaded) {
+        process_email(email, &options).await;
     }
 
     info!("Email processing completed");
     Ok(serde_json::to_string_pretty(&response)?)
 }
 
-/// Convert HTML to text and remove URLs
+/// Fetches a single email by ID and processes it like [`read_emails`]
+pub async fn get_email(id: &str) -> Result<String, GmailMcpError> {
+    let id = id.trim();
+    if id.is_empty() {
+        return Err(GmailMcpError::Invalid(
+            "An email ID is required".to_string(),
+        ));
+    }
+    info!("Reading email {} from Gmail", id);
+
+    let mut email = crate::provider::current().await?.get(id).await?;
+    convert_html_to_text(&mut email).await;
+
+    Ok(serde_json::to_string_pretty(&email)?)
+}
+
+/// The original RFC 822 source of a message.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RawEmail {
+    /// The Gmail message ID.
+    pub id: String,
+    /// Size of the raw message in bytes.
+    pub size: usize,
+    /// The message source; invalid UTF-8 sequences are replaced with U+FFFD.
+    pub raw: String,
+}
+
+/// Fetches the unprocessed RFC 822 source of a single email by ID
+pub async fn get_raw_email(id: &str) -> Result<String, GmailMcpError> {
+    let id = id.trim();
+    if id.is_empty() {
+        return Err(GmailMcpError::Invalid(
+            "An email ID is required".to_string(),
+        ));
+    }
+    info!("Reading raw email {} from Gmail", id);
+
+    let bytes = crate::provider::current().await?.raw(id).await?;
+    let email = RawEmail {
+        id: id.to_string(),
+        size: bytes.len(),
+        raw: String::from_utf8_lossy(&bytes).into_owned(),
+    };
+
+    Ok(serde_json::to_string_pretty(&email)?)
+}
+
+/// Convert HTML to text and remove URLs, keeping only the converted body.
 pub async fn convert_html_to_text(summary: &mut EmailSummary) {
-    // Convert HTML to text if needed
+    convert_html_to_plain_text(summary, InlineImages::default()).await;
+    apply_url_policy(summary, UrlPolicy::Remove);
+    summary.body_text = None;
+    summary.body_html = None;
+}
+
+/// Convert the body to plain text if it is HTML, handling inline images under
+/// `inline_images`.
+async fn convert_html_to_plain_text(summary: &mut EmailSummary, inline_images: InlineImages) {
+    replace_image_refs(summary, inline_images);
     if summary.body_raw.starts_with('<') {
-        let html_body = summary.body_raw.clone();
+        let html_body =
+            replace_inline_images(&summary.body_raw, &summary.attachments, inline_images);
         let plain_text = task::spawn_blocking(move || html_to_text(html_body.as_bytes(), 100))
             .await
             .unwrap();
         summary.body_raw = plain_text;
     }
+}
+
+/// Replace the `[cid:…]` inline image references of the plain-text bodies.
+fn replace_image_refs(summary: &mut EmailSummary, inline_images: InlineImages) {
+    if !summary.body_raw.starts_with('<') {
+        summary.body_raw =
+            replace_inline_image_refs(&summary.body_raw, &summary.attachments, inline_images);
+    }
+    if let Some(text) = &summary.body_text {
+        let text = replace_inline_image_refs(text, &summary.attachments, inline_images);
+        summary.body_text = Some(text);
+    }
+}
 
-    // Remove URLs from text (simplified inline implementation)
-    summary.body_raw = remove_urls_simple(&summary.body_raw);
+/// What happens to the URLs in a converted body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UrlPolicy {
+    /// Leave URLs in place.
+    Keep,
+    /// Delete URLs, keeping the text of Markdown links.
+    Remove,
+    /// Replace each URL with a `[link: example.com]` placeholder naming its domain.
+    Placeholder,
+    /// Delete URLs from the body and list them in [`EmailSummary::links`].
+    Extract,
+    /// Keep URLs, unwrapped from click-tracking redirects and without
+    /// tracking parameters such as `utm_source` (see [`clean_url`]).
+    Clean,
 }
 
-/// Simple URL removal function
-fn remove_urls_simple(text: &str) -> String {
-    // Simple regex to match common URL patterns
-    let url_regex = Regex::new(r"https?://[^\s]+|www\.[^\s]+").unwrap_or_else(|_| {
-        // If regex fails, return a regex that matches nothing
-        Regex::new(r"$^").unwrap()
+impl FromStr for UrlPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "keep" => Ok(Self::Keep),
+            "remove" => Ok(Self::Remove),
+            "placeholder" => Ok(Self::Placeholder),
+            "extract" => Ok(Self::Extract),
+            "clean" => Ok(Self::Clean),
+            other => Err(format!(
+                "Unknown URL policy '{}'; expected keep, remove, placeholder, extract, or clean",
+                other
+            )),
+        }
+    }
+}
+
+/// Apply `policy` to the URLs in the body and the plain-text body, adding
+/// extracted ones to `links`.
+pub fn apply_url_policy(summary: &mut EmailSummary, policy: UrlPolicy) {
+    if policy == UrlPolicy::Keep {
+        return;
+    }
+    summary.body_raw = replace_urls(&summary.body_raw, policy, &mut summary.links);
+    if let Some(text) = &summary.body_text {
+        let text = replace_urls(text, policy, &mut summary.links);
+        summary.body_text = Some(text);
+    }
+}
+
+/// Rewrite the Markdown links and bare URLs of `text` as `policy` asks,
+/// adding each URL to `links` once.
+fn replace_urls(text: &str, policy: UrlPolicy, links: &mut Vec<String>) -> String {
+    // `link` is the whole Markdown link, or empty for a bare URL
+    let mut replace = |url: &str, label: &str, link: &str| -> String {
+        if policy == UrlPolicy::Extract && !links.iter().any(|known| known == url) {
+            links.push(url.to_string());
+        }
+        match policy {
+            UrlPolicy::Placeholder if label.is_empty() => format!("[link: {}]", link_domain(url)),
+            UrlPolicy::Placeholder => format!("{} [link: {}]", label, link_domain(url)),
+            UrlPolicy::Clean if link.is_empty() => clean_url(url),
+            UrlPolicy::Clean => link.replacen(url, &clean_url(url), 1),
+            _ => label.to_string(),
+        }
+    };
+
+    let markdown_link = pattern(r"!?\[([^\]]*)\]\(([^)\s]+)\)");
+    let text = markdown_link.replace_all(text, |caps: &Captures| {
+        replace(&caps[2], &caps[1], &caps[0])
     });
+    let bare_url = pattern(r"https?://[^\s]+|www\.[^\s]+");
+    bare_url
+        .replace_all(&text, |caps: &Captures| {
+            // Punctuation ending a sentence is not part of the URL
+            let url = caps[0].trim_end_matches(['.', ',', ';', ':', '!', '?', ')', '"', '\'']);
+            let trailing = &caps[0][url.len()..];
+            format!("{}{}", replace(url, "", ""), trailing)
+        })
+        .into_owned()
+}
+
+/// Query parameters that only track the click, such as `fbclid`; `utm_*`
+/// parameters are matched by prefix.
+const TRACKING_PARAMS: [&str; 16] = [
+    "fbclid",
+    "gclid",
+    "dclid",
+    "gbraid",
+    "wbraid",
+    "msclkid",
+    "yclid",
+    "igshid",
+    "mc_cid",
+    "mc_eid",
+    "_hsenc",
+    "_hsmi",
+    "mkt_tok",
+    "oly_anon_id",
+    "oly_enc_id",
+    "vero_id",
+];
+
+/// Click-tracking redirectors: the host (subdomains included), the path (`/`
+/// for any), and the query parameter passing the destination. Parameters of
+/// other links are left alone, so that a phishing link passing a trusted
+/// address is not shown as that address.
+const REDIRECTORS: [(&str, &str, &str); 11] = [
+    ("google.com", "/url", "q"),
+    ("google.com", "/url", "url"),
+    ("safelinks.protection.outlook.com", "/", "url"),
+    ("l.facebook.com", "/l.php", "u"),
+    ("lm.facebook.com", "/l.php", "u"),
+    ("l.messenger.com", "/l.php", "u"),
+    ("l.instagram.com", "/", "u"),
+    ("youtube.com", "/redirect", "q"),
+    ("linkedin.com", "/redir/redirect", "url"),
+    ("slack-redir.net", "/link", "url"),
+    ("steamcommunity.com", "/linkfilter/", "url"),
+];
+
+/// The real destination of a URL: unwrapped from known click-tracking
+/// redirects (such as `google.com/url?q=…` or Outlook safe links, see
+/// [`REDIRECTORS`]), and without tracking parameters such as `utm_source`.
+pub fn clean_url(url: &str) -> String {
+    let mut url = url.to_string();
+    // Redirects may be nested, as when a safe link wraps a tracked link
+    for _ in 0..3 {
+        match redirect_target(&url) {
+            Some(target) => url = target,
+            None => break,
+        }
+    }
+    strip_tracking_params(&url)
+}
+
+/// The `http(s)` destination a known redirector passes in its query, if any.
+fn redirect_target(url: &str) -> Option<String> {
+    let host = url_host(url).to_ascii_lowercase();
+    let rest = url.split_once("://")?.1;
+    let (path, query) = rest[rest.find('/')?..].split_once('?')?;
+    let query = query.split('#').next().unwrap_or_default();
+    let params: Vec<&str> = REDIRECTORS
+        .iter()
+        .filter(|(redirector, prefix, _)| {
+            let on_host = host == *redirector || host.ends_with(&format!(".{}", redirector));
+            on_host && path.starts_with(prefix)
+        })
+        .map(|(_, _, param)| *param)
+        .collect();
+    query.split('&').find_map(|pair| {
+        let (name, value) = pair.split_once('=')?;
+        let value = util::percent_decode(value);
+        (params.contains(&name) && (value.starts_with("https://") || value.starts_with("http://")))
+            .then_some(value)
+    })
+}
+
+/// Remove the tracking parameters from the query of `url`.
+fn strip_tracking_params(url: &str) -> String {
+    let (url, fragment) = match url.split_once('#') {
+        Some((url, fragment)) => (url, Some(fragment)),
+        None => (url, None),
+    };
+    let Some((base, query)) = url.split_once('?') else {
+        return match fragment {
+            Some(fragment) => format!("{}#{}", url, fragment),
+            None => url.to_string(),
+        };
+    };
+    let kept: Vec<&str> = query
+        .split('&')
+        .filter(|pair| {
+            let name = pair
+                .split('=')
+                .next()
+                .unwrap_or_default()
+                .to_ascii_lowercase();
+            !pair.is_empty()
+                && !name.starts_with("utm_")
+                && !TRACKING_PARAMS.contains(&name.as_str())
+        })
+        .collect();
+    let mut cleaned = base.to_string();
+    if !kept.is_empty() {
+        cleaned.push('?');
+        cleaned.push_str(&kept.join("&"));
+    }
+    if let Some(fragment) = fragment {
+        cleaned.push('#');
+        cleaned.push_str(fragment);
+    }
+    cleaned
+}
+
+/// The host of a URL, without any user name or port.
+fn url_host(url: &str) -> &str {
+    let authority = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = authority.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority.rsplit('@').next().unwrap_or_default();
+    host.split(':').next().unwrap_or_default()
+}
+
+/// The domain of a URL without its `www.` prefix, such as `example.com`.
+fn link_domain(url: &str) -> &str {
+    let host = url_host(url);
+    host.strip_prefix("www.").unwrap_or(host)
+}
+
+/// Convert the HTML body, when there is one, to Markdown, keeping its links
+/// and handling inline images under `inline_images`.
+pub async fn convert_html_to_markdown(summary: &mut EmailSummary, inline_images: InlineImages) {
+    replace_image_refs(summary, inline_images);
+    let html = match &summary.body_html {
+        Some(html) => html,
+        None if summary.body_raw.starts_with('<') => &summary.body_raw,
+        None => return,
+    };
+    let html = replace_inline_images(html, &summary.attachments, inline_images);
+    summary.body_raw = task::spawn_blocking(move || html_to_markdown(&html))
+        .await
+        .unwrap_or_default();
+}
+
+/// Convert the body to the body format of `options` and apply its URL policy,
+/// then its other body options: strip quoted replies, extract mentions, and
+/// cut each body to `truncate_body_at` characters (see [`truncate_chars`]).
+pub(crate) async fn process_email(email: &mut EmailSummary, options: &FetchOptions) {
+    match options.body_format {
+        BodyFormat::Text => {
+            convert_html_to_plain_text(email, options.inline_images).await;
+            apply_url_policy(email, options.url_policy.unwrap_or(UrlPolicy::Remove));
+            // The original HTML would carry every URL the policy took out
+            email.body_html = None;
+        }
+        BodyFormat::Markdown => {
+            convert_html_to_markdown(email, options.inline_images).await;
+            apply_url_policy(email, options.url_policy.unwrap_or(UrlPolicy::Keep));
+            email.body_html = None;
+        }
+        BodyFormat::Html => {
+            if let Some(html) = &email.body_html {
+                email.body_raw = html.clone();
+            }
+        }
+    }
+    if !options.include_parts {
+        // body_raw now holds the processed body; the parts would repeat it
+        email.body_text = None;
+        email.body_html = None;
+    }
+    if options.strip_quoted {
+        email.body_raw = strip_quoted_reply(&email.body_raw);
+        if let Some(text) = &mut email.body_text {
+            *text = strip_quoted_reply(text);
+        }
+    }
+    if options.extract_addresses {
+        email.addresses_mentioned = extract_addresses(&email.body_raw);
+    }
+    if options.extract_dates {
+        email.dates_mentioned = dates::extract(&email.body_raw, dates::reference_date(email));
+    }
+    if let Some(limit) = options.truncate_body_at {
+        let html = options.body_format == BodyFormat::Html;
+        truncate_chars(&mut email.body_raw, limit, html);
+        if let Some(text) = &mut email.body_text {
+            truncate_chars(text, limit, false);
+        }
+        if let Some(html) = &mut email.body_html {
+            truncate_chars(html, limit, true);
+        }
+    }
+}
+
+/// Keep only the new content of a plain-text reply, dropping the earlier
+/// messages it quotes.
+///
+/// The body is cut at the first attribution line such as `On Mon, 6 May 2024,
+/// Jane wrote:` (which may wrap onto a second line), Outlook's `-----Original
+/// Message-----` separator, or a `From:` header block following a line of
+/// underscores. Any remaining `>`-quoted lines are dropped as well.
+pub fn strip_quoted_reply(text: &str) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut kept = Vec::with_capacity(lines.len());
+    for (i, line) in lines.iter().enumerate() {
+        let trimmed = line.trim();
+        let next = lines.get(i + 1).map_or("", |next| next.trim());
+        let attribution =
+            trimmed.starts_with("On ") && (trimmed.ends_with("wrote:") || next.ends_with("wrote:"));
+        let original_message = trimmed.starts_with("-----Original Message-----");
+        let outlook_header =
+            trimmed.len() >= 10 && trimmed.chars().all(|c| c == '_') && next.starts_with("From:");
+        if attribution || original_message || outlook_header {
+            break;
+        }
+        if !trimmed.starts_with('>') {
+            kept.push(*line);
+        }
+    }
+    kept.join("\n").trim_end().to_string()
+}
+
+/// Set [`EmailSummary::duplicate_of`] on each email repeating an earlier one,
+/// returning how many do.
+///
+/// Copies share a `Message-ID`, as mail sent to several of the account's
+/// aliases does, or else have the same subject and body once case and
+/// whitespace are ignored. Emails fetched without a body are only matched by
+/// `Message-ID`.
+pub fn mark_duplicates<'a>(emails: impl IntoIterator<Item = &'a mut EmailSummary>) -> usize {
+    let mut originals: HashMap<String, String> = HashMap::new();
+    let mut duplicates = 0;
+    let normalize = |text: &str| {
+        text.split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase()
+    };
+    for email in emails {
+        let message_id = email
+            .message_id
+            .as_deref()
+            .map(|id| {
+                id.trim()
+                    .trim_start_matches('<')
+                    .trim_end_matches('>')
+                    .to_ascii_lowercase()
+            })
+            .filter(|id| !id.is_empty())
+            .map(|id| format!("id:{}", id));
+        let body = normalize(email.body_text.as_deref().unwrap_or(&email.body_raw));
+        let content =
+            (!body.is_empty()).then(|| format!("body:{}\n{}", normalize(&email.subject), body));
 
-    url_regex.replace_all(text, "").to_string()
+        let keys: Vec<String> = message_id.into_iter().chain(content).collect();
+        match keys.iter().find_map(|key| originals.get(key)) {
+            Some(original) if *original != email.id => {
+                email.duplicate_of = Some(original.clone());
+                duplicates += 1;
+            }
+            _ => {
+                for key in keys {
+                    originals.entry(key).or_insert_with(|| email.id.clone());
+                }
+            }
+        }
+    }
+    duplicates
+}
+
+/// Every email address mentioned in `text`, lowercased, once each in order of
+/// first appearance.
+///
+/// `cid:` references to inline images, which look like addresses, are skipped.
+pub fn extract_addresses(text: &str) -> Vec<String> {
+    let address =
+        pattern(r"[A-Za-z0-9][A-Za-z0-9._%+-]*@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}\b");
+    let mut addresses: Vec<String> = Vec::new();
+    for found in address.find_iter(text) {
+        let before = &text.as_bytes()[..found.start()];
+        if before.len() >= 4 && before[before.len() - 4..].eq_ignore_ascii_case(b"cid:") {
+            continue;
+        }
+        let found = found.as_str().to_lowercase();
+        if !addresses.contains(&found) {
+            addresses.push(found);
+        }
+    }
+    addresses
+}
+
+/// Cut `text` to at most `limit` characters, backing up to the end of the
+/// last whole word, and mark the cut with how many characters were left out.
+/// The marker counts towards `limit`; when it does not fit, a bare `…` does.
+///
+/// A word is only split when backing up would drop more than half of what is
+/// kept, as with a long URL. With `html`, the cut also backs up out of any tag
+/// or character reference it would split.
+fn truncate_chars(text: &mut String, limit: usize, html: bool) {
+    let total = text.chars().count();
+    if total <= limit {
+        return;
+    }
+    let marker = |omitted: usize| format!("… [truncated, {} more characters]", omitted);
+    // The omitted count never exceeds the total, so this marker is the longest
+    let marker_len = marker(total).chars().count();
+    let (budget, full_marker) = match limit.checked_sub(marker_len) {
+        Some(budget) => (budget, true),
+        None if limit > 0 => (limit - 1, false),
+        None => {
+            text.clear();
+            return;
+        }
+    };
+
+    let index = text
+        .char_indices()
+        .nth(budget)
+        .map_or(text.len(), |(i, _)| i);
+    let mut cut = if text[index..].starts_with(char::is_whitespace) {
+        index
+    } else {
+        match text[..index].rfind(char::is_whitespace) {
+            Some(space) if space >= index / 2 => space,
+            _ => index,
+        }
+    };
+    if html {
+        for (open, close) in [('<', '>'), ('&', ';')] {
+            if let Some(start) = text[..cut].rfind(open) {
+                if !text[start..cut].contains(close) {
+                    cut = start;
+                }
+            }
+        }
+    }
+    let cut = text[..cut].trim_end().len();
+    let omitted = text[cut..].trim_start().chars().count();
+    text.truncate(cut);
+    if full_marker {
+        text.push_str(&marker(omitted));
+    } else {
+        text.push('…');
+    }
 }
 
 #[cfg(test)]
@@ -68,7 +578,7 @@ mod tests {
     #[test]
     fn test_url_removal() {
         let test_text = "Check out this link: https://example.com and this one too: www.test.org. More text here.";
-        let cleaned = remove_urls_simple(test_text);
+        let cleaned = replace_urls(test_text, UrlPolicy::Remove, &mut Vec::new());
 
         assert!(!cleaned.contains("https://example.com"));
         assert!(!cleaned.contains("www.test.org"));
@@ -76,6 +586,199 @@ mod tests {
         assert!(cleaned.contains("More text here."));
     }
 
+    #[test]
+    fn test_url_policies() {
+        let text = "Read [the post](https://www.example.com/post?utm_source=x) or https://blog.example.org/a.";
+        let mut links = Vec::new();
+        assert_eq!(
+            replace_urls(text, UrlPolicy::Remove, &mut links),
+            "Read the post or ."
+        );
+        assert_eq!(
+            replace_urls(text, UrlPolicy::Placeholder, &mut links),
+            "Read the post [link: example.com] or [link: blog.example.org]."
+        );
+        assert!(links.is_empty());
+        assert_eq!(
+            replace_urls(text, UrlPolicy::Extract, &mut links),
+            "Read the post or ."
+        );
+        assert_eq!(
+            links,
+            vec![
+                "https://www.example.com/post?utm_source=x",
+                "https://blog.example.org/a"
+            ]
+        );
+        assert_eq!(
+            replace_urls(text, UrlPolicy::Clean, &mut links),
+            "Read [the post](https://www.example.com/post) or https://blog.example.org/a."
+        );
+        let mut email = EmailSummary {
+            body_raw: text.to_string(),
+            body_text: Some(text.to_string()),
+            ..Default::default()
+        };
+        apply_url_policy(&mut email, UrlPolicy::Extract);
+        assert_eq!(email.body_text.as_deref(), Some("Read the post or ."));
+        assert_eq!(email.links.len(), 2);
+        assert_eq!("Extract".parse::<UrlPolicy>(), Ok(UrlPolicy::Extract));
+        assert!("drop".parse::<UrlPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_clean_url() {
+        assert_eq!(
+            clean_url(
+                "https://shop.example.com/p/1?utm_source=news&utm_medium=email&id=7&fbclid=x#top"
+            ),
+            "https://shop.example.com/p/1?id=7#top"
+        );
+        assert_eq!(
+            clean_url("https://www.google.com/url?q=https%3A%2F%2Fexample.com%2Fa%3Futm_campaign%3Dx&sa=D"),
+            "https://example.com/a"
+        );
+        assert_eq!(
+            clean_url("https://eur01.safelinks.protection.outlook.com/?url=https%3A%2F%2Fl.facebook.com%2Fl.php%3Fu%3Dhttps%253A%252F%252Fexample.org%252F&data=1"),
+            "https://example.org/"
+        );
+        assert_eq!(
+            clean_url("https://www.google.com/search?q=rust"),
+            "https://www.google.com/search?q=rust"
+        );
+        // Only known redirectors are unwrapped
+        for url in [
+            "https://evil.example/?url=https://bank.example.com/",
+            "https://google.com.evil.example/url?q=https://bank.example.com/",
+            "https://google.com@evil.example/url?q=https://bank.example.com/",
+            "https://app.example.com/login?redirect=https://app.example.com/home",
+        ] {
+            assert_eq!(clean_url(url), url);
+        }
+    }
+
+    #[test]
+    fn test_strip_quoted_reply() {
+        let gmail = "Sounds good, see you then.\n\n\
+            On Mon, May 6, 2024 at 9:14 AM Jane Doe <jane@example.com>\n\
+            wrote:\n> Lunch on Friday?\n>\n> Jane\n";
+        assert_eq!(strip_quoted_reply(gmail), "Sounds good, see you then.");
+
+        let outlook = "Approved.\r\n\r\n-----Original Message-----\r\nFrom: Bob\r\nPlease approve.";
+        assert_eq!(strip_quoted_reply(outlook), "Approved.");
+
+        let separator = "Thanks!\n\n________________________________\nFrom: Bob <bob@example.com>\nSent: Monday";
+        assert_eq!(strip_quoted_reply(separator), "Thanks!");
+
+        let inline = "> Can you make it?\nYes.\n> And Carol?\nShe can too.";
+        assert_eq!(strip_quoted_reply(inline), "Yes.\nShe can too.");
+
+        let plain = "On Friday the office is closed.\nEnjoy the weekend.";
+        assert_eq!(strip_quoted_reply(plain), plain);
+    }
+
+    #[test]
+    fn test_mark_duplicates() {
+        let email = |id: &str, message_id: Option<&str>, body: &str| EmailSummary {
+            id: id.to_string(),
+            message_id: message_id.map(str::to_string),
+            subject: "Weekly report".to_string(),
+            body_raw: body.to_string(),
+            ..Default::default()
+        };
+        let mut emails = vec![
+            email("a", Some("<r1@example.com>"), "Numbers are up."),
+            email("b", Some("<R1@example.com>"), "Numbers are up."),
+            email("c", Some("<r2@example.com>"), "Numbers  are\nUP."),
+            email("d", Some("<r3@example.com>"), "Numbers are down."),
+            email("e", None, ""),
+            email("f", None, ""),
+        ];
+        assert_eq!(mark_duplicates(emails.iter_mut()), 2);
+        let duplicate_of: Vec<Option<&str>> = emails
+            .iter()
+            .map(|email| email.duplicate_of.as_deref())
+            .collect();
+        assert_eq!(
+            duplicate_of,
+            vec![None, Some("a"), Some("a"), None, None, None]
+        );
+    }
+
+    #[test]
+    fn test_extract_addresses() {
+        let text = "Ask Ann (ann@example.com) or <Bob.Smith@Mail.Example.co.uk>.\n\
+            [1]: mailto:ann@example.com\n\
+            <img src=\"cid:image001.png@01DA2B3C.4D5E6F70\">\n\
+            Not an address: @example.com, user@localhost";
+        assert_eq!(
+            extract_addresses(text),
+            vec!["ann@example.com", "bob.smith@mail.example.co.uk"]
+        );
+        assert!(extract_addresses("No addresses here.").is_empty());
+    }
+
+    #[test]
+    fn test_truncate_chars() {
+        let mut text = "Grüße aus Köln".to_string();
+        truncate_chars(&mut text, 5, false);
+        assert_eq!(text, "Grüß…");
+        let mut words =
+            "The quick brown fox jumps over the lazy dog and keeps on running".to_string();
+        truncate_chars(&mut words, 50, false);
+        assert_eq!(words, "The quick brown… [truncated, 48 more characters]");
+        let mut url = "See https://example.com/a/very/long/path/that/keeps/going/on".to_string();
+        truncate_chars(&mut url, 45, false);
+        assert_eq!(url, "See https://… [truncated, 48 more characters]");
+        assert_eq!(url.chars().count(), 45);
+        let mut short = "Hi".to_string();
+        truncate_chars(&mut short, 5, false);
+        assert_eq!(short, "Hi");
+        let mut html =
+            "<p>Some <a href=\"https://example.com\">link</a> text here and more</p>".to_string();
+        truncate_chars(&mut html, 50, true);
+        assert_eq!(html, "<p>Some… [truncated, 61 more characters]");
+        let mut empty = "Hello".to_string();
+        truncate_chars(&mut empty, 0, false);
+        assert_eq!(empty, "");
+    }
+
+    #[tokio::test]
+    async fn test_process_email_body_formats() {
+        let email = EmailSummary {
+            body_raw: "Sale ends Friday".to_string(),
+            body_html: Some("<p>Sale ends <b>Friday</b></p>".to_string()),
+            ..Default::default()
+        };
+
+        let mut markdown = email.clone();
+        let options = FetchOptions::new().body_format(BodyFormat::Markdown);
+        process_email(&mut markdown, &options).await;
+        assert_eq!(markdown.body_raw, "Sale ends **Friday**");
+        assert_eq!(markdown.body_html, None);
+
+        let mut html = email.clone();
+        process_email(&mut html, &options.body_format(BodyFormat::Html)).await;
+        assert_eq!(html.body_raw, "<p>Sale ends <b>Friday</b></p>");
+
+        let mut text = email.clone();
+        process_email(&mut text, &FetchOptions::new()).await;
+        assert_eq!(text.body_raw, "Sale ends Friday");
+        assert_eq!(text.body_html, None);
+
+        let mut parts = EmailSummary {
+            body_text: Some("Sale ends Friday".to_string()),
+            ..email
+        };
+        let options = FetchOptions::new().body_format(BodyFormat::Html);
+        let mut single = parts.clone();
+        process_email(&mut single, &options).await;
+        assert_eq!((single.body_text, single.body_html), (None, None));
+        process_email(&mut parts, &options.include_parts(true)).await;
+        assert_eq!(parts.body_text.as_deref(), Some("Sale ends Friday"));
+        assert!(parts.body_html.is_some());
+    }
+
     #[tokio::test]
     async fn test_email_processing_with_urls() {
         let mut email = EmailSummary {
@@ -84,6 +787,7 @@ mod tests {
             subject: "Test Subject".to_string(),
             snippet: "Test snippet".to_string(),
             body_raw: "Check this out: https://example.com\n\nVisit www.test.org for more info.\n\nThanks!".to_string(),
+            ..Default::default()
         };
 
         convert_html_to_text(&mut email).await;
@@ -93,4 +797,25 @@ mod tests {
         assert!(email.body_raw.contains("Check this out:"));
         assert!(email.body_raw.contains("Thanks!"));
     }
+
+    #[tokio::test]
+    async fn test_read_emails_from_mock() {
+        use crate::mock::MockMailProvider;
+        use std::sync::Arc;
+
+        let _mock =
+            crate::provider::install_scoped(Arc::new(MockMailProvider::with_fixtures())).await;
+        let json = read_emails(FetchOptions::new().query("in:inbox".to_string()))
+            .await
+            .unwrap();
+        let response: EmailResponse = serde_json::from_str(&json).unwrap();
+        assert_eq!(response.count, 5);
+        let html = response.emails.iter().find(|e| e.id == "html").unwrap();
+        assert!(html.body_raw.contains("This week"));
+        assert!(!html.body_raw.contains("<h1>"));
+
+        let json = get_email(" plain ").await.unwrap();
+        let plain: EmailSummary = serde_json::from_str(&json).unwrap();
+        assert!(!plain.body_raw.contains("https://example.com"));
+    }
 }
@@ -0,0 +1,256 @@
+//! Retries of Gmail API calls refused by a rate limit or failed by the server.
+//!
+//! Gmail's per-user rate limits answer bursts, such as a 500-message fetch,
+//! with 429s, and its servers occasionally fail with a 5xx. The generated API
+//! calls ask their [`Delegate`] what to do after each failed response, so
+//! `messages.list` and `messages.get` are sent with a [`Backoff`], which waits
+//! for the server's `Retry-After` or a jittered exponential delay and tries
+//! again, up to `GMAIL_MAX_ATTEMPTS` attempts in all. Every attempt is counted
+//! against the quota (see [`crate::quota`]).
+
+use crate::{quota, GmailMcpError};
+use google_gmail1::common::{self, Delegate, Retry};
+use google_gmail1::hyper::header;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Environment variable with the most attempts per request, including the first.
+pub const MAX_ATTEMPTS_ENV: &str = "GMAIL_MAX_ATTEMPTS";
+/// Attempts per request when [`MAX_ATTEMPTS_ENV`] is not set.
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+
+/// Delay before the first retry, doubled for each later one.
+const BASE_DELAY: Duration = Duration::from_millis(500);
+/// Longest wait between attempts; a longer `Retry-After` ends the retries.
+const MAX_DELAY: Duration = Duration::from_secs(32);
+
+/// How often failed requests are retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryConfig {
+    /// Most attempts per request, including the first; 1 disables retries.
+    pub max_attempts: u32,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+        }
+    }
+}
+
+impl RetryConfig {
+    /// Read the configuration from the environment.
+    pub fn from_env() -> Result<Self, String> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// Read the configuration through `var`, failing on a value that is not a
+    /// positive integer.
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let Some(value) = var(MAX_ATTEMPTS_ENV).filter(|v| !v.trim().is_empty()) else {
+            return Ok(Self::default());
+        };
+        match value.trim().parse::<u32>() {
+            Ok(max_attempts) if max_attempts > 0 => Ok(Self { max_attempts }),
+            _ => Err(format!(
+                "Invalid {} '{}'; expected a positive integer",
+                MAX_ATTEMPTS_ENV, value
+            )),
+        }
+    }
+}
+
+static CONFIG: OnceLock<RetryConfig> = OnceLock::new();
+
+/// Apply `config` to this process; later calls are ignored.
+pub fn install(config: RetryConfig) {
+    info!("Gmail API retries: {:?}", config);
+    let _ = CONFIG.set(config);
+}
+
+/// Retries one request after rate limits and server errors.
+///
+/// Pass a fresh one to each call with `.delegate(&mut backoff)`.
+pub struct Backoff {
+    /// Attempts made so far.
+    attempts: u32,
+    max_attempts: u32,
+    /// The method being called, as named by [`quota::cost`].
+    method: &'static str,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Backoff {
+    /// A backoff allowing the installed number of attempts.
+    pub fn new() -> Self {
+        let config = CONFIG.get().copied().unwrap_or_default();
+        Self {
+            attempts: 1,
+            max_attempts: config.max_attempts,
+            method: "",
+        }
+    }
+
+    /// How long to wait before the next attempt, or `None` to give up.
+    fn next_delay(&mut self, retry_after: Option<Duration>) -> Option<Duration> {
+        if self.attempts >= self.max_attempts {
+            return None;
+        }
+        let delay = match retry_after {
+            Some(delay) if delay > MAX_DELAY => return None,
+            Some(delay) => delay,
+            None => jitter(backoff_delay(self.attempts)),
+        };
+        self.attempts += 1;
+        Some(delay)
+    }
+}
+
+impl Delegate for Backoff {
+    fn begin(&mut self, info: common::MethodInfo) {
+        self.method = info.id.strip_prefix("gmail.users.").unwrap_or(info.id);
+        quota::record(self.method, 1);
+    }
+
+    fn http_failure(
+        &mut self,
+        response: &common::Response,
+        err: Option<&serde_json::Value>,
+    ) -> Retry {
+        let status = response.status().as_u16();
+        if !is_retryable(status, err) {
+            return Retry::Abort;
+        }
+        let retry_after = response
+            .headers()
+            .get(header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_retry_after);
+        match self.next_delay(retry_after) {
+            Some(delay) => {
+                warn!(
+                    "Gmail API: Request failed with {}, retrying in {:?} (attempt {} of {})",
+                    status, delay, self.attempts, self.max_attempts
+                );
+                quota::record(self.method, 1);
+                Retry::After(delay)
+            }
+            None => {
+                warn!(
+                    "Gmail API: Request failed with {}, giving up after {} attempt(s)",
+                    status, self.attempts
+                );
+                Retry::Abort
+            }
+        }
+    }
+}
+
+/// Whether a failed response is worth retrying: a server error or a rate limit,
+/// which Gmail also reports as a 403 with a rate limit reason.
+fn is_retryable(status: u16, body: Option<&serde_json::Value>) -> bool {
+    if status >= 500 {
+        return true;
+    }
+    let error = match body {
+        Some(body) if body["error"].is_object() => GmailMcpError::from_body(Some(status), body),
+        _ => GmailMcpError::from_status(status, ""),
+    };
+    matches!(error, GmailMcpError::RateLimited(_))
+}
+
+/// The exponential delay before retry number `attempt` (counting from 1).
+fn backoff_delay(attempt: u32) -> Duration {
+    BASE_DELAY
+        .saturating_mul(1 << attempt.saturating_sub(1).min(16))
+        .min(MAX_DELAY)
+}
+
+/// Spread retries of concurrent requests apart: half of `delay`, plus a random
+/// share of the other half.
+fn jitter(delay: Duration) -> Duration {
+    let mut bytes = [0u8; 4];
+    if SystemRandom::new().fill(&mut bytes).is_err() {
+        return delay;
+    }
+    let share = f64::from(u32::from_le_bytes(bytes)) / f64::from(u32::MAX);
+    delay / 2 + (delay / 2).mul_f64(share)
+}
+
+/// Parse a `Retry-After` value, given in seconds or as an HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let wait = at.timestamp_millis() - chrono::Utc::now().timestamp_millis();
+    Some(Duration::from_millis(wait.max(0) as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_retry_config_from_vars() {
+        assert_eq!(RetryConfig::from_vars(|_| None).unwrap().max_attempts, 5);
+        let config = RetryConfig::from_vars(|_| Some(" 3 ".to_string())).unwrap();
+        assert_eq!(config.max_attempts, 3);
+        assert!(RetryConfig::from_vars(|_| Some("0".to_string())).is_err());
+        assert!(RetryConfig::from_vars(|_| Some("often".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        let reason = |reason: &str| json!({ "error": { "errors": [{ "reason": reason }] } });
+        assert!(is_retryable(429, None));
+        assert!(is_retryable(503, None));
+        assert!(is_retryable(403, Some(&reason("userRateLimitExceeded"))));
+        assert!(!is_retryable(403, Some(&reason("insufficientPermissions"))));
+        assert!(!is_retryable(404, None));
+    }
+
+    #[test]
+    fn test_backoff_delays() {
+        assert_eq!(backoff_delay(1), Duration::from_millis(500));
+        assert_eq!(backoff_delay(3), Duration::from_secs(2));
+        assert_eq!(backoff_delay(40), MAX_DELAY);
+        for _ in 0..10 {
+            let delay = jitter(Duration::from_secs(2));
+            assert!(delay >= Duration::from_secs(1) && delay <= Duration::from_secs(2));
+        }
+
+        let mut backoff = Backoff {
+            attempts: 1,
+            max_attempts: 3,
+            method: "messages.get",
+        };
+        let retry_after = Some(Duration::from_secs(7));
+        assert_eq!(backoff.next_delay(retry_after), retry_after);
+        assert!(backoff.next_delay(None).is_some());
+        assert_eq!(backoff.next_delay(None), None);
+
+        let mut patient = Backoff::new();
+        assert_eq!(patient.next_delay(Some(Duration::from_secs(3600))), None);
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+}
@@ -0,0 +1,475 @@
+//! Parsing for messages fetched with `format=raw`.
+//!
+//! Gmail returns the original RFC 822 bytes without a parsed payload, so the
+//! headers, plain-text and HTML bodies, and attachment names are recovered here
+//! by walking the MIME structure directly, undoing base64 and quoted-printable
+//! transfer encodings along the way.
+
+use base64::{
+    engine::general_purpose::{STANDARD, STANDARD_NO_PAD},
+    Engine as _,
+};
+use encoding_rs::{Encoding, UTF_8};
+
+/// Maximum multipart nesting followed before giving up on a message.
+const MAX_DEPTH: usize = 10;
+
+/// A file attached to a raw message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawAttachment {
+    pub filename: String,
+    pub mime_type: String,
+    pub size: u64,
+    /// The part's `Content-ID`, by which an HTML body shows it inline.
+    pub content_id: Option<String>,
+}
+
+/// The parts of a raw message needed to build an [`crate::EmailSummary`].
+#[derive(Debug, Default)]
+pub struct RawMessage {
+    /// Top-level headers, unfolded, in their original order
+    pub headers: Vec<(String, String)>,
+    /// The first `text/plain` body, or the whole body of a single-part message
+    pub body: String,
+    /// The first `text/html` body
+    pub html: Option<String>,
+    pub attachments: Vec<RawAttachment>,
+}
+
+impl RawMessage {
+    /// Look up the first header with the given name (case-insensitive).
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+}
+
+/// Parse raw RFC 822 bytes into headers, bodies, and attachments.
+pub fn parse(raw: &[u8]) -> RawMessage {
+    let (head, body) = split_head(raw);
+    let headers = parse_headers(&String::from_utf8_lossy(head));
+
+    let mut message = RawMessage::default();
+    let mut text = None;
+    walk(
+        &headers,
+        body,
+        0,
+        &mut text,
+        &mut message.html,
+        &mut message.attachments,
+    );
+    message.body = text.unwrap_or_default();
+    message.headers = headers;
+    message
+}
+
+/// Split a message or part at the blank line ending its headers.
+fn split_head(raw: &[u8]) -> (&[u8], &[u8]) {
+    for (i, window) in raw.windows(2).enumerate() {
+        if window == b"\n\n" {
+            return (&raw[..i], &raw[i + 2..]);
+        }
+        if window == b"\r\n" && raw[i + 2..].starts_with(b"\r\n") {
+            return (&raw[..i], &raw[i + 4..]);
+        }
+    }
+    (raw, &[])
+}
+
+/// Parse a header block, joining folded continuation lines.
+fn parse_headers(head: &str) -> Vec<(String, String)> {
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in head.lines() {
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    headers
+}
+
+/// A `Content-ID` header value without its angle brackets, as `cid:` URLs
+/// refer to it.
+pub(crate) fn content_id(value: &str) -> Option<String> {
+    let id = value
+        .trim()
+        .trim_start_matches('<')
+        .trim_end_matches('>')
+        .trim();
+    (!id.is_empty()).then(|| id.to_string())
+}
+
+fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+/// Read a parameter such as `boundary` or `filename` from a header value.
+pub(crate) fn header_param(value: &str, name: &str) -> Option<String> {
+    value.split(';').skip(1).find_map(|param| {
+        let (key, val) = param.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case(name)
+            .then(|| val.trim().trim_matches('"').to_string())
+    })
+}
+
+/// Decode the RFC 2047 encoded-words in a header value, such as
+/// `=?UTF-8?B?Sm9zw6k=?=` or `=?ISO-8859-1?Q?Caf=E9?=`.
+///
+/// Whitespace between adjacent encoded-words is dropped, as the RFC asks.
+/// Words that are malformed or use an unknown encoding are kept as they are.
+pub fn decode_header(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    let mut after_word = false;
+    while let Some(start) = rest.find("=?") {
+        let (before, candidate) = rest.split_at(start);
+        match decode_encoded_word(candidate) {
+            Some((decoded, len)) => {
+                if !(after_word && before.trim().is_empty()) {
+                    out.push_str(before);
+                }
+                out.push_str(&decoded);
+                rest = &candidate[len..];
+                after_word = true;
+            }
+            None => {
+                out.push_str(before);
+                out.push_str("=?");
+                rest = &candidate[2..];
+                after_word = false;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Decode the encoded-word at the start of `word`, returning the text and the
+/// length of the encoded-word.
+fn decode_encoded_word(word: &str) -> Option<(String, usize)> {
+    let inner = word.strip_prefix("=?")?;
+    let (charset, inner) = inner.split_once('?')?;
+    let (encoding, inner) = inner.split_once('?')?;
+    let end = inner.find("?=")?;
+    let text = &inner[..end];
+    if [charset, encoding, text]
+        .iter()
+        .any(|part| part.contains(char::is_whitespace))
+    {
+        return None;
+    }
+    let bytes = match encoding {
+        "B" | "b" => STANDARD_NO_PAD.decode(text.trim_end_matches('=')).ok()?,
+        "Q" | "q" => decode_q(text)?,
+        _ => return None,
+    };
+    let len = charset.len() + encoding.len() + text.len() + 6;
+    // A charset may carry a language, as in `UTF-8*en`
+    let charset = charset.split('*').next().unwrap_or_default();
+    Some((decode_charset(charset, &bytes), len))
+}
+
+/// Undo the `Q` encoding of an encoded-word: `_` is a space and `=XX` a byte.
+fn decode_q(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut chars = text.bytes();
+    while let Some(b) = chars.next() {
+        match b {
+            b'_' => bytes.push(b' '),
+            b'=' => {
+                let hex = [chars.next()?, chars.next()?];
+                let hex = std::str::from_utf8(&hex).ok()?;
+                bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            }
+            _ => bytes.push(b),
+        }
+    }
+    Some(bytes)
+}
+
+/// Text of `bytes` in `charset`, any label the WHATWG Encoding Standard knows.
+/// Unknown charsets are read as UTF-8, replacing invalid sequences.
+fn decode_charset(charset: &str, bytes: &[u8]) -> String {
+    Encoding::for_label(charset.trim().as_bytes())
+        .unwrap_or(UTF_8)
+        .decode(bytes)
+        .0
+        .into_owned()
+}
+
+/// Undo a part's `Content-Transfer-Encoding`.
+fn decode_body(body: &[u8], encoding: Option<&str>) -> Vec<u8> {
+    match encoding.map(|e| e.trim().to_ascii_lowercase()).as_deref() {
+        Some("base64") => {
+            let compact: Vec<u8> = body
+                .iter()
+                .copied()
+                .filter(|b| !b.is_ascii_whitespace())
+                .collect();
+            STANDARD.decode(compact).unwrap_or_else(|_| body.to_vec())
+        }
+        Some("quoted-printable") => decode_quoted_printable(body),
+        _ => body.to_vec(),
+    }
+}
+
+/// Undo quoted-printable encoding: `=XX` is a byte and a line ending in `=` is
+/// continued on the next line. Malformed escapes are kept as they are.
+fn decode_quoted_printable(body: &[u8]) -> Vec<u8> {
+    let hex = |b: u8| char::from(b).to_digit(16);
+    let mut out = Vec::with_capacity(body.len());
+    let mut i = 0;
+    while i < body.len() {
+        if body[i] != b'=' {
+            out.push(body[i]);
+            i += 1;
+            continue;
+        }
+        let rest = &body[i + 1..];
+        if rest.starts_with(b"\r\n") {
+            i += 3;
+        } else if rest.starts_with(b"\n") {
+            i += 2;
+        } else if let [high, low, ..] = rest {
+            match (hex(*high), hex(*low)) {
+                (Some(high), Some(low)) => {
+                    out.push((high * 16 + low) as u8);
+                    i += 3;
+                }
+                _ => {
+                    out.push(b'=');
+                    i += 1;
+                }
+            }
+        } else {
+            out.push(b'=');
+            i += 1;
+        }
+    }
+    out
+}
+
+/// Split a multipart body into its parts, ignoring the preamble and epilogue.
+fn split_multipart<'a>(text: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = Vec::new();
+    let mut start = None;
+    let mut offset = 0;
+    while offset < text.len() {
+        let end = text[offset..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(text.len(), |p| offset + p + 1);
+        let line = String::from_utf8_lossy(&text[offset..end]);
+        let line = line.trim_end();
+        if line.starts_with(&delimiter) {
+            if let Some(s) = start {
+                // Drop the line break that belongs to the delimiter
+                let mut part_end = offset;
+                if part_end > s && text[part_end - 1] == b'\n' {
+                    part_end -= 1;
+                    if part_end > s && text[part_end - 1] == b'\r' {
+                        part_end -= 1;
+                    }
+                }
+                parts.push(&text[s..part_end]);
+            }
+            if line[delimiter.len()..].starts_with("--") {
+                return parts;
+            }
+            start = Some(end);
+        }
+        offset = end;
+    }
+    parts
+}
+
+/// Recursively record the first plain-text and HTML bodies and any attachments.
+fn walk(
+    headers: &[(String, String)],
+    body: &[u8],
+    depth: usize,
+    text: &mut Option<String>,
+    html: &mut Option<String>,
+    attachments: &mut Vec<RawAttachment>,
+) {
+    let content_type = find_header(headers, "Content-Type").unwrap_or("text/plain");
+    let mime_type = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+
+    if mime_type.starts_with("multipart/") {
+        let Some(boundary) = header_param(content_type, "boundary") else {
+            return;
+        };
+        if depth >= MAX_DEPTH {
+            return;
+        }
+        for part in split_multipart(body, &boundary) {
+            let (head, part_body) = split_head(part);
+            let part_headers = parse_headers(&String::from_utf8_lossy(head));
+            walk(&part_headers, part_body, depth + 1, text, html, attachments);
+        }
+        return;
+    }
+
+    let decoded = decode_body(body, find_header(headers, "Content-Transfer-Encoding"));
+    let filename = find_header(headers, "Content-Disposition")
+        .and_then(|d| header_param(d, "filename"))
+        .or_else(|| header_param(content_type, "name"))
+        .filter(|f| !f.is_empty());
+    let content_id = find_header(headers, "Content-ID").and_then(content_id);
+    // Inline images are often sent with only a Content-ID to refer to them by
+    let inline = content_id.is_some() && !mime_type.starts_with("text/");
+    if filename.is_some() || inline {
+        attachments.push(RawAttachment {
+            filename: filename.unwrap_or_default(),
+            mime_type,
+            size: decoded.len() as u64,
+            content_id,
+        });
+        return;
+    }
+    let charset = header_param(content_type, "charset").unwrap_or_default();
+    if html.is_none() && mime_type == "text/html" {
+        *html = Some(decode_charset(&charset, &decoded));
+    }
+    if text.is_none() && (mime_type == "text/plain" || depth == 0) {
+        *text = Some(decode_charset(&charset, &decoded));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_single_part() {
+        let raw =
+            b"From: Alice <alice@example.com>\r\nSubject: A long\r\n subject\r\n\r\nHello\r\n";
+        let message = parse(raw);
+        assert_eq!(message.header("from"), Some("Alice <alice@example.com>"));
+        assert_eq!(message.header("Subject"), Some("A long subject"));
+        assert_eq!(message.body, "Hello\r\n");
+        assert!(message.attachments.is_empty());
+    }
+
+    #[test]
+    fn test_decode_header() {
+        assert_eq!(decode_header("Plain subject"), "Plain subject");
+        assert_eq!(
+            decode_header("=?UTF-8?B?Sm9zw6k=?= <jose@example.com>"),
+            "José <jose@example.com>"
+        );
+        assert_eq!(
+            decode_header("=?iso-8859-1?q?Caf=E9_cr=E8me?="),
+            "Café crème"
+        );
+        // Whitespace between encoded-words is dropped, but not around them
+        assert_eq!(
+            decode_header("Re: =?UTF-8?Q?Gr=C3=BC?= =?UTF-8?Q?=C3=9Fe?= again"),
+            "Re: Grüße again"
+        );
+        assert_eq!(
+            decode_header("=?windows-1252?Q?=80_5_=96_=93ok=94?="),
+            "\u{20ac} 5 \u{2013} \u{201c}ok\u{201d}"
+        );
+        assert_eq!(decode_header("=?koi8-r?B?8NLJ18XU?="), "Привет");
+        assert_eq!(
+            decode_header("=?UTF-8?X?abc?= =?bad"),
+            "=?UTF-8?X?abc?= =?bad"
+        );
+    }
+
+    #[test]
+    fn test_parse_quoted_printable() {
+        let raw = b"Content-Type: text/plain; charset=utf-8\r\n\
+            Content-Transfer-Encoding: quoted-printable\r\n\r\n\
+            It=E2=80=99s a long line that was wrapped so =\r\n\
+            it fits; 1+1=3D2, =ZZ stays.";
+        assert_eq!(
+            parse(raw).body,
+            "It\u{2019}s a long line that was wrapped so it fits; 1+1=2, =ZZ stays."
+        );
+
+        let latin1 = b"Content-Type: text/plain; charset=\"ISO-8859-1\"\r\n\
+            Content-Transfer-Encoding: Quoted-Printable\r\n\r\n\
+            Caf=E9=\n";
+        assert_eq!(parse(latin1).body, "Caf\u{e9}");
+    }
+
+    #[test]
+    fn test_parse_multipart_with_attachment() {
+        let raw = b"From: bob@example.com\r\n\
+            Content-Type: multipart/mixed; boundary=\"outer\"\r\n\r\n\
+            preamble\r\n\
+            --outer\r\n\
+            Content-Type: multipart/alternative; boundary=inner\r\n\r\n\
+            --inner\r\n\
+            Content-Type: text/plain; charset=UTF-8\r\n\
+            Content-Transfer-Encoding: base64\r\n\r\n\
+            SGVsbG8gdGhlcmU=\r\n\
+            --inner\r\n\
+            Content-Type: text/html\r\n\r\n\
+            <p>Hello there</p>\r\n\
+            --inner--\r\n\
+            --outer\r\n\
+            Content-Type: application/pdf; name=\"report.pdf\"\r\n\
+            Content-Disposition: attachment; filename=\"report.pdf\"\r\n\
+            Content-Transfer-Encoding: base64\r\n\r\n\
+            JVBERi0=\r\n\
+            --outer--\r\n";
+        let message = parse(raw);
+        assert_eq!(message.body, "Hello there");
+        assert_eq!(message.html.as_deref(), Some("<p>Hello there</p>"));
+        assert_eq!(
+            message.attachments,
+            vec![RawAttachment {
+                filename: "report.pdf".to_string(),
+                mime_type: "application/pdf".to_string(),
+                size: 5,
+                content_id: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_inline_image_without_filename() {
+        let raw = b"Content-Type: multipart/related; boundary=rel\r\n\r\n\
+            --rel\r\n\
+            Content-Type: text/html\r\n\
+            Content-ID: <body@example.com>\r\n\r\n\
+            <img src=\"cid:dot@example.com\">\r\n\
+            --rel\r\n\
+            Content-Type: image/gif\r\n\
+            Content-ID: <dot@example.com>\r\n\
+            Content-Transfer-Encoding: base64\r\n\r\n\
+            R0lGOA==\r\n\
+            --rel--\r\n";
+        let message = parse(raw);
+        assert_eq!(
+            message.html.as_deref(),
+            Some("<img src=\"cid:dot@example.com\">")
+        );
+        assert_eq!(
+            message.attachments,
+            vec![RawAttachment {
+                filename: String::new(),
+                mime_type: "image/gif".to_string(),
+                size: 4,
+                content_id: Some("dot@example.com".to_string()),
+            }]
+        );
+    }
+}
@@ -0,0 +1,433 @@
+//! Heuristic phishing and spam scoring.
+//!
+//! [`assess`] adds up points for the signs of phishing a message shows: a
+//! display name that names another address or a well-known brand, a `Reply-To`
+//! on another domain, a sender or links on top-level domains favored by
+//! spammers, links to bare IP addresses or whose text shows another domain,
+//! urgent wording, and failed SPF, DKIM, or DMARC checks. The score is capped
+//! at 100 and is a hint for flagging mail, not a verdict.
+
+use std::net::IpAddr;
+
+use crate::compose::parse_mailbox;
+use crate::markup::{attribute, pattern, strip_tags};
+use crate::EmailSummary;
+
+/// Top-level domains with a high share of abuse.
+const SUSPICIOUS_TLDS: &[&str] = &[
+    "zip", "mov", "xyz", "top", "click", "gq", "tk", "ml", "cf", "ga", "work", "loan", "country",
+    "kim", "men", "date", "review", "rest", "cam", "icu", "buzz", "support",
+];
+
+/// Brands often impersonated in display names.
+const BRANDS: &str = r"(?i)\b(paypal|apple|microsoft|office ?365|outlook|amazon|google|netflix|facebook|instagram|linkedin|dhl|fedex|docusign|dropbox|chase|wells ?fargo|bank ?of ?america)\b";
+
+const MICROSOFT_DOMAINS: &[&str] = &[
+    "microsoft.com",
+    "office.com",
+    "office365.com",
+    "outlook.com",
+    "live.com",
+    "hotmail.com",
+    "microsoftonline.com",
+];
+const META_DOMAINS: &[&str] = &[
+    "facebook.com",
+    "facebookmail.com",
+    "instagram.com",
+    "meta.com",
+];
+
+/// The registrable domains each brand in [`BRANDS`] sends mail from, keyed by
+/// the lowercased brand without spaces.
+const BRAND_DOMAINS: &[(&str, &[&str])] = &[
+    (
+        "paypal",
+        &["paypal.com", "paypal.me", "paypal.co.uk", "paypal.de"],
+    ),
+    ("apple", &["apple.com", "icloud.com", "me.com"]),
+    ("microsoft", MICROSOFT_DOMAINS),
+    ("office365", MICROSOFT_DOMAINS),
+    ("outlook", MICROSOFT_DOMAINS),
+    (
+        "amazon",
+        &[
+            "amazon.com",
+            "amazon.co.uk",
+            "amazon.de",
+            "amazon.fr",
+            "amazon.it",
+            "amazon.es",
+            "amazon.ca",
+            "amazon.co.jp",
+            "amazon.com.au",
+            "amazon.in",
+        ],
+    ),
+    ("google", &["google.com", "gmail.com", "googlemail.com"]),
+    ("netflix", &["netflix.com"]),
+    ("facebook", META_DOMAINS),
+    ("instagram", META_DOMAINS),
+    ("linkedin", &["linkedin.com"]),
+    ("dhl", &["dhl.com", "dhl.de", "dhl.co.uk"]),
+    ("fedex", &["fedex.com"]),
+    ("docusign", &["docusign.com", "docusign.net"]),
+    ("dropbox", &["dropbox.com", "dropboxmail.com"]),
+    ("chase", &["chase.com", "jpmorgan.com"]),
+    ("wellsfargo", &["wellsfargo.com"]),
+    ("bankofamerica", &["bankofamerica.com", "bofa.com"]),
+];
+
+/// Phrases pressing the reader to act before thinking.
+const URGENT_PHRASES: &[&str] = &[
+    "urgent",
+    "immediately",
+    "verify your account",
+    "confirm your identity",
+    "account suspended",
+    "account has been suspended",
+    "unusual activity",
+    "unusual sign-in",
+    "password expires",
+    "act now",
+    "final notice",
+    "within 24 hours",
+    "wire transfer",
+    "gift card",
+];
+/// Most urgent phrases counted towards the score.
+const MAX_URGENT_PHRASES: usize = 3;
+
+/// How risky a message looks, and why.
+#[derive(Debug, Default, PartialEq)]
+pub struct Assessment {
+    /// From 0 (no signs of phishing) to 100.
+    pub score: u32,
+    /// One entry per sign found, such as `SPF check failed`.
+    pub reasons: Vec<String>,
+}
+
+impl Assessment {
+    fn add(&mut self, points: u32, reason: String) {
+        self.score = (self.score + points).min(100);
+        self.reasons.push(reason);
+    }
+}
+
+/// The lowercased domain of `address`.
+fn domain(address: &str) -> Option<String> {
+    let (_, domain) = address.trim().trim_end_matches('>').rsplit_once('@')?;
+    let domain = domain.trim().trim_end_matches('.').to_ascii_lowercase();
+    (!domain.is_empty()).then_some(domain)
+}
+
+/// The registrable part of `domain` under the public suffix list, as
+/// `example.co.uk` for `mail.example.co.uk`; IP addresses are kept whole.
+fn base_domain(domain: &str) -> &str {
+    if domain.parse::<IpAddr>().is_ok() {
+        return domain;
+    }
+    psl::domain_str(domain).unwrap_or(domain)
+}
+
+fn same_site(a: &str, b: &str) -> bool {
+    base_domain(a) == base_domain(b)
+}
+
+fn suspicious_tld(domain: &str) -> bool {
+    domain
+        .rsplit('.')
+        .next()
+        .is_some_and(|tld| SUSPICIOUS_TLDS.contains(&tld))
+}
+
+/// The lowercased host of `url`, without port or credentials.
+fn host(url: &str) -> Option<String> {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit('@').next()?.split(':').next()?;
+    (!host.is_empty()).then(|| host.to_ascii_lowercase())
+}
+
+/// Score `summary`, whose raw headers are `headers`, for signs of phishing.
+pub fn assess(summary: &EmailSummary, headers: &[(String, String)]) -> Assessment {
+    let mut assessment = Assessment::default();
+    let sender_domain = summary
+        .sender
+        .as_ref()
+        .and_then(|sender| domain(&sender.address));
+
+    if let (Some(sender), Some(sender_domain)) = (&summary.sender, &sender_domain) {
+        check_display_name(&mut assessment, sender.name.as_deref(), sender_domain);
+        let reply_domain = summary
+            .reply_to
+            .iter()
+            .filter_map(|reply_to| domain(&parse_mailbox(reply_to).address))
+            .find(|reply_domain| !same_site(reply_domain, sender_domain));
+        if let Some(reply_domain) = reply_domain {
+            assessment.add(
+                15,
+                format!(
+                    "Replies go to {} instead of the sender's domain {}",
+                    reply_domain, sender_domain
+                ),
+            );
+        }
+        if suspicious_tld(sender_domain) {
+            assessment.add(
+                15,
+                format!("Sender domain {} uses a TLD common in spam", sender_domain),
+            );
+        }
+    }
+
+    check_links(&mut assessment, summary);
+    check_wording(&mut assessment, summary);
+    check_authentication(&mut assessment, headers);
+    assessment
+}
+
+/// Flag display names that show another address or name a brand the sender's
+/// domain does not belong to.
+fn check_display_name(assessment: &mut Assessment, name: Option<&str>, sender_domain: &str) {
+    let Some(name) = name.filter(|name| !name.trim().is_empty()) else {
+        return;
+    };
+    if let Some(shown) = pattern(r"[^\s<>@]+@[^\s<>@]+")
+        .find(name)
+        .and_then(|shown| domain(shown.as_str()))
+    {
+        if !same_site(&shown, sender_domain) {
+            assessment.add(
+                30,
+                format!(
+                    "Display name shows an address at {} but the mail is from {}",
+                    shown, sender_domain
+                ),
+            );
+            return;
+        }
+    }
+    let sender_site = base_domain(sender_domain);
+    let impersonated = pattern(BRANDS)
+        .captures_iter(name)
+        .filter_map(|caps| caps.get(1))
+        .find(|brand| {
+            let key = brand.as_str().replace(' ', "").to_ascii_lowercase();
+            BRAND_DOMAINS
+                .iter()
+                .find(|(brand, _)| *brand == key)
+                .is_some_and(|(_, domains)| !domains.contains(&sender_site))
+        });
+    if let Some(brand) = impersonated {
+        assessment.add(
+            25,
+            format!(
+                "Display name mentions {} but the mail is from {}",
+                brand.as_str(),
+                sender_domain
+            ),
+        );
+    }
+}
+
+/// Flag links to bare IP addresses or spammy TLDs, and HTML links whose text
+/// shows a different domain than they lead to.
+fn check_links(assessment: &mut Assessment, summary: &EmailSummary) {
+    let body = summary.body_html.as_deref().unwrap_or(&summary.body_raw);
+    let hosts: Vec<String> = pattern(r#"(?i)https?://[^\s<>"']+"#)
+        .find_iter(body)
+        .filter_map(|url| host(url.as_str()))
+        .collect();
+    let ip = pattern(r"^\d{1,3}(?:\.\d{1,3}){3}$");
+    if let Some(host) = hosts.iter().find(|host| ip.is_match(host)) {
+        assessment.add(15, format!("Links to the bare IP address {}", host));
+    }
+    if let Some(host) = hosts.iter().find(|host| suspicious_tld(host)) {
+        assessment.add(10, format!("Links to {}, on a TLD common in spam", host));
+    }
+
+    let Some(html) = &summary.body_html else {
+        return;
+    };
+    let shown_domain = pattern(r"(?i)^(?:https?://)?((?:[a-z0-9-]+\.)+[a-z]{2,})(?:[/:?#]\S*)?$");
+    for caps in pattern(r"(?is)<a\b([^>]*)>(.*?)</a\s*>").captures_iter(html) {
+        let Some(target) = attribute(&caps[1], "href").and_then(|href| host(&href)) else {
+            continue;
+        };
+        let text = strip_tags(&caps[2]);
+        let Some(shown) = shown_domain
+            .captures(text.trim())
+            .map(|shown| shown[1].to_ascii_lowercase())
+        else {
+            continue;
+        };
+        if !same_site(&shown, &target) {
+            assessment.add(25, format!("A link showing {} leads to {}", shown, target));
+            return;
+        }
+    }
+}
+
+/// Flag wording that pushes the reader to act at once.
+fn check_wording(assessment: &mut Assessment, summary: &EmailSummary) {
+    let text = format!(
+        "{}\n{}",
+        summary.subject,
+        summary.body_text.as_deref().unwrap_or(&summary.snippet)
+    )
+    .to_lowercase();
+    let found: Vec<&str> = URGENT_PHRASES
+        .iter()
+        .copied()
+        .filter(|phrase| text.contains(phrase))
+        .take(MAX_URGENT_PHRASES)
+        .collect();
+    if !found.is_empty() {
+        let quoted: Vec<String> = found.iter().map(|phrase| format!("'{}'", phrase)).collect();
+        assessment.add(
+            10 * found.len() as u32,
+            format!("Urgent wording: {}", quoted.join(", ")),
+        );
+    }
+}
+
+/// Flag failed SPF, DKIM, and DMARC checks recorded by the receiving server.
+fn check_authentication(assessment: &mut Assessment, headers: &[(String, String)]) {
+    let values = |name: &'static str| {
+        headers
+            .iter()
+            .filter(move |(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.to_ascii_lowercase())
+    };
+    let result = pattern(r"\b(spf|dkim|dmarc)=([a-z]+)");
+    let mut results: Vec<(String, String)> = values("Authentication-Results")
+        .flat_map(|value| {
+            result
+                .captures_iter(&value)
+                .map(|caps| (caps[1].to_string(), caps[2].to_string()))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    if !results.iter().any(|(method, _)| method == "spf") {
+        if let Some(spf) = values("Received-SPF").next() {
+            let verdict = spf
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_string();
+            results.push(("spf".to_string(), verdict));
+        }
+    }
+
+    for (method, points) in [("spf", 20), ("dkim", 20), ("dmarc", 25)] {
+        let verdicts: Vec<&str> = results
+            .iter()
+            .filter(|(m, _)| m == method)
+            .map(|(_, verdict)| verdict.as_str())
+            .collect();
+        let name = method.to_ascii_uppercase();
+        if verdicts.iter().any(|v| matches!(*v, "fail" | "permerror")) {
+            assessment.add(points, format!("{} check failed", name));
+        } else if method == "spf" && verdicts.contains(&"softfail") {
+            assessment.add(points / 2, format!("{} check soft-failed", name));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compose::Mailbox;
+
+    fn summary(name: &str, address: &str) -> EmailSummary {
+        EmailSummary {
+            sender: Some(Mailbox {
+                name: Some(name.to_string()),
+                address: address.to_string(),
+            }),
+            subject: "Your invoice".to_string(),
+            body_text: Some("Please find the invoice attached.".to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn headers(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_assess_clean_message() {
+        let headers = headers(&[(
+            "Authentication-Results",
+            "mx.google.com; dkim=pass header.i=@example.com; spf=pass; dmarc=pass",
+        )]);
+        let assessment = assess(&summary("Ann", "ann@mail.example.com"), &headers);
+        assert_eq!(assessment, Assessment::default());
+    }
+
+    #[test]
+    fn test_assess_phishing() {
+        let mut email = summary("PayPal Security", "alerts@secure-login.xyz");
+        email.subject = "URGENT: Account suspended".to_string();
+        email.reply_to = vec!["help@other.example".to_string()];
+        email.body_html = Some(
+            "<p>Verify your account within 24 hours:</p>\
+             <a href=\"http://192.168.4.2/login\">https://www.paypal.com/signin</a>"
+                .to_string(),
+        );
+        email.body_text = Some("Verify your account within 24 hours.".to_string());
+        let headers = headers(&[(
+            "Authentication-Results",
+            "mx.google.com; spf=softfail; dkim=none; dmarc=fail",
+        )]);
+
+        let assessment = assess(&email, &headers);
+        assert_eq!(assessment.score, 100);
+        assert_eq!(
+            assessment.reasons,
+            vec![
+                "Display name mentions PayPal but the mail is from secure-login.xyz",
+                "Replies go to other.example instead of the sender's domain secure-login.xyz",
+                "Sender domain secure-login.xyz uses a TLD common in spam",
+                "Links to the bare IP address 192.168.4.2",
+                "A link showing www.paypal.com leads to 192.168.4.2",
+                "Urgent wording: 'urgent', 'verify your account', 'account suspended'",
+                "SPF check soft-failed",
+                "DMARC check failed",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_display_name_brand() {
+        let flagged =
+            |name: &str, address: &str| !assess(&summary(name, address), &[]).reasons.is_empty();
+        assert!(flagged("PayPal", "service@paypal-login.net"));
+        assert!(flagged("Apple Support", "help@pineapple.com"));
+        assert!(!flagged("Outlook", "no-reply@microsoft.com"));
+        assert!(!flagged("Office 365", "alerts@email.office.com"));
+        assert!(!flagged("Amazon.co.jp", "order@amazon.co.jp"));
+    }
+
+    #[test]
+    fn test_display_name_address() {
+        let assessment = assess(
+            &summary("billing@bank.example", "billing@bank-example.top"),
+            &[],
+        );
+        assert_eq!(assessment.score, 30 + 15);
+        assert!(assessment.reasons[0].starts_with("Display name shows an address at bank.example"));
+        assert_eq!(base_domain("mail.example.com"), "example.com");
+        assert_eq!(base_domain("mail.example.co.uk"), "example.co.uk");
+        assert!(!same_site("shop.example.com.au", "bank.com.au"));
+        assert_eq!(base_domain("192.168.4.2"), "192.168.4.2");
+        assert_eq!(
+            host("https://user@Example.com:8443/path?q=1").as_deref(),
+            Some("example.com")
+        );
+    }
+}
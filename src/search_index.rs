@@ -0,0 +1,376 @@
+//! Local full-text index of the emails downloaded so far.
+//!
+//! Every email fetched in full or raw format is added to a tantivy index in
+//! `GMAIL_INDEX_DIR`, with its sender, subject, text body, thread, and dates. The
+//! `gmail_local_search` tool answers queries from the index alone, so it works
+//! without Gmail quota and while offline, but only finds mail fetched before.
+
+use crate::{EmailResponse, EmailSummary, GmailMcpError};
+use chrono::SecondsFormat;
+use google_gmail1::api::Message;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use tantivy::collector::{Count, TopDocs};
+use tantivy::directory::MmapDirectory;
+use tantivy::query::{QueryParser, TermQuery};
+use tantivy::schema::{
+    Field, IndexRecordOption, Schema, Value, FAST, INDEXED, STORED, STRING, TEXT,
+};
+use tantivy::{DateTime, Index, IndexReader, IndexWriter, TantivyDocument, TantivyError, Term};
+use tracing::{info, warn};
+
+/// Environment variable with the directory holding the index.
+pub const INDEX_DIR_ENV: &str = "GMAIL_INDEX_DIR";
+
+/// Memory the index writer may buffer before flushing to disk.
+const WRITER_MEMORY: usize = 50_000_000;
+/// Characters of the body kept as the snippet of a search result.
+const SNIPPET_CHARS: usize = 200;
+
+/// Fields of an indexed email.
+struct Fields {
+    id: Field,
+    thread_id: Field,
+    from: Field,
+    subject: Field,
+    body: Field,
+    /// When Gmail received the email, searchable with `date:`.
+    date: Field,
+    /// The email's `Date` header, as in [`EmailSummary::date`].
+    sent: Field,
+}
+
+/// A tantivy index of emails, searchable by sender, subject, body, and date.
+pub struct LocalIndex {
+    index: Index,
+    reader: IndexReader,
+    writer: Mutex<IndexWriter>,
+    fields: Fields,
+}
+
+impl LocalIndex {
+    /// Open the index in `dir`, creating the directory and index if needed.
+    /// An index written with other fields is emptied and recreated, since it
+    /// only holds mail that can be fetched again.
+    pub fn open(dir: &Path) -> Result<Self, GmailMcpError> {
+        let fail = |e: &dyn std::fmt::Display| {
+            index_error(&format!("Cannot open the index {}", dir.display()), e)
+        };
+        std::fs::create_dir_all(dir)?;
+        let mut builder = Schema::builder();
+        let fields = Fields {
+            id: builder.add_text_field("id", STRING | STORED),
+            thread_id: builder.add_text_field("thread_id", STRING | STORED),
+            from: builder.add_text_field("from", TEXT | STORED),
+            subject: builder.add_text_field("subject", TEXT | STORED),
+            body: builder.add_text_field("body", TEXT | STORED),
+            date: builder.add_date_field("date", INDEXED | STORED | FAST),
+            sent: builder.add_text_field("sent", STORED),
+        };
+        let schema = builder.build();
+        let directory = MmapDirectory::open(dir).map_err(|e| fail(&e))?;
+        let index = match Index::open_or_create(directory, schema.clone()) {
+            Err(TantivyError::SchemaError(e)) => {
+                warn!("Local index: Recreating {}: {}", dir.display(), e);
+                std::fs::remove_dir_all(dir)?;
+                std::fs::create_dir_all(dir)?;
+                Index::create_in_dir(dir, schema)
+            }
+            opened => opened,
+        }
+        .map_err(|e| fail(&e))?;
+        let reader = index.reader().map_err(|e| fail(&e))?;
+        let writer = index.writer(WRITER_MEMORY).map_err(|e| fail(&e))?;
+        info!("Local index: Using {}", dir.display());
+        Ok(Self {
+            index,
+            reader,
+            writer: Mutex::new(writer),
+            fields,
+        })
+    }
+
+    /// Add or replace the emails, each with the message it was summarized from.
+    pub fn add(&self, emails: &[(Message, EmailSummary)]) -> Result<(), GmailMcpError> {
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        for (message, email) in emails {
+            let fields = &self.fields;
+            writer.delete_term(Term::from_field_text(fields.id, &email.id));
+            let mut doc = TantivyDocument::default();
+            doc.add_text(fields.id, &email.id);
+            doc.add_text(fields.thread_id, &email.thread_id);
+            doc.add_text(fields.from, &email.from);
+            doc.add_text(fields.subject, &email.subject);
+            doc.add_text(fields.body, body_text(email));
+            if let Some(date) = message.internal_date {
+                doc.add_date(fields.date, DateTime::from_timestamp_millis(date));
+            }
+            if let Some(sent) = &email.date {
+                doc.add_text(fields.sent, sent);
+            }
+            writer
+                .add_document(doc)
+                .map_err(|e| index_error(&format!("Cannot index message {}", email.id), &e))?;
+        }
+        writer
+            .commit()
+            .map_err(|e| index_error("Cannot commit the index", &e))?;
+        self.reader
+            .reload()
+            .map_err(|e| index_error("Cannot reload the index", &e))?;
+        Ok(())
+    }
+
+    /// Whether email `id` has been indexed.
+    pub fn contains(&self, id: &str) -> bool {
+        let query = TermQuery::new(
+            Term::from_field_text(self.fields.id, id),
+            IndexRecordOption::Basic,
+        );
+        self.reader
+            .searcher()
+            .search(&query, &Count)
+            .is_ok_and(|count| count > 0)
+    }
+
+    /// Remove the emails `ids`, and with `all` every email.
+    pub fn remove(&self, ids: &[String], all: bool) -> Result<(), GmailMcpError> {
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        if all {
+            writer
+                .delete_all_documents()
+                .map_err(|e| index_error("Cannot clear the index", &e))?;
+        }
+        for id in ids {
+            writer.delete_term(Term::from_field_text(self.fields.id, id));
+        }
+        writer
+            .commit()
+            .map_err(|e| index_error("Cannot commit the index", &e))?;
+        self.reader
+            .reload()
+            .map_err(|e| index_error("Cannot reload the index", &e))?;
+        Ok(())
+    }
+
+    /// The best `max_results` emails matching `query`, most relevant first.
+    ///
+    /// Queries use tantivy's syntax: words match the sender, subject, or body,
+    /// and `from:`, `subject:`, `body:`, and `date:[… TO …]` restrict a term to
+    /// one field.
+    pub fn search(&self, query: &str, max_results: usize) -> Result<EmailResponse, GmailMcpError> {
+        let fields = &self.fields;
+        let parser =
+            QueryParser::for_index(&self.index, vec![fields.from, fields.subject, fields.body]);
+        let query = parser
+            .parse_query(query)
+            .map_err(|e| GmailMcpError::Invalid(format!("Invalid local search query: {}", e)))?;
+        let searcher = self.reader.searcher();
+        let hits = searcher
+            .search(&query, &TopDocs::with_limit(max_results))
+            .map_err(|e| index_error("Local search failed", &e))?;
+
+        let mut emails = Vec::with_capacity(hits.len());
+        for (_, address) in hits {
+            let doc: TantivyDocument = searcher
+                .doc(address)
+                .map_err(|e| index_error("Cannot read indexed email", &e))?;
+            let text = |field: Field| {
+                doc.get_first(field)
+                    .and_then(|value| value.as_str())
+                    .unwrap_or_default()
+                    .to_string()
+            };
+            let body = text(fields.body);
+            let sent = text(fields.sent);
+            let received = doc
+                .get_first(fields.date)
+                .and_then(|value| value.as_datetime())
+                .and_then(|date| {
+                    chrono::DateTime::from_timestamp_millis(date.into_timestamp_millis())
+                });
+            emails.push(EmailSummary {
+                id: text(fields.id),
+                thread_id: text(fields.thread_id),
+                from: text(fields.from),
+                subject: text(fields.subject),
+                snippet: body.chars().take(SNIPPET_CHARS).collect(),
+                body_raw: body,
+                date: (!sent.is_empty()).then_some(sent),
+                internal_date: received.map(|date| date.to_rfc3339_opts(SecondsFormat::Secs, true)),
+                ..Default::default()
+            });
+        }
+        Ok(EmailResponse {
+            count: emails.len(),
+            emails,
+            next_page_token: None,
+            threads: None,
+        })
+    }
+}
+
+/// The plain-text body of an email, as [`crate::summarize_message`] made it.
+fn body_text(email: &EmailSummary) -> &str {
+    email.body_text.as_deref().unwrap_or(&email.body_raw)
+}
+
+static INDEX: OnceLock<LocalIndex> = OnceLock::new();
+
+/// Directory of the index, from the environment, if one is configured.
+pub fn dir_from_env() -> Option<PathBuf> {
+    std::env::var(INDEX_DIR_ENV)
+        .ok()
+        .filter(|v| !v.trim().is_empty())
+        .map(|v| PathBuf::from(v.trim()))
+}
+
+/// Open the index in `dir` for this process; later calls are ignored.
+pub fn install(dir: &Path) -> Result<(), GmailMcpError> {
+    if INDEX.get().is_none() {
+        let _ = INDEX.set(LocalIndex::open(dir)?);
+    }
+    Ok(())
+}
+
+/// Whether an index has been installed.
+pub fn is_enabled() -> bool {
+    INDEX.get().is_some()
+}
+
+/// Add fetched emails not indexed yet to the installed index, if any, off the
+/// async runtime. Emails never change, so those already indexed are skipped.
+/// Failures are logged, since indexing never fails a fetch.
+pub(crate) fn index_in_background(mut emails: Vec<(Message, EmailSummary)>) {
+    let Some(index) = INDEX.get() else {
+        return;
+    };
+    if emails.is_empty() {
+        return;
+    }
+    tokio::task::spawn_blocking(move || {
+        emails.retain(|(_, email)| !index.contains(&email.id));
+        if emails.is_empty() {
+            return;
+        }
+        if let Err(e) = index.add(&emails) {
+            warn!("Local index: {}", e);
+        }
+    });
+}
+
+/// Remove deleted emails from the installed index, if any, and with `all`
+/// every email. Failures are logged.
+pub(crate) async fn remove(ids: Vec<String>, all: bool) {
+    let Some(index) = INDEX.get() else {
+        return;
+    };
+    let removed = tokio::task::spawn_blocking(move || index.remove(&ids, all)).await;
+    if let Ok(Err(e)) = removed {
+        warn!("Local index: {}", e);
+    }
+}
+
+/// An error reading or writing the index, which lives on local disk.
+fn index_error(context: &str, e: &dyn std::fmt::Display) -> GmailMcpError {
+    GmailMcpError::Io(std::io::Error::other(format!("{}: {}", context, e)))
+}
+
+/// Search the installed index, returning pretty-printed JSON.
+pub async fn local_search(query: &str, max_results: u32) -> Result<String, GmailMcpError> {
+    let index = INDEX.get().ok_or_else(|| {
+        GmailMcpError::Invalid(format!(
+            "The local index is disabled; set {} to a directory to enable it",
+            INDEX_DIR_ENV
+        ))
+    })?;
+    let query = query.trim().to_string();
+    if query.is_empty() {
+        return Err(GmailMcpError::Invalid(
+            "A search query is required".to_string(),
+        ));
+    }
+    let max_results = max_results.clamp(1, 500) as usize;
+    info!("Local index: Searching for '{}'", query);
+    let response = tokio::task::spawn_blocking(move || index.search(&query, max_results))
+        .await
+        .map_err(|e| index_error("Local search failed", &e))??;
+    Ok(serde_json::to_string_pretty(&response)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email(id: &str, from: &str, subject: &str, body: &str) -> (Message, EmailSummary) {
+        let message = Message {
+            internal_date: Some(1_700_000_000_000),
+            ..Default::default()
+        };
+        let summary = EmailSummary {
+            id: id.to_string(),
+            thread_id: format!("t-{}", id),
+            from: from.to_string(),
+            subject: subject.to_string(),
+            body_raw: body.to_string(),
+            body_text: body
+                .starts_with('<')
+                .then(|| html2text::from_read(body.as_bytes(), 100)),
+            date: Some("2023-11-14T22:13:20Z".to_string()),
+            ..Default::default()
+        };
+        (message, summary)
+    }
+
+    #[test]
+    fn test_index_and_search() {
+        let dir = std::env::temp_dir().join(format!("gmail-mcp-index-{}", std::process::id()));
+        let index = LocalIndex::open(&dir).unwrap();
+        index
+            .add(&[
+                email("a", "Alice <alice@example.com>", "Lunch", "Pizza on Friday"),
+                email(
+                    "b",
+                    "Bob <bob@example.com>",
+                    "Invoice",
+                    "<p>Your invoice is due</p>",
+                ),
+            ])
+            .unwrap();
+        // Re-adding replaces rather than duplicates
+        index
+            .add(&[email(
+                "a",
+                "Alice <alice@example.com>",
+                "Lunch",
+                "Pizza on Friday",
+            )])
+            .unwrap();
+
+        let pizza = index.search("pizza", 10).unwrap();
+        assert_eq!(pizza.count, 1);
+        assert_eq!(pizza.emails[0].id, "a");
+        assert_eq!(pizza.emails[0].thread_id, "t-a");
+        assert_eq!(
+            pizza.emails[0].date.as_deref(),
+            Some("2023-11-14T22:13:20Z")
+        );
+        assert_eq!(
+            pizza.emails[0].internal_date.as_deref(),
+            Some("2023-11-14T22:13:20Z")
+        );
+        assert!(index.contains("a"));
+        assert!(!index.contains("c"));
+        assert_eq!(index.search("invoice", 10).unwrap().emails[0].id, "b");
+        assert_eq!(index.search("from:bob", 10).unwrap().count, 1);
+        assert_eq!(
+            index
+                .search("date:[2020-01-01T00:00:00Z TO *]", 10)
+                .unwrap()
+                .count,
+            2
+        );
+        assert!(index.search("from:(", 10).is_err());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,342 @@
+//! Sending mail through `users.messages.send`.
+
+use crate::capabilities::{self, Capability};
+use crate::compose::{
+    load_attachments, split_addresses, Attachment, AttachmentInput, OutgoingEmail,
+};
+use crate::markup::{render_body, BodyFormat};
+use crate::{header_value, GmailHub, GmailMcpError, SCOPE_SEND};
+use google_gmail1::api::{Message, MessagePartHeader};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use tracing::{error, info};
+
+/// Identifiers of a message Gmail accepted for delivery.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SendResponse {
+    /// The ID of the sent message.
+    pub id: String,
+    /// The thread the sent message belongs to.
+    pub thread_id: Option<String>,
+}
+
+/// Send a composed email, optionally into an existing thread.
+pub async fn send_message(
+    hub: &GmailHub,
+    email: &OutgoingEmail,
+    thread_id: Option<&str>,
+) -> Result<SendResponse, GmailMcpError> {
+    let raw = email.to_rfc822()?;
+    info!(
+        "Gmail API: Sending email '{}' to {} recipient(s)",
+        email.subject,
+        email.to.len() + email.cc.len() + email.bcc.len()
+    );
+
+    let request = Message {
+        thread_id: thread_id.map(str::to_string),
+        ..Default::default()
+    };
+    crate::quota::record("messages.send", 1);
+    let (_, sent) = hub
+        .users()
+        .messages_send(request, "me")
+        .add_scope(SCOPE_SEND)
+        .upload(Cursor::new(raw), "message/rfc822".parse()?)
+        .await
+        .map_err(|e| {
+            error!("Gmail API: Failed to send email: {}", e);
+            e
+        })?;
+
+    Ok(SendResponse {
+        id: sent.id.unwrap_or_default(),
+        thread_id: sent.thread_id,
+    })
+}
+
+/// Connect to Gmail and send an email, returning JSON with the new message ID.
+///
+/// HTML and Markdown bodies are sent as `multipart/alternative` with a text fallback.
+pub async fn send_email(
+    to: Vec<String>,
+    cc: Vec<String>,
+    bcc: Vec<String>,
+    subject: &str,
+    body: &str,
+    format: BodyFormat,
+    attachments: &[AttachmentInput],
+) -> Result<String, GmailMcpError> {
+    capabilities::require(Capability::Send)?;
+    let (body, html_body) = render_body(body, format);
+    let email = OutgoingEmail {
+        to,
+        cc,
+        bcc,
+        subject: subject.to_string(),
+        body,
+        html_body,
+        attachments: load_attachments(attachments)?,
+        ..Default::default()
+    };
+    // Validate before connecting so a malformed email never triggers an OAuth prompt
+    email.to_rfc822()?;
+
+    let provider = crate::provider::current().await?;
+    let response = provider.send(&email, None).await?;
+    info!("Gmail API: Sent message {}", response.id);
+    Ok(serde_json::to_string_pretty(&response)?)
+}
+
+/// Prefix a subject with `Re:` unless it already has one.
+fn reply_subject(subject: &str) -> String {
+    let trimmed = subject.trim();
+    if trimmed
+        .get(..3)
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case("re:"))
+    {
+        trimmed.to_string()
+    } else {
+        format!("Re: {}", trimmed)
+    }
+}
+
+/// Build a reply to a message from its headers, addressed to every address of
+/// `Reply-To` (or `From`) with `In-Reply-To`/`References` set so clients thread
+/// it correctly.
+fn reply_email(headers: &[MessagePartHeader], body: &str) -> Result<OutgoingEmail, GmailMcpError> {
+    let recipients = ["Reply-To", "From"]
+        .iter()
+        .filter_map(|name| header_value(headers, name))
+        .map(|value| split_addresses(&value))
+        .find(|addresses| !addresses.is_empty())
+        .ok_or_else(|| {
+            GmailMcpError::Invalid("Original message has no From or Reply-To header".to_string())
+        })?;
+    let subject = header_value(headers, "Subject").unwrap_or_default();
+
+    let mut email = OutgoingEmail::new(recipients, &reply_subject(&subject), body);
+    if let Some(message_id) = header_value(headers, "Message-ID") {
+        let references = match header_value(headers, "References") {
+            Some(refs) => format!("{} {}", refs.trim(), message_id),
+            None => message_id.clone(),
+        };
+        email
+            .extra_headers
+            .push(("In-Reply-To".to_string(), message_id));
+        email
+            .extra_headers
+            .push(("References".to_string(), references));
+    }
+    Ok(email)
+}
+
+/// Connect to Gmail and reply to `message_id` within its thread, returning JSON.
+pub async fn reply_to(message_id: &str, body: &str) -> Result<String, GmailMcpError> {
+    capabilities::require(Capability::Send)?;
+    let message_id = message_id.trim();
+    if message_id.is_empty() {
+        return Err(GmailMcpError::Invalid(
+            "A message ID is required to reply".to_string(),
+        ));
+    }
+
+    let provider = crate::provider::current().await?;
+    let original = provider.message(message_id).await?;
+    let headers = original
+        .payload
+        .as_ref()
+        .and_then(|p| p.headers.as_deref())
+        .unwrap_or_default();
+    let email = reply_email(headers, body)?;
+
+    let response = provider.send(&email, original.thread_id.as_deref()).await?;
+    info!(
+        "Gmail API: Sent reply {} to message {}",
+        response.id, message_id
+    );
+    Ok(serde_json::to_string_pretty(&response)?)
+}
+
+/// Prefix a subject with `Fwd:` unless it already has one.
+fn forward_subject(subject: &str) -> String {
+    let trimmed = subject.trim();
+    if trimmed
+        .get(..4)
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case("fwd:"))
+    {
+        trimmed.to_string()
+    } else {
+        format!("Fwd: {}", trimmed)
+    }
+}
+
+/// Build the inline forwarded block: the original headers followed by its text.
+fn forwarded_block(headers: &[MessagePartHeader], original_text: &str) -> String {
+    let mut block = String::from("---------- Forwarded message ---------\n");
+    for name in ["From", "Date", "Subject", "To", "Cc"] {
+        if let Some(value) = header_value(headers, name) {
+            block.push_str(&format!("{}: {}\n", name, value));
+        }
+    }
+    block.push('\n');
+    block.push_str(original_text);
+    block
+}
+
+/// Join an optional note and the forwarded content with a blank line.
+fn with_note(note: Option<&str>, content: &str) -> String {
+    match note.map(str::trim).filter(|n| !n.is_empty()) {
+        Some(note) => format!("{}\n\n{}", note, content),
+        None => content.to_string(),
+    }
+}
+
+/// Connect to Gmail and forward `message_id` to new recipients, returning JSON.
+///
+/// The original is quoted inline with its headers, or attached unchanged as a
+/// `message/rfc822` part when `as_attachment` is set.
+pub async fn forward_email(
+    message_id: &str,
+    to: Vec<String>,
+    note: Option<&str>,
+    as_attachment: bool,
+) -> Result<String, GmailMcpError> {
+    capabilities::require(Capability::Send)?;
+    let message_id = message_id.trim();
+    if message_id.is_empty() {
+        return Err(GmailMcpError::Invalid(
+            "A message ID is required to forward".to_string(),
+        ));
+    }
+
+    let provider = crate::provider::current().await?;
+    let original = provider.message(message_id).await?;
+    let headers = original
+        .payload
+        .as_ref()
+        .and_then(|p| p.headers.as_deref())
+        .unwrap_or_default();
+    let subject = forward_subject(&header_value(headers, "Subject").unwrap_or_default());
+
+    let email = if as_attachment {
+        let raw = provider.raw(message_id).await?;
+        let mut email = OutgoingEmail::new(to, &subject, &with_note(note, ""));
+        email.attachments.push(Attachment {
+            filename: format!("{}.eml", message_id),
+            mime_type: "message/rfc822".to_string(),
+            data: raw,
+        });
+        email
+    } else {
+        let summary = crate::summarize_message(&original)
+            .ok_or_else(|| GmailMcpError::Parse(format!("message {}: no ID", message_id)))?;
+        let text = if summary.body_raw.starts_with('<') {
+            html2text::from_read(summary.body_raw.as_bytes(), 100)
+        } else {
+            summary.body_raw
+        };
+        OutgoingEmail::new(
+            to,
+            &subject,
+            &with_note(note, &forwarded_block(headers, &text)),
+        )
+    };
+
+    let response = provider.send(&email, None).await?;
+    info!(
+        "Gmail API: Forwarded message {} as {}",
+        message_id, response.id
+    );
+    Ok(serde_json::to_string_pretty(&response)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(name: &str, value: &str) -> MessagePartHeader {
+        MessagePartHeader {
+            name: Some(name.to_string()),
+            value: Some(value.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_reply_subject() {
+        assert_eq!(reply_subject("Lunch?"), "Re: Lunch?");
+        assert_eq!(reply_subject("RE: Lunch?"), "RE: Lunch?");
+        assert_eq!(reply_subject("éé"), "Re: éé");
+    }
+
+    #[test]
+    fn test_reply_email_sets_threading_headers() {
+        let headers = vec![
+            header("From", "Ann <ann@example.com>"),
+            header("Subject", "Lunch?"),
+            header("Message-ID", "<m2@example.com>"),
+            header("References", "<m1@example.com>"),
+        ];
+        let email = reply_email(&headers, "Sure!").unwrap();
+
+        assert_eq!(email.to, vec!["Ann <ann@example.com>".to_string()]);
+        assert_eq!(email.subject, "Re: Lunch?");
+        assert_eq!(
+            email.extra_headers,
+            vec![
+                ("In-Reply-To".to_string(), "<m2@example.com>".to_string()),
+                (
+                    "References".to_string(),
+                    "<m1@example.com> <m2@example.com>".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_forwarded_block_preserves_headers() {
+        let headers = vec![
+            header("Subject", "Report"),
+            header("From", "Ann <ann@example.com>"),
+            header("Date", "Mon, 1 Jan 2024 09:00:00 +0000"),
+            header("X-Other", "ignored"),
+        ];
+        let block = forwarded_block(&headers, "Numbers attached.");
+
+        assert_eq!(
+            block,
+            "---------- Forwarded message ---------\nFrom: Ann <ann@example.com>\nDate: Mon, 1 Jan 2024 09:00:00 +0000\nSubject: Report\n\nNumbers attached."
+        );
+        assert_eq!(forward_subject("Report"), "Fwd: Report");
+        assert_eq!(with_note(Some("FYI"), "body"), "FYI\n\nbody");
+    }
+
+    #[test]
+    fn test_reply_email_prefers_reply_to() {
+        let headers = vec![
+            header("From", "ann@example.com"),
+            header("Reply-To", "team@example.com"),
+        ];
+        let email = reply_email(&headers, "Ok").unwrap();
+        assert_eq!(email.to, vec!["team@example.com".to_string()]);
+    }
+
+    #[test]
+    fn test_reply_email_to_every_reply_to_address() {
+        let headers = vec![
+            header("From", "ann@example.com"),
+            header(
+                "Reply-To",
+                "\"Sales, EMEA\" <sales@example.com>, support@example.com",
+            ),
+        ];
+        let email = reply_email(&headers, "Ok").unwrap();
+        assert_eq!(
+            email.to,
+            vec![
+                "\"Sales, EMEA\" <sales@example.com>".to_string(),
+                "support@example.com".to_string()
+            ]
+        );
+    }
+}
@@ -0,0 +1,263 @@
+//! MCP protocol wiring for the server's tools and prompts.
+//!
+//! mcp-core's `ServerProtocolBuilder` only knows about tools, so the protocol is
+//! assembled here from its lower-level `ProtocolBuilder`: the same `initialize`
+//! handshake and tool handlers, plus `prompts/list` and `prompts/get`.
+//!
+//! Tools registered with an output schema also publish it as `outputSchema`,
+//! and their JSON results are returned as `structuredContent` besides the text
+//! that clients without structured output read.
+
+use crate::{limits, progress, shutdown};
+use anyhow::Result;
+use mcp_core::protocol::Protocol;
+use mcp_core::tools::ToolHandlerFn;
+use mcp_core::types::{
+    CallToolRequest, CallToolResponse, Implementation, InitializeRequest, InitializeResponse,
+    ListRequest, Prompt, PromptCapabilities, PromptMessage, PromptsListResponse, ProtocolVersion,
+    ServerCapabilities, Tool, ToolCapabilities, ToolResponseContent,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Parameters of a `prompts/get` request.
+#[derive(Debug, Clone, Deserialize)]
+pub struct GetPromptRequest {
+    pub name: String,
+    #[serde(default)]
+    pub arguments: Option<HashMap<String, String>>,
+}
+
+/// Result of a `prompts/get` request.
+#[derive(Debug, Clone, Serialize)]
+pub struct GetPromptResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub messages: Vec<PromptMessage>,
+}
+
+/// Builds the messages of a prompt from its arguments.
+pub type PromptHandlerFn =
+    fn(
+        HashMap<String, String>,
+    ) -> Pin<Box<dyn Future<Output = Result<GetPromptResponse, String>> + Send>>;
+
+/// A tool with its handler and optional output schema.
+struct RegisteredTool {
+    tool: Tool,
+    output_schema: Option<Value>,
+    handler: ToolHandlerFn,
+}
+
+impl RegisteredTool {
+    /// The tool's `tools/list` entry.
+    fn describe(&self) -> Value {
+        let mut description = serde_json::to_value(&self.tool).unwrap_or_default();
+        if let (Some(schema), Value::Object(fields)) = (&self.output_schema, &mut description) {
+            fields.insert("outputSchema".to_string(), schema.clone());
+        }
+        description
+    }
+}
+
+/// Serialize a tool's result, adding `structuredContent` when the tool has an
+/// output schema and succeeded with a JSON object.
+fn tool_result(response: CallToolResponse, structured: bool) -> Result<Value> {
+    let content = match (structured, response.is_error, response.content.first()) {
+        (true, None | Some(false), Some(ToolResponseContent::Text(text))) => {
+            serde_json::from_str::<Value>(&text.text)
+                .ok()
+                .filter(Value::is_object)
+        }
+        _ => None,
+    };
+    let mut result = serde_json::to_value(response)?;
+    if let (Some(content), Value::Object(fields)) = (content, &mut result) {
+        fields.insert("structuredContent".to_string(), content);
+    }
+    Ok(result)
+}
+
+/// Builder for the server's [`Protocol`].
+pub struct ServerBuilder {
+    server_info: Implementation,
+    protocol_version: ProtocolVersion,
+    instructions: Option<String>,
+    /// Tools in registration order.
+    tools: Vec<RegisteredTool>,
+    /// Prompts in registration order.
+    prompts: Vec<(Prompt, PromptHandlerFn)>,
+}
+
+impl ServerBuilder {
+    pub fn new(name: &str, version: &str, protocol_version: ProtocolVersion) -> Self {
+        Self {
+            server_info: Implementation {
+                name: name.to_string(),
+                version: version.to_string(),
+            },
+            protocol_version,
+            instructions: None,
+            tools: Vec::new(),
+            prompts: Vec::new(),
+        }
+    }
+
+    pub fn set_instructions(mut self, instructions: &str) -> Self {
+        self.instructions = Some(instructions.to_string());
+        self
+    }
+
+    pub fn register_tool(mut self, tool: Tool, f: ToolHandlerFn) -> Self {
+        self.tools.push(RegisteredTool {
+            tool,
+            output_schema: None,
+            handler: f,
+        });
+        self
+    }
+
+    /// Register a tool whose successful results are JSON matching `output_schema`.
+    pub fn register_tool_with_output(
+        mut self,
+        tool: Tool,
+        output_schema: Value,
+        f: ToolHandlerFn,
+    ) -> Self {
+        self.tools.push(RegisteredTool {
+            tool,
+            output_schema: Some(output_schema),
+            handler: f,
+        });
+        self
+    }
+
+    pub fn register_prompt(mut self, prompt: Prompt, f: PromptHandlerFn) -> Self {
+        self.prompts.push((prompt, f));
+        self
+    }
+
+    /// Assemble the protocol; the capabilities follow from what was registered.
+    pub fn build(self) -> Protocol {
+        let capabilities = ServerCapabilities {
+            tools: (!self.tools.is_empty()).then(ToolCapabilities::default),
+            prompts: (!self.prompts.is_empty()).then(PromptCapabilities::default),
+            ..Default::default()
+        };
+        let initialized = Arc::new(AtomicBool::new(false));
+        let tools = Arc::new(self.tools);
+        let prompts = Arc::new(self.prompts);
+
+        let response = InitializeResponse {
+            protocol_version: self.protocol_version.as_str().to_string(),
+            capabilities,
+            server_info: self.server_info,
+            instructions: self.instructions,
+        };
+        let on_initialized = initialized.clone();
+        let list_tools = (tools.clone(), initialized.clone());
+        let call_tool = (tools, initialized.clone());
+        let list_prompts = (prompts.clone(), initialized.clone());
+        let get_prompt = (prompts, initialized);
+
+        Protocol::builder()
+            .request_handler("initialize", move |_req: InitializeRequest| {
+                let response = response.clone();
+                Box::pin(async move { Ok(response) })
+            })
+            .notification_handler("notifications/initialized", move |_: ()| {
+                on_initialized.store(true, Ordering::SeqCst);
+                Box::pin(async { Ok(()) })
+            })
+            .request_handler("tools/list", move |_req: ListRequest| {
+                let (tools, initialized) = list_tools.clone();
+                Box::pin(async move {
+                    require_initialized(&initialized)?;
+                    let tools: Vec<Value> = tools.iter().map(RegisteredTool::describe).collect();
+                    Ok(json!({ "tools": tools }))
+                })
+            })
+            .request_handler("tools/call", move |req: CallToolRequest| {
+                let (tools, initialized) = call_tool.clone();
+                Box::pin(async move {
+                    require_initialized(&initialized)?;
+                    let (f, structured) = tools
+                        .iter()
+                        .find(|t| t.tool.name == req.name)
+                        .map(|t| (t.handler, t.output_schema.is_some()))
+                        .ok_or_else(|| anyhow::anyhow!("Tool not found: {}", req.name))?;
+                    let _call = shutdown::begin_call()
+                        .ok_or_else(|| anyhow::anyhow!("The server is shutting down"))?;
+                    let _permit = limits::call_permit().await;
+                    let token = progress::token(req.meta.as_ref());
+                    let response = progress::track(token, f(req)).await;
+                    tool_result(response, structured)
+                })
+            })
+            .request_handler("prompts/list", move |_req: ListRequest| {
+                let (prompts, initialized) = list_prompts.clone();
+                Box::pin(async move {
+                    require_initialized(&initialized)?;
+                    Ok(PromptsListResponse {
+                        prompts: prompts.iter().map(|(prompt, _)| prompt.clone()).collect(),
+                        next_cursor: None,
+                        meta: None,
+                    })
+                })
+            })
+            .request_handler("prompts/get", move |req: GetPromptRequest| {
+                let (prompts, initialized) = get_prompt.clone();
+                Box::pin(async move {
+                    require_initialized(&initialized)?;
+                    let f = prompts
+                        .iter()
+                        .find(|(prompt, _)| prompt.name == req.name)
+                        .map(|(_, f)| *f)
+                        .ok_or_else(|| anyhow::anyhow!("Prompt not found: {}", req.name))?;
+                    f(req.arguments.unwrap_or_default())
+                        .await
+                        .map_err(anyhow::Error::msg)
+                })
+            })
+            .build()
+    }
+}
+
+fn require_initialized(initialized: &AtomicBool) -> Result<()> {
+    if initialized.load(Ordering::SeqCst) {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("Client not initialized"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mcp_core::tool_text_content;
+
+    fn response(text: &str, is_error: Option<bool>) -> CallToolResponse {
+        CallToolResponse {
+            content: vec![tool_text_content!(text)],
+            is_error,
+            meta: None,
+        }
+    }
+
+    #[test]
+    fn test_tool_result_structured_content() {
+        let result = tool_result(response(r#"{"emails":[],"count":0}"#, None), true).unwrap();
+        assert_eq!(result["structuredContent"]["count"], 0);
+        assert_eq!(result["content"][0]["type"], "text");
+
+        let unstructured = tool_result(response(r#"{"count":0}"#, None), false).unwrap();
+        assert!(unstructured.get("structuredContent").is_none());
+        let failed = tool_result(response("Gmail API error", Some(true)), true).unwrap();
+        assert!(failed.get("structuredContent").is_none());
+    }
+}
@@ -0,0 +1,712 @@
+//! Mailbox settings: server-side filters, send-as aliases with their signatures,
+//! and mailbox delegates.
+
+use crate::actions::{self, Action};
+use crate::capabilities::{self, Capability};
+use crate::labels::{list_labels, resolve_label_ids, LabelInfo};
+use crate::{GmailHub, GmailMcpError, SCOPE_SETTINGS_BASIC, SCOPE_SETTINGS_SHARING};
+use google_gmail1::api::{self, Delegate, Filter, FilterCriteria, SendAs};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use tracing::{error, info};
+
+/// The messages a filter matches.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct FilterCriteriaInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    /// Gmail search query the message must match.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    /// Gmail search query the message must not match.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub negated_query: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub has_attachment: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exclude_chats: Option<bool>,
+    /// Message size in bytes, compared using `size_comparison`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<i32>,
+    /// `larger` or `smaller`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size_comparison: Option<String>,
+}
+
+impl FilterCriteriaInfo {
+    /// Whether no criterion is set, which Gmail rejects.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl From<FilterCriteria> for FilterCriteriaInfo {
+    fn from(criteria: FilterCriteria) -> Self {
+        Self {
+            from: criteria.from,
+            to: criteria.to,
+            subject: criteria.subject,
+            query: criteria.query,
+            negated_query: criteria.negated_query,
+            has_attachment: criteria.has_attachment,
+            exclude_chats: criteria.exclude_chats,
+            size: criteria.size,
+            size_comparison: criteria.size_comparison,
+        }
+    }
+}
+
+impl From<FilterCriteriaInfo> for FilterCriteria {
+    fn from(criteria: FilterCriteriaInfo) -> Self {
+        Self {
+            from: criteria.from,
+            to: criteria.to,
+            subject: criteria.subject,
+            query: criteria.query,
+            negated_query: criteria.negated_query,
+            has_attachment: criteria.has_attachment,
+            exclude_chats: criteria.exclude_chats,
+            size: criteria.size,
+            size_comparison: criteria.size_comparison,
+        }
+    }
+}
+
+/// What a filter does to matching messages.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct FilterActionInfo {
+    /// Labels to apply, by name where known.
+    pub add_labels: Vec<String>,
+    /// Labels to remove, by name where known (`INBOX` archives, `UNREAD` marks read).
+    pub remove_labels: Vec<String>,
+    /// Address to forward matching messages to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub forward: Option<String>,
+}
+
+impl FilterActionInfo {
+    /// Whether the filter would do nothing, which Gmail rejects.
+    pub fn is_empty(&self) -> bool {
+        self.add_labels.is_empty() && self.remove_labels.is_empty() && self.forward.is_none()
+    }
+}
+
+/// A server-side Gmail filter as exposed to MCP clients.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FilterInfo {
+    pub id: String,
+    pub criteria: FilterCriteriaInfo,
+    pub action: FilterActionInfo,
+}
+
+/// Response structure that wraps the filter list
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FilterResponse {
+    pub filters: Vec<FilterInfo>,
+    pub count: usize,
+}
+
+/// Describe a filter, showing label names instead of IDs where they are known.
+fn describe_filter(filter: Filter, labels: &[LabelInfo]) -> FilterInfo {
+    let names = |ids: Option<Vec<String>>| -> Vec<String> {
+        ids.unwrap_or_default()
+            .into_iter()
+            .map(|id| {
+                labels
+                    .iter()
+                    .find(|l| l.id == id)
+                    .map(|l| l.name.clone())
+                    .unwrap_or(id)
+            })
+            .collect()
+    };
+    let action = filter.action.unwrap_or_default();
+    FilterInfo {
+        id: filter.id.unwrap_or_default(),
+        criteria: filter.criteria.unwrap_or_default().into(),
+        action: FilterActionInfo {
+            add_labels: names(action.add_label_ids),
+            remove_labels: names(action.remove_label_ids),
+            forward: action.forward,
+        },
+    }
+}
+
+/// Fetch every filter in the mailbox.
+pub async fn list_filters(hub: &GmailHub) -> Result<Vec<Filter>, google_gmail1::Error> {
+    info!("Gmail API: Requesting filter list");
+    crate::quota::record("settings.filters.list", 1);
+    let (_, response) = hub
+        .users()
+        .settings_filters_list("me")
+        .add_scope(SCOPE_SETTINGS_BASIC)
+        .doit()
+        .await
+        .map_err(|e| {
+            error!("Gmail API: Failed to list filters: {}", e);
+            e
+        })?;
+    Ok(response.filter.unwrap_or_default())
+}
+
+/// Create a filter; label names in `action` are resolved to IDs.
+pub async fn create_filter(
+    hub: &GmailHub,
+    criteria: FilterCriteriaInfo,
+    action: FilterActionInfo,
+) -> Result<Filter, GmailMcpError> {
+    let add_label_ids = resolve_label_ids(hub, &action.add_labels).await?;
+    let remove_label_ids = resolve_label_ids(hub, &action.remove_labels).await?;
+    let request = Filter {
+        criteria: Some(criteria.into()),
+        action: Some(api::FilterAction {
+            add_label_ids: Some(add_label_ids).filter(|ids| !ids.is_empty()),
+            remove_label_ids: Some(remove_label_ids).filter(|ids| !ids.is_empty()),
+            forward: action.forward,
+        }),
+        id: None,
+    };
+
+    info!("Gmail API: Creating filter");
+    crate::quota::record("settings.filters.create", 1);
+    let (_, filter) = hub
+        .users()
+        .settings_filters_create(request, "me")
+        .add_scope(SCOPE_SETTINGS_BASIC)
+        .doit()
+        .await
+        .map_err(|e| {
+            error!("Gmail API: Failed to create filter: {}", e);
+            e
+        })?;
+    Ok(filter)
+}
+
+/// Delete a filter by ID.
+pub async fn delete_filter(hub: &GmailHub, id: &str) -> Result<(), google_gmail1::Error> {
+    info!("Gmail API: Deleting filter {}", id);
+    crate::quota::record("settings.filters.delete", 1);
+    hub.users()
+        .settings_filters_delete("me", id)
+        .add_scope(SCOPE_SETTINGS_BASIC)
+        .doit()
+        .await
+        .map_err(|e| {
+            error!("Gmail API: Failed to delete filter {}: {}", id, e);
+            e
+        })?;
+    Ok(())
+}
+
+/// Actions of the `gmail_filters` tool, named apart from the Gmail API's `FilterAction`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterToolAction {
+    List,
+    Create,
+    Delete,
+}
+
+impl Action for FilterToolAction {
+    const SUBJECT: &'static str = "filter";
+    const ALL: &'static [(Self, &'static str, &'static str)] = &[
+        (FilterToolAction::List, "list", "no parameters"),
+        (FilterToolAction::Create, "create", "requires at least one of from, to, subject, query, negated_query, or has_attachment, and at least one of add_labels, remove_labels, or forward"),
+        (FilterToolAction::Delete, "delete", "requires id"),
+    ];
+}
+
+impl FromStr for FilterToolAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        actions::parse(s)
+    }
+}
+
+/// Dispatch a filter action, returning JSON.
+pub async fn manage_filters(
+    action: FilterToolAction,
+    id: Option<String>,
+    criteria: FilterCriteriaInfo,
+    filter_action: FilterActionInfo,
+) -> Result<String, GmailMcpError> {
+    capabilities::require(Capability::Settings)?;
+    match action {
+        FilterToolAction::List => {
+            let hub = crate::connect().await?;
+            let labels = list_labels(&hub).await?;
+            let filters: Vec<FilterInfo> = list_filters(&hub)
+                .await?
+                .into_iter()
+                .map(|f| describe_filter(f, &labels))
+                .collect();
+            let response = FilterResponse {
+                count: filters.len(),
+                filters,
+            };
+            Ok(serde_json::to_string_pretty(&response)?)
+        }
+        FilterToolAction::Create => {
+            if criteria.is_empty() {
+                return Err(GmailMcpError::Invalid(
+                    "Filter action 'create' requires at least one criterion".to_string(),
+                ));
+            }
+            if filter_action.is_empty() {
+                return Err(GmailMcpError::Invalid(
+                    "Filter action 'create' requires add_labels, remove_labels, or forward"
+                        .to_string(),
+                ));
+            }
+            let hub = crate::connect().await?;
+            let filter = create_filter(&hub, criteria, filter_action).await?;
+            let labels = list_labels(&hub).await?;
+            Ok(serde_json::to_string_pretty(&describe_filter(
+                filter, &labels,
+            ))?)
+        }
+        FilterToolAction::Delete => {
+            let id = id.filter(|id| !id.trim().is_empty()).ok_or_else(|| {
+                GmailMcpError::Invalid("Filter action 'delete' requires 'id'".to_string())
+            })?;
+            let hub = crate::connect().await?;
+            delete_filter(&hub, id.trim()).await?;
+            Ok(serde_json::to_string_pretty(
+                &serde_json::json!({ "deleted": id.trim() }),
+            )?)
+        }
+    }
+}
+
+/// A send-as alias as exposed to MCP clients.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SendAsInfo {
+    /// The address mail is sent from.
+    pub email: String,
+    pub display_name: Option<String>,
+    /// The HTML signature appended to mail sent from this address.
+    pub signature: Option<String>,
+    pub reply_to: Option<String>,
+    /// Whether this is the default address for new mail.
+    pub is_default: bool,
+    /// Whether this is the account's primary address.
+    pub is_primary: bool,
+    /// `accepted` or `pending` for aliases that need verification.
+    pub verification_status: Option<String>,
+}
+
+impl From<SendAs> for SendAsInfo {
+    fn from(send_as: SendAs) -> Self {
+        Self {
+            email: send_as.send_as_email.unwrap_or_default(),
+            display_name: send_as.display_name.filter(|n| !n.is_empty()),
+            signature: send_as.signature.filter(|s| !s.is_empty()),
+            reply_to: send_as.reply_to_address.filter(|r| !r.is_empty()),
+            is_default: send_as.is_default.unwrap_or(false),
+            is_primary: send_as.is_primary.unwrap_or(false),
+            verification_status: send_as.verification_status,
+        }
+    }
+}
+
+/// Response structure that wraps the send-as alias list
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SendAsResponse {
+    pub aliases: Vec<SendAsInfo>,
+    pub count: usize,
+}
+
+/// Fetch every send-as alias, including the primary address.
+pub async fn list_send_as(hub: &GmailHub) -> Result<Vec<SendAsInfo>, google_gmail1::Error> {
+    info!("Gmail API: Requesting send-as aliases");
+    crate::quota::record("settings.sendAs.list", 1);
+    let (_, response) = hub
+        .users()
+        .settings_send_as_list("me")
+        .add_scope(SCOPE_SETTINGS_BASIC)
+        .doit()
+        .await
+        .map_err(|e| {
+            error!("Gmail API: Failed to list send-as aliases: {}", e);
+            e
+        })?;
+    Ok(response
+        .send_as
+        .unwrap_or_default()
+        .into_iter()
+        .map(SendAsInfo::from)
+        .collect())
+}
+
+/// Fetch a single send-as alias.
+pub async fn get_send_as(hub: &GmailHub, email: &str) -> Result<SendAsInfo, google_gmail1::Error> {
+    info!("Gmail API: Requesting send-as alias {}", email);
+    crate::quota::record("settings.sendAs.get", 1);
+    let (_, send_as) = hub
+        .users()
+        .settings_send_as_get("me", email)
+        .add_scope(SCOPE_SETTINGS_BASIC)
+        .doit()
+        .await
+        .map_err(|e| {
+            error!("Gmail API: Failed to fetch send-as alias {}: {}", email, e);
+            e
+        })?;
+    Ok(send_as.into())
+}
+
+/// Replace the signature of a send-as alias; an empty signature removes it.
+pub async fn set_signature(
+    hub: &GmailHub,
+    email: &str,
+    signature: &str,
+) -> Result<SendAsInfo, google_gmail1::Error> {
+    info!("Gmail API: Updating signature for {}", email);
+    let request = SendAs {
+        signature: Some(signature.to_string()),
+        ..Default::default()
+    };
+    crate::quota::record("settings.sendAs.patch", 1);
+    let (_, send_as) = hub
+        .users()
+        .settings_send_as_patch(request, "me", email)
+        .add_scope(SCOPE_SETTINGS_BASIC)
+        .doit()
+        .await
+        .map_err(|e| {
+            error!("Gmail API: Failed to update signature for {}: {}", email, e);
+            e
+        })?;
+    Ok(send_as.into())
+}
+
+/// Actions of the `gmail_signatures` tool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureAction {
+    List,
+    Get,
+    Set,
+}
+
+impl Action for SignatureAction {
+    const SUBJECT: &'static str = "signature";
+    const ALL: &'static [(Self, &'static str, &'static str)] = &[
+        (SignatureAction::List, "list", "no parameters"),
+        (SignatureAction::Get, "get", "requires email"),
+        (
+            SignatureAction::Set,
+            "set",
+            "requires signature, and either email or all_aliases",
+        ),
+    ];
+}
+
+impl FromStr for SignatureAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        actions::parse(s)
+    }
+}
+
+/// Dispatch a signature action, returning JSON.
+///
+/// `set` updates the alias named by `email`, or every alias when `all_aliases`
+/// is set, so one signature can be kept in sync across addresses.
+pub async fn manage_signatures(
+    action: SignatureAction,
+    email: Option<String>,
+    signature: Option<String>,
+    all_aliases: bool,
+) -> Result<String, GmailMcpError> {
+    capabilities::require(Capability::Settings)?;
+    let email = email
+        .map(|e| e.trim().to_string())
+        .filter(|e| !e.is_empty());
+
+    match action {
+        SignatureAction::List => {
+            let hub = crate::connect().await?;
+            let aliases = list_send_as(&hub).await?;
+            let response = SendAsResponse {
+                count: aliases.len(),
+                aliases,
+            };
+            Ok(serde_json::to_string_pretty(&response)?)
+        }
+        SignatureAction::Get => {
+            let email = email.ok_or_else(|| {
+                GmailMcpError::Invalid("Signature action 'get' requires 'email'".to_string())
+            })?;
+            let hub = crate::connect().await?;
+            Ok(serde_json::to_string_pretty(
+                &get_send_as(&hub, &email).await?,
+            )?)
+        }
+        SignatureAction::Set => {
+            let signature = signature.ok_or_else(|| {
+                GmailMcpError::Invalid("Signature action 'set' requires 'signature'".to_string())
+            })?;
+            if email.is_some() == all_aliases {
+                return Err(GmailMcpError::Invalid(
+                    "Signature action 'set' requires either 'email' or 'all_aliases', not both"
+                        .to_string(),
+                ));
+            }
+            let hub = crate::connect().await?;
+            let targets = match email {
+                Some(email) => vec![email],
+                None => list_send_as(&hub)
+                    .await?
+                    .into_iter()
+                    .map(|alias| alias.email)
+                    .collect(),
+            };
+            let mut aliases = Vec::new();
+            for target in targets {
+                aliases.push(set_signature(&hub, &target, &signature).await?);
+            }
+            let response = SendAsResponse {
+                count: aliases.len(),
+                aliases,
+            };
+            Ok(serde_json::to_string_pretty(&response)?)
+        }
+    }
+}
+
+/// A mailbox delegate as exposed to MCP clients.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct DelegateInfo {
+    /// The address that can read, send, and delete mail on the account's behalf.
+    pub email: String,
+    /// `accepted`, `pending`, `rejected`, or `expired`.
+    pub verification_status: Option<String>,
+}
+
+impl From<Delegate> for DelegateInfo {
+    fn from(delegate: Delegate) -> Self {
+        Self {
+            email: delegate.delegate_email.unwrap_or_default(),
+            verification_status: delegate.verification_status,
+        }
+    }
+}
+
+/// Response structure that wraps the delegate list
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DelegateResponse {
+    pub delegates: Vec<DelegateInfo>,
+    pub count: usize,
+}
+
+/// Fetch every delegate of the mailbox.
+///
+/// Delegation is only available to Google Workspace accounts whose
+/// administrator has enabled it.
+pub async fn list_delegates(hub: &GmailHub) -> Result<Vec<DelegateInfo>, google_gmail1::Error> {
+    info!("Gmail API: Requesting delegates");
+    crate::quota::record("settings.delegates.list", 1);
+    let (_, response) = hub
+        .users()
+        .settings_delegates_list("me")
+        .add_scope(SCOPE_SETTINGS_SHARING)
+        .doit()
+        .await
+        .map_err(|e| {
+            error!("Gmail API: Failed to list delegates: {}", e);
+            e
+        })?;
+    Ok(response
+        .delegates
+        .unwrap_or_default()
+        .into_iter()
+        .map(DelegateInfo::from)
+        .collect())
+}
+
+/// Grant `email` delegate access to the mailbox.
+pub async fn create_delegate(
+    hub: &GmailHub,
+    email: &str,
+) -> Result<DelegateInfo, google_gmail1::Error> {
+    info!("Gmail API: Adding delegate {}", email);
+    let delegate = Delegate {
+        delegate_email: Some(email.to_string()),
+        ..Default::default()
+    };
+    crate::quota::record("settings.delegates.create", 1);
+    let (_, created) = hub
+        .users()
+        .settings_delegates_create(delegate, "me")
+        .add_scope(SCOPE_SETTINGS_SHARING)
+        .doit()
+        .await
+        .map_err(|e| {
+            error!("Gmail API: Failed to add delegate {}: {}", email, e);
+            e
+        })?;
+    Ok(created.into())
+}
+
+/// Revoke the delegate access of `email`.
+pub async fn delete_delegate(hub: &GmailHub, email: &str) -> Result<(), google_gmail1::Error> {
+    info!("Gmail API: Removing delegate {}", email);
+    crate::quota::record("settings.delegates.delete", 1);
+    hub.users()
+        .settings_delegates_delete("me", email)
+        .add_scope(SCOPE_SETTINGS_SHARING)
+        .doit()
+        .await
+        .map_err(|e| {
+            error!("Gmail API: Failed to remove delegate {}: {}", email, e);
+            e
+        })?;
+    Ok(())
+}
+
+/// Actions of the `gmail_delegates` tool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DelegateAction {
+    List,
+    Create,
+    Delete,
+}
+
+impl Action for DelegateAction {
+    const SUBJECT: &'static str = "delegate";
+    const ALL: &'static [(Self, &'static str, &'static str)] = &[
+        (DelegateAction::List, "list", "no parameters"),
+        (DelegateAction::Create, "create", "requires email"),
+        (DelegateAction::Delete, "delete", "requires email"),
+    ];
+}
+
+impl FromStr for DelegateAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        actions::parse(s)
+    }
+}
+
+/// List, add, or remove mailbox delegates for the MCP tool.
+pub async fn manage_delegates(
+    action: DelegateAction,
+    email: Option<String>,
+) -> Result<String, GmailMcpError> {
+    capabilities::require(Capability::Delegation)?;
+    let email = email
+        .map(|e| e.trim().to_string())
+        .filter(|e| !e.is_empty());
+    let require = |email: Option<String>| {
+        email.ok_or_else(|| {
+            GmailMcpError::Invalid(format!(
+                "Delegate action '{}' requires 'email'",
+                action.name()
+            ))
+        })
+    };
+
+    match action {
+        DelegateAction::List => {
+            let hub = crate::connect().await?;
+            let delegates = list_delegates(&hub).await?;
+            let response = DelegateResponse {
+                count: delegates.len(),
+                delegates,
+            };
+            Ok(serde_json::to_string_pretty(&response)?)
+        }
+        DelegateAction::Create => {
+            let email = require(email)?;
+            let hub = crate::connect().await?;
+            Ok(serde_json::to_string_pretty(
+                &create_delegate(&hub, &email).await?,
+            )?)
+        }
+        DelegateAction::Delete => {
+            let email = require(email)?;
+            let hub = crate::connect().await?;
+            delete_delegate(&hub, &email).await?;
+            Ok(serde_json::to_string_pretty(&serde_json::json!({
+                "deleted": email
+            }))?)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_filter() {
+        let labels = vec![LabelInfo {
+            id: "Label_1".to_string(),
+            name: "Receipts".to_string(),
+            label_type: "user".to_string(),
+            message_list_visibility: None,
+            label_list_visibility: None,
+        }];
+        let filter = Filter {
+            id: Some("f1".to_string()),
+            criteria: Some(FilterCriteria {
+                from: Some("shop@example.com".to_string()),
+                ..Default::default()
+            }),
+            action: Some(api::FilterAction {
+                add_label_ids: Some(vec!["Label_1".to_string()]),
+                remove_label_ids: Some(vec!["INBOX".to_string()]),
+                forward: None,
+            }),
+        };
+
+        let info = describe_filter(filter, &labels);
+        assert_eq!(info.id, "f1");
+        assert_eq!(info.criteria.from.as_deref(), Some("shop@example.com"));
+        assert_eq!(info.action.add_labels, vec!["Receipts"]);
+        assert_eq!(info.action.remove_labels, vec!["INBOX"]);
+    }
+
+    #[test]
+    fn test_send_as_info_from() {
+        let info = SendAsInfo::from(SendAs {
+            send_as_email: Some("me@example.com".to_string()),
+            display_name: Some(String::new()),
+            signature: Some("<b>Me</b>".to_string()),
+            is_primary: Some(true),
+            ..Default::default()
+        });
+        assert_eq!(info.email, "me@example.com");
+        assert_eq!(info.display_name, None);
+        assert_eq!(info.signature.as_deref(), Some("<b>Me</b>"));
+        assert!(info.is_primary);
+        assert!(!info.is_default);
+    }
+
+    #[test]
+    fn test_filter_emptiness() {
+        assert!(FilterCriteriaInfo::default().is_empty());
+        assert!(!FilterCriteriaInfo {
+            has_attachment: Some(true),
+            ..Default::default()
+        }
+        .is_empty());
+        assert!(FilterActionInfo::default().is_empty());
+    }
+
+    #[test]
+    fn test_delegate_info_from() {
+        let info = DelegateInfo::from(Delegate {
+            delegate_email: Some("assistant@example.com".to_string()),
+            verification_status: Some("pending".to_string()),
+        });
+        assert_eq!(info.email, "assistant@example.com");
+        assert_eq!(info.verification_status.as_deref(), Some("pending"));
+    }
+}
@@ -0,0 +1,73 @@
+//! Graceful shutdown on SIGINT or SIGTERM.
+//!
+//! Once a signal arrives the server refuses new tool calls and sessions, waits
+//! up to [`GRACE_PERIOD`] for the tool calls in flight to finish, and saves the
+//! uncollected new-mail events before exiting.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// How long in-flight tool calls may take to finish after a signal.
+pub const GRACE_PERIOD: Duration = Duration::from_secs(30);
+
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether a shutdown has begun.
+pub fn is_shutting_down() -> bool {
+    SHUTTING_DOWN.load(Ordering::SeqCst)
+}
+
+/// Number of tool calls still running.
+pub fn in_flight() -> usize {
+    IN_FLIGHT.load(Ordering::SeqCst)
+}
+
+/// Marks a tool call as in flight until dropped.
+pub struct CallGuard(());
+
+impl Drop for CallGuard {
+    fn drop(&mut self) {
+        IN_FLIGHT.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Register a tool call, or `None` once shutting down.
+pub fn begin_call() -> Option<CallGuard> {
+    // Count first, so a shutdown starting now either sees this call or is seen by it
+    IN_FLIGHT.fetch_add(1, Ordering::SeqCst);
+    let guard = CallGuard(());
+    (!is_shutting_down()).then_some(guard)
+}
+
+/// Wait for SIGINT or SIGTERM, returning the signal's name.
+pub async fn signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => return "SIGINT",
+                _ = terminate.recv() => return "SIGTERM",
+            }
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+    "SIGINT"
+}
+
+/// Refuse new tool calls and wait up to `grace` for those in flight.
+///
+/// Returns whether every call finished in time.
+pub async fn drain(grace: Duration) -> bool {
+    SHUTTING_DOWN.store(true, Ordering::SeqCst);
+    let deadline = Instant::now() + grace;
+    while in_flight() > 0 {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    true
+}
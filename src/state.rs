@@ -0,0 +1,107 @@
+//! Small on-disk state shared between invocations.
+
+use crate::events::NewMailEvent;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+/// File that remembers when unread mail was last checked, the history ID the
+/// local cache was last synced to, and the new-mail events left uncollected at
+/// shutdown.
+pub const STATE_FILE: &str = "gmail_state.json";
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct State {
+    /// Unix timestamp (seconds) of the last successful unread check.
+    last_check: Option<i64>,
+    /// History ID the local cache and index were last synced to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sync_history_id: Option<u64>,
+    /// New-mail events saved at shutdown, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pending_events: Vec<NewMailEvent>,
+}
+
+fn load() -> State {
+    std::fs::read_to_string(STATE_FILE)
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+fn save(state: &State) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(state)
+        .map_err(|e| format!("Failed to serialize state: {}", e))?;
+    std::fs::write(STATE_FILE, json).map_err(|e| format!("Failed to write {}: {}", STATE_FILE, e))
+}
+
+/// Timestamp of the previous unread check, if one was recorded.
+pub fn last_check() -> Option<i64> {
+    load().last_check
+}
+
+/// Record `timestamp` as the time of the latest unread check.
+///
+/// Failures are logged rather than returned: losing the marker only means
+/// the next check returns more mail than strictly necessary.
+pub fn record_check(timestamp: i64) {
+    let state = State {
+        last_check: Some(timestamp),
+        ..load()
+    };
+    match save(&state) {
+        Ok(()) => info!("Recorded unread check at {}", timestamp),
+        Err(e) => warn!("{}", e),
+    }
+}
+
+/// History ID the local cache was last synced to, if a sync completed.
+pub fn sync_history_id() -> Option<u64> {
+    load().sync_history_id
+}
+
+/// Record `history_id` as the point the local cache is synced to, or forget
+/// it with `None` so the next sync starts over.
+pub fn record_sync(history_id: Option<u64>) {
+    let state = State {
+        sync_history_id: history_id,
+        ..load()
+    };
+    if let Err(e) = save(&state) {
+        warn!("{}", e);
+    }
+}
+
+/// Save uncollected new-mail events so the next run can still deliver them.
+pub fn save_pending_events(events: Vec<NewMailEvent>) {
+    let count = events.len();
+    let state = State {
+        pending_events: events,
+        ..load()
+    };
+    match save(&state) {
+        Ok(()) => info!("Saved {} uncollected new-mail event(s)", count),
+        Err(e) => warn!("{}", e),
+    }
+}
+
+/// Remove and return the new-mail events saved by the previous run.
+pub fn take_saved_events() -> Vec<NewMailEvent> {
+    let mut state = load();
+    if state.pending_events.is_empty() {
+        return Vec::new();
+    }
+    let events = std::mem::take(&mut state.pending_events);
+    if let Err(e) = save(&state) {
+        warn!("{}", e);
+    }
+    events
+}
+
+/// Current time as a Unix timestamp in seconds.
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,144 @@
+//! Emails as a stream, yielded as each batch arrives.
+//!
+//! [`fetch_stream`] lists message IDs a batch at a time and fetches each batch
+//! before listing the next, so the first emails can be shown or summarized
+//! while the rest are still being fetched.
+
+use crate::batch::MAX_BATCH_SIZE;
+use crate::reademail::process_email;
+use crate::{cache, client, labels, state};
+use crate::{EmailSummary, FetchOptions, GmailHub, GmailMcpError};
+use futures::stream::{self, Stream, TryStreamExt};
+use tracing::info;
+
+/// Stream the emails selected by `options`, processed like
+/// [`crate::reademail::read_emails`] does.
+///
+/// `group_by_thread` does not apply; emails arrive newest first. The stream ends
+/// after the first error. With `since_last_check`, the check is recorded once
+/// the stream has been read to the end.
+pub fn fetch_stream(
+    options: FetchOptions,
+) -> impl Stream<Item = Result<EmailSummary, GmailMcpError>> {
+    stream::try_unfold(Cursor::Start(options), |cursor| async move {
+        let mut pager = match cursor {
+            Cursor::Start(options) => Pager::open(options).await?,
+            Cursor::Page(pager) => *pager,
+            Cursor::Done => return Ok(None),
+        };
+        let emails = pager.next_page().await?;
+        let next = if pager.is_exhausted() {
+            pager.finish();
+            Cursor::Done
+        } else {
+            Cursor::Page(Box::new(pager))
+        };
+        Ok::<_, GmailMcpError>(Some((emails, next)))
+    })
+    .map_ok(|emails| stream::iter(emails.into_iter().map(Ok::<_, GmailMcpError>)))
+    .try_flatten()
+}
+
+/// How far a stream has got.
+enum Cursor {
+    Start(FetchOptions),
+    Page(Box<Pager>),
+    Done,
+}
+
+/// Lists and fetches one batch of messages at a time.
+struct Pager {
+    hub: GmailHub,
+    query: String,
+    label_id: Option<String>,
+    page_token: Option<String>,
+    remaining: u32,
+    listed_all: bool,
+    check_started: i64,
+    options: FetchOptions,
+}
+
+impl Pager {
+    async fn open(options: FetchOptions) -> Result<Self, GmailMcpError> {
+        let check_started = state::now();
+        let query = crate::build_query(&options)?;
+        let hub = client::shared().await?.hub().clone();
+        let label_id = match &options.label {
+            Some(label) => Some(labels::resolve_label_id(&hub, label).await?),
+            None => None,
+        };
+        info!("Gmail API: Streaming emails matching '{}'", query);
+        Ok(Self {
+            hub,
+            query,
+            label_id,
+            page_token: options.page_token.clone(),
+            remaining: options.max_results.clamp(1, 500),
+            listed_all: false,
+            check_started,
+            options,
+        })
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.remaining == 0 || self.listed_all
+    }
+
+    /// List the next batch of IDs and fetch their messages.
+    async fn next_page(&mut self) -> Result<Vec<EmailSummary>, GmailMcpError> {
+        let (messages, page_token) = crate::list_messages(
+            &self.hub,
+            &self.query,
+            self.label_id.as_deref(),
+            self.remaining.min(MAX_BATCH_SIZE as u32),
+            self.page_token.take(),
+            self.options.include_spam_trash,
+        )
+        .await?;
+        self.listed_all = page_token.is_none();
+        self.page_token = page_token;
+        self.remaining = self.remaining.saturating_sub(messages.len() as u32);
+        if messages.is_empty() {
+            self.listed_all = true;
+            return Ok(Vec::new());
+        }
+
+        let ids: Vec<String> = messages.into_iter().filter_map(|m| m.id).collect();
+        let format = self.options.format;
+        // Failures are logged by `fetch_messages`; missing messages are skipped
+        let mut summaries = cache::fetch_summaries(&self.hub, &ids, format).await?;
+        if self.options.include_headers {
+            crate::attach_headers(&self.hub, &mut summaries).await?;
+        }
+        let mut emails = Vec::with_capacity(summaries.len());
+        for (_, mut email) in summaries {
+            process_email(&mut email, &self.options).await;
+            emails.push(email);
+        }
+        Ok(emails)
+    }
+
+    /// Record the unread check, when the stream was asked to.
+    fn finish(&self) {
+        if self.options.unread_only && self.options.since_last_check {
+            state::record_check(self.check_started);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn test_stream_ends_after_error() {
+        let options = FetchOptions::new().after("last tuesday".to_string());
+        let mut emails = Box::pin(fetch_stream(options));
+        assert!(matches!(
+            emails.next().await,
+            Some(Err(GmailMcpError::Invalid(_)))
+        ));
+        assert!(emails.next().await.is_none());
+    }
+}
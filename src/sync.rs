@@ -0,0 +1,193 @@
+//! Incremental sync of the local cache and index with the mailbox.
+//!
+//! A background task remembers the history ID it last synced to (see
+//! [`crate::state`]) and on each round reads the changes since then with
+//! `history.list`: added messages are fetched into the cache and index,
+//! deleted ones are dropped, and relabeled ones get their current labels.
+//! When there is no start point, the cache is empty (as after a restart
+//! without a database), or Gmail no longer keeps history that far back, it
+//! resyncs in full: the cache and index are cleared and refilled with the
+//! newest messages matching the sync query. Each round connects anew, so a
+//! logout and sign-in to another account is picked up by the next round.
+
+use crate::history::{current_history_id, fetch_changes, MailboxChanges};
+use crate::{cache, state, FetchFormat, GmailHub, GmailMcpError};
+use serde::Serialize;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// Environment variable with the sync interval in seconds; setting it enables syncing.
+pub const INTERVAL_ENV: &str = "GMAIL_SYNC_INTERVAL";
+/// Environment variable with the Gmail query selecting the mail of a full resync.
+pub const QUERY_ENV: &str = "GMAIL_SYNC_QUERY";
+
+/// Shortest allowed interval, to stay well within Gmail's quota.
+const MIN_INTERVAL: Duration = Duration::from_secs(30);
+/// How many of the newest messages a full resync fetches.
+const RESYNC_WINDOW: u32 = 500;
+
+/// Sync settings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncConfig {
+    /// Time between sync rounds.
+    pub interval: Duration,
+    /// Gmail search query selecting the messages fetched by a full resync.
+    pub query: String,
+}
+
+impl SyncConfig {
+    /// Read the configuration from the environment; `None` when syncing is not enabled.
+    pub fn from_env() -> Option<Self> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+        let seconds = var(INTERVAL_ENV)?;
+        let seconds = match seconds.trim().parse::<u64>() {
+            Ok(seconds) => seconds,
+            Err(_) => {
+                warn!("Ignoring invalid {}: {}", INTERVAL_ENV, seconds);
+                return None;
+            }
+        };
+        Some(Self {
+            interval: Duration::from_secs(seconds).max(MIN_INTERVAL),
+            query: crate::resolve_query(var(QUERY_ENV)),
+        })
+    }
+}
+
+/// What one sync round did.
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct SyncReport {
+    /// The history ID the cache is now synced to.
+    pub history_id: u64,
+    /// Whether the cache was rebuilt instead of updated.
+    pub full_resync: bool,
+    /// Messages fetched into the cache.
+    pub fetched: usize,
+    /// Messages dropped from the cache.
+    pub removed: usize,
+    /// Messages whose labels were refreshed.
+    pub relabeled: usize,
+}
+
+/// IDs of the messages whose labels changed, once each, leaving out added
+/// messages, which are fetched with their current labels anyway.
+fn relabeled_ids(changes: &MailboxChanges) -> Vec<String> {
+    let mut ids: Vec<String> = changes
+        .labels_added
+        .iter()
+        .chain(&changes.labels_removed)
+        .map(|change| change.id.clone())
+        .filter(|id| !changes.added.contains(id))
+        .collect();
+    ids.sort();
+    ids.dedup();
+    ids
+}
+
+/// Bring the cache and index up to date, incrementally when possible.
+pub async fn sync_once(hub: &GmailHub, query: &str) -> Result<SyncReport, GmailMcpError> {
+    let Some(since) = state::sync_history_id() else {
+        return full_resync(hub, query).await;
+    };
+    if cache::is_empty().await {
+        info!("Sync: The cache is empty, resyncing in full");
+        return full_resync(hub, query).await;
+    }
+    let changes = match fetch_changes(hub, since).await {
+        Ok(changes) => changes,
+        // Gmail answers 404 once the start point has left its history window
+        Err(e) if e.status() == Some(404) => {
+            warn!("Sync: History {} has expired, resyncing in full", since);
+            return full_resync(hub, query).await;
+        }
+        Err(e) => return Err(e),
+    };
+
+    let fetched = cache::fetch_summaries(hub, &changes.added, FetchFormat::Full)
+        .await?
+        .len();
+    cache::remove(&changes.deleted).await;
+    #[cfg(feature = "search")]
+    crate::search_index::remove(changes.deleted.clone(), false).await;
+
+    let relabeled = cache::refresh_labels(hub, &relabeled_ids(&changes)).await?;
+
+    let history_id = changes.history_id.parse().unwrap_or(since);
+    state::record_sync(Some(history_id));
+    Ok(SyncReport {
+        history_id,
+        full_resync: false,
+        fetched,
+        removed: changes.deleted.len(),
+        relabeled,
+    })
+}
+
+/// Clear the cache and index and refill them with the newest messages matching `query`.
+async fn full_resync(hub: &GmailHub, query: &str) -> Result<SyncReport, GmailMcpError> {
+    info!(
+        "Sync: Resyncing the newest {} messages of '{}'",
+        RESYNC_WINDOW, query
+    );
+    // Read the start point first, so changes made during the resync are synced next round
+    let history_id = current_history_id(hub).await?;
+    state::record_sync(None);
+    cache::clear().await;
+    #[cfg(feature = "search")]
+    crate::search_index::remove(Vec::new(), true).await;
+
+    let (messages, _) = crate::list_messages(hub, query, None, RESYNC_WINDOW, None, false).await?;
+    let ids: Vec<String> = messages.into_iter().filter_map(|m| m.id).collect();
+    let fetched = cache::fetch_summaries(hub, &ids, FetchFormat::Full)
+        .await?
+        .len();
+
+    state::record_sync(Some(history_id));
+    Ok(SyncReport {
+        history_id,
+        full_resync: true,
+        fetched,
+        ..Default::default()
+    })
+}
+
+/// Sync forever on the configured interval, starting right away.
+pub async fn run(config: SyncConfig) -> Result<(), GmailMcpError> {
+    info!(
+        "Syncing the local cache every {}s",
+        config.interval.as_secs()
+    );
+    loop {
+        // The shared client is replaced after a logout, maybe by another account's
+        let synced = match crate::connect().await {
+            Ok(hub) => sync_once(&hub, &config.query).await,
+            Err(e) => Err(e),
+        };
+        match synced {
+            Ok(report) => info!("Sync: {:?}", report),
+            Err(e) => error!("Failed to sync the local cache: {}", e),
+        }
+        tokio::time::sleep(config.interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::LabelChange;
+
+    #[test]
+    fn test_relabeled_ids() {
+        let change = |id: &str| LabelChange {
+            id: id.to_string(),
+            label_ids: vec!["STARRED".to_string()],
+        };
+        let changes = MailboxChanges {
+            added: vec!["new".to_string()],
+            labels_added: vec![change("b"), change("new"), change("a")],
+            labels_removed: vec![change("b")],
+            ..Default::default()
+        };
+        assert_eq!(relabeled_ids(&changes), vec!["a", "b"]);
+    }
+}
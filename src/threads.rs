@@ -0,0 +1,193 @@
+//! Thread-level fetching so consumers see whole conversations.
+
+use crate::reademail::convert_html_to_text;
+use crate::{
+    limits, resolve_query, summarize_message, EmailSummary, GmailHub, GmailMcpError, SCOPE_READONLY,
+};
+use futures::stream::{self, StreamExt};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::{error, info, warn};
+
+/// Messages from a single listing that share a Gmail thread.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct EmailThread {
+    /// The Gmail thread ID shared by these messages.
+    pub thread_id: String,
+    /// The thread's messages, oldest first.
+    pub emails: Vec<EmailSummary>,
+}
+
+/// Group `(thread_id, internal_date, summary)` entries by thread.
+///
+/// Messages within a thread are ordered oldest first; threads are ordered by
+/// their most recent message, newest first, mirroring the Gmail inbox.
+pub fn group_by_thread(entries: Vec<(String, i64, EmailSummary)>) -> Vec<EmailThread> {
+    let mut grouped: HashMap<String, Vec<(i64, EmailSummary)>> = HashMap::new();
+    for (thread_id, date, summary) in entries {
+        grouped.entry(thread_id).or_default().push((date, summary));
+    }
+
+    let mut threads: Vec<(i64, EmailThread)> = grouped
+        .into_iter()
+        .map(|(thread_id, mut emails)| {
+            emails.sort_by_key(|(date, _)| *date);
+            let latest = emails.last().map(|(date, _)| *date).unwrap_or_default();
+            let emails = emails.into_iter().map(|(_, summary)| summary).collect();
+            (latest, EmailThread { thread_id, emails })
+        })
+        .collect();
+    threads.sort_by_key(|(latest, _)| std::cmp::Reverse(*latest));
+    threads.into_iter().map(|(_, thread)| thread).collect()
+}
+
+/// A Gmail conversation with all of its messages.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ThreadSummary {
+    /// The unique Gmail thread ID.
+    pub id: String,
+    /// Subject of the first message in the thread.
+    pub subject: String,
+    /// Messages in the order Gmail returns them (oldest first).
+    pub messages: Vec<EmailSummary>,
+}
+
+/// Response structure that wraps the thread summaries
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ThreadResponse {
+    /// The list of thread summaries
+    pub threads: Vec<ThreadSummary>,
+    /// The total number of threads fetched
+    pub count: usize,
+}
+
+/// Fetch a single thread with full message bodies.
+async fn fetch_thread(hub: &GmailHub, id: &str) -> Result<ThreadSummary, GmailMcpError> {
+    crate::quota::record("threads.get", 1);
+    let (_, thread) = hub
+        .users()
+        .threads_get("me", id)
+        .format("full")
+        .add_scope(SCOPE_READONLY)
+        .doit()
+        .await
+        .map_err(|e| {
+            error!("Gmail API: Failed to fetch thread {}: {}", id, e);
+            e
+        })?;
+
+    let messages: Vec<EmailSummary> = thread
+        .messages
+        .unwrap_or_default()
+        .iter()
+        .filter_map(summarize_message)
+        .collect();
+    let subject = messages
+        .first()
+        .map(|m| m.subject.clone())
+        .unwrap_or_else(|| "No Subject".to_string());
+
+    Ok(ThreadSummary {
+        id: id.to_string(),
+        subject,
+        messages,
+    })
+}
+
+/// Fetch up to `max_results` threads matching `query` (default `in:inbox`),
+/// returning pretty-printed JSON with bodies converted to plain text.
+pub async fn fetch_threads(
+    max_results: u32,
+    query: Option<String>,
+) -> Result<String, GmailMcpError> {
+    let max_results = max_results.clamp(1, 500);
+    let query = resolve_query(query);
+    info!(
+        "Gmail API: Starting to fetch {} threads matching '{}'",
+        max_results, query
+    );
+
+    let hub = crate::connect().await?;
+    crate::quota::record("threads.list", 1);
+    let (_, list) = hub
+        .users()
+        .threads_list("me")
+        .q(&query)
+        .max_results(max_results)
+        .add_scope(SCOPE_READONLY)
+        .doit()
+        .await
+        .map_err(|e| {
+            error!("Gmail API: Failed to list threads: {}", e);
+            e
+        })?;
+
+    let ids = list
+        .threads
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|thread| {
+            if thread.id.is_none() {
+                warn!("Gmail API: Thread has no ID");
+            }
+            thread.id
+        });
+    let hub = &hub;
+    let fetched: Vec<_> = stream::iter(ids)
+        .map(|id| async move { (fetch_thread(hub, &id).await, id) })
+        .buffered(limits::fetch_concurrency())
+        .collect()
+        .await;
+
+    let mut threads = Vec::new();
+    for (fetched, id) in fetched {
+        let mut summary = match fetched {
+            Ok(summary) => summary,
+            Err(e) => {
+                warn!("Gmail API: Skipping thread {}: {}", id, e);
+                continue;
+            }
+        };
+        for email in summary.messages.iter_mut() {
+            convert_html_to_text(email).await;
+        }
+        threads.push(summary);
+    }
+
+    info!("Gmail API: Returning {} threads", threads.len());
+    let response = ThreadResponse {
+        count: threads.len(),
+        threads,
+    };
+    Ok(serde_json::to_string_pretty(&response)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email(id: &str) -> EmailSummary {
+        EmailSummary {
+            id: id.to_string(),
+            from: "a@example.com".to_string(),
+            subject: "Subject".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_group_by_thread_orders_messages_and_threads() {
+        let grouped = group_by_thread(vec![
+            ("t1".to_string(), 300, email("m3")),
+            ("t2".to_string(), 200, email("m2")),
+            ("t1".to_string(), 100, email("m1")),
+        ]);
+
+        assert_eq!(grouped.len(), 2);
+        assert_eq!(grouped[0].thread_id, "t1");
+        let ids: Vec<&str> = grouped[0].emails.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, vec!["m1", "m3"]);
+        assert_eq!(grouped[1].thread_id, "t2");
+    }
+}
@@ -0,0 +1,138 @@
+//! Timeouts for Gmail API connections, reads, and whole fetches.
+//!
+//! A stalled connection would otherwise hang the tool call waiting on it
+//! forever. The HTTPS client gives up on connecting after
+//! `GMAIL_CONNECT_TIMEOUT` seconds and on a response that sends nothing for
+//! `GMAIL_READ_TIMEOUT` seconds, failing that request with a network error
+//! (which is not retried). `GMAIL_FETCH_TIMEOUT` bounds a whole listing fetch,
+//! with all its pages, batches, and retries. Setting any of them to `0` turns
+//! that timeout off.
+
+use crate::GmailMcpError;
+use std::future::Future;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tracing::info;
+
+/// Environment variable with the seconds allowed to open a connection.
+pub const CONNECT_TIMEOUT_ENV: &str = "GMAIL_CONNECT_TIMEOUT";
+/// Environment variable with the seconds a response may go without sending data.
+pub const READ_TIMEOUT_ENV: &str = "GMAIL_READ_TIMEOUT";
+/// Environment variable with the seconds allowed for a whole fetch.
+pub const FETCH_TIMEOUT_ENV: &str = "GMAIL_FETCH_TIMEOUT";
+
+/// Connect timeout when [`CONNECT_TIMEOUT_ENV`] is not set.
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Read timeout when [`READ_TIMEOUT_ENV`] is not set.
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(30);
+/// Fetch timeout when [`FETCH_TIMEOUT_ENV`] is not set.
+pub const DEFAULT_FETCH_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Configured timeouts; `None` means no timeout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeoutConfig {
+    pub connect: Option<Duration>,
+    pub read: Option<Duration>,
+    pub fetch: Option<Duration>,
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        Self {
+            connect: Some(DEFAULT_CONNECT_TIMEOUT),
+            read: Some(DEFAULT_READ_TIMEOUT),
+            fetch: Some(DEFAULT_FETCH_TIMEOUT),
+        }
+    }
+}
+
+impl TimeoutConfig {
+    /// Read the timeouts from the environment.
+    pub fn from_env() -> Result<Self, String> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// Read the timeouts through `var`, failing on values that are not whole
+    /// numbers of seconds.
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let seconds = |name: &str, default: Duration| -> Result<Option<Duration>, String> {
+            let Some(value) = var(name).filter(|v| !v.trim().is_empty()) else {
+                return Ok(Some(default));
+            };
+            match value.trim().parse::<u64>() {
+                Ok(0) => Ok(None),
+                Ok(seconds) => Ok(Some(Duration::from_secs(seconds))),
+                Err(_) => Err(format!(
+                    "Invalid {} '{}'; expected a number of seconds",
+                    name, value
+                )),
+            }
+        };
+        Ok(Self {
+            connect: seconds(CONNECT_TIMEOUT_ENV, DEFAULT_CONNECT_TIMEOUT)?,
+            read: seconds(READ_TIMEOUT_ENV, DEFAULT_READ_TIMEOUT)?,
+            fetch: seconds(FETCH_TIMEOUT_ENV, DEFAULT_FETCH_TIMEOUT)?,
+        })
+    }
+}
+
+static CONFIG: OnceLock<TimeoutConfig> = OnceLock::new();
+
+/// Apply `config` to this process; later calls are ignored.
+///
+/// Install before the first Gmail call, since the client reads the connect
+/// and read timeouts when it is built.
+pub fn install(config: TimeoutConfig) {
+    info!("Gmail API timeouts: {:?}", config);
+    let _ = CONFIG.set(config);
+}
+
+/// The installed timeouts, or the defaults.
+pub fn current() -> TimeoutConfig {
+    CONFIG.get().copied().unwrap_or_default()
+}
+
+/// Run `fetch`, failing with a network error if it outlasts the fetch timeout.
+pub(crate) async fn fetch<T>(
+    fetch: impl Future<Output = Result<T, GmailMcpError>>,
+) -> Result<T, GmailMcpError> {
+    let Some(limit) = current().fetch else {
+        return fetch.await;
+    };
+    tokio::time::timeout(limit, fetch).await.map_err(|_| {
+        GmailMcpError::Network(format!(
+            "The fetch did not finish within {}s; try fewer results or raise {}",
+            limit.as_secs(),
+            FETCH_TIMEOUT_ENV
+        ))
+    })?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeouts_from_vars() {
+        assert_eq!(
+            TimeoutConfig::from_vars(|_| None).unwrap(),
+            TimeoutConfig::default()
+        );
+        let config = TimeoutConfig::from_vars(|name| match name {
+            CONNECT_TIMEOUT_ENV => Some("5".to_string()),
+            FETCH_TIMEOUT_ENV => Some("0".to_string()),
+            _ => None,
+        })
+        .unwrap();
+        assert_eq!(config.connect, Some(Duration::from_secs(5)));
+        assert_eq!(config.read, Some(DEFAULT_READ_TIMEOUT));
+        assert_eq!(config.fetch, None);
+        assert!(TimeoutConfig::from_vars(|_| Some("soon".to_string())).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_within_timeout() {
+        let value = fetch(async { Ok::<_, GmailMcpError>(7) }).await.unwrap();
+        assert_eq!(value, 7);
+    }
+}
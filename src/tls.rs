@@ -0,0 +1,142 @@
+//! Native TLS for the HTTP transports.
+//!
+//! With `GMAIL_TLS_CERT` and `GMAIL_TLS_KEY` naming PEM files, the server takes
+//! HTTPS connections itself instead of needing a reverse proxy. TLS is
+//! terminated with rustls on the public port, and each decrypted connection is
+//! relayed to the transport listening on a loopback port.
+
+use crate::GmailMcpError;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::ServerConfig;
+use std::fs::File;
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::TlsAcceptor;
+use tracing::{debug, info, warn};
+
+/// Environment variable with the path of the PEM certificate chain.
+pub const CERT_ENV: &str = "GMAIL_TLS_CERT";
+/// Environment variable with the path of the PEM private key.
+pub const KEY_ENV: &str = "GMAIL_TLS_KEY";
+
+/// Paths of the certificate chain and private key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TlsConfig {
+    pub cert_path: String,
+    pub key_path: String,
+}
+
+impl TlsConfig {
+    /// Read the configuration from the environment; `None` when TLS is off.
+    pub fn from_env() -> Result<Option<Self>, String> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// Read the configuration through `var`, failing when only one path is set.
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Option<Self>, String> {
+        let var = |name: &str| var(name).filter(|v| !v.trim().is_empty());
+        match (var(CERT_ENV), var(KEY_ENV)) {
+            (Some(cert_path), Some(key_path)) => Ok(Some(Self {
+                cert_path,
+                key_path,
+            })),
+            (None, None) => Ok(None),
+            _ => Err(format!(
+                "TLS needs both {} and {}, or neither",
+                CERT_ENV, KEY_ENV
+            )),
+        }
+    }
+
+    /// Load the certificate chain and key into an acceptor.
+    pub fn acceptor(&self) -> Result<TlsAcceptor, GmailMcpError> {
+        let open = |path: &str| {
+            File::open(path)
+                .map(BufReader::new)
+                .map_err(|e| GmailMcpError::Invalid(format!("Cannot open {}: {}", path, e)))
+        };
+        let certs = rustls_pemfile::certs(&mut open(&self.cert_path)?)
+            .collect::<Result<Vec<CertificateDer>, _>>()
+            .map_err(|e| {
+                GmailMcpError::Invalid(format!("Invalid certificate in {}: {}", self.cert_path, e))
+            })?;
+        if certs.is_empty() {
+            return Err(GmailMcpError::Invalid(format!(
+                "No certificate found in {}",
+                self.cert_path
+            )));
+        }
+        let key: PrivateKeyDer = rustls_pemfile::private_key(&mut open(&self.key_path)?)
+            .map_err(|e| {
+                GmailMcpError::Invalid(format!("Invalid private key in {}: {}", self.key_path, e))
+            })?
+            .ok_or_else(|| {
+                GmailMcpError::Invalid(format!("No private key found in {}", self.key_path))
+            })?;
+
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, key))
+            .map_err(|e| GmailMcpError::Invalid(format!("Invalid TLS configuration: {}", e)))?;
+        // The transports behind the relay speak HTTP/1.1
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+}
+
+/// Accept TLS connections on `addr` and relay them to `backend` until the server stops.
+pub async fn serve(
+    acceptor: TlsAcceptor,
+    addr: (String, u16),
+    backend: SocketAddr,
+) -> Result<(), GmailMcpError> {
+    let listener = TcpListener::bind(&addr).await?;
+    info!("TLS listening on {}:{}", addr.0, addr.1);
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("TLS accept failed: {}", e);
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        tokio::spawn(async move {
+            if let Err(e) = relay(acceptor, stream, backend).await {
+                debug!("TLS connection from {} ended: {}", peer, e);
+            }
+        });
+    }
+}
+
+/// Complete the handshake on `stream` and copy bytes both ways until either side closes.
+async fn relay(
+    acceptor: TlsAcceptor,
+    stream: TcpStream,
+    backend: SocketAddr,
+) -> std::io::Result<()> {
+    let mut tls = acceptor.accept(stream).await?;
+    let mut plain = TcpStream::connect(backend).await?;
+    tokio::io::copy_bidirectional(&mut tls, &mut plain).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tls_config_from_vars() {
+        assert_eq!(TlsConfig::from_vars(|_| None), Ok(None));
+        let both = TlsConfig::from_vars(|name| Some(format!("/etc/tls/{}.pem", name))).unwrap();
+        assert_eq!(
+            both.map(|c| c.key_path),
+            Some(format!("/etc/tls/{}.pem", KEY_ENV))
+        );
+        let cert_only = TlsConfig::from_vars(|name| (name == CERT_ENV).then(|| "c.pem".into()));
+        assert!(cert_only.is_err());
+    }
+}
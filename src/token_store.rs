@@ -0,0 +1,530 @@
+//! Where OAuth tokens are kept between runs.
+//!
+//! Tokens go to `token_cache.json` by default. With `GMAIL_TOKEN_STORE=keyring`
+//! they are kept in the operating system's credential store instead, through
+//! the `keyring` crate: the macOS Keychain, the Secret Service (GNOME Keyring,
+//! KWallet) on Linux, or the Windows Credential Manager.
+//! With `GMAIL_TOKEN_STORE=encrypted` they are written to `token_cache.enc`,
+//! encrypted with AES-256-GCM under a key derived from `GMAIL_TOKEN_KEY` or,
+//! when that is unset, from a random key kept in the keyring.
+
+use crate::GmailMcpError;
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::num::NonZeroU32;
+use std::str::FromStr;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tracing::{error, info};
+use yup_oauth2::storage::{TokenInfo, TokenStorage};
+
+/// Environment variable selecting the token store (`file`, `keyring`, or `encrypted`).
+pub const STORE_ENV: &str = "GMAIL_TOKEN_STORE";
+/// Environment variable with the passphrase for the encrypted store.
+pub const KEY_ENV: &str = "GMAIL_TOKEN_KEY";
+/// Where the encrypted store keeps its tokens.
+pub const ENCRYPTED_CACHE_PATH: &str = "token_cache.enc";
+/// Service name of the keyring entries.
+pub const KEYRING_SERVICE: &str = "gmail-mcp-server";
+/// Keyring account holding the tokens.
+const TOKENS_ACCOUNT: &str = "oauth-tokens";
+/// Keyring account holding the generated encryption key.
+const KEY_ACCOUNT: &str = "token-key";
+
+/// PBKDF2 rounds when deriving the encryption key from a passphrase.
+const PBKDF2_ITERATIONS: NonZeroU32 = match NonZeroU32::new(100_000) {
+    Some(n) => n,
+    None => unreachable!(),
+};
+
+/// Backend used to persist OAuth tokens.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TokenStore {
+    /// Plaintext JSON in [`crate::auth::TOKEN_CACHE_PATH`].
+    #[default]
+    File,
+    /// The operating system's credential store.
+    Keyring,
+    /// AES-256-GCM encrypted JSON in [`ENCRYPTED_CACHE_PATH`].
+    Encrypted,
+}
+
+impl FromStr for TokenStore {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "" | "file" => Ok(Self::File),
+            "keyring" => Ok(Self::Keyring),
+            "encrypted" => Ok(Self::Encrypted),
+            other => Err(format!(
+                "Unknown {} '{}'; expected file, keyring, or encrypted",
+                STORE_ENV, other
+            )),
+        }
+    }
+}
+
+impl TokenStore {
+    /// Read the store from [`STORE_ENV`], defaulting to [`TokenStore::File`].
+    pub fn from_env() -> Result<Self, String> {
+        std::env::var(STORE_ENV).unwrap_or_default().parse()
+    }
+}
+
+/// A token together with the scopes it was granted for.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct ScopedToken {
+    scopes: Vec<String>,
+    token: TokenInfo,
+}
+
+/// Every cached token, serialized as a single keyring secret.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+struct ScopedTokens {
+    tokens: Vec<ScopedToken>,
+}
+
+impl ScopedTokens {
+    /// Find a token covering all of `scopes`.
+    fn get(&self, scopes: &[&str]) -> Option<TokenInfo> {
+        self.tokens
+            .iter()
+            .find(|t| scopes.iter().all(|s| t.scopes.iter().any(|held| held == s)))
+            .map(|t| t.token.clone())
+    }
+
+    /// Store a token for exactly `scopes`, replacing any previous one.
+    fn set(&mut self, scopes: &[&str], token: TokenInfo) {
+        let mut scopes: Vec<String> = scopes.iter().map(|s| s.to_string()).collect();
+        scopes.sort();
+        scopes.dedup();
+        match self.tokens.iter_mut().find(|t| t.scopes == scopes) {
+            Some(existing) => existing.token = token,
+            None => self.tokens.push(ScopedToken { scopes, token }),
+        }
+    }
+}
+
+/// A cached token as reported by the auth status tool, without its secrets.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CachedToken {
+    /// The scopes the token was granted for.
+    pub scopes: Vec<String>,
+    /// When the access token expires, in epoch seconds.
+    pub expires_at: Option<i64>,
+    /// Whether the token can be refreshed without a new consent.
+    pub has_refresh_token: bool,
+}
+
+impl From<&ScopedToken> for CachedToken {
+    fn from(scoped: &ScopedToken) -> Self {
+        Self {
+            scopes: scoped.scopes.clone(),
+            expires_at: scoped.token.expires_at.map(|t| t.unix_timestamp()),
+            has_refresh_token: scoped.token.refresh_token.is_some(),
+        }
+    }
+}
+
+/// List the tokens cached in `store`; empty when nothing has been cached yet.
+pub async fn cached_tokens(store: TokenStore) -> Result<Vec<CachedToken>, GmailMcpError> {
+    let tokens = match Backend::for_store(store) {
+        None => match tokio::fs::read_to_string(crate::auth::TOKEN_CACHE_PATH).await {
+            Ok(json) => serde_json::from_str::<Vec<ScopedToken>>(&json)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        },
+        Some(backend) => match backend.read().await.map_err(store_error)? {
+            Some(json) => serde_json::from_str::<ScopedTokens>(&json)?.tokens,
+            None => Vec::new(),
+        },
+    };
+    Ok(tokens.iter().map(CachedToken::from).collect())
+}
+
+/// Remove every cached token from `store`.
+///
+/// Returns the refresh token (or, lacking one, the access token) of each
+/// removed entry, so the caller can revoke them with Google.
+pub async fn clear(store: TokenStore) -> Result<Vec<String>, GmailMcpError> {
+    let tokens = match Backend::for_store(store) {
+        None => {
+            let path = crate::auth::TOKEN_CACHE_PATH;
+            let tokens = match tokio::fs::read_to_string(path).await {
+                // An unreadable cache is still removed; there is just nothing to revoke
+                Ok(json) => serde_json::from_str::<Vec<ScopedToken>>(&json).unwrap_or_default(),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+                Err(e) => return Err(e.into()),
+            };
+            info!("Gmail auth: Deleting {}", path);
+            tokio::fs::remove_file(path).await?;
+            tokens
+        }
+        Some(backend) => {
+            let tokens = load_tokens(backend).await.tokens;
+            backend.delete().await.map_err(store_error)?;
+            tokens
+        }
+    };
+
+    let mut secrets: Vec<String> = tokens
+        .into_iter()
+        .filter_map(|t| t.token.refresh_token.or(t.token.access_token))
+        .collect();
+    secrets.sort();
+    secrets.dedup();
+    Ok(secrets)
+}
+
+/// Where a [`SecretStorage`] keeps its serialized tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    Keyring,
+    EncryptedFile,
+}
+
+impl Backend {
+    /// The backend for `store`; `None` for the plain file, which yup-oauth2 handles itself.
+    fn for_store(store: TokenStore) -> Option<Self> {
+        match store {
+            TokenStore::File => None,
+            TokenStore::Keyring => Some(Self::Keyring),
+            TokenStore::Encrypted => Some(Self::EncryptedFile),
+        }
+    }
+
+    /// Read the serialized tokens; `None` when nothing has been stored yet.
+    async fn read(self) -> anyhow::Result<Option<String>> {
+        match self {
+            Self::Keyring => read_secret(TOKENS_ACCOUNT).await,
+            Self::EncryptedFile => {
+                let sealed = match tokio::fs::read_to_string(ENCRYPTED_CACHE_PATH).await {
+                    Ok(sealed) => sealed,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+                    Err(e) => return Err(e.into()),
+                };
+                let key = encryption_key().await?;
+                Ok(Some(open(&serde_json::from_str(&sealed)?, &key)?))
+            }
+        }
+    }
+
+    async fn write(self, tokens: &str) -> anyhow::Result<()> {
+        match self {
+            Self::Keyring => write_secret(TOKENS_ACCOUNT, tokens).await,
+            Self::EncryptedFile => {
+                info!("Gmail auth: Saving tokens to {}", ENCRYPTED_CACHE_PATH);
+                let key = encryption_key().await?;
+                let sealed = serde_json::to_string(&seal(tokens, &key)?)?;
+                write_private(ENCRYPTED_CACHE_PATH, &sealed).await
+            }
+        }
+    }
+
+    async fn delete(self) -> anyhow::Result<()> {
+        match self {
+            Self::Keyring => delete_secret(TOKENS_ACCOUNT).await,
+            Self::EncryptedFile => {
+                info!("Gmail auth: Deleting {}", ENCRYPTED_CACHE_PATH);
+                match tokio::fs::remove_file(ENCRYPTED_CACHE_PATH).await {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+                    _ => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+/// Token storage in the system keyring or an encrypted file.
+pub struct SecretStorage {
+    backend: Backend,
+    /// Tokens read from the backend, loaded on first use.
+    tokens: Mutex<Option<ScopedTokens>>,
+}
+
+impl SecretStorage {
+    /// Storage for `store`; `None` for the plain file store.
+    pub fn new(store: TokenStore) -> Option<Self> {
+        Backend::for_store(store).map(|backend| Self {
+            backend,
+            tokens: Mutex::new(None),
+        })
+    }
+}
+
+#[async_trait]
+impl TokenStorage for SecretStorage {
+    async fn set(&self, scopes: &[&str], token: TokenInfo) -> anyhow::Result<()> {
+        let mut tokens = self.tokens.lock().await;
+        let mut updated = match tokens.take() {
+            Some(cached) => cached,
+            None => load_tokens(self.backend).await,
+        };
+        updated.set(scopes, token);
+        self.backend
+            .write(&serde_json::to_string(&updated)?)
+            .await?;
+        *tokens = Some(updated);
+        Ok(())
+    }
+
+    async fn get(&self, scopes: &[&str]) -> Option<TokenInfo> {
+        let mut tokens = self.tokens.lock().await;
+        if tokens.is_none() {
+            *tokens = Some(load_tokens(self.backend).await);
+        }
+        tokens.as_ref()?.get(scopes)
+    }
+}
+
+/// Report a failing token store as an authentication error.
+fn store_error(e: anyhow::Error) -> GmailMcpError {
+    GmailMcpError::Auth(format!("Token store: {}", e))
+}
+
+/// Read the cached tokens, treating a missing or unreadable entry as empty.
+async fn load_tokens(backend: Backend) -> ScopedTokens {
+    match backend.read().await {
+        Ok(Some(secret)) => serde_json::from_str(&secret).unwrap_or_else(|e| {
+            error!("Gmail auth: Ignoring unreadable token cache: {}", e);
+            ScopedTokens::default()
+        }),
+        Ok(None) => ScopedTokens::default(),
+        Err(e) => {
+            error!("Gmail auth: Failed to read cached tokens: {}", e);
+            ScopedTokens::default()
+        }
+    }
+}
+
+/// The contents of the encrypted token cache.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct SealedTokens {
+    /// Base64 PBKDF2 salt.
+    salt: String,
+    /// Base64 AES-GCM nonce.
+    nonce: String,
+    /// Base64 ciphertext followed by the authentication tag.
+    ciphertext: String,
+}
+
+/// Derive the AES-256 key for `passphrase` and `salt`.
+fn derive_key(passphrase: &str, salt: &[u8]) -> anyhow::Result<LessSafeKey> {
+    let mut key = [0u8; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        PBKDF2_ITERATIONS,
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+    let key = UnboundKey::new(&AES_256_GCM, &key)
+        .map_err(|_| anyhow::anyhow!("Invalid token encryption key"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// Encrypt `plaintext` with a key derived from `passphrase` and a fresh salt and nonce.
+fn seal(plaintext: &str, passphrase: &str) -> anyhow::Result<SealedTokens> {
+    let rng = SystemRandom::new();
+    let mut salt = [0u8; 16];
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill(&mut salt)
+        .and_then(|_| rng.fill(&mut nonce))
+        .map_err(|_| anyhow::anyhow!("No secure random numbers available"))?;
+
+    let mut in_out = plaintext.as_bytes().to_vec();
+    derive_key(passphrase, &salt)?
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::empty(),
+            &mut in_out,
+        )
+        .map_err(|_| anyhow::anyhow!("Failed to encrypt the token cache"))?;
+    Ok(SealedTokens {
+        salt: STANDARD.encode(salt),
+        nonce: STANDARD.encode(nonce),
+        ciphertext: STANDARD.encode(in_out),
+    })
+}
+
+/// Decrypt tokens sealed by [`seal`], failing if the key is wrong or the file was altered.
+fn open(sealed: &SealedTokens, passphrase: &str) -> anyhow::Result<String> {
+    let salt = STANDARD.decode(&sealed.salt)?;
+    let nonce: [u8; NONCE_LEN] = STANDARD
+        .decode(&sealed.nonce)?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Invalid nonce in the token cache"))?;
+    let mut in_out = STANDARD.decode(&sealed.ciphertext)?;
+    let plaintext = derive_key(passphrase, &salt)?
+        .open_in_place(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::empty(),
+            &mut in_out,
+        )
+        .map_err(|_| {
+            anyhow::anyhow!(
+                "Cannot decrypt {}; was {} changed?",
+                ENCRYPTED_CACHE_PATH,
+                KEY_ENV
+            )
+        })?;
+    Ok(String::from_utf8(plaintext.to_vec())?)
+}
+
+/// The passphrase for the encrypted cache: [`KEY_ENV`] when set, otherwise a
+/// random key kept in the system keyring and generated on first use.
+async fn encryption_key() -> anyhow::Result<String> {
+    if let Some(key) = std::env::var(KEY_ENV).ok().filter(|k| !k.trim().is_empty()) {
+        return Ok(key);
+    }
+    let missing = |e: anyhow::Error| {
+        anyhow::anyhow!(
+            "Set {} to encrypt the token cache; the system keyring is unavailable: {}",
+            KEY_ENV,
+            e
+        )
+    };
+    if let Some(key) = read_secret(KEY_ACCOUNT).await.map_err(missing)? {
+        return Ok(key);
+    }
+    // A new key could never decrypt the existing cache, so keep the cache
+    // recoverable instead of replacing the key it was sealed with
+    if tokio::fs::try_exists(ENCRYPTED_CACHE_PATH)
+        .await
+        .unwrap_or(true)
+    {
+        anyhow::bail!(
+            "The keyring has no token encryption key, but {} exists; restore the key, \
+             set {}, or delete {} to sign in again",
+            ENCRYPTED_CACHE_PATH,
+            KEY_ENV,
+            ENCRYPTED_CACHE_PATH
+        );
+    }
+
+    info!("Gmail auth: Generating a token encryption key in the keyring");
+    let mut key = [0u8; 32];
+    SystemRandom::new()
+        .fill(&mut key)
+        .map_err(|_| anyhow::anyhow!("No secure random numbers available"))?;
+    let key = STANDARD.encode(key);
+    write_secret(KEY_ACCOUNT, &key).await.map_err(missing)?;
+    Ok(key)
+}
+
+/// Write `contents` to `path`, readable only by the current user where supported.
+async fn write_private(path: &str, contents: &str) -> anyhow::Result<()> {
+    let mut options = tokio::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(path).await?;
+    file.write_all(contents.as_bytes()).await?;
+    file.flush().await?;
+    Ok(())
+}
+
+/// Run `call` on the keyring entry for `account` on a blocking thread, since
+/// the platform credential stores block.
+async fn with_entry<T, F>(account: &'static str, call: F) -> keyring::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(keyring::Entry) -> keyring::Result<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || call(keyring::Entry::new(KEYRING_SERVICE, account)?))
+        .await
+        .map_err(|e| keyring::Error::PlatformFailure(Box::new(e)))?
+}
+
+/// Read a keyring entry; `None` when it does not exist yet.
+async fn read_secret(account: &'static str) -> anyhow::Result<Option<String>> {
+    match with_entry(account, |entry| entry.get_password()).await {
+        Ok(secret) => Ok(Some(secret)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        // A locked keyring, a denied prompt, or no keyring at all
+        Err(e) => anyhow::bail!("Cannot read {} from the keyring: {}", account, e),
+    }
+}
+
+/// Create or replace a keyring entry.
+async fn write_secret(account: &'static str, secret: &str) -> anyhow::Result<()> {
+    info!("Gmail auth: Saving {} to the keyring", account);
+    let secret = secret.to_string();
+    with_entry(account, move |entry| entry.set_password(&secret))
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to save {} to the keyring: {}", account, e))
+}
+
+/// Remove a keyring entry; a missing entry is not an error.
+async fn delete_secret(account: &'static str) -> anyhow::Result<()> {
+    info!("Gmail auth: Removing {} from the keyring", account);
+    match with_entry(account, |entry| entry.delete_credential()).await {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => anyhow::bail!("Failed to remove {} from the keyring: {}", account, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(access: &str) -> TokenInfo {
+        TokenInfo {
+            access_token: Some(access.to_string()),
+            refresh_token: None,
+            expires_at: None,
+            id_token: None,
+        }
+    }
+
+    #[test]
+    fn test_scoped_tokens() {
+        let mut tokens = ScopedTokens::default();
+        tokens.set(&["b", "a"], token("first"));
+        assert_eq!(tokens.get(&["a"]), Some(token("first")));
+        assert_eq!(tokens.get(&["a", "b"]), Some(token("first")));
+        assert_eq!(tokens.get(&["c"]), None);
+
+        tokens.set(&["a", "b"], token("second"));
+        assert_eq!(tokens.tokens.len(), 1);
+        assert_eq!(tokens.get(&["b"]), Some(token("second")));
+
+        let json = serde_json::to_string(&tokens).unwrap();
+        assert_eq!(serde_json::from_str::<ScopedTokens>(&json).unwrap(), tokens);
+    }
+
+    #[test]
+    fn test_cached_token_from_disk_format() {
+        let json = r#"[{"scopes":["https://www.googleapis.com/auth/gmail.readonly"],
+            "token":{"access_token":"a","refresh_token":"r","expires_at":[2024,150,12,0,0,0,0,0,0],"id_token":null}}]"#;
+        let tokens: Vec<ScopedToken> = serde_json::from_str(json).unwrap();
+        let cached = CachedToken::from(&tokens[0]);
+        assert_eq!(cached.scopes, vec![crate::SCOPE_READONLY]);
+        assert_eq!(cached.expires_at, Some(1_716_984_000));
+        assert!(cached.has_refresh_token);
+    }
+
+    #[test]
+    fn test_seal_and_open() {
+        let sealed = seal("{\"tokens\":[]}", "correct horse").unwrap();
+        assert!(!sealed.ciphertext.contains("tokens"));
+        assert_eq!(open(&sealed, "correct horse").unwrap(), "{\"tokens\":[]}");
+        assert!(open(&sealed, "wrong").is_err());
+
+        // Fresh salt and nonce on every write
+        assert_ne!(seal("x", "k").unwrap(), seal("x", "k").unwrap());
+    }
+
+    #[test]
+    fn test_token_store_from_str() {
+        assert_eq!("".parse::<TokenStore>(), Ok(TokenStore::File));
+        assert_eq!("Keyring".parse::<TokenStore>(), Ok(TokenStore::Keyring));
+        assert_eq!("encrypted".parse::<TokenStore>(), Ok(TokenStore::Encrypted));
+        assert!("vault".parse::<TokenStore>().is_err());
+    }
+}
@@ -0,0 +1,924 @@
+//! Choosing how MCP clients connect to the server.
+//!
+//! The default SSE transport listens on port 3003. `--transport stdio` instead
+//! talks JSON-RPC over stdin and stdout, for clients that launch the server as
+//! a subprocess, and `--transport http` serves the streamable HTTP transport on
+//! the same port.
+//!
+//! Both HTTP transports are implemented here rather than taken from mcp-core,
+//! whose SSE transport cannot check who connects. With [`AUTH_TOKEN_ENV`] set,
+//! every request must carry the token as `Authorization: Bearer <token>` or
+//! `X-API-Key: <token>`. Requests from browsers must come from a loopback
+//! origin or one in [`ALLOWED_ORIGINS_ENV`]. Both also serve the health report at [`HEALTH_PATH`],
+//! which needs no token so that probes can reach it.
+//!
+//! The SSE transport streams each session's responses from [`SSE_PATH`] and
+//! takes its messages at [`MESSAGE_PATH`]. With streamable HTTP every client
+//! message is POSTed to [`HTTP_PATH`] and answered with plain JSON, or with an
+//! SSE stream when a request asks for progress and the client accepts one. The
+//! server sends nothing unprompted, so it offers no standalone stream. Every
+//! message but `initialize` must name a session it handed out, and sessions
+//! unused for [`SESSION_IDLE_TIMEOUT`] end.
+
+use crate::health::{self, HEALTH_PATH};
+use crate::{progress, shutdown};
+use actix_web::http::{header, StatusCode};
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpResponseBuilder, HttpServer};
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::{stream, StreamExt};
+use mcp_core::protocol::{Protocol, RequestOptions};
+use mcp_core::transport::{
+    JsonRpcError, JsonRpcMessage, JsonRpcResponse, Message, RequestId, Transport,
+};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::TcpListener;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+/// Address the HTTP transports listen on when [`HOST_ENV`] is unset.
+pub const HOST: &str = "127.0.0.1";
+/// Environment variable for the address the HTTP transports listen on.
+pub const HOST_ENV: &str = "GMAIL_MCP_HOST";
+pub const PORT: u16 = 3003;
+/// Endpoint of the streamable HTTP transport.
+pub const HTTP_PATH: &str = "/mcp";
+/// Event stream of the SSE transport.
+pub const SSE_PATH: &str = "/sse";
+/// Endpoint taking the SSE transport's client messages.
+pub const MESSAGE_PATH: &str = "/message";
+/// Environment variable with the token clients must present.
+pub const AUTH_TOKEN_ENV: &str = "GMAIL_MCP_TOKEN";
+/// Environment variable with the comma-separated web origins, such as
+/// `https://app.example.com`, allowed to call the server besides loopback ones.
+pub const ALLOWED_ORIGINS_ENV: &str = "GMAIL_MCP_ALLOWED_ORIGINS";
+
+/// Header carrying the session assigned at initialization.
+const SESSION_HEADER: &str = "Mcp-Session-Id";
+/// Header carrying the token as an alternative to `Authorization`.
+const API_KEY_HEADER: &str = "X-API-Key";
+/// Largest request body accepted; tool arguments can carry base64 attachments.
+const MAX_BODY_BYTES: usize = 64 * 1024 * 1024;
+/// How often an idle SSE stream gets a keep-alive comment.
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+/// How long a streamable HTTP session lasts without a message.
+pub const SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// The MCP transport to serve.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransportKind {
+    /// Server-Sent Events over HTTP.
+    #[default]
+    Sse,
+    /// Standard input and output of the server process.
+    Stdio,
+    /// MCP streamable HTTP: JSON-RPC over POST requests.
+    Http,
+}
+
+impl FromStr for TransportKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "" | "sse" => Ok(Self::Sse),
+            "stdio" => Ok(Self::Stdio),
+            "http" | "streamable-http" => Ok(Self::Http),
+            other => Err(format!(
+                "Unknown transport '{}'; expected sse, stdio, or http",
+                other
+            )),
+        }
+    }
+}
+
+impl TransportKind {
+    /// Read `--transport <kind>` or `--transport=<kind>` from the command-line
+    /// arguments, excluding the program name.
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut args = args.into_iter();
+        let mut kind = Self::default();
+        while let Some(arg) = args.next() {
+            if arg == "--transport" {
+                let value = args
+                    .next()
+                    .ok_or("--transport needs a value: sse, stdio, or http")?;
+                kind = value.parse()?;
+            } else if let Some(value) = arg.strip_prefix("--transport=") {
+                kind = value.parse()?;
+            } else {
+                return Err(format!(
+                    "Unknown argument '{}'; usage: gmail-mcp-server [--transport sse|stdio|http]",
+                    arg
+                ));
+            }
+        }
+        Ok(kind)
+    }
+}
+
+/// Server side of the MCP streamable HTTP transport.
+#[derive(Clone)]
+pub struct StreamableHttpTransport {
+    protocol: Protocol,
+    host: String,
+    port: u16,
+    /// Sessions handed out at initialization and not yet ended, with when each
+    /// was last used.
+    sessions: Arc<Mutex<HashMap<String, Instant>>>,
+    /// Token clients must present, if any.
+    token: Option<Arc<str>>,
+    /// Origins allowed besides loopback ones.
+    origins: Arc<[String]>,
+    /// Socket to serve on instead of binding `host` and `port`.
+    listener: Option<Arc<TcpListener>>,
+}
+
+impl StreamableHttpTransport {
+    pub fn new(host: String, port: u16, protocol: Protocol) -> Self {
+        Self {
+            protocol,
+            host,
+            port,
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            token: None,
+            origins: allowed_origins().into(),
+            listener: None,
+        }
+    }
+
+    /// Require clients to present `token`.
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token = token.map(Arc::from);
+        self
+    }
+
+    /// Serve on `listener`, such as the loopback end of the TLS relay.
+    pub fn with_listener(mut self, listener: Option<TcpListener>) -> Self {
+        self.listener = listener.map(Arc::new);
+        self
+    }
+
+    /// Handle one POSTed JSON-RPC message, returning the response to a request.
+    async fn dispatch(&self, message: JsonRpcMessage) -> Option<JsonRpcResponse> {
+        dispatch(&self.protocol, message).await
+    }
+}
+
+/// Hand one client message to `protocol`, returning the response to a request.
+async fn dispatch(protocol: &Protocol, message: JsonRpcMessage) -> Option<JsonRpcResponse> {
+    match message {
+        JsonRpcMessage::Request(request) => Some(protocol.handle_request(request).await),
+        JsonRpcMessage::Notification(notification) => {
+            protocol.handle_notification(notification).await;
+            None
+        }
+        JsonRpcMessage::Response(response) => {
+            protocol.handle_response(response).await;
+            None
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for StreamableHttpTransport {
+    async fn open(&self) -> Result<()> {
+        let transport = self.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(web::Data::new(transport.clone()))
+                .app_data(web::PayloadConfig::new(MAX_BODY_BYTES))
+                .route(HTTP_PATH, web::post().to(post_handler))
+                .route(HTTP_PATH, web::get().to(get_handler))
+                .route(HTTP_PATH, web::delete().to(delete_handler))
+                .route(HEALTH_PATH, web::get().to(health_handler))
+        })
+        // Shutdown is coordinated by the server, which first drains tool calls
+        .disable_signals();
+        let server = match &self.listener {
+            Some(listener) => server.listen(listener.try_clone()?)?,
+            None => server.bind((self.host.clone(), self.port))?,
+        }
+        .run();
+        server
+            .await
+            .map_err(|e| anyhow::anyhow!("Server error: {:?}", e))
+    }
+
+    async fn close(&self) -> Result<()> {
+        Ok(())
+    }
+
+    // Messages arrive through the HTTP routes rather than by polling
+    async fn poll_message(&self) -> Result<Option<Message>> {
+        Ok(None)
+    }
+
+    // The server sends no requests or notifications of its own
+    fn request(
+        &self,
+        _method: &str,
+        _params: Option<Value>,
+        _options: RequestOptions,
+    ) -> Pin<Box<dyn Future<Output = Result<JsonRpcResponse>> + Send + Sync>> {
+        Box::pin(async move { Ok(JsonRpcResponse::default()) })
+    }
+
+    async fn send_notification(&self, _method: &str, _params: Option<Value>) -> Result<()> {
+        Ok(())
+    }
+
+    async fn send_response(
+        &self,
+        _id: RequestId,
+        _result: Option<Value>,
+        _error: Option<JsonRpcError>,
+    ) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The address to listen on, from [`HOST_ENV`] or else [`HOST`].
+pub fn host() -> String {
+    std::env::var(HOST_ENV)
+        .ok()
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| HOST.to_string())
+}
+
+/// Refuse to let anyone who can reach a non-loopback `host` call the tools,
+/// which can send and delete mail, without `token`.
+pub fn check_exposure(host: &str, token: Option<&str>) -> Result<(), String> {
+    if token.is_none() && !is_loopback(&format!("{}:{}", host, PORT)) {
+        return Err(format!(
+            "Refusing to serve the MCP tools on {} without {}; set it, \
+             or listen on a loopback address",
+            host, AUTH_TOKEN_ENV
+        ));
+    }
+    Ok(())
+}
+
+/// The token configured in [`AUTH_TOKEN_ENV`], if any.
+pub fn auth_token() -> Option<String> {
+    std::env::var(AUTH_TOKEN_ENV)
+        .ok()
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+}
+
+/// Compare without stopping at the first difference, so timing reveals nothing.
+pub(crate) fn tokens_match(presented: &str, expected: &str) -> bool {
+    presented.len() == expected.len()
+        && presented
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Whether `addr`, as `host:port`, only accepts connections from this machine.
+pub(crate) fn is_loopback(addr: &str) -> bool {
+    let host = addr.rsplit_once(':').map_or(addr, |(host, _)| host);
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    host.eq_ignore_ascii_case("localhost")
+        || host
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback())
+}
+
+/// The origins configured in [`ALLOWED_ORIGINS_ENV`].
+pub fn allowed_origins() -> Vec<String> {
+    std::env::var(ALLOWED_ORIGINS_ENV)
+        .unwrap_or_default()
+        .split(',')
+        .map(|o| o.trim().trim_end_matches('/').to_string())
+        .filter(|o| !o.is_empty())
+        .collect()
+}
+
+/// Whether a request with this `Origin` may call the server: loopback origins
+/// and those in `allowed` can, so a web page elsewhere cannot reach the tools
+/// through the browser, for example after rebinding its DNS name to this host.
+fn origin_allowed(origin: &str, allowed: &[String]) -> bool {
+    let origin = origin.trim().trim_end_matches('/');
+    if allowed.iter().any(|a| a.eq_ignore_ascii_case(origin)) {
+        return true;
+    }
+    let Some((_, authority)) = origin.split_once("://") else {
+        return false;
+    };
+    let host = match authority.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or_default(),
+        None => authority.split(':').next().unwrap_or_default(),
+    };
+    is_loopback(&format!("[{}]:0", host))
+}
+
+/// Whether `req` carries no `Origin`, as from a non-browser client, or an
+/// allowed one.
+fn from_allowed_origin(req: &HttpRequest, allowed: &[String]) -> bool {
+    let Some(origin) = req.headers().get(header::ORIGIN) else {
+        return true;
+    };
+    let ok = origin
+        .to_str()
+        .is_ok_and(|origin| origin_allowed(origin, allowed));
+    if !ok {
+        warn!(
+            "Rejected {} {} from origin {:?}",
+            req.method(),
+            req.path(),
+            origin
+        );
+    }
+    ok
+}
+
+fn forbidden_origin() -> HttpResponse {
+    HttpResponse::Forbidden().body("Origin not allowed")
+}
+
+/// Whether `req` carries `token`, or no token is required.
+fn authorized(req: &HttpRequest, token: Option<&str>) -> bool {
+    let Some(token) = token else {
+        return true;
+    };
+    let header = |name| req.headers().get(name).and_then(|v| v.to_str().ok());
+    let presented = header(header::AUTHORIZATION.as_str())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .or_else(|| header(API_KEY_HEADER));
+    let allowed = presented.is_some_and(|p| tokens_match(p.trim(), token));
+    if !allowed {
+        warn!(
+            "Rejected {} {} with a missing or wrong token",
+            req.method(),
+            req.path()
+        );
+    }
+    allowed
+}
+
+fn unauthorized() -> HttpResponse {
+    HttpResponse::Unauthorized()
+        .insert_header((header::WWW_AUTHENTICATE, "Bearer"))
+        .finish()
+}
+
+/// A JSON-RPC error that is not tied to a request ID.
+fn rpc_error(status: StatusCode, code: i32, message: &str) -> HttpResponse {
+    HttpResponse::build(status).json(json!({
+        "jsonrpc": "2.0",
+        "id": null,
+        "error": { "code": code, "message": message },
+    }))
+}
+
+fn new_session_id() -> Option<String> {
+    let mut bytes = [0u8; 16];
+    SystemRandom::new().fill(&mut bytes).ok()?;
+    Some(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Parse a POST body into its messages; the flag tells whether it was a batch.
+fn parse_messages(body: &[u8]) -> Result<(Vec<JsonRpcMessage>, bool), String> {
+    let value: Value = serde_json::from_slice(body).map_err(|e| format!("Parse error: {}", e))?;
+    let (values, batch) = match value {
+        Value::Array(values) if values.is_empty() => return Err("Empty batch".to_string()),
+        Value::Array(values) => (values, true),
+        value => (vec![value], false),
+    };
+    let messages = values
+        .into_iter()
+        .map(serde_json::from_value)
+        .collect::<Result<Vec<JsonRpcMessage>, _>>()
+        .map_err(|e| format!("Invalid JSON-RPC message: {}", e))?;
+    Ok((messages, batch))
+}
+
+/// Drop the sessions idle for [`SESSION_IDLE_TIMEOUT`] at `now`.
+fn evict_idle(sessions: &mut HashMap<String, Instant>, now: Instant) {
+    sessions.retain(|_, used| now.duration_since(*used) < SESSION_IDLE_TIMEOUT);
+}
+
+/// Mark session `id` as used at `now`, returning whether it is still open.
+fn touch_session(sessions: &mut HashMap<String, Instant>, id: &str, now: Instant) -> bool {
+    evict_idle(sessions, now);
+    match sessions.get_mut(id) {
+        Some(used) => {
+            *used = now;
+            true
+        }
+        None => false,
+    }
+}
+
+fn session_of(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(SESSION_HEADER)
+        .and_then(|v| v.to_str().ok())
+}
+
+async fn post_handler(
+    req: HttpRequest,
+    transport: web::Data<StreamableHttpTransport>,
+    body: web::Bytes,
+) -> HttpResponse {
+    if !from_allowed_origin(&req, &transport.origins) {
+        return forbidden_origin();
+    }
+    if !authorized(&req, transport.token.as_deref()) {
+        return unauthorized();
+    }
+    let (messages, batch) = match parse_messages(&body) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            warn!("Rejected streamable HTTP message: {}", e);
+            return rpc_error(StatusCode::BAD_REQUEST, -32700, &e);
+        }
+    };
+
+    let initializing = messages
+        .iter()
+        .any(|m| matches!(m, JsonRpcMessage::Request(r) if r.method == "initialize"));
+    let mut new_session = None;
+    if initializing && shutdown::is_shutting_down() {
+        return rpc_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            -32000,
+            "Server is shutting down",
+        );
+    } else if initializing {
+        let Some(id) = new_session_id() else {
+            return rpc_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                -32603,
+                "Cannot create a session",
+            );
+        };
+        let now = Instant::now();
+        let mut sessions = transport.sessions.lock().unwrap();
+        evict_idle(&mut sessions, now);
+        sessions.insert(id.clone(), now);
+        new_session = Some(id);
+    } else {
+        let Some(id) = session_of(&req) else {
+            return rpc_error(
+                StatusCode::BAD_REQUEST,
+                -32000,
+                "Missing Mcp-Session-Id header; initialize first",
+            );
+        };
+        // Unknown and expired sessions get 404, telling the client to initialize again
+        if !touch_session(&mut transport.sessions.lock().unwrap(), id, Instant::now()) {
+            return rpc_error(StatusCode::NOT_FOUND, -32001, "Session not found");
+        }
+    }
+
+    let mut response = HttpResponse::Ok();
+    if let Some(id) = new_session {
+        info!("Started streamable HTTP session {}", id);
+        response.insert_header((SESSION_HEADER, id));
+    }
+    response.insert_header((header::CACHE_CONTROL, "no-store"));
+    if accepts_event_stream(&req) && messages.iter().any(wants_progress) {
+        return stream_responses(response, transport, messages);
+    }
+
+    let mut responses = Vec::new();
+    for message in messages {
+        if let Some(response) = transport.dispatch(message).await {
+            responses.push(response);
+        }
+    }
+    if responses.is_empty() {
+        return HttpResponse::Accepted().finish();
+    }
+    if batch {
+        response.json(responses)
+    } else {
+        response.json(&responses[0])
+    }
+}
+
+fn accepts_event_stream(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/event-stream"))
+}
+
+/// Whether `message` is a request asking for progress notifications.
+fn wants_progress(message: &JsonRpcMessage) -> bool {
+    match message {
+        JsonRpcMessage::Request(request) => {
+            progress::token(request.params.as_ref().and_then(|p| p.get("_meta"))).is_some()
+        }
+        _ => false,
+    }
+}
+
+/// Answer with an SSE stream, so progress notifications can precede the responses.
+fn stream_responses(
+    mut response: HttpResponseBuilder,
+    transport: web::Data<StreamableHttpTransport>,
+    messages: Vec<JsonRpcMessage>,
+) -> HttpResponse {
+    let (sender, receiver) = mpsc::unbounded_channel::<Value>();
+    let notifications = sender.clone();
+    let sink: progress::Sink = Arc::new(move |notification| {
+        let _ = notifications.send(notification);
+    });
+    // The stream ends once the handling task drops both senders
+    actix_web::rt::spawn(progress::with_sink(sink, async move {
+        for message in messages {
+            if let Some(response) = transport.dispatch(message).await {
+                if let Ok(value) = serde_json::to_value(&response) {
+                    let _ = sender.send(value);
+                }
+            }
+        }
+    }));
+
+    let events = stream::unfold(receiver, |mut receiver| async move {
+        let message = receiver.recv().await?;
+        let event = format!("event: message\ndata: {}\n\n", message);
+        Some((Ok::<_, Infallible>(web::Bytes::from(event)), receiver))
+    });
+    response.content_type("text/event-stream").streaming(events)
+}
+
+/// Report whether the server can reach Gmail; open to probes without the token.
+async fn health_handler() -> HttpResponse {
+    let health = health::check().await;
+    if health.is_healthy() {
+        HttpResponse::Ok().json(health)
+    } else {
+        warn!("Health check failed: {:?}", health.errors);
+        HttpResponse::ServiceUnavailable().json(health)
+    }
+}
+
+/// The server has nothing to push, so it offers no SSE stream.
+async fn get_handler(
+    req: HttpRequest,
+    transport: web::Data<StreamableHttpTransport>,
+) -> HttpResponse {
+    if !from_allowed_origin(&req, &transport.origins) {
+        return forbidden_origin();
+    }
+    HttpResponse::MethodNotAllowed()
+        .insert_header((header::ALLOW, "POST, DELETE"))
+        .finish()
+}
+
+async fn delete_handler(
+    req: HttpRequest,
+    transport: web::Data<StreamableHttpTransport>,
+) -> HttpResponse {
+    if !from_allowed_origin(&req, &transport.origins) {
+        return forbidden_origin();
+    }
+    if !authorized(&req, transport.token.as_deref()) {
+        return unauthorized();
+    }
+    let Some(id) = session_of(&req) else {
+        return HttpResponse::BadRequest().finish();
+    };
+    if transport.sessions.lock().unwrap().remove(id).is_some() {
+        info!("Ended streamable HTTP session {}", id);
+        HttpResponse::Ok().finish()
+    } else {
+        HttpResponse::NotFound().finish()
+    }
+}
+
+/// Server side of the MCP SSE transport.
+#[derive(Clone)]
+pub struct SseTransport {
+    protocol: Protocol,
+    host: String,
+    port: u16,
+    /// Open event streams by session ID.
+    sessions: Arc<Mutex<HashMap<String, mpsc::UnboundedSender<Value>>>>,
+    /// Token clients must present, if any.
+    token: Option<Arc<str>>,
+    /// Origins allowed besides loopback ones.
+    origins: Arc<[String]>,
+    /// Socket to serve on instead of binding `host` and `port`.
+    listener: Option<Arc<TcpListener>>,
+}
+
+impl SseTransport {
+    pub fn new(host: String, port: u16, protocol: Protocol) -> Self {
+        Self {
+            protocol,
+            host,
+            port,
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            token: None,
+            origins: allowed_origins().into(),
+            listener: None,
+        }
+    }
+
+    /// Require clients to present `token`.
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token = token.map(Arc::from);
+        self
+    }
+
+    /// Serve on `listener`, such as the loopback end of the TLS relay.
+    pub fn with_listener(mut self, listener: Option<TcpListener>) -> Self {
+        self.listener = listener.map(Arc::new);
+        self
+    }
+}
+
+#[async_trait]
+impl Transport for SseTransport {
+    async fn open(&self) -> Result<()> {
+        let transport = self.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(web::Data::new(transport.clone()))
+                .app_data(web::PayloadConfig::new(MAX_BODY_BYTES))
+                .route(SSE_PATH, web::get().to(sse_handler))
+                .route(MESSAGE_PATH, web::post().to(message_handler))
+                .route(HEALTH_PATH, web::get().to(health_handler))
+        })
+        // Shutdown is coordinated by the server, which first drains tool calls
+        .disable_signals();
+        let server = match &self.listener {
+            Some(listener) => server.listen(listener.try_clone()?)?,
+            None => server.bind((self.host.clone(), self.port))?,
+        }
+        .run();
+        server
+            .await
+            .map_err(|e| anyhow::anyhow!("Server error: {:?}", e))
+    }
+
+    async fn close(&self) -> Result<()> {
+        Ok(())
+    }
+
+    // Messages arrive through the HTTP routes rather than by polling
+    async fn poll_message(&self) -> Result<Option<Message>> {
+        Ok(None)
+    }
+
+    // The server sends no requests or notifications of its own
+    fn request(
+        &self,
+        _method: &str,
+        _params: Option<Value>,
+        _options: RequestOptions,
+    ) -> Pin<Box<dyn Future<Output = Result<JsonRpcResponse>> + Send + Sync>> {
+        Box::pin(async move { Ok(JsonRpcResponse::default()) })
+    }
+
+    async fn send_notification(&self, _method: &str, _params: Option<Value>) -> Result<()> {
+        Ok(())
+    }
+
+    async fn send_response(
+        &self,
+        _id: RequestId,
+        _result: Option<Value>,
+        _error: Option<JsonRpcError>,
+    ) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// An open event stream; dropping it when the client goes away ends its session.
+struct SseStream {
+    id: String,
+    receiver: mpsc::UnboundedReceiver<Value>,
+    sessions: Arc<Mutex<HashMap<String, mpsc::UnboundedSender<Value>>>>,
+}
+
+impl Drop for SseStream {
+    fn drop(&mut self) {
+        self.sessions.lock().unwrap().remove(&self.id);
+        info!("Ended SSE session {}", self.id);
+    }
+}
+
+async fn sse_handler(req: HttpRequest, transport: web::Data<SseTransport>) -> HttpResponse {
+    if !from_allowed_origin(&req, &transport.origins) {
+        return forbidden_origin();
+    }
+    if !authorized(&req, transport.token.as_deref()) {
+        return unauthorized();
+    }
+    if shutdown::is_shutting_down() {
+        return HttpResponse::ServiceUnavailable().finish();
+    }
+    let Some(id) = new_session_id() else {
+        return HttpResponse::InternalServerError().finish();
+    };
+    let (sender, receiver) = mpsc::unbounded_channel();
+    transport
+        .sessions
+        .lock()
+        .unwrap()
+        .insert(id.clone(), sender);
+    info!("Started SSE session {}", id);
+
+    let endpoint = format!(
+        "event: endpoint\ndata: {}?sessionId={}\n\n",
+        MESSAGE_PATH, id
+    );
+    let state = SseStream {
+        id,
+        receiver,
+        sessions: transport.sessions.clone(),
+    };
+    let messages = stream::unfold(state, |mut state| async move {
+        let event = match tokio::time::timeout(KEEP_ALIVE, state.receiver.recv()).await {
+            Ok(Some(message)) => format!("event: message\ndata: {}\n\n", message),
+            Ok(None) => return None,
+            Err(_) => ": keep-alive\n\n".to_string(),
+        };
+        Some((Ok::<_, Infallible>(web::Bytes::from(event)), state))
+    });
+    let events = stream::once(async move { Ok(web::Bytes::from(endpoint)) }).chain(messages);
+    HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .content_type("text/event-stream")
+        .streaming(events)
+}
+
+async fn message_handler(
+    req: HttpRequest,
+    transport: web::Data<SseTransport>,
+    query: web::Query<HashMap<String, String>>,
+    body: web::Bytes,
+) -> HttpResponse {
+    if !from_allowed_origin(&req, &transport.origins) {
+        return forbidden_origin();
+    }
+    if !authorized(&req, transport.token.as_deref()) {
+        return unauthorized();
+    }
+    let Some(id) = query.get("sessionId") else {
+        return HttpResponse::BadRequest().body("Session ID not specified");
+    };
+    let Some(sender) = transport.sessions.lock().unwrap().get(id).cloned() else {
+        return HttpResponse::NotFound().body(format!("Session {} not found", id));
+    };
+    let message: JsonRpcMessage = match serde_json::from_slice(&body) {
+        Ok(message) => message,
+        Err(e) => {
+            warn!("Rejected SSE message: {}", e);
+            return rpc_error(
+                StatusCode::BAD_REQUEST,
+                -32700,
+                &format!("Parse error: {}", e),
+            );
+        }
+    };
+
+    // Progress notifications travel on the session's stream ahead of the response
+    let notifications = sender.clone();
+    let sink: progress::Sink = Arc::new(move |notification| {
+        let _ = notifications.send(notification);
+    });
+    if let Some(response) = progress::with_sink(sink, dispatch(&transport.protocol, message)).await
+    {
+        if let Ok(value) = serde_json::to_value(&response) {
+            let _ = sender.send(value);
+        }
+    }
+    HttpResponse::Accepted().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn test_transport_from_args() {
+        assert_eq!(TransportKind::from_args(args(&[])), Ok(TransportKind::Sse));
+        assert_eq!(
+            TransportKind::from_args(args(&["--transport", "stdio"])),
+            Ok(TransportKind::Stdio)
+        );
+        assert_eq!(
+            TransportKind::from_args(args(&["--transport=SSE"])),
+            Ok(TransportKind::Sse)
+        );
+        assert!(TransportKind::from_args(args(&["--transport"])).is_err());
+        assert!(TransportKind::from_args(args(&["--transport", "grpc"])).is_err());
+        assert!(TransportKind::from_args(args(&["--verbose"])).is_err());
+        assert_eq!(
+            TransportKind::from_args(args(&["--transport", "streamable-http"])),
+            Ok(TransportKind::Http)
+        );
+    }
+
+    #[test]
+    fn test_tokens_match() {
+        assert!(tokens_match("s3cret", "s3cret"));
+        assert!(!tokens_match("s3cres", "s3cret"));
+        assert!(!tokens_match("s3cret-longer", "s3cret"));
+        assert!(!tokens_match("", "s3cret"));
+    }
+
+    #[test]
+    fn test_is_loopback() {
+        assert!(is_loopback("127.0.0.1:3005"));
+        assert!(is_loopback("localhost:3005"));
+        assert!(is_loopback("[::1]:3005"));
+        assert!(!is_loopback("0.0.0.0:3005"));
+        assert!(!is_loopback("[::]:3005"));
+        assert!(!is_loopback("192.168.1.10:3005"));
+    }
+
+    #[test]
+    fn test_origin_allowed() {
+        let allowed = vec!["https://app.example.com".to_string()];
+        assert!(origin_allowed("http://localhost:6274", &allowed));
+        assert!(origin_allowed("http://127.0.0.1", &allowed));
+        assert!(origin_allowed("http://[::1]:3000", &allowed));
+        assert!(origin_allowed("https://app.example.com/", &allowed));
+        assert!(!origin_allowed("https://evil.example", &allowed));
+        assert!(!origin_allowed("http://localhost.evil.example", &allowed));
+        assert!(!origin_allowed("null", &allowed));
+
+        let request = |origin: Option<&str>| {
+            let mut request = actix_web::test::TestRequest::post();
+            if let Some(origin) = origin {
+                request = request.insert_header((header::ORIGIN, origin));
+            }
+            request.to_http_request()
+        };
+        assert!(from_allowed_origin(&request(None), &allowed));
+        assert!(from_allowed_origin(
+            &request(Some("http://localhost:6274")),
+            &allowed
+        ));
+        assert!(!from_allowed_origin(
+            &request(Some("https://evil.example")),
+            &allowed
+        ));
+        assert_eq!(forbidden_origin().status(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn test_check_exposure() {
+        assert!(check_exposure("127.0.0.1", None).is_ok());
+        assert!(check_exposure("::1", None).is_ok());
+        assert!(check_exposure("0.0.0.0", None).is_err());
+        assert!(check_exposure("0.0.0.0", Some("s3cret")).is_ok());
+    }
+
+    #[test]
+    fn test_parse_messages() {
+        let (messages, batch) =
+            parse_messages(br#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#).unwrap();
+        assert!(!batch);
+        assert!(matches!(&messages[0], JsonRpcMessage::Request(r) if r.method == "tools/list"));
+
+        let (messages, batch) = parse_messages(
+            br#"[{"jsonrpc":"2.0","method":"notifications/initialized"},{"jsonrpc":"2.0","id":2,"method":"ping"}]"#,
+        )
+        .unwrap();
+        assert!(batch);
+        assert!(matches!(&messages[0], JsonRpcMessage::Notification(_)));
+        assert!(matches!(&messages[1], JsonRpcMessage::Request(_)));
+
+        assert!(parse_messages(b"[]").is_err());
+        assert!(parse_messages(b"{").is_err());
+    }
+
+    #[test]
+    fn test_touch_session() {
+        let start = Instant::now();
+        let mut sessions = HashMap::from([("a".to_string(), start), ("b".to_string(), start)]);
+        let later = start + SESSION_IDLE_TIMEOUT / 2;
+        assert!(touch_session(&mut sessions, "a", later));
+        assert!(!touch_session(&mut sessions, "c", later));
+
+        // Only "a" was used since the start
+        let expiry = start + SESSION_IDLE_TIMEOUT + Duration::from_secs(1);
+        assert!(!touch_session(&mut sessions, "b", expiry));
+        assert!(touch_session(&mut sessions, "a", expiry));
+        assert_eq!(sessions.len(), 1);
+    }
+}
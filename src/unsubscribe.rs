@@ -0,0 +1,318 @@
+//! Unsubscribe options of mailing-list mail.
+//!
+//! Bulk senders list where to unsubscribe in the `List-Unsubscribe` header
+//! (RFC 2369): web links and a `mailto:` address. With
+//! `List-Unsubscribe-Post: List-Unsubscribe=One-Click` (RFC 8058) they also
+//! promise that a single POST to the web link unsubscribes, without a page to
+//! click through. Senders without the header usually link to an unsubscribe
+//! page from the body instead.
+
+use crate::markup::{attribute, pattern, strip_tags};
+use crate::{EmailSummary, FetchFormat, FetchOptions, GmailMcpError};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::info;
+
+/// Query of `gmail_unsubscribe_candidates` when none is given.
+pub const DEFAULT_CANDIDATES_QUERY: &str = "unsubscribe newer_than:90d";
+
+/// Most links taken from one body.
+const MAX_BODY_LINKS: usize = 5;
+
+/// Where and how to unsubscribe from the list a message was sent to.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default, PartialEq)]
+pub struct Unsubscribe {
+    /// Web links from the `List-Unsubscribe` header.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub urls: Vec<String>,
+    /// The `mailto:` link from the `List-Unsubscribe` header, for unsubscribing by email.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mailto: Option<String>,
+    /// Whether a POST to the header's web link unsubscribes in one click (RFC 8058).
+    #[serde(default)]
+    pub one_click: bool,
+    /// Links in the body whose text or address mentions unsubscribing or opting out.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub body_links: Vec<String>,
+}
+
+impl Unsubscribe {
+    /// Whether the `List-Unsubscribe` header offers a way to unsubscribe.
+    pub fn in_headers(&self) -> bool {
+        !self.urls.is_empty() || self.mailto.is_some()
+    }
+}
+
+/// Find the unsubscribe options of a message from its headers and its HTML
+/// and text bodies, or `None` if it has none.
+pub fn find(headers: &[(String, String)], html: Option<&str>, text: &str) -> Option<Unsubscribe> {
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    };
+
+    let mut unsubscribe = Unsubscribe::default();
+    let links = header("List-Unsubscribe").map(header_links);
+    for link in links.unwrap_or_default() {
+        if link.to_ascii_lowercase().starts_with("mailto:") {
+            unsubscribe.mailto.get_or_insert(link);
+        } else if link.starts_with("https://") || link.starts_with("http://") {
+            unsubscribe.urls.push(link);
+        }
+    }
+    unsubscribe.one_click = !unsubscribe.urls.is_empty()
+        && header("List-Unsubscribe-Post").is_some_and(|post| {
+            post.to_ascii_lowercase()
+                .contains("list-unsubscribe=one-click")
+        });
+    unsubscribe.body_links = body_links(html, text);
+
+    (unsubscribe.in_headers() || !unsubscribe.body_links.is_empty()).then_some(unsubscribe)
+}
+
+/// The links of a `List-Unsubscribe` header, which are normally each in angle
+/// brackets, as in `<mailto:leave@example.com>, <https://example.com/u/1>`.
+fn header_links(value: &str) -> Vec<String> {
+    let bracketed: Vec<String> = pattern(r"<([^>]+)>")
+        .captures_iter(value)
+        .map(|caps| caps[1].trim().to_string())
+        .collect();
+    if !bracketed.is_empty() {
+        return bracketed;
+    }
+    value
+        .split(',')
+        .map(|link| link.trim().to_string())
+        .filter(|link| !link.is_empty())
+        .collect()
+}
+
+/// Whether a link's text or address is about unsubscribing.
+fn mentions_unsubscribe(text: &str) -> bool {
+    let text = text.to_ascii_lowercase();
+    ["unsubscribe", "opt-out", "opt out", "optout"]
+        .iter()
+        .any(|word| text.contains(word))
+}
+
+/// The unsubscribe links of a body: HTML anchors whose text or address mentions
+/// unsubscribing, and such URLs in the plain text.
+fn body_links(html: Option<&str>, text: &str) -> Vec<String> {
+    let mut links: Vec<String> = Vec::new();
+    let mut add = |link: String| {
+        if links.len() < MAX_BODY_LINKS && !links.contains(&link) {
+            links.push(link);
+        }
+    };
+    if let Some(html) = html {
+        for caps in pattern(r"(?is)<a\b([^>]*)>(.*?)</a\s*>").captures_iter(html) {
+            let Some(href) = attribute(&caps[1], "href") else {
+                continue;
+            };
+            let web_link = href.starts_with("https://") || href.starts_with("http://");
+            if web_link
+                && (mentions_unsubscribe(&strip_tags(&caps[2])) || mentions_unsubscribe(&href))
+            {
+                add(href);
+            }
+        }
+    }
+    if !text.starts_with('<') {
+        for url in pattern(r"https?://[^\s<>]+").find_iter(text) {
+            let url = url
+                .as_str()
+                .trim_end_matches(['.', ',', ';', ')', '"', '\'']);
+            if mentions_unsubscribe(url) {
+                add(url.to_string());
+            }
+        }
+    }
+    links
+}
+
+/// A sender whose mail can be unsubscribed from through its headers.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Candidate {
+    /// The sender's address.
+    pub address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// How many of the scanned messages came from the sender.
+    pub messages: usize,
+    /// ID of the sender's newest scanned message.
+    pub latest_id: String,
+    /// Subject of the sender's newest scanned message.
+    pub latest_subject: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latest_date: Option<String>,
+    /// The unsubscribe options of the newest message.
+    pub unsubscribe: Unsubscribe,
+}
+
+/// Group `emails`, newest first, by sender, keeping the senders whose mail
+/// offers unsubscribing in its headers. One-click senders come first, then
+/// those with the most messages.
+pub fn candidates(emails: &[EmailSummary]) -> Vec<Candidate> {
+    let mut candidates: Vec<Candidate> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for email in emails {
+        let Some(sender) = &email.sender else {
+            continue;
+        };
+        let address = sender.address.to_ascii_lowercase();
+        if let Some(&i) = index.get(&address) {
+            candidates[i].messages += 1;
+            continue;
+        }
+        let Some(unsubscribe) = email.unsubscribe.as_ref().filter(|u| u.in_headers()) else {
+            continue;
+        };
+        index.insert(address.clone(), candidates.len());
+        candidates.push(Candidate {
+            address,
+            name: sender.name.clone(),
+            messages: 1,
+            latest_id: email.id.clone(),
+            latest_subject: email.subject.clone(),
+            latest_date: email.date.clone(),
+            unsubscribe: unsubscribe.clone(),
+        });
+    }
+    candidates.sort_by(|a, b| {
+        b.unsubscribe
+            .one_click
+            .cmp(&a.unsubscribe.one_click)
+            .then(b.messages.cmp(&a.messages))
+    });
+    candidates
+}
+
+/// Senders of the mail matching `query` that can be unsubscribed from, as
+/// pretty-printed JSON. Up to `max_results` messages are scanned, headers only.
+pub async fn unsubscribe_candidates(
+    query: Option<String>,
+    max_results: u32,
+) -> Result<String, GmailMcpError> {
+    let query = query
+        .map(|q| q.trim().to_string())
+        .filter(|q| !q.is_empty())
+        .unwrap_or_else(|| DEFAULT_CANDIDATES_QUERY.to_string());
+    info!("Scanning mail matching '{}' for unsubscribe options", query);
+
+    let options = FetchOptions::new()
+        .query(query)
+        .max_results(max_results)
+        .format(FetchFormat::Metadata);
+    let query = crate::build_query(&options)?;
+    let response = crate::provider::current()
+        .await?
+        .list(&query, &options)
+        .await?;
+    let candidates = candidates(&response.emails);
+    info!(
+        "Found {} senders to unsubscribe from in {} messages",
+        candidates.len(),
+        response.count
+    );
+    Ok(serde_json::to_string_pretty(&candidates)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compose::Mailbox;
+
+    fn headers(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_find_in_headers() {
+        let found = find(
+            &headers(&[
+                (
+                    "List-Unsubscribe",
+                    "<mailto:leave@news.example.com?subject=unsubscribe>, <https://news.example.com/u/42>",
+                ),
+                ("List-Unsubscribe-Post", "List-Unsubscribe=One-Click"),
+            ]),
+            None,
+            "",
+        )
+        .unwrap();
+        assert_eq!(found.urls, vec!["https://news.example.com/u/42"]);
+        assert_eq!(
+            found.mailto.as_deref(),
+            Some("mailto:leave@news.example.com?subject=unsubscribe")
+        );
+        assert!(found.one_click);
+
+        let mailto_only = find(
+            &headers(&[
+                ("List-Unsubscribe", "<mailto:leave@example.com>"),
+                ("List-Unsubscribe-Post", "List-Unsubscribe=One-Click"),
+            ]),
+            None,
+            "",
+        )
+        .unwrap();
+        assert!(!mailto_only.one_click);
+        assert!(find(&headers(&[("Subject", "Hi")]), None, "Hello").is_none());
+    }
+
+    #[test]
+    fn test_find_in_body() {
+        let html = "<p>Sale!</p><a href=\"https://shop.example.com/p/1\">Shop</a>\
+            <a href=\"https://shop.example.com/prefs?u=1&amp;t=2\"><span>Unsubscribe</span></a>";
+        let found = find(&[], Some(html), "").unwrap();
+        assert_eq!(
+            found.body_links,
+            vec!["https://shop.example.com/prefs?u=1&t=2"]
+        );
+        assert!(!found.in_headers());
+
+        let text = "To stop these emails visit https://example.com/unsubscribe/abc.";
+        let found = find(&[], None, text).unwrap();
+        assert_eq!(
+            found.body_links,
+            vec!["https://example.com/unsubscribe/abc"]
+        );
+    }
+
+    #[test]
+    fn test_candidates() {
+        let email = |id: &str, address: &str, one_click: Option<bool>| EmailSummary {
+            id: id.to_string(),
+            sender: Some(Mailbox {
+                name: None,
+                address: address.to_string(),
+            }),
+            unsubscribe: one_click.map(|one_click| Unsubscribe {
+                urls: vec![format!("https://{}/u", address)],
+                one_click,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let found = candidates(&[
+            email("1", "news@a.example", Some(false)),
+            email("2", "friend@b.example", None),
+            email("3", "News@A.example", Some(false)),
+            email("4", "deals@c.example", Some(true)),
+        ]);
+        let summary: Vec<(&str, usize, &str)> = found
+            .iter()
+            .map(|c| (c.address.as_str(), c.messages, c.latest_id.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![("deals@c.example", 1, "4"), ("news@a.example", 2, "1")]
+        );
+    }
+}
@@ -0,0 +1,341 @@
+//! Push notifications via `users.watch` and Cloud Pub/Sub.
+//!
+//! Gmail publishes a small notification to a Pub/Sub topic whenever the mailbox
+//! changes. A push subscription delivers it to the HTTP endpoint served here,
+//! which reads the new messages through the History API and raises
+//! [`NewMailEvent`](crate::events::NewMailEvent)s.
+
+use crate::events::{self, NewMailEvent};
+use crate::history::fetch_changes;
+use crate::reademail::convert_html_to_text;
+use crate::transport::{is_loopback, tokens_match};
+use crate::{GmailHub, GmailMcpError, SCOPE_READONLY};
+use actix_web::{web, App, HttpResponse, HttpServer};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use google_gmail1::api::WatchRequest;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+/// Environment variable naming the Pub/Sub topic; setting it enables push notifications.
+pub const TOPIC_ENV: &str = "GMAIL_PUBSUB_TOPIC";
+/// Environment variable for the push endpoint's listen address.
+pub const ADDR_ENV: &str = "GMAIL_PUBSUB_ADDR";
+/// Listen address of the push endpoint when [`ADDR_ENV`] is unset, for a
+/// reverse proxy on the same machine to forward to.
+pub const DEFAULT_ADDR: &str = "127.0.0.1:3004";
+/// Environment variable for the push endpoint's path.
+pub const PATH_ENV: &str = "GMAIL_PUBSUB_PATH";
+/// Environment variable holding a shared secret expected as the endpoint's `token` query parameter.
+pub const TOKEN_ENV: &str = "GMAIL_PUBSUB_TOKEN";
+/// Environment variable listing the label IDs to watch, comma separated.
+pub const LABELS_ENV: &str = "GMAIL_WATCH_LABELS";
+
+/// Watches expire after seven days; Google recommends renewing them daily.
+const RENEW_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Push notification settings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchConfig {
+    /// Full topic name, e.g. `projects/my-project/topics/gmail`.
+    pub topic: String,
+    /// Address the push endpoint listens on.
+    pub addr: String,
+    /// Path Pub/Sub posts to.
+    pub path: String,
+    /// Shared secret the push subscription must send as `?token=`; required
+    /// unless `addr` is a loopback address.
+    pub token: Option<String>,
+    /// Label IDs whose changes trigger notifications.
+    pub label_ids: Vec<String>,
+}
+
+impl WatchConfig {
+    /// Read the configuration from the environment; `None` when no topic is set.
+    pub fn from_env() -> Option<Self> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+        let topic = var(TOPIC_ENV)?;
+        Some(Self {
+            topic,
+            addr: var(ADDR_ENV).unwrap_or_else(|| DEFAULT_ADDR.to_string()),
+            path: var(PATH_ENV).unwrap_or_else(|| "/gmail/push".to_string()),
+            token: var(TOKEN_ENV),
+            label_ids: var(LABELS_ENV)
+                .map(|labels| {
+                    labels
+                        .split(',')
+                        .map(|l| l.trim().to_string())
+                        .filter(|l| !l.is_empty())
+                        .collect()
+                })
+                .unwrap_or_else(|| vec!["INBOX".to_string()]),
+        })
+    }
+
+    /// Refuse to accept pushes, which anyone could forge, from other machines
+    /// without a token.
+    fn check(&self) -> Result<(), GmailMcpError> {
+        if self.token.is_none() && !is_loopback(&self.addr) {
+            return Err(GmailMcpError::Invalid(format!(
+                "Refusing to serve the push endpoint on {} without {}; set it, \
+                 or listen on a loopback address",
+                self.addr, TOKEN_ENV
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// The result of starting a watch.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WatchInfo {
+    /// The mailbox history ID at the time the watch started.
+    pub history_id: u64,
+    /// When the watch expires (Unix epoch milliseconds).
+    pub expiration: Option<i64>,
+}
+
+/// Ask Gmail to publish changes to `label_ids` on `topic`.
+pub async fn start_watch(
+    hub: &GmailHub,
+    topic: &str,
+    label_ids: &[String],
+) -> Result<WatchInfo, GmailMcpError> {
+    info!("Gmail API: Starting watch on topic {}", topic);
+    let request = WatchRequest {
+        topic_name: Some(topic.to_string()),
+        label_ids: Some(label_ids.to_vec()),
+        label_filter_behavior: Some("include".to_string()),
+        ..Default::default()
+    };
+    crate::quota::record("watch", 1);
+    let (_, response) = hub
+        .users()
+        .watch(request, "me")
+        .add_scope(SCOPE_READONLY)
+        .doit()
+        .await
+        .map_err(|e| {
+            error!("Gmail API: Failed to start watch on {}: {}", topic, e);
+            e
+        })?;
+
+    Ok(WatchInfo {
+        history_id: response.history_id.unwrap_or_default(),
+        expiration: response.expiration,
+    })
+}
+
+/// Stop push notifications for the mailbox.
+pub async fn stop_watch(hub: &GmailHub) -> Result<(), google_gmail1::Error> {
+    info!("Gmail API: Stopping watch");
+    crate::quota::record("stop", 1);
+    hub.users()
+        .stop("me")
+        .add_scope(SCOPE_READONLY)
+        .doit()
+        .await
+        .map_err(|e| {
+            error!("Gmail API: Failed to stop watch: {}", e);
+            e
+        })?;
+    Ok(())
+}
+
+/// The body Pub/Sub posts to a push endpoint.
+#[derive(Deserialize, Debug)]
+struct PushEnvelope {
+    message: PushMessage,
+}
+
+#[derive(Deserialize, Debug)]
+struct PushMessage {
+    /// Base64-encoded JSON notification from Gmail.
+    data: String,
+}
+
+/// The notification Gmail publishes when the mailbox changes.
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct GmailNotification {
+    pub email_address: String,
+    pub history_id: u64,
+}
+
+/// Decode the Gmail notification inside a Pub/Sub push body.
+pub fn parse_push(body: &[u8]) -> Result<GmailNotification, GmailMcpError> {
+    let envelope: PushEnvelope = serde_json::from_slice(body)?;
+    let data = STANDARD.decode(envelope.message.data.trim())?;
+    Ok(serde_json::from_slice(&data)?)
+}
+
+/// State shared by the push endpoint's handlers.
+struct PushState {
+    hub: GmailHub,
+    token: Option<String>,
+    /// The history ID up to which changes have been reported.
+    last_history_id: Mutex<u64>,
+}
+
+/// Turn the changes since the last reported history ID into a new-mail event.
+async fn process_notification(state: &PushState, notification: GmailNotification) {
+    let mut last = state.last_history_id.lock().await;
+    if notification.history_id <= *last {
+        return;
+    }
+
+    let changes = match fetch_changes(&state.hub, *last).await {
+        Ok(changes) => changes,
+        Err(e) => {
+            // Keep the old start point so the next notification retries this range
+            error!("Failed to read changes for push notification: {}", e);
+            return;
+        }
+    };
+    *last = changes
+        .history_id
+        .parse()
+        .unwrap_or(notification.history_id)
+        .max(notification.history_id);
+    if changes.added.is_empty() {
+        return;
+    }
+
+    let mut emails = Vec::new();
+    for id in &changes.added {
+        let mut email = match crate::fetch_email(&state.hub, id).await {
+            Ok(email) => email,
+            Err(e) => {
+                warn!("Gmail API: Skipping added message {}: {}", id, e);
+                continue;
+            }
+        };
+        convert_html_to_text(&mut email).await;
+        emails.push(email);
+    }
+
+    events::publish(NewMailEvent {
+        source: "push".to_string(),
+        history_id: Some(last.to_string()),
+        message_ids: changes.added,
+        emails,
+        received_at: crate::state::now(),
+    });
+}
+
+async fn push_handler(
+    state: web::Data<Arc<PushState>>,
+    query: web::Query<HashMap<String, String>>,
+    body: web::Bytes,
+) -> HttpResponse {
+    if let Some(token) = &state.token {
+        let presented = query.get("token");
+        if !presented.is_some_and(|presented| tokens_match(presented, token)) {
+            warn!("Rejected Pub/Sub push with a missing or wrong token");
+            return HttpResponse::Forbidden().finish();
+        }
+    }
+
+    let notification = match parse_push(&body) {
+        Ok(notification) => notification,
+        Err(e) => {
+            // Acknowledge anyway: Pub/Sub would keep redelivering a malformed message
+            warn!("Ignoring malformed Pub/Sub push: {}", e);
+            return HttpResponse::NoContent().finish();
+        }
+    };
+    info!(
+        "Pub/Sub push for {} at history {}",
+        notification.email_address, notification.history_id
+    );
+    process_notification(&state, notification).await;
+    HttpResponse::NoContent().finish()
+}
+
+/// Start the watch, renew it daily, and serve the Pub/Sub push endpoint until it stops.
+pub async fn run(config: WatchConfig) -> Result<(), GmailMcpError> {
+    config.check()?;
+    let hub = crate::connect().await?;
+    let watch = start_watch(&hub, &config.topic, &config.label_ids).await?;
+    info!(
+        "Watching {:?} from history {} (expires {:?})",
+        config.label_ids, watch.history_id, watch.expiration
+    );
+
+    let renew_hub = hub.clone();
+    let renew_config = config.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(RENEW_INTERVAL).await;
+            let renewed = start_watch(&renew_hub, &renew_config.topic, &renew_config.label_ids)
+                .await
+                .map(|w| w.expiration);
+            match renewed {
+                Ok(expiration) => info!("Renewed Gmail watch (expires {:?})", expiration),
+                Err(e) => error!("Failed to renew Gmail watch: {}", e),
+            }
+        }
+    });
+
+    let state = Arc::new(PushState {
+        hub,
+        token: config.token.clone(),
+        last_history_id: Mutex::new(watch.history_id),
+    });
+    let path = config.path.clone();
+    info!(
+        "Pub/Sub push endpoint listening on http://{}{}",
+        config.addr, config.path
+    );
+    // Build the server in its own statement: the app factory is not Send and
+    // must not be held across the await
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route(&path, web::post().to(push_handler))
+    })
+    .bind(&config.addr)?
+    .run();
+    server.await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_push() {
+        let data = STANDARD.encode(r#"{"emailAddress":"me@example.com","historyId":9876}"#);
+        let body = format!(
+            r#"{{"message":{{"data":"{}","messageId":"1","publishTime":"2024-05-01T00:00:00Z"}},"subscription":"projects/p/subscriptions/s"}}"#,
+            data
+        );
+        assert_eq!(
+            parse_push(body.as_bytes()).unwrap(),
+            GmailNotification {
+                email_address: "me@example.com".to_string(),
+                history_id: 9876,
+            }
+        );
+        assert!(parse_push(b"{}").is_err());
+    }
+
+    #[test]
+    fn test_config_check() {
+        let config = |addr: &str, token: Option<&str>| WatchConfig {
+            topic: "projects/p/topics/gmail".to_string(),
+            addr: addr.to_string(),
+            path: "/gmail/push".to_string(),
+            token: token.map(str::to_string),
+            label_ids: vec!["INBOX".to_string()],
+        };
+        assert!(config(DEFAULT_ADDR, None).check().is_ok());
+        assert!(config("0.0.0.0:3004", None).check().is_err());
+        assert!(config("0.0.0.0:3004", Some("s3cret")).check().is_ok());
+    }
+}
@@ -0,0 +1,310 @@
+//! OAuth consent through the running server, for hosted deployments.
+//!
+//! With `GMAIL_AUTH_FLOW=web` the server serves `/oauth/start` and
+//! `/oauth/callback`. Opening the start page begins an OAuth flow and redirects
+//! the browser to Google's consent screen; Google then sends the browser back to
+//! the callback, which hands the code to the waiting flow. The resulting token
+//! is cached in the configured token store and used by later tool calls.
+
+use crate::transport::{is_loopback, tokens_match};
+use crate::GmailMcpError;
+use actix_web::http::{header, StatusCode};
+use actix_web::{web, App, HttpResponse, HttpServer};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tracing::{error, info, warn};
+use yup_oauth2::authenticator_delegate::InstalledFlowDelegate;
+
+/// Environment variable for the OAuth routes' listen address.
+pub const ADDR_ENV: &str = "GMAIL_OAUTH_ADDR";
+/// Listen address of the OAuth routes when [`ADDR_ENV`] is unset.
+pub const DEFAULT_ADDR: &str = "127.0.0.1:3005";
+/// Environment variable with the public URL of `/oauth/callback`, as registered
+/// with the OAuth client.
+pub const REDIRECT_URL_ENV: &str = "GMAIL_OAUTH_REDIRECT_URL";
+/// Environment variable holding a shared secret expected as `/oauth/start`'s `token` query parameter.
+pub const TOKEN_ENV: &str = "GMAIL_OAUTH_TOKEN";
+
+const START_PATH: &str = "/oauth/start";
+const CALLBACK_PATH: &str = "/oauth/callback";
+
+/// How long a flow waits for the browser to come back from the consent screen.
+const CONSENT_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// How long `/oauth/start` waits for a flow to produce its consent URL.
+const START_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Web flow settings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebAuthConfig {
+    /// Address the OAuth routes listen on.
+    pub addr: String,
+    /// Public URL of the callback route.
+    pub redirect_url: String,
+    /// Shared secret required to start a flow; required unless `addr` is a
+    /// loopback address.
+    pub token: Option<String>,
+}
+
+impl WebAuthConfig {
+    /// Read the configuration from the environment, with local defaults.
+    pub fn from_env() -> Self {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+        let addr = var(ADDR_ENV).unwrap_or_else(|| DEFAULT_ADDR.to_string());
+        let port = addr.rsplit(':').next().unwrap_or("3005");
+        Self {
+            redirect_url: var(REDIRECT_URL_ENV)
+                .unwrap_or_else(|| format!("http://localhost:{}{}", port, CALLBACK_PATH)),
+            addr,
+            token: var(TOKEN_ENV),
+        }
+    }
+
+    /// Refuse to let anyone who can reach a non-loopback address start a flow
+    /// that would bind the server to their account.
+    fn check(&self) -> Result<(), GmailMcpError> {
+        if self.token.is_none() && !is_loopback(&self.addr) {
+            return Err(GmailMcpError::Invalid(format!(
+                "Refusing to serve the OAuth routes on {} without {}; set it, \
+                 or listen on a loopback address",
+                self.addr, TOKEN_ENV
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// A flow waiting for the browser to return from the consent screen.
+struct PendingConsent {
+    /// Consent URL, including `state`.
+    url: String,
+    /// Random value the callback must echo back.
+    state: String,
+    /// Delivers the authorization code to the waiting flow.
+    code: oneshot::Sender<Result<String, String>>,
+}
+
+/// The flow currently awaiting consent; a newer flow replaces an older one.
+static PENDING: Mutex<Option<PendingConsent>> = Mutex::new(None);
+
+fn pending_url() -> Option<String> {
+    PENDING.lock().unwrap().as_ref().map(|p| p.url.clone())
+}
+
+/// Generate an unguessable `state` value.
+fn random_state() -> Result<String, String> {
+    let mut bytes = [0u8; 16];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| "No secure random numbers available".to_string())?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Publishes the consent URL for `/oauth/start` and waits for the callback.
+pub struct WebRedirect {
+    redirect_url: String,
+}
+
+impl WebRedirect {
+    pub fn new(config: &WebAuthConfig) -> Self {
+        Self {
+            redirect_url: config.redirect_url.clone(),
+        }
+    }
+}
+
+impl InstalledFlowDelegate for WebRedirect {
+    fn redirect_uri(&self) -> Option<&str> {
+        Some(&self.redirect_url)
+    }
+
+    fn present_user_url<'a>(
+        &'a self,
+        url: &'a str,
+        _need_code: bool,
+    ) -> Pin<Box<dyn Future<Output = Result<String, String>> + Send + 'a>> {
+        Box::pin(async move {
+            let state = random_state()?;
+            let (sender, receiver) = oneshot::channel();
+            let replaced = PENDING.lock().unwrap().replace(PendingConsent {
+                url: format!("{}&state={}", url, state),
+                state,
+                code: sender,
+            });
+            if replaced.is_some() {
+                info!("Gmail auth: Replacing an earlier pending consent");
+            }
+            warn!(
+                "Gmail auth: Open {} on this server in a browser to grant access",
+                START_PATH
+            );
+
+            match tokio::time::timeout(CONSENT_TIMEOUT, receiver).await {
+                Ok(Ok(code)) => code,
+                Ok(Err(_)) => Err("The consent was superseded by a newer one".to_string()),
+                Err(_) => {
+                    PENDING.lock().unwrap().take();
+                    Err("Timed out waiting for consent".to_string())
+                }
+            }
+        })
+    }
+}
+
+/// Hand the callback's query to the pending flow, returning a message for the browser.
+fn complete(query: &HashMap<String, String>) -> Result<&'static str, String> {
+    let mut pending = PENDING.lock().unwrap();
+    let expected = pending.as_ref().map(|p| p.state.as_str());
+    if expected.is_none() || query.get("state").map(String::as_str) != expected {
+        return Err("This consent is unknown or has expired; start again".to_string());
+    }
+    let consent = pending.take().expect("pending consent checked above");
+
+    let result = match (query.get("code"), query.get("error")) {
+        (Some(code), _) if !code.is_empty() => Ok(code.clone()),
+        (_, Some(e)) => Err(format!("Consent was not granted: {}", e)),
+        _ => Err("The callback has no authorization code".to_string()),
+    };
+    let outcome = match &result {
+        Ok(_) => Ok("Access granted. You can close this window."),
+        Err(e) => Err(e.clone()),
+    };
+    // The flow may have given up in the meantime; nothing is waiting then
+    let _ = consent.code.send(result);
+    outcome
+}
+
+fn page(status: StatusCode, message: &str) -> HttpResponse {
+    HttpResponse::build(status)
+        .content_type("text/plain; charset=utf-8")
+        .body(message.to_string())
+}
+
+async fn start_handler(
+    config: web::Data<WebAuthConfig>,
+    query: web::Query<HashMap<String, String>>,
+) -> HttpResponse {
+    if let Some(token) = &config.token {
+        let presented = query.get("token");
+        if !presented.is_some_and(|presented| tokens_match(presented, token)) {
+            warn!(
+                "Gmail auth: Rejected {} with a missing or wrong token",
+                START_PATH
+            );
+            return HttpResponse::Forbidden().finish();
+        }
+    }
+
+    // Start a flow unless one is already waiting; it runs until the callback arrives
+    if pending_url().is_none() {
+        let flow = tokio::spawn(async {
+            if let Err(e) = crate::connect().await {
+                error!("Gmail auth: Web consent failed: {}", e);
+            }
+        });
+        let deadline = tokio::time::Instant::now() + START_TIMEOUT;
+        while pending_url().is_none() {
+            if flow.is_finished() {
+                return page(StatusCode::OK, "Already authorized; no consent is needed.");
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return page(
+                    StatusCode::GATEWAY_TIMEOUT,
+                    "Timed out starting the OAuth flow; see the server log.",
+                );
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    match pending_url() {
+        Some(url) => HttpResponse::Found()
+            .insert_header((header::LOCATION, url))
+            .finish(),
+        None => page(
+            StatusCode::CONFLICT,
+            "The consent finished in the meantime; start again if needed.",
+        ),
+    }
+}
+
+async fn callback_handler(query: web::Query<HashMap<String, String>>) -> HttpResponse {
+    match complete(&query) {
+        Ok(message) => {
+            info!("Gmail auth: Received consent through {}", CALLBACK_PATH);
+            page(StatusCode::OK, message)
+        }
+        Err(e) => {
+            warn!("Gmail auth: {}", e);
+            page(StatusCode::BAD_REQUEST, &e)
+        }
+    }
+}
+
+/// Serve the OAuth routes until the server stops.
+pub async fn run(config: WebAuthConfig) -> Result<(), GmailMcpError> {
+    config.check()?;
+    info!(
+        "Gmail auth: OAuth routes listening on http://{} (callback {})",
+        config.addr, config.redirect_url
+    );
+    let addr = config.addr.clone();
+    let data = web::Data::new(config);
+    // Build the server in its own statement: the app factory is not Send and
+    // must not be held across the await
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(data.clone())
+            .route(START_PATH, web::get().to(start_handler))
+            .route(CALLBACK_PATH, web::get().to(callback_handler))
+    })
+    .bind(&addr)?
+    .run();
+    server.await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_check() {
+        let config = |addr: &str, token: Option<&str>| WebAuthConfig {
+            addr: addr.to_string(),
+            redirect_url: String::new(),
+            token: token.map(str::to_string),
+        };
+        assert!(config(DEFAULT_ADDR, None).check().is_ok());
+        assert!(config("0.0.0.0:3005", None).check().is_err());
+        assert!(config("0.0.0.0:3005", Some("s3cret")).check().is_ok());
+    }
+
+    #[test]
+    fn test_complete_checks_state() {
+        let query = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        assert!(complete(&query(&[("code", "abc"), ("state", "x")])).is_err());
+
+        let (sender, mut receiver) = oneshot::channel();
+        *PENDING.lock().unwrap() = Some(PendingConsent {
+            url: "https://accounts.google.com/o/oauth2/auth?state=s1".to_string(),
+            state: "s1".to_string(),
+            code: sender,
+        });
+        assert!(complete(&query(&[("code", "abc"), ("state", "wrong")])).is_err());
+        assert!(PENDING.lock().unwrap().is_some());
+
+        assert!(complete(&query(&[("code", "4/abc"), ("state", "s1")])).is_ok());
+        assert_eq!(receiver.try_recv().unwrap(), Ok("4/abc".to_string()));
+        assert!(PENDING.lock().unwrap().is_none());
+    }
+}